@@ -0,0 +1,156 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use image::{Rgba, RgbaImage};
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/pixel_ops.rs"]
+mod pixel_ops;
+
+use pixel_ops::Colormap;
+
+const WIDTH: u32 = 3840;
+const HEIGHT: u32 = 2160;
+
+fn test_image(seed: u32) -> RgbaImage {
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        let v = x.wrapping_mul(31) ^ y.wrapping_mul(17) ^ seed;
+        Rgba([v as u8, (v >> 3) as u8, (v >> 5) as u8, 255])
+    })
+}
+
+fn scalar_diff(mut one: RgbaImage, two: &RgbaImage) -> RgbaImage {
+    let (w, h) = one.dimensions();
+    for y in 0..h {
+        for x in 0..w {
+            let op = one.get_pixel_mut(x, y);
+            let tp = two.get_pixel(x, y);
+            for c in 0..3 {
+                op[c] = op[c].abs_diff(tp[c]);
+            }
+        }
+    }
+    one
+}
+
+fn scalar_gamma(mut img: RgbaImage, gamma: f32) -> RgbaImage {
+    let inv_gamma = 1.0 / gamma;
+    let (w, h) = img.dimensions();
+    for y in 0..h {
+        for x in 0..w {
+            let p = img.get_pixel_mut(x, y);
+            for c in 0..3 {
+                p[c] = ((p[c] as f32 / 255.0).powf(inv_gamma) * 255.0) as u8;
+            }
+        }
+    }
+    img
+}
+
+fn bench_diff(c: &mut Criterion) {
+    let one = test_image(0);
+    let two = test_image(0x5a5a);
+    let mut group = c.benchmark_group("image_diff 4K");
+    group.sample_size(20);
+    group.bench_function("scalar", |b| {
+        b.iter(|| scalar_diff(black_box(one.clone()), black_box(&two)))
+    });
+    group.bench_function("buffer", |b| {
+        b.iter(|| pixel_ops::image_diff(black_box(one.clone()), black_box(&two)))
+    });
+    group.bench_function("linear", |b| {
+        b.iter(|| pixel_ops::image_diff_linear(black_box(one.clone()), black_box(&two)))
+    });
+    group.bench_function("histograms", |b| {
+        b.iter(|| {
+            let a = pixel_ops::luma_histogram(black_box(&one));
+            let b = pixel_ops::luma_histogram(black_box(&two));
+            pixel_ops::histogram_distance(&a, &b)
+        })
+    });
+    group.finish();
+}
+
+fn bench_gamma(c: &mut Criterion) {
+    let img = test_image(0);
+    let mut group = c.benchmark_group("image_gamma 4K");
+    group.sample_size(20);
+    group.bench_function("scalar", |b| {
+        b.iter(|| scalar_gamma(black_box(img.clone()), black_box(2.2)))
+    });
+    group.bench_function("lut", |b| {
+        b.iter(|| {
+            let lut = pixel_ops::gamma_lut(black_box(2.2));
+            pixel_ops::apply_lut(black_box(img.clone()), &lut)
+        })
+    });
+    group.bench_function("adjustment lut", |b| {
+        b.iter(|| {
+            let lut = pixel_ops::adjustment_lut(black_box(1.5), 0.1, 1.2);
+            pixel_ops::apply_lut(black_box(img.clone()), &lut)
+        })
+    });
+    group.finish();
+}
+
+fn bench_downsample(c: &mut Criterion) {
+    let img = test_image(0);
+    let mut group = c.benchmark_group("downsample 4K");
+    group.sample_size(20);
+    group.bench_function("resize", |b| {
+        b.iter(|| {
+            image::imageops::resize(
+                black_box(&img),
+                WIDTH / 2,
+                HEIGHT / 2,
+                image::imageops::FilterType::Triangle,
+            )
+        })
+    });
+    group.bench_function("box", |b| {
+        b.iter(|| pixel_ops::downsample_half(black_box(&img)))
+    });
+    group.bench_function("linear box", |b| {
+        b.iter(|| pixel_ops::downscale_linear(black_box(&img), WIDTH / 2, HEIGHT / 2))
+    });
+    group.finish();
+}
+
+fn bench_alpha(c: &mut Criterion) {
+    let img = test_image(0);
+    let mut group = c.benchmark_group("alpha 4K");
+    group.sample_size(20);
+    group.bench_function("alpha stats", |b| {
+        b.iter(|| pixel_ops::alpha_stats(black_box(&img)))
+    });
+    group.bench_function("unpremultiply", |b| {
+        b.iter(|| pixel_ops::unpremultiply(black_box(img.clone())))
+    });
+    group.finish();
+}
+
+fn bench_colormap(c: &mut Criterion) {
+    let img = test_image(0);
+    let mut group = c.benchmark_group("colormap 4K");
+    group.sample_size(20);
+    group.bench_function("stats", |b| {
+        b.iter(|| pixel_ops::scalar_stats(black_box(&img)))
+    });
+    for colormap in [Colormap::Grayscale, Colormap::Viridis, Colormap::Turbo] {
+        group.bench_function(format!("{:?}", colormap), |b| {
+            b.iter(|| {
+                let lut = pixel_ops::colormap_lut(black_box(colormap), 16, 240);
+                pixel_ops::apply_colormap(black_box(&img), &lut)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_diff,
+    bench_gamma,
+    bench_downsample,
+    bench_alpha,
+    bench_colormap
+);
+criterion_main!(benches);
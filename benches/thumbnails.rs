@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use image::{Rgb, RgbImage};
+use std::path::PathBuf;
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/decode.rs"]
+mod decode;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/error.rs"]
+mod error;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/pixel_ops.rs"]
+mod pixel_ops;
+#[allow(dead_code, unused_imports)]
+#[path = "../src/utils.rs"]
+mod utils;
+
+const WIDTH: u32 = 12000;
+const HEIGHT: u32 = 8000;
+const FILES: u32 = 4;
+const THUMBNAIL_SIZE: u32 = 150;
+
+fn test_folder() -> Vec<PathBuf> {
+    let dir = std::env::temp_dir().join("imview_thumbnail_bench");
+    std::fs::create_dir_all(&dir).unwrap();
+    (0..FILES)
+        .map(|seed| {
+            let path = dir.join(format!("{}.jpg", seed));
+            if !path.exists() {
+                let img = RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+                    let v = (x / 7) ^ (y / 5) ^ seed;
+                    Rgb([v as u8, (x / 47) as u8, (y / 31) as u8])
+                });
+                img.save(&path).unwrap();
+            }
+            path
+        })
+        .collect()
+}
+
+fn bench_thumbnails(c: &mut Criterion) {
+    let paths = test_folder();
+    let mut group = c.benchmark_group("thumbnails of 96 MP JPEGs");
+    group.sample_size(10);
+    for (name, linear) in [("", false), (", linear", true)] {
+        let scaling = decode::ThumbnailScaling {
+            filter: decode::ThumbnailFilter::Box,
+            linear,
+        };
+        group.bench_function(format!("full decode{}", name), |b| {
+            b.iter(|| {
+                for path in paths.iter() {
+                    let img = decode::decode(black_box(path)).unwrap();
+                    black_box(decode::to_thumbnail(img, THUMBNAIL_SIZE, scaling));
+                }
+            })
+        });
+        group.bench_function(format!("scaled decode{}", name), |b| {
+            b.iter(|| {
+                for path in paths.iter() {
+                    let path = black_box(path);
+                    black_box(decode::decode_thumbnail(path, THUMBNAIL_SIZE, scaling).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_thumbnails);
+criterion_main!(benches);
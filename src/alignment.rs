@@ -0,0 +1,186 @@
+use crate::image_ui_state::DiffMode;
+use image::imageops::{resize, FilterType};
+use image::{GrayImage, RgbaImage};
+
+/// Side of the square the halves are downscaled to before correlation.
+const CORRELATION_SIZE: usize = 256;
+/// Minimal peak-to-sidelobe ratio accepted as a reliable match.
+const MIN_CONFIDENCE: f32 = 8.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Alignment {
+    /// Horizontal offset of the second image relative to the first, in pixels.
+    pub dx: i32,
+    /// Vertical offset of the second image relative to the first, in pixels.
+    pub dy: i32,
+    /// Peak-to-sidelobe ratio of the correlation peak.
+    pub confidence: f32,
+}
+
+pub enum AlignmentStatus {
+    None,
+    Running(DiffMode),
+    Estimated(DiffMode, Alignment),
+    Failed(String),
+}
+
+type Complex = (f32, f32);
+
+fn fft(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f32::consts::PI / len as f32;
+        let w_len = (angle.cos(), angle.sin());
+        for start in (0..n).step_by(len) {
+            let mut w = (1.0, 0.0);
+            for k in 0..len / 2 {
+                let (ur, ui) = data[start + k];
+                let (vr, vi) = data[start + k + len / 2];
+                let v = (vr * w.0 - vi * w.1, vr * w.1 + vi * w.0);
+                data[start + k] = (ur + v.0, ui + v.1);
+                data[start + k + len / 2] = (ur - v.0, ui - v.1);
+                w = (w.0 * w_len.0 - w.1 * w_len.1, w.0 * w_len.1 + w.1 * w_len.0);
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        for v in data.iter_mut() {
+            v.0 /= n as f32;
+            v.1 /= n as f32;
+        }
+    }
+}
+
+fn fft_2d(data: &mut [Complex], size: usize, inverse: bool) {
+    for row in data.chunks_mut(size) {
+        fft(row, inverse);
+    }
+    let mut column = vec![(0.0, 0.0); size];
+    for x in 0..size {
+        for y in 0..size {
+            column[y] = data[y * size + x];
+        }
+        fft(&mut column, inverse);
+        for y in 0..size {
+            data[y * size + x] = column[y];
+        }
+    }
+}
+
+fn to_spectrum(img: &RgbaImage) -> Vec<Complex> {
+    let size = CORRELATION_SIZE as u32;
+    let gray: GrayImage = image::DynamicImage::ImageRgba8(img.clone()).to_luma8();
+    let gray = resize(&gray, size, size, FilterType::Triangle);
+    let window = |i: u32| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / size as f32).cos();
+    let mut data = gray
+        .enumerate_pixels()
+        .map(|(x, y, p)| (p[0] as f32 * window(x) * window(y), 0.0))
+        .collect::<Vec<_>>();
+    fft_2d(&mut data, CORRELATION_SIZE, false);
+    data
+}
+
+/// Estimates the translation between two images of the same size using
+/// phase correlation. Returns an error when the correlation peak is not
+/// distinct enough to be trusted.
+pub fn estimate_translation(one: &RgbaImage, two: &RgbaImage) -> std::io::Result<Alignment> {
+    if one.width() < 2 || one.height() < 2 || one.dimensions() != two.dimensions() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Images are too small or differ in size",
+        ));
+    }
+
+    let f1 = to_spectrum(one);
+    let f2 = to_spectrum(two);
+    let mut cross = f1
+        .iter()
+        .zip(f2.iter())
+        .map(|(a, b)| {
+            let re = a.0 * b.0 + a.1 * b.1;
+            let im = a.1 * b.0 - a.0 * b.1;
+            let norm = (re * re + im * im).sqrt().max(f32::EPSILON);
+            (re / norm, im / norm)
+        })
+        .collect::<Vec<_>>();
+    fft_2d(&mut cross, CORRELATION_SIZE, true);
+
+    let values = cross.iter().map(|c| c.0).collect::<Vec<_>>();
+    let (peak_idx, peak) = values
+        .iter()
+        .copied()
+        .enumerate()
+        .fold(
+            (0, f32::MIN),
+            |acc, (i, v)| if v > acc.1 { (i, v) } else { acc },
+        );
+    let count = values.len() as f32;
+    let mean = values.iter().sum::<f32>() / count;
+    let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / count;
+    let confidence = (peak - mean) / variance.sqrt().max(f32::EPSILON);
+    if confidence < MIN_CONFIDENCE {
+        return Err(std::io::Error::other(format!(
+            "Alignment confidence is too low ({:.1})",
+            confidence
+        )));
+    }
+
+    let at = |x: usize, y: usize| {
+        values[(y % CORRELATION_SIZE) * CORRELATION_SIZE + x % CORRELATION_SIZE]
+    };
+    // Sub-pixel refinement by fitting a parabola through the peak neighbours.
+    let refine = |prev: f32, next: f32| {
+        let denom = prev - 2.0 * peak + next;
+        if denom.abs() > f32::EPSILON {
+            (0.5 * (prev - next) / denom).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        }
+    };
+    let unwrap = |v: usize| {
+        if v > CORRELATION_SIZE / 2 {
+            v as f32 - CORRELATION_SIZE as f32
+        } else {
+            v as f32
+        }
+    };
+    let (x, y) = (peak_idx % CORRELATION_SIZE, peak_idx / CORRELATION_SIZE);
+    let last = CORRELATION_SIZE - 1;
+    let px = unwrap(x) + refine(at(x + last, y), at(x + 1, y));
+    let py = unwrap(y) + refine(at(x, y + last), at(x, y + 1));
+    let sx = one.width() as f32 / CORRELATION_SIZE as f32;
+    let sy = one.height() as f32 / CORRELATION_SIZE as f32;
+    Ok(Alignment {
+        dx: (-px * sx).round() as i32,
+        dy: (-py * sy).round() as i32,
+        confidence,
+    })
+}
+
+/// Resamples `img` so that content shifted by `alignment` lines up with the
+/// first image. Pixels outside the source are clamped to the nearest edge.
+pub fn apply_translation(img: &RgbaImage, alignment: &Alignment) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    RgbaImage::from_fn(w, h, |x, y| {
+        let sx = (x as i64 + alignment.dx as i64).clamp(0, w as i64 - 1) as u32;
+        let sy = (y as i64 + alignment.dy as i64).clamp(0, h as i64 - 1) as u32;
+        *img.get_pixel(sx, sy)
+    })
+}
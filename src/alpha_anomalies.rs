@@ -0,0 +1,73 @@
+//! Finds color hidden under transparency. Pixels with (almost) zero alpha
+//! but non-zero RGB look fine until the image is resized or premultiplied,
+//! when the hidden color bleeds into the visible neighbors.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+
+/// Color the anomalies are marked with in the overlay.
+const MARKER: [u8; 4] = [255, 0, 255, 255];
+/// Side of the square tiles anomalies are grouped into for navigation.
+const REGION_SIZE: u32 = 64;
+
+pub struct AlphaAnomalies {
+    /// Pixels with alpha below this value are checked.
+    pub threshold: u8,
+    pub count: usize,
+    /// `MARKER` where the anomalies are, transparent elsewhere.
+    pub mask: RgbaImage,
+    /// Centers of the tiles holding anomalies in row-major order, in pixels.
+    pub regions: Vec<(u32, u32)>,
+}
+
+pub fn find_alpha_anomalies(img: &RgbaImage, threshold: u8) -> AlphaAnomalies {
+    let (w, h) = img.dimensions();
+    let mut mask = RgbaImage::new(w, h);
+    let row = (w as usize * 4).max(4);
+    let count = mask
+        .par_chunks_mut(row)
+        .zip(img.par_chunks(row))
+        .map(|(mask_row, img_row)| {
+            let mut count = 0;
+            for (m, p) in mask_row.chunks_exact_mut(4).zip(img_row.chunks_exact(4)) {
+                if p[3] < threshold && (p[0] | p[1] | p[2]) != 0 {
+                    m.copy_from_slice(&MARKER);
+                    count += 1;
+                }
+            }
+            count
+        })
+        .sum();
+
+    let tiles_x = w.div_ceil(REGION_SIZE);
+    let tiles_y = h.div_ceil(REGION_SIZE);
+    let mut flagged = vec![false; (tiles_x * tiles_y) as usize];
+    if count > 0 {
+        for (y, mask_row) in mask.chunks(row).enumerate() {
+            let tile_row = (y as u32 / REGION_SIZE * tiles_x) as usize;
+            for (x, m) in mask_row.chunks_exact(4).enumerate() {
+                if m[3] != 0 {
+                    flagged[tile_row + x / REGION_SIZE as usize] = true;
+                }
+            }
+        }
+    }
+    let regions = flagged
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| **f)
+        .map(|(idx, _)| {
+            let (tx, ty) = (idx as u32 % tiles_x, idx as u32 / tiles_x);
+            let x = (tx * REGION_SIZE + REGION_SIZE / 2).min(w - 1);
+            let y = (ty * REGION_SIZE + REGION_SIZE / 2).min(h - 1);
+            (x, y)
+        })
+        .collect();
+
+    AlphaAnomalies {
+        threshold,
+        count,
+        mask,
+        regions,
+    }
+}
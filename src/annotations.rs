@@ -0,0 +1,521 @@
+use crate::geometry::ViewTransform;
+use crate::image_ui_state::{Edit, EditHistory};
+use crate::raster::{draw_line, draw_text, segment_distance};
+use eframe::egui::*;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const HIT_DISTANCE: f32 = 6.0;
+const TEXT_SIZE: f32 = 16.0;
+const PIN_RADIUS: f32 = 5.0;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AnnotationTool {
+    None,
+    Select,
+    Rectangle,
+    Arrow,
+    Text,
+    Pin,
+}
+
+/// Annotation geometry in image pixel coordinates.
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum AnnotationShape {
+    Rectangle {
+        min: [f32; 2],
+        max: [f32; 2],
+    },
+    Arrow {
+        from: [f32; 2],
+        to: [f32; 2],
+    },
+    Text {
+        pos: [f32; 2],
+        text: String,
+    },
+    /// Marker with a note next to it.
+    Pin {
+        pos: [f32; 2],
+        note: String,
+    },
+}
+
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct Annotation {
+    pub shape: AnnotationShape,
+    pub color: [u8; 4],
+}
+
+fn to_pos(p: [f32; 2]) -> Pos2 {
+    pos2(p[0], p[1])
+}
+
+fn from_pos(p: Pos2) -> [f32; 2] {
+    [p.x, p.y]
+}
+
+impl Annotation {
+    pub fn color32(&self) -> Color32 {
+        let [r, g, b, a] = self.color;
+        Color32::from_rgba_unmultiplied(r, g, b, a)
+    }
+
+    pub fn translate(&mut self, delta: Vec2) {
+        let shift = |p: &mut [f32; 2]| {
+            p[0] += delta.x;
+            p[1] += delta.y;
+        };
+        match &mut self.shape {
+            AnnotationShape::Rectangle { min, max } => {
+                shift(min);
+                shift(max);
+            }
+            AnnotationShape::Arrow { from, to } => {
+                shift(from);
+                shift(to);
+            }
+            AnnotationShape::Text { pos, .. } | AnnotationShape::Pin { pos, .. } => shift(pos),
+        }
+    }
+
+    fn text_rect(&self, painter: &Painter, transform: &ViewTransform) -> Option<Rect> {
+        match &self.shape {
+            AnnotationShape::Text { pos, text } => {
+                let galley = painter.layout_no_wrap(
+                    text.clone(),
+                    FontId::proportional(TEXT_SIZE),
+                    self.color32(),
+                );
+                Some(Rect::from_min_size(
+                    transform.to_screen(to_pos(*pos)),
+                    galley.size(),
+                ))
+            }
+            AnnotationShape::Pin { pos, note } => {
+                let galley = painter.layout_no_wrap(
+                    note.clone(),
+                    FontId::proportional(TEXT_SIZE),
+                    self.color32(),
+                );
+                let center = transform.to_screen(to_pos(*pos));
+                let label = Rect::from_min_size(
+                    center + vec2(2.0 * PIN_RADIUS, -galley.size().y / 2.0),
+                    galley.size(),
+                );
+                Some(Rect::from_center_size(center, Vec2::splat(2.0 * PIN_RADIUS)).union(label))
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks whether the screen position hits the annotation.
+    pub fn hit(&self, painter: &Painter, transform: &ViewTransform, screen_pos: Pos2) -> bool {
+        match &self.shape {
+            AnnotationShape::Rectangle { min, max } => {
+                let r = Rect::from_two_pos(
+                    transform.to_screen(to_pos(*min)),
+                    transform.to_screen(to_pos(*max)),
+                );
+                r.expand(HIT_DISTANCE).contains(screen_pos)
+            }
+            AnnotationShape::Arrow { from, to } => {
+                let a = transform.to_screen(to_pos(*from));
+                let b = transform.to_screen(to_pos(*to));
+                segment_distance(screen_pos, a, b) <= HIT_DISTANCE
+            }
+            AnnotationShape::Text { .. } | AnnotationShape::Pin { .. } => self
+                .text_rect(painter, transform)
+                .map(|r| r.expand(HIT_DISTANCE).contains(screen_pos))
+                .unwrap_or(false),
+        }
+    }
+
+    pub fn paint(&self, painter: &Painter, transform: &ViewTransform, selected: bool) {
+        let color = self.color32();
+        let stroke = Stroke::new(2.0, color);
+        match &self.shape {
+            AnnotationShape::Rectangle { min, max } => {
+                let r = Rect::from_two_pos(
+                    transform.to_screen(to_pos(*min)),
+                    transform.to_screen(to_pos(*max)),
+                );
+                painter.rect_stroke(r, Rounding::none(), stroke);
+            }
+            AnnotationShape::Arrow { from, to } => {
+                let a = transform.to_screen(to_pos(*from));
+                let b = transform.to_screen(to_pos(*to));
+                painter.arrow(a, b - a, stroke);
+            }
+            AnnotationShape::Text { pos, text } => {
+                painter.text(
+                    transform.to_screen(to_pos(*pos)),
+                    Align2::LEFT_TOP,
+                    text,
+                    FontId::proportional(TEXT_SIZE),
+                    color,
+                );
+            }
+            AnnotationShape::Pin { pos, note } => {
+                let center = transform.to_screen(to_pos(*pos));
+                painter.circle(center, PIN_RADIUS, color, Stroke::new(1.0, Color32::BLACK));
+                if !note.is_empty() {
+                    let galley = painter.layout_no_wrap(
+                        note.clone(),
+                        FontId::proportional(TEXT_SIZE),
+                        color,
+                    );
+                    let label = Rect::from_min_size(
+                        center + vec2(2.0 * PIN_RADIUS, -galley.size().y / 2.0),
+                        galley.size(),
+                    );
+                    painter.rect_filled(
+                        label.expand(2.0),
+                        Rounding::same(2.0),
+                        Color32::from_black_alpha(160),
+                    );
+                    painter.galley(label.min, galley);
+                }
+            }
+        }
+        if selected {
+            let bounds = match &self.shape {
+                AnnotationShape::Rectangle { min, max } => Rect::from_two_pos(
+                    transform.to_screen(to_pos(*min)),
+                    transform.to_screen(to_pos(*max)),
+                ),
+                AnnotationShape::Arrow { from, to } => Rect::from_two_pos(
+                    transform.to_screen(to_pos(*from)),
+                    transform.to_screen(to_pos(*to)),
+                ),
+                AnnotationShape::Text { .. } | AnnotationShape::Pin { .. } => {
+                    self.text_rect(painter, transform).unwrap()
+                }
+            };
+            painter.rect_stroke(
+                bounds.expand(HIT_DISTANCE),
+                Rounding::none(),
+                Stroke::new(1.0, Color32::WHITE),
+            );
+        }
+    }
+}
+
+/// Annotations of one image together with the editing state.
+pub struct Annotations {
+    pub items: Vec<Annotation>,
+    pub tool: AnnotationTool,
+    pub color: [u8; 4],
+    pub text: String,
+    pub selected: Option<usize>,
+    drag_start: Option<Pos2>,
+    /// Whether the sidecar file was asked for, see `set_loaded`.
+    pub load_requested: bool,
+    /// Annotations as last read from or written to the sidecar file.
+    saved: Vec<Annotation>,
+    /// The items came from the session and stay as they are when the
+    /// sidecar file is read.
+    restored: bool,
+}
+
+impl Annotations {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            tool: AnnotationTool::None,
+            color: [255, 0, 0, 255],
+            text: String::new(),
+            selected: None,
+            drag_start: None,
+            load_requested: false,
+            saved: Vec::new(),
+            restored: false,
+        }
+    }
+
+    /// Takes the annotations read from the sidecar file, keeping the ones
+    /// made before it was read. Annotations restored from the session
+    /// already include the saved ones, with their unsaved changes.
+    pub fn set_loaded(&mut self, items: Vec<Annotation>) {
+        self.saved = items.clone();
+        if !self.restored {
+            let added = std::mem::replace(&mut self.items, items);
+            self.items.extend(added);
+        }
+        self.selected = None;
+    }
+
+    /// Puts back the annotations kept in the session.
+    pub fn set_restored(&mut self, items: Vec<Annotation>) {
+        self.items = items;
+        self.selected = None;
+        self.restored = true;
+    }
+
+    pub fn set_saved(&mut self, items: Vec<Annotation>) {
+        self.saved = items;
+    }
+
+    /// Whether there are changes the sidecar file doesn't have.
+    pub fn is_modified(&self) -> bool {
+        self.items != self.saved
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.tool != AnnotationTool::None
+    }
+
+    /// Remembers the current annotations in `history` so the next edit
+    /// can be undone.
+    fn checkpoint(&self, history: &mut EditHistory) {
+        history.push(Edit::Annotations(self.items.clone()));
+    }
+
+    /// Puts back annotations kept by the history, returning the replaced
+    /// ones.
+    pub fn restore(&mut self, items: Vec<Annotation>) -> Vec<Annotation> {
+        self.selected = None;
+        self.drag_start = None;
+        std::mem::replace(&mut self.items, items)
+    }
+
+    /// Corners of the selected annotation when it's a rectangle.
+    pub fn selected_rectangle(&self) -> Option<([f32; 2], [f32; 2])> {
+        match self.items.get(self.selected?)?.shape {
+            AnnotationShape::Rectangle { min, max } => Some((min, max)),
+            _ => None,
+        }
+    }
+
+    pub fn delete_selected(&mut self, history: &mut EditHistory) {
+        if let Some(idx) = self.selected.take() {
+            if idx < self.items.len() {
+                self.checkpoint(history);
+                self.items.remove(idx);
+            }
+        }
+    }
+
+    pub fn set_selected_color(&mut self, color: [u8; 4], history: &mut EditHistory) {
+        if let Some(idx) = self.selected {
+            if self.items[idx].color != color {
+                self.checkpoint(history);
+                self.items[idx].color = color;
+            }
+        }
+    }
+
+    pub fn paint(&self, painter: &Painter, transform: &ViewTransform) {
+        for (idx, a) in self.items.iter().enumerate() {
+            a.paint(painter, transform, self.selected == Some(idx));
+        }
+        if let (Some(start), Some(current)) = (self.drag_start, painter.ctx().pointer_latest_pos())
+        {
+            let a = transform.to_screen(start);
+            let stroke = Stroke::new(1.0, Color32::WHITE);
+            match self.tool {
+                AnnotationTool::Rectangle => {
+                    painter.rect_stroke(Rect::from_two_pos(a, current), Rounding::none(), stroke)
+                }
+                AnnotationTool::Arrow => painter.arrow(a, current - a, stroke),
+                _ => (),
+            }
+        }
+    }
+
+    /// Handles editing input on the view with the given transform, edits
+    /// are recorded in `history`.
+    pub fn interact(
+        &mut self,
+        response: &Response,
+        painter: &Painter,
+        transform: &ViewTransform,
+        history: &mut EditHistory,
+    ) {
+        let pointer = response.interact_pointer_pos();
+        match self.tool {
+            AnnotationTool::None => (),
+            AnnotationTool::Select => {
+                if response.drag_started() || response.clicked() {
+                    self.selected = pointer.and_then(|p| {
+                        self.items
+                            .iter()
+                            .rposition(|a| a.hit(painter, transform, p))
+                    });
+                    if response.drag_started() && self.selected.is_some() {
+                        self.checkpoint(history);
+                    }
+                }
+                if response.dragged_by(PointerButton::Primary) {
+                    if let Some(idx) = self.selected {
+                        let d = response.drag_delta() / transform.pixel_size();
+                        self.items[idx].translate(d);
+                    }
+                }
+            }
+            AnnotationTool::Rectangle | AnnotationTool::Arrow => {
+                if response.drag_started() {
+                    self.drag_start = pointer.map(|p| transform.to_image(p));
+                }
+                if response.drag_released() {
+                    let end = response
+                        .ctx
+                        .pointer_latest_pos()
+                        .map(|p| transform.to_image(p));
+                    if let (Some(start), Some(end)) = (self.drag_start.take(), end) {
+                        let shape = if self.tool == AnnotationTool::Rectangle {
+                            AnnotationShape::Rectangle {
+                                min: from_pos(start),
+                                max: from_pos(end),
+                            }
+                        } else {
+                            AnnotationShape::Arrow {
+                                from: from_pos(start),
+                                to: from_pos(end),
+                            }
+                        };
+                        self.checkpoint(history);
+                        self.items.push(Annotation {
+                            shape,
+                            color: self.color,
+                        });
+                        self.selected = Some(self.items.len() - 1);
+                    }
+                }
+            }
+            AnnotationTool::Pin => {
+                if let Some(p) = pointer.filter(|_| response.clicked()) {
+                    self.checkpoint(history);
+                    self.items.push(Annotation {
+                        shape: AnnotationShape::Pin {
+                            pos: from_pos(transform.to_image(p)),
+                            note: self.text.clone(),
+                        },
+                        color: self.color,
+                    });
+                    self.selected = Some(self.items.len() - 1);
+                }
+            }
+            AnnotationTool::Text => {
+                if response.clicked() && !self.text.is_empty() {
+                    if let Some(p) = pointer {
+                        self.checkpoint(history);
+                        self.items.push(Annotation {
+                            shape: AnnotationShape::Text {
+                                pos: from_pos(transform.to_image(p)),
+                                text: self.text.clone(),
+                            },
+                            color: self.color,
+                        });
+                        self.selected = Some(self.items.len() - 1);
+                    }
+                }
+            }
+        }
+
+        // Ctrl+Z is handled with the other edits by the image controls.
+        if response.ctx.wants_keyboard_input() {
+            return;
+        }
+        if response.ctx.input().key_pressed(Key::Delete) {
+            self.delete_selected(history);
+        }
+    }
+}
+
+/// Draws the annotations into `img`. `to_output` maps image pixel
+/// coordinates to `img` pixel coordinates and `scale` is the output pixel
+/// size of one screen point, used for stroke widths and text sizes.
+pub fn rasterize(
+    items: &[Annotation],
+    img: &mut RgbaImage,
+    to_output: impl Fn(Pos2) -> Pos2,
+    scale: f32,
+) {
+    let width = 2.0 * scale;
+    for a in items {
+        match &a.shape {
+            AnnotationShape::Rectangle { min, max } => {
+                let r = Rect::from_two_pos(to_output(to_pos(*min)), to_output(to_pos(*max)));
+                draw_line(img, r.left_top(), r.right_top(), width, a.color);
+                draw_line(img, r.right_top(), r.right_bottom(), width, a.color);
+                draw_line(img, r.right_bottom(), r.left_bottom(), width, a.color);
+                draw_line(img, r.left_bottom(), r.left_top(), width, a.color);
+            }
+            AnnotationShape::Arrow { from, to } => {
+                let origin = to_output(to_pos(*from));
+                let tip = to_output(to_pos(*to));
+                let vec = tip - origin;
+                let rot = emath::Rot2::from_angle(std::f32::consts::TAU / 10.0);
+                let tip_length = vec.length() / 4.0;
+                let dir = vec.normalized();
+                draw_line(img, origin, tip, width, a.color);
+                draw_line(img, tip, tip - tip_length * (rot * dir), width, a.color);
+                draw_line(
+                    img,
+                    tip,
+                    tip - tip_length * (rot.inverse() * dir),
+                    width,
+                    a.color,
+                );
+            }
+            AnnotationShape::Text { pos, text } => {
+                draw_text(
+                    img,
+                    to_output(to_pos(*pos)),
+                    text,
+                    TEXT_SIZE * scale,
+                    a.color,
+                );
+            }
+            AnnotationShape::Pin { pos, note } => {
+                let center = to_output(to_pos(*pos));
+                draw_line(img, center, center, 2.0 * PIN_RADIUS * scale, a.color);
+                let size = TEXT_SIZE * scale;
+                let origin = center + vec2(2.0 * PIN_RADIUS * scale, -size / 2.0);
+                draw_text(img, origin, note, size, a.color);
+            }
+        }
+    }
+}
+
+/// Annotations as stored in the sidecar file.
+#[derive(Serialize, Deserialize)]
+struct Sidecar {
+    annotations: Vec<Annotation>,
+}
+
+/// File the annotations of `image` are kept in, `<image>.imview.json`.
+pub fn sidecar_path(image: &Path) -> PathBuf {
+    let mut name = image.file_name().unwrap_or_default().to_owned();
+    name.push(".imview.json");
+    image.with_file_name(name)
+}
+
+/// Reads the annotations saved for `image`, none when there's no sidecar.
+pub fn read_sidecar(image: &Path) -> std::io::Result<Vec<Annotation>> {
+    let file = match std::fs::File::open(sidecar_path(image)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let sidecar: Sidecar = serde_json::from_reader(std::io::BufReader::new(file))?;
+    Ok(sidecar.annotations)
+}
+
+/// Saves the annotations of `image`, an empty list removes the sidecar.
+pub fn write_sidecar(image: &Path, annotations: &[Annotation]) -> std::io::Result<()> {
+    let path = sidecar_path(image);
+    if annotations.is_empty() {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let sidecar = Sidecar {
+        annotations: annotations.to_vec(),
+    };
+    let json = serde_json::to_vec_pretty(&sidecar)?;
+    std::fs::write(path, json)
+}
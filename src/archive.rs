@@ -0,0 +1,118 @@
+//! Images read from ZIP archives, like `.cbz` comic books.
+
+use crate::decode::{
+    alloc_budget, decode_memory, probe_memory, to_thumbnail, SourceInfo, ThumbnailScaling,
+};
+use crate::error::ImViewError;
+use image::RgbaImage;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+const ARCHIVE_EXTENSIONS: [&str; 2] = ["zip", "cbz"];
+
+fn zip_err(e: zip::result::ZipError) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+fn open(path: &Path) -> std::io::Result<ZipArchive<BufReader<File>>> {
+    ZipArchive::new(BufReader::new(File::open(path)?)).map_err(zip_err)
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| ARCHIVE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Names of the entries of the archive that look like readable images,
+/// directories and other files are left out.
+pub fn list_images(path: &Path) -> std::io::Result<Vec<String>> {
+    let archive = open(path)?;
+    Ok(archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .filter(|name| {
+            image::ImageFormat::from_path(name)
+                .map(|f| f.can_read())
+                .unwrap_or(false)
+        })
+        .map(|name| name.to_string())
+        .collect())
+}
+
+/// Decodes `entry` of the archive at `path` within the limits of
+/// `decode::decode_memory`. Entries larger than the allocation budget are
+/// refused, whatever size their header declares.
+pub fn decode_entry(
+    path: &Path,
+    entry: &str,
+    max_dimension: u32,
+    max_memory: Option<u64>,
+) -> std::io::Result<(RgbaImage, SourceInfo)> {
+    let mut archive = open(path)?;
+    let mut file = archive.by_name(entry).map_err(zip_err)?;
+    let limit = alloc_budget(max_dimension, max_memory);
+    let too_large = || {
+        const MIB: u64 = 1024 * 1024;
+        let msg = format!("{} is larger than {} MiB", entry, limit.div_ceil(MIB));
+        std::io::Error::from(ImViewError::MemoryLimit(msg))
+    };
+    if file.size() > limit {
+        return Err(too_large());
+    }
+    let mut bytes = Vec::new();
+    (&mut file).take(limit + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > limit {
+        return Err(too_large());
+    }
+    let info = probe_memory(&bytes, file.compressed_size());
+    decode_memory(&bytes, max_dimension, max_memory).map(|img| (img, info))
+}
+
+pub fn decode_thumbnail(
+    path: &Path,
+    entry: &str,
+    size: u32,
+    scaling: ThumbnailScaling,
+    max_dimension: u32,
+    max_memory: Option<u64>,
+) -> std::io::Result<(RgbaImage, (u32, u32))> {
+    decode_entry(path, entry, max_dimension, max_memory).map(|(img, _)| {
+        let dimensions = img.dimensions();
+        (to_thumbnail(img, size, scaling), dimensions)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_path;
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn entries_over_the_budget_are_refused() {
+        let path = temp_path("budget.cbz");
+        let mut png = Vec::new();
+        RgbaImage::new(4, 4)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("small.png", FileOptions::default()).unwrap();
+        zip.write_all(&png).unwrap();
+        // Compresses to almost nothing, the declared size counts.
+        zip.start_file("large.png", FileOptions::default()).unwrap();
+        zip.write_all(&vec![0; 1 << 16]).unwrap();
+        zip.finish().unwrap();
+
+        let small = decode_entry(&path, "small.png", 1024, Some(1 << 10));
+        let large = decode_entry(&path, "large.png", 1024, Some(1 << 10));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(small.unwrap().0.dimensions(), (4, 4));
+        let err = ImViewError::from(&large.unwrap_err());
+        assert!(matches!(err, ImViewError::MemoryLimit(_)), "{:?}", err);
+    }
+}
@@ -0,0 +1,1072 @@
+use crate::error::ImViewError;
+use crate::pixel_ops::downscale_linear;
+use image::codecs::gif::GifDecoder;
+use image::codecs::jpeg::JpegDecoder;
+use image::imageops::FilterType;
+use image::io::{Limits, Reader as ImageReader};
+use image::{AnimationDecoder, ColorType, DynamicImage, ImageDecoder, ImageFormat, RgbaImage};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Minimal time between two partial images of a non-interlaced PNG.
+const PARTIAL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Adam7 passes as (x start, y start, x step, y step, block width, block
+/// height), where the block is the area a pixel of the pass is spread over
+/// until the following passes fill it in.
+const ADAM7_PASSES: [(u32, u32, u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8, 8, 8),
+    (4, 0, 8, 8, 4, 8),
+    (0, 4, 4, 8, 4, 4),
+    (2, 0, 4, 4, 2, 4),
+    (0, 2, 2, 4, 2, 2),
+    (1, 0, 2, 2, 1, 2),
+    (0, 1, 1, 2, 1, 1),
+];
+
+/// Bytes read from the start of a file to tell its format from the magic
+/// numbers.
+const SNIFF_LEN: u64 = 32;
+
+/// Maximal relative aspect ratio difference of an embedded thumbnail, larger
+/// differences usually mean the thumbnail is letterboxed.
+const MAX_ASPECT_ERROR: f32 = 0.01;
+
+/// Frames kept of an animated GIF, the later ones are dropped.
+const MAX_FRAMES: usize = 1000;
+
+/// TIFF tag listing the offsets of the SubIFDs of a page, which hold
+/// reduced-resolution previews among others.
+const SUBIFDS_TAG: u16 = 330;
+
+fn other_err<E>(e: E) -> std::io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    std::io::Error::other(e)
+}
+
+fn check_size(img: RgbaImage) -> std::io::Result<RgbaImage> {
+    if img.width() == 0 || img.height() == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Image has zero width or height",
+        ));
+    }
+    Ok(img)
+}
+
+/// Decodes the image at `path`, rejecting images without pixels since
+/// nothing downstream can display them.
+pub fn decode(path: &Path) -> std::io::Result<RgbaImage> {
+    decode_with_limits(path, Limits::default())
+}
+
+fn decode_with_limits(path: &Path, limits: Limits) -> std::io::Result<RgbaImage> {
+    // The content decides, names like `photo.png.bak` say little.
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    reader.limits(limits);
+    let img = reader.decode().map_err(other_err)?.to_rgba8();
+    check_size(img)
+}
+
+/// Refuses images with more pixels than a `max_dimension` square or whose
+/// RGBA pixels don't fit `max_memory` bytes, judged from the header before
+/// anything is allocated for them.
+pub fn check_pixel_budget(
+    path: &Path,
+    max_dimension: u32,
+    max_memory: Option<u64>,
+) -> std::io::Result<()> {
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+    check_header_budget(reader, max_dimension, max_memory)
+}
+
+/// `check_pixel_budget` for an image held in memory.
+fn check_memory_pixel_budget(
+    bytes: &[u8],
+    max_dimension: u32,
+    max_memory: Option<u64>,
+) -> std::io::Result<()> {
+    let reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    check_header_budget(reader, max_dimension, max_memory)
+}
+
+fn check_header_budget<R: BufRead + Seek>(
+    reader: ImageReader<R>,
+    max_dimension: u32,
+    max_memory: Option<u64>,
+) -> std::io::Result<()> {
+    let (width, height) = reader.into_dimensions().map_err(other_err)?;
+    let pixels = width as u64 * height as u64;
+    let budget = max_dimension as u64 * max_dimension as u64;
+    if pixels > budget {
+        return Err(ImViewError::TooLarge(format!(
+            "Image is {}x{}, more pixels than the {}x{} limit",
+            width, height, max_dimension, max_dimension
+        ))
+        .into());
+    }
+    if let Some(max_memory) = max_memory.filter(|m| pixels * 4 > *m) {
+        const MIB: u64 = 1024 * 1024;
+        return Err(ImViewError::MemoryLimit(format!(
+            "{}x{} needs {} MiB, the limit is {} MiB",
+            width,
+            height,
+            (pixels * 4).div_ceil(MIB),
+            max_memory / MIB
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Memory the pages of one file may take, see `decode_pages`.
+struct PageBudget {
+    /// Bytes a decoder may allocate at once.
+    max_alloc: u64,
+    /// Bytes the RGBA pixels of all pages together may take.
+    max_memory: Option<u64>,
+    used: u64,
+}
+
+impl PageBudget {
+    fn new(max_dimension: u32, max_memory: Option<u64>) -> Self {
+        // Room for the pixels at 16 bits per channel while converting.
+        let max_alloc = max_memory.unwrap_or(max_dimension as u64 * max_dimension as u64 * 8);
+        Self {
+            max_alloc,
+            max_memory,
+            used: 0,
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_alloc = Some(self.max_alloc);
+        limits
+    }
+
+    fn max_alloc_usize(&self) -> usize {
+        self.max_alloc.min(usize::MAX as u64) as usize
+    }
+
+    /// Counts a `width`x`height` page before it's decoded, refusing the
+    /// file once its pages together pass `max_memory`.
+    fn take(&mut self, width: u32, height: u32) -> std::io::Result<()> {
+        self.used += width as u64 * height as u64 * 4;
+        match self.max_memory {
+            Some(max_memory) if self.used > max_memory => {
+                const MIB: u64 = 1024 * 1024;
+                Err(ImViewError::MemoryLimit(format!(
+                    "The pages need more than {} MiB",
+                    max_memory / MIB
+                ))
+                .into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Bytes a decoder may allocate at once under these limits, also the most
+/// an encoded image read into memory may take.
+pub fn alloc_budget(max_dimension: u32, max_memory: Option<u64>) -> u64 {
+    PageBudget::new(max_dimension, max_memory).max_alloc
+}
+
+/// Shrinks `img` to fit a `max_dimension` square when a side is longer.
+fn fit_dimension(img: RgbaImage, max_dimension: u32) -> RgbaImage {
+    if img.width().max(img.height()) <= max_dimension {
+        return img;
+    }
+    log::warn!(
+        "Downscaling a {}x{} image to fit {} px",
+        img.width(),
+        img.height(),
+        max_dimension
+    );
+    to_thumbnail(img, max_dimension, ThumbnailScaling::FAST)
+}
+
+/// Decodes an image held in memory, the format is guessed from its content.
+/// Limited like a single page of `decode_pages`.
+pub fn decode_memory(
+    bytes: &[u8],
+    max_dimension: u32,
+    max_memory: Option<u64>,
+) -> std::io::Result<RgbaImage> {
+    check_memory_pixel_budget(bytes, max_dimension, max_memory)?;
+    let budget = PageBudget::new(max_dimension, max_memory);
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    reader.limits(budget.limits());
+    let img = check_size(reader.decode().map_err(other_err)?.to_rgba8())?;
+    Ok(fit_dimension(img, max_dimension))
+}
+
+/// What an image was decoded from, its pixels are always RGBA8 after
+/// decoding.
+#[derive(Clone, Copy, Debug)]
+pub struct SourceInfo {
+    pub format: Option<ImageFormat>,
+    /// Color type the decoder produced before the conversion to RGBA8.
+    pub color: Option<ColorType>,
+    /// Bytes stored on disk, compressed bytes for archive entries.
+    pub file_size: Option<u64>,
+}
+
+/// Reads the color type from the header of an image in `format`, formats
+/// without a header-only decoder here give `None`.
+fn color_type<R: BufRead + Seek>(reader: R, format: ImageFormat) -> Option<ColorType> {
+    use image::codecs;
+    let color = match format {
+        ImageFormat::Png => codecs::png::PngDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Jpeg => JpegDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Gif => GifDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Bmp => codecs::bmp::BmpDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Ico => codecs::ico::IcoDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::Tiff => codecs::tiff::TiffDecoder::new(reader).ok()?.color_type(),
+        ImageFormat::WebP => codecs::webp::WebPDecoder::new(reader).ok()?.color_type(),
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// Tells from its first bytes whether the file at `path` is an image the
+/// decoders read, for files whose names don't say.
+pub fn sniff_image(path: &Path) -> bool {
+    let mut head = Vec::with_capacity(SNIFF_LEN as usize);
+    let read = File::open(path).and_then(|f| f.take(SNIFF_LEN).read_to_end(&mut head));
+    match read {
+        Ok(_) => image::guess_format(&head).is_ok_and(|f| f.can_read()),
+        Err(_) => false,
+    }
+}
+
+/// Reads the format and color type of the image at `path` from its header,
+/// without decoding the pixels.
+pub fn probe(path: &Path) -> SourceInfo {
+    let file_size = std::fs::metadata(path).ok().map(|m| m.len());
+    let format = ImageReader::open(path)
+        .and_then(|r| r.with_guessed_format())
+        .ok()
+        .and_then(|r| r.format());
+    let color = format.and_then(|f| color_type(BufReader::new(File::open(path).ok()?), f));
+    SourceInfo {
+        format,
+        color,
+        file_size,
+    }
+}
+
+/// Like `probe`, for an image held in memory that took `file_size` bytes
+/// on disk.
+pub fn probe_memory(bytes: &[u8], file_size: u64) -> SourceInfo {
+    let format = image::guess_format(bytes).ok();
+    let color = format.and_then(|f| color_type(Cursor::new(bytes), f));
+    SourceInfo {
+        format,
+        color,
+        file_size: Some(file_size),
+    }
+}
+
+/// Size of an image of `width`x`height` fitted into a `size` square.
+fn fit_size(width: u32, height: u32, size: u32) -> (u32, u32) {
+    let ws = size as f32 / width as f32;
+    let hs = size as f32 / height as f32;
+    let s = ws.min(hs);
+
+    let w = ((width as f32 * s).floor() as u32).max(1);
+    let h = ((height as f32 * s).floor() as u32).max(1);
+    (w, h)
+}
+
+/// Filter images are shrunk to thumbnails with, independent of the
+/// magnification filter of the view.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ArgEnum)]
+#[clap(rename_all = "lower")]
+pub enum ThumbnailFilter {
+    /// Averages the pixels each thumbnail pixel covers.
+    Box,
+    Nearest,
+    Triangle,
+    CatmullRom,
+    /// Sharpest, but rings around hard edges like line art.
+    Lanczos,
+}
+
+impl ThumbnailFilter {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ThumbnailFilter::Box => "Box",
+            ThumbnailFilter::Nearest => "Nearest",
+            ThumbnailFilter::Triangle => "Triangle",
+            ThumbnailFilter::CatmullRom => "Catmull-Rom",
+            ThumbnailFilter::Lanczos => "Lanczos",
+        }
+    }
+}
+
+/// How images are shrunk to thumbnails.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThumbnailScaling {
+    pub filter: ThumbnailFilter,
+    /// Average in linear light instead of on the encoded values, which is
+    /// slower. Only the box filter has it.
+    pub linear: bool,
+}
+
+impl ThumbnailScaling {
+    /// Box filter on the encoded values, the fastest.
+    pub const FAST: Self = Self {
+        filter: ThumbnailFilter::Box,
+        linear: false,
+    };
+}
+
+/// Shrinks `img` to fit a `size` square with the filter of `scaling`.
+pub fn to_thumbnail(img: RgbaImage, size: u32, scaling: ThumbnailScaling) -> RgbaImage {
+    let (w, h) = fit_size(img.width(), img.height(), size);
+    let filter = match scaling.filter {
+        ThumbnailFilter::Box if scaling.linear => return downscale_linear(&img, w, h),
+        ThumbnailFilter::Box => return image::imageops::thumbnail(&img, w, h),
+        ThumbnailFilter::Nearest => FilterType::Nearest,
+        ThumbnailFilter::Triangle => FilterType::Triangle,
+        ThumbnailFilter::CatmullRom => FilterType::CatmullRom,
+        ThumbnailFilter::Lanczos => FilterType::Lanczos3,
+    };
+    image::imageops::resize(&img, w, h, filter)
+}
+
+/// Returns the EXIF thumbnail of the file when it has the image aspect ratio
+/// and is large enough to produce a `size` thumbnail without upscaling.
+fn exif_thumbnail(path: &Path, width: u32, height: u32, size: u32) -> Option<RgbaImage> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+    let field = |tag| {
+        exif.get_field(tag, exif::In::THUMBNAIL)
+            .and_then(|f| f.value.get_uint(0))
+            .map(|v| v as usize)
+    };
+    let offset = field(exif::Tag::JPEGInterchangeFormat)?;
+    let length = field(exif::Tag::JPEGInterchangeFormatLength)?;
+    let data = exif.buf().get(offset..offset.checked_add(length)?)?;
+    let thumb = image::load_from_memory_with_format(data, ImageFormat::Jpeg)
+        .ok()?
+        .to_rgba8();
+
+    let (tw, th) = thumb.dimensions();
+    preview_fits(tw, th, width, height, size).then_some(thumb)
+}
+
+/// Whether an embedded preview of `tw` x `th` has the aspect ratio of the
+/// `width` x `height` image and is large enough for a `size` thumbnail.
+fn preview_fits(tw: u32, th: u32, width: u32, height: u32, size: u32) -> bool {
+    let (fw, fh) = fit_size(width, height, size);
+    let aspect = (tw as f32 * height as f32) / (th.max(1) as f32 * width as f32);
+    tw >= fw && th >= fh && (aspect - 1.0).abs() <= MAX_ASPECT_ERROR
+}
+
+/// Reads a TIFF as if the IFD at `ifd` came first. The tiff crate only
+/// follows the main chain of pages, this lets it decode a SubIFD.
+struct SubIfdReader<R> {
+    inner: R,
+    /// Header of the file with the first IFD offset replaced.
+    header: Vec<u8>,
+}
+
+impl<R: Read + Seek> SubIfdReader<R> {
+    fn new(mut inner: R, ifd: u64) -> std::io::Result<Self> {
+        let mut header = vec![0; 16];
+        inner.read_exact(&mut header[..8])?;
+        let little = &header[..2] == b"II";
+        let version = match little {
+            true => u16::from_le_bytes([header[2], header[3]]),
+            false => u16::from_be_bytes([header[2], header[3]]),
+        };
+        if version == 43 {
+            let offset = if little {
+                ifd.to_le_bytes()
+            } else {
+                ifd.to_be_bytes()
+            };
+            header[8..].copy_from_slice(&offset);
+        } else {
+            let ifd = u32::try_from(ifd).map_err(other_err)?;
+            let offset = if little {
+                ifd.to_le_bytes()
+            } else {
+                ifd.to_be_bytes()
+            };
+            header.truncate(8);
+            header[4..].copy_from_slice(&offset);
+        }
+        inner.seek(SeekFrom::Start(0))?;
+        Ok(Self { inner, header })
+    }
+}
+
+impl<R: Read + Seek> Read for SubIfdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let pos = self.inner.stream_position()?;
+        match self.header.get(pos as usize..) {
+            Some(rest) if !rest.is_empty() => {
+                let n = rest.len().min(buf.len());
+                buf[..n].copy_from_slice(&rest[..n]);
+                self.inner.seek(SeekFrom::Current(n as i64))?;
+                Ok(n)
+            }
+            _ => self.inner.read(buf),
+        }
+    }
+}
+
+impl<R: Seek> Seek for SubIfdReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Decodes the smallest SubIFD preview of the first page of a TIFF that
+/// is large enough for a `size` thumbnail, along with the dimensions of
+/// the page. `None` when there is no such preview or it can't be read.
+fn tiff_preview(path: &Path, size: u32) -> Option<(RgbaImage, (u32, u32))> {
+    use tiff::decoder::Decoder;
+
+    let open = || File::open(path).map(BufReader::new).ok();
+    let mut decoder = Decoder::new(open()?).ok()?;
+    let (width, height) = decoder.dimensions().ok()?;
+    let offsets = decoder
+        .find_tag_unsigned_vec::<u64>(tiff::tags::Tag::Unknown(SUBIFDS_TAG))
+        .ok()??;
+    let (_, mut preview) = offsets
+        .into_iter()
+        .filter_map(|offset| {
+            let mut decoder = Decoder::new(SubIfdReader::new(open()?, offset).ok()?).ok()?;
+            let (w, h) = decoder.dimensions().ok()?;
+            preview_fits(w, h, width, height, size).then_some((w as u64 * h as u64, decoder))
+        })
+        .min_by_key(|(area, _)| *area)?;
+    let (w, h) = preview.dimensions().ok()?;
+    let color = preview.colortype().ok()?;
+    let data = preview.read_image().ok()?;
+    let img = tiff_page(w, h, color, data).ok()?;
+    Some((img, (width, height)))
+}
+
+/// Decodes a JPEG with the decoder scaled down to the smallest power of two
+/// fraction (down to 1/8) that is still at least `size` along the fitted axis.
+fn decode_jpeg_thumbnail(
+    path: &Path,
+    size: u32,
+    scaling: ThumbnailScaling,
+) -> std::io::Result<(RgbaImage, (u32, u32))> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut decoder = JpegDecoder::new(reader).map_err(other_err)?;
+    let (width, height) = decoder.dimensions();
+    if width == 0 || height == 0 {
+        return check_size(RgbaImage::new(width, height)).map(|img| (img, (width, height)));
+    }
+    if let Some(thumb) = exif_thumbnail(path, width, height, size) {
+        return Ok((to_thumbnail(thumb, size, scaling), (width, height)));
+    }
+    let requested = size.min(u16::MAX as u32) as u16;
+    decoder.scale(requested, requested).map_err(other_err)?;
+    let img = DynamicImage::from_decoder(decoder)
+        .map_err(other_err)?
+        .to_rgba8();
+    check_size(img).map(|img| (to_thumbnail(img, size, scaling), (width, height)))
+}
+
+/// Loads the image at `path` downscaled to fit a `size` square, along with
+/// the dimensions of the full image.
+///
+/// JPEGs are decoded at a reduced scale or replaced by their EXIF thumbnail
+/// when it's large enough, TIFFs by their smallest large enough SubIFD
+/// preview. Other images are decoded in full and shrunk. See `to_thumbnail`
+/// for `scaling`.
+pub fn decode_thumbnail(
+    path: &Path,
+    size: u32,
+    scaling: ThumbnailScaling,
+) -> std::io::Result<(RgbaImage, (u32, u32))> {
+    let format = ImageReader::open(path)?.with_guessed_format()?.format();
+    let preview = match format {
+        Some(ImageFormat::Jpeg) => return decode_jpeg_thumbnail(path, size, scaling),
+        Some(ImageFormat::Tiff) => tiff_preview(path, size),
+        _ => None,
+    };
+    let (img, dimensions) = match preview {
+        Some(preview) => preview,
+        None => {
+            let img = decode(path)?;
+            let dimensions = img.dimensions();
+            (img, dimensions)
+        }
+    };
+    Ok((to_thumbnail(img, size, scaling), dimensions))
+}
+
+fn to_rgba(px: &[u8]) -> [u8; 4] {
+    match px.len() {
+        1 => [px[0], px[0], px[0], 255],
+        2 => [px[0], px[0], px[0], px[1]],
+        3 => [px[0], px[1], px[2], 255],
+        _ => [px[0], px[1], px[2], px[3]],
+    }
+}
+
+/// Number of pass pixels along a dimension of `full` pixels.
+fn pass_len(full: u32, start: u32, step: u32) -> u32 {
+    if full > start {
+        (full - start).div_ceil(step)
+    } else {
+        0
+    }
+}
+
+fn decode_png_progressive(
+    path: &Path,
+    budget: &PageBudget,
+    mut on_partial: impl FnMut(&RgbaImage, Range<u32>, usize),
+) -> std::io::Result<RgbaImage> {
+    let limits = png::Limits {
+        bytes: budget.max_alloc_usize(),
+    };
+    let mut decoder = png::Decoder::new_with_limits(BufReader::new(File::open(path)?), limits);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(other_err)?;
+    let (width, height) = (reader.info().width, reader.info().height);
+    let interlaced = reader.info().interlaced;
+    let channels = reader.output_color_type().0.samples();
+    let mut img = check_size(RgbaImage::new(width, height))?;
+
+    let passes: &[_] = if interlaced {
+        &ADAM7_PASSES
+    } else {
+        &[(0, 0, 1, 1, 1, 1)]
+    };
+    let mut last_partial = Instant::now();
+    let mut first_new_row = 0;
+    for (idx, &(xs, ys, dx, dy, bw, bh)) in passes.iter().enumerate() {
+        let pass_width = pass_len(width, xs, dx) as usize;
+        let pass_height = pass_len(height, ys, dy);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        for line in 0..pass_height {
+            let row = reader.next_row().map_err(other_err)?.ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "PNG data ended early")
+            })?;
+            let y = ys + line * dy;
+            let stride = width as usize * 4;
+            let pixels = row.data().chunks_exact(channels).take(pass_width);
+            let buf: &mut [u8] = &mut img;
+            if !interlaced {
+                let out = &mut buf[y as usize * stride..][..stride];
+                if channels == 4 {
+                    out.copy_from_slice(&row.data()[..stride]);
+                } else {
+                    for (dst, px) in out.chunks_exact_mut(4).zip(pixels) {
+                        dst.copy_from_slice(&to_rgba(px));
+                    }
+                }
+            } else {
+                // Each pixel covers its block until the later passes refine it.
+                for (i, px) in pixels.enumerate() {
+                    let x = xs as usize + i * dx as usize;
+                    let color = to_rgba(px);
+                    let block = (x + bw as usize).min(width as usize) - x;
+                    for by in y..(y + bh).min(height) {
+                        let start = by as usize * stride + x * 4;
+                        for dst in buf[start..start + block * 4].chunks_exact_mut(4) {
+                            dst.copy_from_slice(&color);
+                        }
+                    }
+                }
+            }
+            if !interlaced && last_partial.elapsed() >= PARTIAL_INTERVAL {
+                on_partial(&img, first_new_row..y + 1, 0);
+                first_new_row = y + 1;
+                last_partial = Instant::now();
+            }
+        }
+        if interlaced && idx + 1 < passes.len() {
+            on_partial(&img, 0..height, idx + 1);
+        }
+    }
+    Ok(img)
+}
+
+/// Converts a decoded TIFF page to RGBA, 16 bit samples keep their high byte.
+fn tiff_page(
+    width: u32,
+    height: u32,
+    color: tiff::ColorType,
+    data: tiff::decoder::DecodingResult,
+) -> std::io::Result<RgbaImage> {
+    use tiff::decoder::DecodingResult;
+    use tiff::ColorType;
+
+    let unsupported = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Unsupported TIFF page format: {:?}", color),
+        )
+    };
+    let channels = match color {
+        ColorType::Gray(8 | 16) => 1,
+        ColorType::GrayA(8 | 16) => 2,
+        ColorType::RGB(8 | 16) => 3,
+        ColorType::RGBA(8 | 16) => 4,
+        _ => return Err(unsupported()),
+    };
+    let samples = match data {
+        DecodingResult::U8(v) => v,
+        DecodingResult::U16(v) => v.iter().map(|s| (s >> 8) as u8).collect(),
+        _ => return Err(unsupported()),
+    };
+    let pixels = samples
+        .chunks_exact(channels)
+        .flat_map(to_rgba)
+        .collect::<Vec<_>>();
+    RgbaImage::from_raw(width, height, pixels).ok_or_else(unsupported)
+}
+
+/// Decodes every page of a TIFF. Files with a page `tiff_page` can't
+/// convert, like bilevel, CMYK, palette or float pages, fall back to the
+/// image crate, which only reads the first page.
+fn decode_tiff_pages(path: &Path, budget: &mut PageBudget) -> std::io::Result<Vec<RgbaImage>> {
+    read_tiff_pages(path, budget).or_else(|e| {
+        if let ImViewError::MemoryLimit(_) = ImViewError::from(&e) {
+            return Err(e);
+        }
+        log::warn!("Reading only the first page of {}: {}", path.display(), e);
+        decode_with_limits(path, budget.limits()).map(|img| vec![img])
+    })
+}
+
+fn read_tiff_pages(path: &Path, budget: &mut PageBudget) -> std::io::Result<Vec<RgbaImage>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut limits = tiff::decoder::Limits::default();
+    limits.decoding_buffer_size = budget.max_alloc_usize();
+    let mut decoder = tiff::decoder::Decoder::new(reader)
+        .map_err(other_err)?
+        .with_limits(limits);
+    if !decoder.more_images() {
+        return decode_with_limits(path, budget.limits()).map(|img| vec![img]);
+    }
+    let mut pages = Vec::new();
+    loop {
+        let (width, height) = decoder.dimensions().map_err(other_err)?;
+        budget.take(width, height)?;
+        let color = decoder.colortype().map_err(other_err)?;
+        let data = decoder.read_image().map_err(other_err)?;
+        pages.push(check_size(tiff_page(width, height, color, data)?)?);
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image().map_err(other_err)?;
+    }
+    Ok(pages)
+}
+
+/// Decodes every entry of an ICO file. The image crate only decodes the
+/// largest entry, so each one is wrapped into a single entry ICO first.
+fn decode_ico_pages(path: &Path, budget: &mut PageBudget) -> std::io::Result<Vec<RgbaImage>> {
+    const HEADER_LEN: usize = 6;
+    const ENTRY_LEN: usize = 16;
+    let data = std::fs::read(path)?;
+    let count = match data.get(4..HEADER_LEN) {
+        Some(c) => u16::from_le_bytes([c[0], c[1]]) as usize,
+        None => 0,
+    };
+    if count < 2 {
+        return decode_with_limits(path, budget.limits()).map(|img| vec![img]);
+    }
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "Broken ICO directory");
+    (0..count)
+        .map(|idx| {
+            let start = HEADER_LEN + idx * ENTRY_LEN;
+            let entry = data.get(start..start + ENTRY_LEN).ok_or_else(invalid)?;
+            let le_u32 = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize;
+            let (size, offset) = (le_u32(&entry[8..12]), le_u32(&entry[12..16]));
+            let payload = data.get(offset..offset + size).ok_or_else(invalid)?;
+            // A size of 0 stands for 256 pixels.
+            let side = |b: u8| if b == 0 { 256 } else { b as u32 };
+            budget.take(side(entry[0]), side(entry[1]))?;
+
+            let mut single = vec![0, 0, 1, 0, 1, 0];
+            single.extend_from_slice(&entry[..12]);
+            single.extend_from_slice(&((HEADER_LEN + ENTRY_LEN) as u32).to_le_bytes());
+            single.extend_from_slice(payload);
+            let mut reader = ImageReader::with_format(Cursor::new(single), ImageFormat::Ico);
+            reader.limits(budget.limits());
+            check_size(reader.decode().map_err(other_err)?.to_rgba8())
+        })
+        .collect()
+}
+
+/// Decodes every frame of an animated GIF, composited onto the full canvas.
+/// Only the first `MAX_FRAMES` are kept, each frame takes the memory of a
+/// whole canvas.
+fn decode_gif_frames(path: &Path, budget: &mut PageBudget) -> std::io::Result<Vec<RgbaImage>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut decoder = GifDecoder::new(reader).map_err(other_err)?;
+    decoder.set_limits(budget.limits()).map_err(other_err)?;
+    let (width, height) = decoder.dimensions();
+    let mut frames = decoder
+        .into_frames()
+        .take(MAX_FRAMES + 1)
+        .map(|frame| {
+            budget.take(width, height)?;
+            check_size(frame.map_err(other_err)?.into_buffer())
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    if frames.len() > MAX_FRAMES {
+        log::warn!(
+            "Keeping the first {} frames of {}",
+            MAX_FRAMES,
+            path.display()
+        );
+        frames.truncate(MAX_FRAMES);
+    }
+    if frames.is_empty() {
+        return decode_with_limits(path, budget.limits()).map(|img| vec![img]);
+    }
+    Ok(frames)
+}
+
+/// Receiver of the rows of a progressive decode, see `decode_pages`.
+pub type OnPartial<'a> = &'a mut dyn FnMut(&RgbaImage, Range<u32>, usize);
+
+/// Decodes all pages of the image at `path`, single page formats give one.
+///
+/// Multi-page TIFFs, multi-entry ICOs and animated GIFs give a page per
+/// image or frame. With `on_partial` PNGs are decoded progressively, calling it
+/// with the image decoded so far, the rows changed since the previous call
+/// and the finished Adam7 pass (0 for non-interlaced images).
+///
+/// Images with more pixels than a `max_dimension` square are refused, the
+/// pages with a longer side are downscaled to fit it. With `max_memory` set,
+/// decoders may allocate at most that many bytes at once and files whose
+/// pages together need more for their RGBA pixels are refused.
+pub fn decode_pages(
+    path: &Path,
+    max_dimension: u32,
+    max_memory: Option<u64>,
+    on_partial: Option<OnPartial<'_>>,
+) -> std::io::Result<Vec<RgbaImage>> {
+    check_pixel_budget(path, max_dimension, max_memory)?;
+    let mut budget = PageBudget::new(max_dimension, max_memory);
+    let format = ImageReader::open(path)?.with_guessed_format()?.format();
+    let pages = match format {
+        Some(ImageFormat::Png) if on_partial.is_some() => {
+            decode_png_progressive(path, &budget, on_partial.unwrap()).map(|img| vec![img])
+        }
+        Some(ImageFormat::Tiff) => decode_tiff_pages(path, &mut budget),
+        Some(ImageFormat::Ico) => decode_ico_pages(path, &mut budget),
+        Some(ImageFormat::Gif) => decode_gif_frames(path, &mut budget),
+        _ => decode_with_limits(path, budget.limits()).map(|img| vec![img]),
+    }?;
+    Ok(pages
+        .into_iter()
+        .map(|img| fit_dimension(img, max_dimension))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_path;
+    use image::{GrayImage, ImageEncoder, Luma, Rgb, RgbImage, Rgba};
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    fn png_bytes(img: &RgbaImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::codecs::png::PngEncoder::new(&mut bytes)
+            .write_image(img.as_raw(), img.width(), img.height(), ColorType::Rgba8)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn empty_images_are_refused() {
+        assert!(check_size(RgbaImage::new(0, 0)).is_err());
+        assert!(check_size(RgbaImage::new(0, 5)).is_err());
+        assert!(check_size(RgbaImage::new(5, 0)).is_err());
+        assert!(decode_memory(&png_bytes(&RgbaImage::new(1, 1))[..20], 1024, None).is_err());
+    }
+
+    #[test]
+    fn tiny_images_decode_and_shrink() {
+        for (w, h, thumb) in [(1, 1, (64, 64)), (1, 10000, (1, 64)), (10000, 1, (64, 1))] {
+            let img = decode_memory(&png_bytes(&RgbaImage::new(w, h)), 10000, None).unwrap();
+            assert_eq!(img.dimensions(), (w, h));
+            let img = to_thumbnail(img, 64, ThumbnailScaling::FAST);
+            assert_eq!(img.dimensions(), thumb);
+        }
+    }
+
+    #[test]
+    fn checkerboard_thumbnails_keep_their_brightness_in_linear_light() {
+        let board = RgbaImage::from_fn(64, 64, |x, y| match (x + y) % 2 {
+            0 => image::Rgba([0, 0, 0, 255]),
+            _ => image::Rgba([255, 255, 255, 255]),
+        });
+        let shrink = |linear| {
+            let scaling = ThumbnailScaling {
+                filter: ThumbnailFilter::Box,
+                linear,
+            };
+            to_thumbnail(board.clone(), 16, scaling)
+        };
+        // Half the light of white is encoded as 188, the average of the
+        // encoded values is much darker.
+        let linear = shrink(true);
+        assert_eq!(linear.dimensions(), (16, 16));
+        assert!(linear.pixels().all(|p| p.0 == [188, 188, 188, 255]));
+        let encoded = shrink(false);
+        assert_eq!(encoded.dimensions(), (16, 16));
+        assert!(encoded
+            .pixels()
+            .all(|p| (127..=128).contains(&p[0]) && p[0] == p[1] && p[0] == p[2]));
+    }
+
+    #[test]
+    fn extensionless_png_is_sniffed() {
+        let path = temp_path("extensionless");
+        std::fs::write(&path, png_bytes(&RgbaImage::new(2, 2))).unwrap();
+        let png = sniff_image(&path);
+        std::fs::write(&path, b"just some notes, not an image").unwrap();
+        let text = sniff_image(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(png);
+        assert!(!text);
+        assert!(!sniff_image(&temp_path("missing")));
+    }
+
+    /// Largest and mean difference of the channel values of two images of
+    /// the same size.
+    fn difference(a: &RgbaImage, b: &RgbaImage) -> (u8, f32) {
+        assert_eq!(a.dimensions(), b.dimensions());
+        let diffs = a
+            .as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .map(|(x, y)| x.abs_diff(*y));
+        let (max, sum) = diffs.fold((0, 0u64), |(max, sum), d| (max.max(d), sum + d as u64));
+        (max, sum as f32 / a.as_raw().len() as f32)
+    }
+
+    /// Classic little-endian TIFF of the gray `main` image with `preview`
+    /// in a SubIFD, written by hand since the encoder doesn't write SubIFDs.
+    fn tiff_with_preview(main: &GrayImage, preview: &GrayImage) -> Vec<u8> {
+        const SHORT: u16 = 3;
+        const LONG: u16 = 4;
+        let entry = |tag: u16, kind: u16, value: u32| {
+            let mut entry = tag.to_le_bytes().to_vec();
+            entry.extend(kind.to_le_bytes());
+            entry.extend(1u32.to_le_bytes());
+            entry.extend(value.to_le_bytes());
+            entry
+        };
+        let ifd = |img: &GrayImage, data: u32, sub_ifd: Option<u32>| {
+            let mut entries = vec![
+                // Reduced-resolution image.
+                entry(254, LONG, sub_ifd.is_none() as u32),
+                entry(256, LONG, img.width()),
+                entry(257, LONG, img.height()),
+                entry(258, SHORT, 8),
+                entry(259, SHORT, 1),
+                entry(262, SHORT, 1),
+                entry(273, LONG, data),
+                entry(277, SHORT, 1),
+                entry(278, LONG, img.height()),
+                entry(279, LONG, img.width() * img.height()),
+            ];
+            entries.extend(sub_ifd.map(|offset| entry(SUBIFDS_TAG, LONG, offset)));
+            let mut ifd = (entries.len() as u16).to_le_bytes().to_vec();
+            ifd.extend(entries.concat());
+            ifd.extend(0u32.to_le_bytes());
+            ifd
+        };
+        let main_data = 8u32;
+        let preview_data = main_data + main.len() as u32;
+        let main_ifd = preview_data + preview.len() as u32;
+        let main_ifd_bytes = ifd(main, main_data, Some(main_ifd + 2 + 11 * 12 + 4));
+        let mut bytes = b"II*\0".to_vec();
+        bytes.extend(main_ifd.to_le_bytes());
+        bytes.extend(main.as_raw());
+        bytes.extend(preview.as_raw());
+        bytes.extend(main_ifd_bytes);
+        bytes.extend(ifd(preview, preview_data, None));
+        bytes
+    }
+
+    #[test]
+    fn tiff_thumbnails_come_from_a_large_enough_preview() {
+        let main = GrayImage::from_fn(400, 200, |x, y| Luma([((x + y) * 255 / 600) as u8]));
+        let preview = image::imageops::resize(&main, 200, 100, FilterType::Triangle);
+        let path = temp_path("preview.tiff");
+        std::fs::write(&path, tiff_with_preview(&main, &preview)).unwrap();
+        let small = tiff_preview(&path, 100);
+        let large = tiff_preview(&path, 300);
+        let thumb = decode_thumbnail(&path, 100, ThumbnailScaling::FAST);
+        let full = decode(&path).map(|img| to_thumbnail(img, 100, ThumbnailScaling::FAST));
+        std::fs::remove_file(&path).unwrap();
+
+        let (img, dimensions) = small.unwrap();
+        assert_eq!((img.dimensions(), dimensions), ((200, 100), (400, 200)));
+        assert!(large.is_none());
+        let (thumb, dimensions) = thumb.unwrap();
+        assert_eq!(dimensions, (400, 200));
+        let (max, mean) = difference(&thumb, &full.unwrap());
+        assert!(max <= 2 && mean <= 0.5, "max {} mean {}", max, mean);
+    }
+
+    #[test]
+    fn scaled_jpeg_thumbnails_look_like_full_decodes() {
+        use image::codecs::jpeg::JpegEncoder;
+
+        let img = RgbImage::from_fn(1600, 1200, |x, y| {
+            let ring = (((x as f32 - 800.0).hypot(y as f32 - 600.0) / 120.0).sin() + 1.0) * 127.0;
+            Rgb([(x / 7) as u8, (y / 5) as u8, ring as u8])
+        });
+        let path = temp_path("scaled.jpg");
+        JpegEncoder::new_with_quality(File::create(&path).unwrap(), 90)
+            .encode_image(&img)
+            .unwrap();
+        let thumb = decode_thumbnail(&path, 150, ThumbnailScaling::FAST);
+        let full = decode(&path).map(|img| to_thumbnail(img, 150, ThumbnailScaling::FAST));
+        std::fs::remove_file(&path).unwrap();
+
+        let (thumb, dimensions) = thumb.unwrap();
+        assert_eq!(dimensions, (1600, 1200));
+        let (max, mean) = difference(&thumb, &full.unwrap());
+        assert!(max <= 16 && mean <= 1.5, "max {} mean {}", max, mean);
+    }
+
+    #[test]
+    fn tiff_pages_are_all_decoded() {
+        let path = temp_path("pages.tiff");
+        let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+        encoder
+            .write_image::<colortype::Gray8>(2, 1, &[0, 255])
+            .unwrap();
+        encoder
+            .write_image::<colortype::RGB8>(1, 1, &[10, 20, 30])
+            .unwrap();
+        drop(encoder);
+        let pages = decode_tiff_pages(&path, &mut PageBudget::new(1024, None)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].as_raw(), &[0, 0, 0, 255, 255, 255, 255, 255]);
+        assert_eq!(pages[1].as_raw(), &[10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn tiff_with_unsupported_page_falls_back_to_first_page() {
+        let path = temp_path("float.tiff");
+        let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+        encoder
+            .write_image::<colortype::Gray8>(2, 1, &[0, 255])
+            .unwrap();
+        encoder
+            .write_image::<colortype::Gray32Float>(1, 1, &[0.5])
+            .unwrap();
+        drop(encoder);
+        let pages = decode_tiff_pages(&path, &mut PageBudget::new(1024, None));
+        std::fs::remove_file(&path).unwrap();
+        let pages = pages.unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].dimensions(), (2, 1));
+    }
+
+    #[test]
+    fn gif_frames_are_capped() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Frame, Rgba};
+
+        let path = temp_path("frames.gif");
+        let mut encoder = GifEncoder::new(File::create(&path).unwrap());
+        let frames = (0..MAX_FRAMES + 2)
+            .map(|i| Frame::new(RgbaImage::from_pixel(2, 1, Rgba([i as u8, 0, 0, 255]))));
+        encoder.encode_frames(frames).unwrap();
+        drop(encoder);
+        let frames = decode_gif_frames(&path, &mut PageBudget::new(1024, None));
+        std::fs::remove_file(&path).unwrap();
+        let frames = frames.unwrap();
+        assert_eq!(frames.len(), MAX_FRAMES);
+        assert!(frames.iter().all(|f| f.dimensions() == (2, 1)));
+    }
+
+    #[test]
+    fn progressive_pngs_decode_like_the_image_crate() {
+        let rgba = RgbaImage::from_fn(37, 23, |x, y| Rgba([x as u8 * 7, y as u8 * 11, 90, 200]));
+        let rgb = DynamicImage::ImageRgba8(rgba.clone()).to_rgb8();
+        let gray = DynamicImage::ImageRgba8(rgba.clone()).to_luma8();
+        let path = temp_path("progressive.png");
+        let budget = PageBudget::new(1024, None);
+        for img in [
+            DynamicImage::ImageRgba8(rgba),
+            DynamicImage::ImageRgb8(rgb),
+            DynamicImage::ImageLuma8(gray),
+        ] {
+            img.save(&path).unwrap();
+            let progressive = decode_png_progressive(&path, &budget, |_, _, _| ());
+            let expected = img.to_rgba8();
+            assert_eq!(progressive.unwrap(), expected, "{:?}", img.color());
+            assert_eq!(decode_pages(&path, 1024, None, None).unwrap(), [expected]);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn memory_budget_counts_every_page() {
+        let path = temp_path("budget.tiff");
+        let mut encoder = TiffEncoder::new(File::create(&path).unwrap()).unwrap();
+        let page = vec![0u8; 512 * 512];
+        for _ in 0..2 {
+            encoder
+                .write_image::<colortype::Gray8>(512, 512, &page)
+                .unwrap();
+        }
+        drop(encoder);
+        // Each page takes 1 MiB as RGBA, only one fits.
+        let limited = decode_pages(&path, 1024, Some(3 << 19), None);
+        let unlimited = decode_pages(&path, 1024, None, None);
+        std::fs::remove_file(&path).unwrap();
+        let err = limited.unwrap_err();
+        assert!(matches!(
+            ImViewError::from(&err),
+            ImViewError::MemoryLimit(_)
+        ));
+        assert_eq!(unlimited.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn images_in_memory_are_limited_like_files() {
+        let bytes = png_bytes(&RgbaImage::new(100, 100));
+        let too_large = decode_memory(&bytes, 50, None).unwrap_err();
+        assert!(matches!(
+            ImViewError::from(&too_large),
+            ImViewError::TooLarge(_)
+        ));
+        let too_much = decode_memory(&bytes, 1024, Some(1 << 10)).unwrap_err();
+        assert!(matches!(
+            ImViewError::from(&too_much),
+            ImViewError::MemoryLimit(_)
+        ));
+        // Fewer pixels than the limit squared, the longer side is shrunk.
+        let wide = png_bytes(&RgbaImage::new(200, 10));
+        assert_eq!(
+            decode_memory(&wide, 100, None).unwrap().dimensions(),
+            (100, 5)
+        );
+    }
+}
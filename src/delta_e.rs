@@ -0,0 +1,232 @@
+//! Perceptual color difference of sRGB pixels, CIEDE2000 on CIELAB.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+
+/// Gray steps per ΔE unit in the images `delta_e_image` makes, differences
+/// of 25.5 and above are white.
+pub const DELTA_E_SCALE: f32 = 10.0;
+
+/// Summary of the ΔE values of a compared pair.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeltaEStats {
+    pub mean: f32,
+    pub p99: f32,
+}
+
+fn srgb_decode(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// CIELAB coordinates of an sRGB color under the D65 white point.
+pub fn srgb_to_lab(p: &[u8]) -> [f64; 3] {
+    let [r, g, b] = [0, 1, 2].map(|c| srgb_decode(p[c] as f64 / 255.0));
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883;
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Hue angle in degrees within `[0, 360)`, 0 for neutral colors.
+fn hue(a: f64, b: f64) -> f64 {
+    if a == 0.0 && b == 0.0 {
+        return 0.0;
+    }
+    b.atan2(a).to_degrees().rem_euclid(360.0)
+}
+
+/// CIEDE2000 color difference of two CIELAB colors, with the parametric
+/// weights all set to 1.
+pub fn ciede2000(lab1: [f64; 3], lab2: [f64; 3]) -> f64 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+    let pow25_7 = 25f64.powi(7);
+    let c_mean = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_mean.powi(7) / (c_mean.powi(7) + pow25_7)).sqrt());
+    let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = match h2 - h1 {
+        _ if c1 * c2 == 0.0 => 0.0,
+        d if d > 180.0 => d - 360.0,
+        d if d < -180.0 => d + 360.0,
+        d => d,
+    };
+    let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = match h1 + h2 {
+        sum if c1 * c2 == 0.0 => sum,
+        sum if (h1 - h2).abs() <= 180.0 => sum / 2.0,
+        sum if sum < 360.0 => (sum + 360.0) / 2.0,
+        sum => (sum - 360.0) / 2.0,
+    };
+    let cos = |deg: f64| deg.to_radians().cos();
+    let t =
+        1.0 - 0.17 * cos(h_mean - 30.0) + 0.24 * cos(2.0 * h_mean) + 0.32 * cos(3.0 * h_mean + 6.0)
+            - 0.20 * cos(4.0 * h_mean - 63.0);
+    let d_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let rc = 2.0 * (c_mean.powi(7) / (c_mean.powi(7) + pow25_7)).sqrt();
+    let l50 = (l_mean - 50.0).powi(2);
+    let sl = 1.0 + 0.015 * l50 / (20.0 + l50).sqrt();
+    let sc = 1.0 + 0.045 * c_mean;
+    let sh = 1.0 + 0.015 * c_mean * t;
+    let rt = -(2.0 * d_theta).to_radians().sin() * rc;
+
+    let (l, c, h) = (dl / sl, dc / sc, dh / sh);
+    (l * l + c * c + h * h + rt * c * h).max(0.0).sqrt()
+}
+
+/// Replaces the colors of `one` with the gray ΔE to `two` scaled by
+/// `DELTA_E_SCALE`, keeping the alpha channel of `one`. Alpha is not taken
+/// into account.
+pub fn delta_e_image(mut one: RgbaImage, two: &RgbaImage) -> (RgbaImage, DeltaEStats) {
+    let mut values: Vec<f32> = one
+        .as_raw()
+        .par_chunks_exact(4)
+        .zip(two.as_raw().par_chunks_exact(4))
+        .map(|(p, q)| ciede2000(srgb_to_lab(p), srgb_to_lab(q)) as f32)
+        .collect();
+    one.par_chunks_exact_mut(4)
+        .zip(values.par_iter())
+        .for_each(|(p, v)| {
+            let gray = (v * DELTA_E_SCALE).round().min(255.0) as u8;
+            p[..3].fill(gray);
+        });
+    (one, stats(&mut values))
+}
+
+/// Mean and 99th percentile of `values`, reorders them.
+fn stats(values: &mut [f32]) -> DeltaEStats {
+    if values.is_empty() {
+        return DeltaEStats {
+            mean: 0.0,
+            p99: 0.0,
+        };
+    }
+    let mean = values.par_iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64;
+    let index = ((values.len() - 1) as f64 * 0.99).round() as usize;
+    let (_, p99, _) = values.select_nth_unstable_by(index, |a, b| a.total_cmp(b));
+    DeltaEStats {
+        mean: mean as f32,
+        p99: *p99,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pairs with their differences from Sharma, Wu and Dalal, "The
+    /// CIEDE2000 color-difference formula: Implementation notes,
+    /// supplementary test data, and mathematical observations" (2005).
+    const SHARMA: [([f64; 3], [f64; 3], f64); 22] = [
+        ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+        ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+        ([50.0, 2.8361, -74.0200], [50.0, 0.0, -82.7485], 3.4412),
+        ([50.0, -1.3802, -84.2814], [50.0, 0.0, -82.7485], 1.0000),
+        ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+        ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0009], 7.1792),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0010], 7.1792),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0011], 7.2195),
+        ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0012], 7.2195),
+        ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+        ([50.0, 2.5, 0.0], [61.0, -5.0, 29.0], 22.8977),
+        ([50.0, 2.5, 0.0], [56.0, -27.0, -3.0], 31.9030),
+        ([50.0, 2.5, 0.0], [58.0, 24.0, 15.0], 19.4535),
+        ([50.0, 2.5, 0.0], [50.0, 3.1736, 0.5854], 1.0000),
+        (
+            [60.2574, -34.0099, 36.2677],
+            [60.4626, -34.1751, 39.4387],
+            1.2644,
+        ),
+        (
+            [63.0109, -31.0961, -5.8663],
+            [62.8187, -29.7946, -4.0864],
+            1.2630,
+        ),
+        (
+            [61.2901, 3.7196, -5.3901],
+            [61.4292, 2.2480, -4.9620],
+            1.8731,
+        ),
+        (
+            [35.0831, -44.1164, 3.7933],
+            [35.0232, -40.0716, 1.5901],
+            1.8645,
+        ),
+        (
+            [22.7233, 20.0904, -46.6940],
+            [23.0331, 14.9730, -42.5619],
+            2.0373,
+        ),
+        (
+            [90.8027, -2.0831, 1.4410],
+            [91.1528, -1.6435, 0.0447],
+            1.4441,
+        ),
+        (
+            [2.0776, 0.0795, -1.1350],
+            [0.9033, -0.0636, -0.5514],
+            0.9082,
+        ),
+    ];
+
+    #[test]
+    fn ciede2000_matches_sharma_reference_pairs() {
+        for (lab1, lab2, expected) in SHARMA {
+            let de = ciede2000(lab1, lab2);
+            assert!(
+                (de - expected).abs() < 1e-4,
+                "{:?} {:?}: {} != {}",
+                lab1,
+                lab2,
+                de,
+                expected
+            );
+            assert!((ciede2000(lab2, lab1) - de).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn srgb_to_lab_reference_colors() {
+        let close = |a: [f64; 3], b: [f64; 3]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 0.01);
+        assert!(close(srgb_to_lab(&[255, 255, 255]), [100.0, 0.0, 0.0]));
+        assert!(close(srgb_to_lab(&[0, 0, 0]), [0.0, 0.0, 0.0]));
+        assert!(close(srgb_to_lab(&[255, 0, 0]), [53.24, 80.09, 67.20]));
+        assert!(close(srgb_to_lab(&[0, 0, 255]), [32.30, 79.19, -107.86]));
+    }
+
+    #[test]
+    fn equal_images_have_no_delta_e() {
+        let img = RgbaImage::from_fn(5, 3, |x, y| {
+            image::Rgba([x as u8 * 50, y as u8 * 80, 30, 255])
+        });
+        let (diff, stats) = delta_e_image(img.clone(), &img);
+        assert!(diff.pixels().all(|p| p.0 == [0, 0, 0, 255]));
+        assert_eq!(
+            stats,
+            DeltaEStats {
+                mean: 0.0,
+                p99: 0.0
+            }
+        );
+    }
+}
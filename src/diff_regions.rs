@@ -0,0 +1,82 @@
+//! Finds the changed areas of a difference image, so a review can visit
+//! them one after another instead of searching a mostly black image.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+
+/// Side of the square tiles differences are grouped into.
+const TILE_SIZE: u32 = 16;
+
+/// Bounding box in pixels of touching tiles holding differences.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DiffRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Clusters of tiles with a channel difference above `threshold`, tiles
+/// touching at an edge or corner belong to the same cluster. The regions
+/// are ordered by their first tile in row-major order.
+pub fn find_diff_regions(diff: &RgbaImage, threshold: u8) -> Vec<DiffRegion> {
+    let (w, h) = diff.dimensions();
+    if w == 0 || h == 0 {
+        return Vec::new();
+    }
+    let tiles_x = w.div_ceil(TILE_SIZE) as usize;
+    let tiles_y = h.div_ceil(TILE_SIZE) as usize;
+    let row = w as usize * 4;
+    let flagged = diff
+        .par_chunks(row * TILE_SIZE as usize)
+        .flat_map_iter(|band| {
+            let mut tiles = vec![false; tiles_x];
+            for line in band.chunks_exact(row) {
+                for (x, p) in line.chunks_exact(4).enumerate() {
+                    if p[0].max(p[1]).max(p[2]) > threshold {
+                        tiles[x / TILE_SIZE as usize] = true;
+                    }
+                }
+            }
+            tiles
+        })
+        .collect::<Vec<_>>();
+
+    let mut visited = vec![false; flagged.len()];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..flagged.len() {
+        if !flagged[start] || visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        stack.push(start);
+        let (mut x0, mut y0) = (start % tiles_x, start / tiles_x);
+        let (mut x1, mut y1) = (x0, y0);
+        while let Some(tile) = stack.pop() {
+            let (tx, ty) = (tile % tiles_x, tile / tiles_x);
+            x0 = x0.min(tx);
+            y0 = y0.min(ty);
+            x1 = x1.max(tx);
+            y1 = y1.max(ty);
+            for ny in ty.saturating_sub(1)..=(ty + 1).min(tiles_y - 1) {
+                for nx in tx.saturating_sub(1)..=(tx + 1).min(tiles_x - 1) {
+                    let n = ny * tiles_x + nx;
+                    if flagged[n] && !visited[n] {
+                        visited[n] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+        }
+        let x = x0 as u32 * TILE_SIZE;
+        let y = y0 as u32 * TILE_SIZE;
+        regions.push(DiffRegion {
+            x,
+            y,
+            width: ((x1 as u32 + 1) * TILE_SIZE).min(w) - x,
+            height: ((y1 as u32 + 1) * TILE_SIZE).min(h) - y,
+        });
+    }
+    regions
+}
@@ -0,0 +1,348 @@
+//! Typed failures of loading an image, so the UI can say what went wrong
+//! and what to do about it. They travel inside `std::io::Error` like the
+//! other errors of the crate and are recovered with `ImViewError::from`.
+
+use image::error::{ImageFormatHint, LimitErrorKind, UnsupportedErrorKind};
+use image::ImageError;
+use std::fmt;
+use std::io::ErrorKind;
+
+/// Windows error codes of a file another program opened without sharing it.
+const ERROR_SHARING_VIOLATION: i32 = 32;
+const ERROR_LOCK_VIOLATION: i32 = 33;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImViewError {
+    /// Reading the file failed.
+    Io { kind: ErrorKind, detail: String },
+    /// The data of a known format is broken.
+    Decode {
+        format: Option<String>,
+        detail: String,
+    },
+    /// Not a format the decoders read, named like `AVIF` when known.
+    UnsupportedFormat(String),
+    /// More pixels than `--max-dimension` allows.
+    TooLarge(String),
+    /// Decoding needs more memory than `--max-memory` allows.
+    MemoryLimit(String),
+    /// Another program still holds the file open, usually while writing it.
+    Locked(String),
+}
+
+/// OS error code of `err` or of the I/O error a decoder wrapped in it.
+fn os_error(err: &std::io::Error) -> Option<i32> {
+    err.raw_os_error()
+        .or_else(|| match err.get_ref()?.downcast_ref::<ImageError>()? {
+            ImageError::IoError(e) => e.raw_os_error(),
+            _ => None,
+        })
+}
+
+fn is_sharing_violation(err: &std::io::Error) -> bool {
+    cfg!(windows)
+        && matches!(
+            os_error(err),
+            Some(ERROR_SHARING_VIOLATION | ERROR_LOCK_VIOLATION)
+        )
+}
+
+/// The file may be held open by another program. Windows also denies
+/// access to files still being written, so that counts as well there.
+pub fn is_locked(err: &std::io::Error) -> bool {
+    let denied = err.kind() == ErrorKind::PermissionDenied
+        || matches!(
+            err.get_ref().and_then(|e| e.downcast_ref::<ImageError>()),
+            Some(ImageError::IoError(e)) if e.kind() == ErrorKind::PermissionDenied
+        );
+    is_sharing_violation(err) || (cfg!(windows) && denied)
+}
+
+fn format_name(hint: &ImageFormatHint) -> Option<String> {
+    match hint {
+        ImageFormatHint::Exact(format) => Some(format!("{:?}", format).to_uppercase()),
+        ImageFormatHint::Name(name) => Some(name.to_uppercase()),
+        ImageFormatHint::PathExtension(ext) => Some(ext.to_string_lossy().to_uppercase()),
+        _ => None,
+    }
+}
+
+impl ImViewError {
+    fn from_image_error(err: &ImageError) -> Self {
+        match err {
+            ImageError::Unsupported(e) => match e.kind() {
+                UnsupportedErrorKind::Format(hint) => ImViewError::UnsupportedFormat(
+                    format_name(&hint).unwrap_or_else(|| "unknown".to_string()),
+                ),
+                _ => ImViewError::Decode {
+                    format: format_name(&e.format_hint()),
+                    detail: e.to_string(),
+                },
+            },
+            ImageError::Decoding(e) => ImViewError::Decode {
+                format: format_name(&e.format_hint()),
+                detail: e.to_string(),
+            },
+            ImageError::Limits(e) => match e.kind() {
+                LimitErrorKind::InsufficientMemory => ImViewError::MemoryLimit(e.to_string()),
+                _ => ImViewError::TooLarge(e.to_string()),
+            },
+            ImageError::IoError(e) => ImViewError::Io {
+                kind: e.kind(),
+                detail: e.to_string(),
+            },
+            e => ImViewError::Decode {
+                format: None,
+                detail: e.to_string(),
+            },
+        }
+    }
+
+    /// A few words for places too small for the whole message.
+    pub fn short_label(&self) -> &'static str {
+        match self {
+            ImViewError::Io {
+                kind: ErrorKind::NotFound,
+                ..
+            } => "Missing file",
+            ImViewError::Io {
+                kind: ErrorKind::PermissionDenied,
+                ..
+            } => "Permission denied",
+            ImViewError::Io { .. } => "Read error",
+            ImViewError::Decode { .. } => "Broken image",
+            ImViewError::UnsupportedFormat(_) => "Unsupported format",
+            ImViewError::TooLarge(_) => "Too large",
+            ImViewError::MemoryLimit(_) => "Over memory limit",
+            ImViewError::Locked(_) => "File in use",
+        }
+    }
+
+    /// What the user can do about the error, if anything.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            ImViewError::Io {
+                kind: ErrorKind::PermissionDenied,
+                ..
+            } => Some("Check the permissions of the file and retry".to_string()),
+            ImViewError::Io { .. } => Some("Retry once the file is readable".to_string()),
+            ImViewError::UnsupportedFormat(format) if format == "AVIF" => {
+                Some("Build with the avif-decoder feature of the image crate".to_string())
+            }
+            ImViewError::UnsupportedFormat(_) => {
+                Some("Convert it to PNG, JPEG, TIFF or WebP".to_string())
+            }
+            ImViewError::TooLarge(_) => Some("Increase --max-dimension to open it".to_string()),
+            ImViewError::MemoryLimit(_) => Some("Increase --max-memory to open it".to_string()),
+            ImViewError::Locked(_) => Some("Retry once the program writing it is done".to_string()),
+            ImViewError::Decode { .. } => None,
+        }
+    }
+
+    /// Reading again may succeed, the file itself isn't at fault.
+    pub fn can_retry(&self) -> bool {
+        matches!(self, ImViewError::Io { .. } | ImViewError::Locked(_))
+    }
+}
+
+impl fmt::Display for ImViewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImViewError::Io { detail, .. } => write!(f, "{}", detail),
+            ImViewError::Decode {
+                format: Some(format),
+                detail,
+            } => write!(f, "Broken {} data: {}", format, detail),
+            ImViewError::Decode {
+                format: None,
+                detail,
+            } => write!(f, "Broken image data: {}", detail),
+            ImViewError::UnsupportedFormat(format) => write!(f, "Unsupported format {}", format),
+            ImViewError::TooLarge(detail) => write!(f, "{}", detail),
+            ImViewError::MemoryLimit(detail) => {
+                write!(f, "Image exceeds the configured memory limit: {}", detail)
+            }
+            ImViewError::Locked(detail) => write!(f, "{}", detail),
+        }
+    }
+}
+
+impl std::error::Error for ImViewError {}
+
+impl From<ImViewError> for std::io::Error {
+    fn from(err: ImViewError) -> Self {
+        let kind = match &err {
+            ImViewError::Io { kind, .. } => *kind,
+            ImViewError::Locked(_) => ErrorKind::PermissionDenied,
+            ImViewError::UnsupportedFormat(_) => ErrorKind::Unsupported,
+            ImViewError::MemoryLimit(_) => ErrorKind::OutOfMemory,
+            ImViewError::Decode { .. } | ImViewError::TooLarge(_) => ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
+
+/// Recovers the typed error an `std::io::Error` carries, the errors of the
+/// decoders it wraps are sorted by their kind.
+impl From<&std::io::Error> for ImViewError {
+    fn from(err: &std::io::Error) -> Self {
+        let inner = err.get_ref();
+        if let Some(e) = inner.and_then(|e| e.downcast_ref::<ImViewError>()) {
+            return e.clone();
+        }
+        if is_sharing_violation(err) {
+            return ImViewError::Locked(err.to_string());
+        }
+        if let Some(e) = inner.and_then(|e| e.downcast_ref::<ImageError>()) {
+            return Self::from_image_error(e);
+        }
+        let decoder_format = match inner {
+            Some(e) if e.is::<png::DecodingError>() => Some("PNG"),
+            Some(e) if e.is::<tiff::TiffError>() => Some("TIFF"),
+            _ => None,
+        };
+        match (decoder_format, err.kind()) {
+            (Some(format), _) => ImViewError::Decode {
+                format: Some(format.to_string()),
+                detail: err.to_string(),
+            },
+            (None, ErrorKind::InvalidData | ErrorKind::UnexpectedEof) => ImViewError::Decode {
+                format: None,
+                detail: err.to_string(),
+            },
+            (None, kind) => ImViewError::Io {
+                kind,
+                detail: err.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::error::{DecodingError, LimitError, UnsupportedError};
+    use image::ImageFormat;
+
+    fn image_io_error(err: ImageError) -> std::io::Error {
+        std::io::Error::other(err)
+    }
+
+    #[test]
+    fn display_says_what_went_wrong() {
+        let cases = [
+            (
+                ImViewError::Decode {
+                    format: Some("PNG".to_string()),
+                    detail: "bad CRC".to_string(),
+                },
+                "Broken PNG data: bad CRC",
+            ),
+            (
+                ImViewError::Decode {
+                    format: None,
+                    detail: "bad CRC".to_string(),
+                },
+                "Broken image data: bad CRC",
+            ),
+            (
+                ImViewError::UnsupportedFormat("AVIF".to_string()),
+                "Unsupported format AVIF",
+            ),
+            (
+                ImViewError::MemoryLimit("needs 2 GiB".to_string()),
+                "Image exceeds the configured memory limit: needs 2 GiB",
+            ),
+            (ImViewError::TooLarge("too wide".to_string()), "too wide"),
+            (ImViewError::Locked("in use".to_string()), "in use"),
+            (
+                ImViewError::Io {
+                    kind: ErrorKind::NotFound,
+                    detail: "no such file".to_string(),
+                },
+                "no such file",
+            ),
+        ];
+        for (err, text) in cases {
+            assert_eq!(err.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn typed_errors_survive_io_errors() {
+        let errors = [
+            ImViewError::UnsupportedFormat("AVIF".to_string()),
+            ImViewError::TooLarge("too wide".to_string()),
+            ImViewError::MemoryLimit("needs 2 GiB".to_string()),
+            ImViewError::Locked("in use".to_string()),
+            ImViewError::Io {
+                kind: ErrorKind::NotFound,
+                detail: "no such file".to_string(),
+            },
+        ];
+        let kinds = [
+            ErrorKind::Unsupported,
+            ErrorKind::InvalidData,
+            ErrorKind::OutOfMemory,
+            ErrorKind::PermissionDenied,
+            ErrorKind::NotFound,
+        ];
+        for (err, kind) in errors.into_iter().zip(kinds) {
+            let io: std::io::Error = err.clone().into();
+            assert_eq!(io.kind(), kind);
+            assert_eq!(io.to_string(), err.to_string());
+            assert_eq!(ImViewError::from(&io), err);
+        }
+    }
+
+    #[test]
+    fn decoder_errors_are_sorted_by_kind() {
+        let unsupported = ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+            ImageFormatHint::Name("avif".to_string()),
+            UnsupportedErrorKind::Format(ImageFormatHint::Name("avif".to_string())),
+        ));
+        assert_eq!(
+            ImViewError::from(&image_io_error(unsupported)),
+            ImViewError::UnsupportedFormat("AVIF".to_string())
+        );
+
+        let broken = ImageError::Decoding(DecodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            "bad CRC",
+        ));
+        match ImViewError::from(&image_io_error(broken)) {
+            ImViewError::Decode { format, .. } => assert_eq!(format.as_deref(), Some("PNG")),
+            e => panic!("{:?}", e),
+        }
+
+        let memory = ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory));
+        assert!(matches!(
+            ImViewError::from(&image_io_error(memory)),
+            ImViewError::MemoryLimit(_)
+        ));
+        let size = ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError));
+        assert!(matches!(
+            ImViewError::from(&image_io_error(size)),
+            ImViewError::TooLarge(_)
+        ));
+
+        let truncated = std::io::Error::new(ErrorKind::UnexpectedEof, "truncated");
+        assert!(matches!(
+            ImViewError::from(&truncated),
+            ImViewError::Decode { format: None, .. }
+        ));
+        let missing = std::io::Error::new(ErrorKind::NotFound, "no such file");
+        let err = ImViewError::from(&missing);
+        assert_eq!(err.short_label(), "Missing file");
+        assert!(err.can_retry());
+    }
+
+    #[test]
+    fn hints_point_at_the_option_to_change() {
+        let hint = |err: ImViewError| err.hint().unwrap_or_default();
+        assert!(hint(ImViewError::TooLarge(String::new())).contains("--max-dimension"));
+        assert!(hint(ImViewError::MemoryLimit(String::new())).contains("--max-memory"));
+        assert!(hint(ImViewError::UnsupportedFormat("AVIF".to_string())).contains("avif"));
+        assert!(!ImViewError::UnsupportedFormat("AVIF".to_string()).can_retry());
+    }
+}
@@ -0,0 +1,114 @@
+use crate::annotations::{rasterize, Annotation};
+use crate::image_ui_state::DiffMode;
+use eframe::egui::*;
+use image::imageops::{crop_imm, replace, resize, FilterType};
+use image::RgbaImage;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ExportResolution {
+    Screen,
+    Source,
+}
+
+fn uv_crop_rect(source: &RgbaImage, uv: &Rect) -> (u32, u32, u32, u32) {
+    let (w, h) = source.dimensions();
+    let x0 = ((uv.left() * w as f32).round() as u32).min(w - 1);
+    let y0 = ((uv.top() * h as f32).round() as u32).min(h - 1);
+    let x1 = ((uv.right() * w as f32).round() as u32).clamp(x0 + 1, w);
+    let y1 = ((uv.bottom() * h as f32).round() as u32).clamp(y0 + 1, h);
+    (x0, y0, x1 - x0, y1 - y0)
+}
+
+/// The view panels to export and the resolution to export them at.
+#[derive(Clone, Copy)]
+pub struct ExportView<'a> {
+    pub mode: DiffMode,
+    /// Panel sizes in screen points.
+    pub sizes: &'a [Vec2],
+    /// Panel rects in texture coordinates of the source.
+    pub uvs: &'a [Rect],
+    pub resolution: ExportResolution,
+    pub pixels_per_point: f32,
+}
+
+/// Rasterizes the panels of `view` the same way `SplittedImage` paints
+/// them. The last panel of the triptych is cut from `diff`.
+pub fn render_view(
+    source: &RgbaImage,
+    diff: Option<&RgbaImage>,
+    annotations: &[Annotation],
+    view: ExportView,
+) -> RgbaImage {
+    let ExportView {
+        mode,
+        sizes,
+        uvs,
+        resolution,
+        pixels_per_point,
+    } = view;
+    let panels = sizes
+        .iter()
+        .zip(uvs.iter())
+        .enumerate()
+        .map(|(i, (size, uv))| {
+            let source = match diff {
+                Some(diff) if mode == DiffMode::Triptych && i == 2 => diff,
+                _ => source,
+            };
+            let (x, y, w, h) = uv_crop_rect(source, uv);
+            let crop = crop_imm(source, x, y, w, h).to_image();
+            match resolution {
+                ExportResolution::Source => crop,
+                ExportResolution::Screen => {
+                    let w = ((size.x * pixels_per_point).round() as u32).max(1);
+                    let h = ((size.y * pixels_per_point).round() as u32).max(1);
+                    resize(&crop, w, h, FilterType::Triangle)
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let (width, height) = match mode {
+        DiffMode::VSplit | DiffMode::Triptych => (
+            panels.iter().map(|p| p.width()).sum(),
+            panels.iter().map(|p| p.height()).max().unwrap_or(0),
+        ),
+        DiffMode::HSplit => (
+            panels.iter().map(|p| p.width()).max().unwrap_or(0),
+            panels.iter().map(|p| p.height()).sum(),
+        ),
+        DiffMode::Full
+        | DiffMode::VColorDiff
+        | DiffMode::HColorDiff
+        | DiffMode::PageDiff
+        | DiffMode::PreviousDiff
+        | DiffMode::ReferenceDiff => panels[0].dimensions(),
+    };
+    let mut result = RgbaImage::new(width, height);
+    let mut offset = (0, 0);
+    for panel in panels.iter() {
+        replace(&mut result, panel, offset.0, offset.1);
+        match mode {
+            DiffMode::VSplit | DiffMode::Triptych => offset.0 += panel.width() as i64,
+            DiffMode::HSplit => offset.1 += panel.height() as i64,
+            _ => (),
+        }
+    }
+
+    if mode == DiffMode::Full && !annotations.is_empty() {
+        let (x, y, w, h) = uv_crop_rect(source, &uvs[0]);
+        let sx = result.width() as f32 / w as f32;
+        let sy = result.height() as f32 / h as f32;
+        let scale = match resolution {
+            ExportResolution::Screen => pixels_per_point,
+            ExportResolution::Source => sx.min(sy) * w as f32 / sizes[0].x,
+        };
+        rasterize(
+            annotations,
+            &mut result,
+            |p| pos2((p.x - x as f32) * sx, (p.y - y as f32) * sy),
+            scale,
+        );
+    }
+    result
+}
@@ -1,14 +1,75 @@
-use crossbeam::channel::{never, unbounded, Receiver, Select, Sender};
-use image::io::Reader as ImageReader;
+use crate::alignment::{estimate_translation, Alignment};
+use crate::alpha_anomalies::{find_alpha_anomalies, AlphaAnomalies};
+use crate::annotations::{read_sidecar, sidecar_path, write_sidecar, Annotation};
+use crate::archive;
+use crate::decode::{self, decode_pages, decode_thumbnail, SourceInfo, ThumbnailScaling};
+use crate::error::{self, ImViewError};
+use crate::image_source::ImageSource;
+use crate::links;
+use crate::montage::{page_path, render_page, MontageSettings};
+use crate::region_stats::{region_stats, RegionKey, RegionStats};
+use crate::report::{self, PairStats};
+#[cfg(feature = "video")]
+use crate::video::{self, VideoFrame};
+use crossbeam::channel::{bounded, never, select, unbounded, Receiver, Sender, TrySendError};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use image::imageops::crop_imm;
 use image::RgbaImage;
-use log::{error, trace};
+use log::{debug, error, trace, warn};
 use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use rayon::{ThreadPool, ThreadPoolBuilder};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel as std_channel, Receiver as StdReceiver};
-use std::sync::{atomic::AtomicBool, Arc};
-use std::time::Duration;
+use std::sync::mpsc::{channel as std_channel, Receiver as StdReceiver, RecvTimeoutError};
+use std::sync::{atomic::AtomicBool, Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often the background threads check the shutdown flag.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long `shutdown` waits for the background threads to finish.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest side of a decoded image unless `set_max_dimension` changes it,
+/// the largest texture side most GPUs support.
+pub const DEFAULT_MAX_DIMENSION: u32 = 16384;
+/// Most folders outside the watched ones that symlinked images point into
+/// are watched as well, see `FileSystem::start`.
+const MAX_LINK_TARGET_DIRS: usize = 16;
+/// Reads of a file another program holds open are tried again this many
+/// times, waiting twice as long before each retry.
+const LOCKED_RETRIES: u32 = 4;
+const LOCKED_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// Partial images waiting for the main thread, a decoder with a full queue
+/// sends its rows along with the next ones.
+const PARTIAL_QUEUE_LEN: usize = 1;
+
+/// Runs `read` of `path` again while the file is held open by another
+/// program, see `error::is_locked`. A file that stays locked gives
+/// `ImViewError::Locked` instead of whatever the decoder made of it.
+fn retry_locked<T>(
+    path: &Path,
+    mut read: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut delay = LOCKED_RETRY_DELAY;
+    for _ in 0..LOCKED_RETRIES {
+        match read() {
+            Err(e) if error::is_locked(&e) => {
+                debug!("{} is locked, retrying in {:?}", path.display(), delay);
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            res => return res,
+        }
+    }
+    read().map_err(|e| {
+        if error::is_locked(&e) {
+            let msg = format!("{} is in use by another program", path.display());
+            ImViewError::Locked(msg).into()
+        } else {
+            e
+        }
+    })
+}
 
 struct Notify {
     watcher: RecommendedWatcher,
@@ -16,44 +77,352 @@ struct Notify {
 }
 pub struct FileSystem {
     pub receiver: Receiver<FileSystemEvent>,
+    /// Rows of the current image while it's decoding, see `read_file`.
+    pub partial_receiver: Receiver<PartialImage>,
+    partial_sender: Sender<PartialImage>,
+    /// Image the partial images are sent for, see `set_current`.
+    current: Arc<RwLock<Option<PathBuf>>>,
+    notifier: Arc<dyn Fn() + Send + Sync>,
     op_sender: Sender<InternalFSEvent>,
+    fs_sender: Sender<FileSystemEvent>,
+    /// Shared with the watcher thread, replaced by `set_filter`.
+    filter: Arc<RwLock<ScanFilter>>,
+    /// Watched folders that were listed in full, rescanned by `set_filter`.
+    listed_roots: Vec<PathBuf>,
     thumbs_thread_pool: ThreadPool,
     image_thread_pool: ThreadPool,
+    pool_sizes: PoolSizes,
+    max_dimension: u32,
+    /// Bytes a decode may allocate at once, the decoders' own limits when
+    /// `None`.
+    max_memory: Option<u64>,
     shutdown_flag: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
 
+    /// One per watched folder, their events are merged into one channel.
     #[allow(dead_code)]
-    notify_watcher: Option<RecommendedWatcher>,
+    notify_watchers: Vec<RecommendedWatcher>,
 }
 
 fn map_err_notify(err: notify::Error) -> std::io::Error {
     match err {
         notify::Error::Io(err) => err,
-        notify::Error::PathNotFound => {
-            std::io::Error::new(std::io::ErrorKind::Other, "Path not found")
+        notify::Error::PathNotFound => std::io::Error::other("Path not found"),
+        notify::Error::WatchNotFound => std::io::Error::other("Watch not found"),
+        notify::Error::Generic(err) => std::io::Error::other(err),
+    }
+}
+
+/// Number of threads in the worker pools.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoolSizes {
+    pub decode_threads: usize,
+    pub thumb_threads: usize,
+}
+
+impl PoolSizes {
+    pub fn new() -> Self {
+        let cpus = num_cpus::get();
+        Self {
+            decode_threads: cpus,
+            thumb_threads: (cpus / 2).max(1),
         }
-        notify::Error::WatchNotFound => {
-            std::io::Error::new(std::io::ErrorKind::Other, "Watch not found")
+    }
+}
+
+/// Patterns of entries that are never listed unless they are opened
+/// explicitly.
+pub const DEFAULT_IGNORED: [&str; 3] = ["__MACOSX", "node_modules", "Thumbs.db"];
+
+/// Decides which directory entries are listed.
+#[derive(Clone, Debug)]
+pub struct ScanFilter {
+    /// List entries whose names start with a dot.
+    pub show_hidden: bool,
+    /// Read the first bytes of files whose names don't tell if they are
+    /// images, off for slow network folders.
+    pub sniff_content: bool,
+    /// Glob patterns of the entries that are never listed.
+    ignored: Vec<String>,
+    /// `ignored` compiled, matched against the entry name and its full path.
+    matcher: GlobSet,
+}
+
+impl ScanFilter {
+    pub fn new() -> Self {
+        let mut filter = Self {
+            show_hidden: false,
+            sniff_content: true,
+            ignored: Vec::new(),
+            matcher: GlobSet::empty(),
+        };
+        let ignored = DEFAULT_IGNORED.iter().map(|s| s.to_string()).collect();
+        filter.set_ignored(ignored).unwrap();
+        filter
+    }
+
+    pub fn ignored(&self) -> &[String] {
+        &self.ignored
+    }
+
+    /// Replaces the ignore patterns, nothing changes if one of them isn't a
+    /// valid glob.
+    pub fn set_ignored(&mut self, patterns: Vec<String>) -> std::io::Result<()> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns.iter() {
+            builder.add(parse_glob(pattern)?);
+        }
+        self.matcher = builder
+            .build()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        self.ignored = patterns;
+        Ok(())
+    }
+
+    pub fn accepts(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name,
+            None => return true,
+        };
+        if !self.show_hidden && name.to_string_lossy().starts_with('.') {
+            return false;
         }
-        notify::Error::Generic(err) => std::io::Error::new(std::io::ErrorKind::Other, err),
+        !self.matcher.is_match(name) && !self.matcher.is_match(path)
+    }
+
+    /// `check_name` of the accepted entries, names that need the content to
+    /// tell are rejected without sniffing.
+    fn check(&self, path: &Path) -> NameCheck {
+        if !self.accepts(path) {
+            return NameCheck::NotImage;
+        }
+        match check_name(path) {
+            NameCheck::Unknown if !self.sniff_content => NameCheck::NotImage,
+            check => check,
+        }
+    }
+}
+
+/// Compiles an ignore pattern, `*` doesn't cross folder separators.
+pub fn parse_glob(pattern: &str) -> std::io::Result<Glob> {
+    globset::GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+fn build_pool(
+    name: &'static str,
+    threads: usize,
+    low_priority: bool,
+) -> std::io::Result<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads.max(1))
+        .thread_name(move |idx| format!("{}-{}", name, idx))
+        .start_handler(move |_| {
+            if low_priority {
+                lower_thread_priority();
+            }
+        })
+        .build()
+        .map_err(std::io::Error::other)
+}
+
+/// Lowers the priority of the calling thread so it yields to the UI and the
+/// full image decoding. Linux keeps a nice value per thread, elsewhere this
+/// is a no-op.
+#[cfg(target_os = "linux")]
+fn lower_thread_priority() {
+    // Who 0 is the calling thread on Linux.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 10) } != 0 {
+        warn!(
+            "Can't lower thread priority: {}",
+            std::io::Error::last_os_error()
+        );
     }
 }
 
-fn is_image(path: &Path) -> bool {
-    image::ImageFormat::from_path(path)
-        .map(|f| f.can_read())
-        .unwrap_or(false)
+#[cfg(not(target_os = "linux"))]
+fn lower_thread_priority() {}
+
+/// Content hash of a file and the modification time it was computed for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileHash {
+    pub modified: SystemTime,
+    pub hash: blake3::Hash,
+}
+
+/// First page of the image at `path`, see `decode_pages`. Read through
+/// symlinks and retried while the file is locked, like in `read_file`.
+fn first_page(
+    path: &Path,
+    max_dimension: u32,
+    max_memory: Option<u64>,
+) -> std::io::Result<RgbaImage> {
+    let target = links::resolve(path)?;
+    retry_locked(path, || {
+        decode_pages(&target, max_dimension, max_memory, None)
+    })?
+    .into_iter()
+    .next()
+    .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "No pages decoded"))
+}
+
+/// Hashes the content of `path`, reusing `cached` when the file wasn't
+/// modified since it was computed.
+fn hash_file(path: &Path, cached: Option<FileHash>) -> std::io::Result<FileHash> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    if let Some(cached) = cached.filter(|c| c.modified == modified) {
+        return Ok(cached);
+    }
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(FileHash {
+        modified,
+        hash: hasher.finalize(),
+    })
+}
+
+/// Path `path` gets when renamed to `new_name` in its folder. Fails for
+/// names that aren't a single file name and for names already taken,
+/// except by the file itself as in a change of letter case.
+pub fn rename_target(path: &Path, new_name: &str) -> std::io::Result<PathBuf> {
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+    let name = Path::new(new_name);
+    if new_name.trim().is_empty() {
+        return Err(invalid("The name is empty"));
+    }
+    if name.file_name() != Some(name.as_os_str()) {
+        return Err(invalid("The name can't contain a folder"));
+    }
+    let target = path.with_file_name(name);
+    let taken = match links::absolute(&target) {
+        Ok(existing) => links::absolute(path).map_or(true, |p| p != existing),
+        Err(_) => false,
+    };
+    if taken {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            "A file with this name exists",
+        ));
+    }
+    Ok(target)
+}
+
+/// Extensions of the formats the decoders read, compared ignoring case.
+const IMAGE_EXTENSIONS: [&str; 21] = [
+    "png", "jpg", "jpeg", "jpe", "jfif", "gif", "bmp", "ico", "tif", "tiff", "webp", "tga", "dds",
+    "hdr", "exr", "pbm", "pgm", "ppm", "pam", "pnm", "ff",
+];
+
+/// Extensions of files still being written, never listed even when the name
+/// before them is an image, like `photo.webp.part`.
+const PARTIAL_EXTENSIONS: [&str; 4] = ["part", "crdownload", "download", "tmp"];
+
+/// What the name of a file tells about it being an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NameCheck {
+    Image,
+    /// No extension, or an unknown one after an image extension like
+    /// `photo.png.bak`. Only the content can tell.
+    Unknown,
+    NotImage,
+}
+
+fn check_name(path: &Path) -> NameCheck {
+    #[cfg(feature = "video")]
+    if video::is_video(path) {
+        return NameCheck::Image;
+    }
+    let lower = |ext: &std::ffi::OsStr| ext.to_string_lossy().to_ascii_lowercase();
+    let ext = match path.extension() {
+        Some(ext) => lower(ext),
+        None => return NameCheck::Unknown,
+    };
+    if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        return NameCheck::Image;
+    }
+    if PARTIAL_EXTENSIONS.contains(&ext.as_str()) {
+        return NameCheck::NotImage;
+    }
+    let inner = path
+        .file_stem()
+        .and_then(|stem| Path::new(stem).extension())
+        .map(lower);
+    match inner {
+        Some(inner) if IMAGE_EXTENSIONS.contains(&inner.as_str()) => NameCheck::Unknown,
+        _ => NameCheck::NotImage,
+    }
 }
 
 pub enum FileEvent {
-    Added(PathBuf),
+    Added(ImageSource),
+    /// File that appeared in the watched directory after the initial listing.
+    Created(PathBuf),
     Removed(PathBuf),
     Modified(PathBuf),
     Renamed(PathBuf, PathBuf),
+    /// File whose name doesn't tell if it's an image, listed or changed.
+    /// `FileSystem::sniff_files` checks the content.
+    Unsniffed(PathBuf),
+}
+
+/// Thumbnail and the dimensions of the full image.
+pub type SizedThumbnail = (RgbaImage, (u32, u32));
+
+/// Rows of a PNG decoded since the previous partial image of it, see
+/// `FileSystem::read_file`.
+pub struct PartialImage {
+    pub source: ImageSource,
+    /// Size of the whole image.
+    pub size: (u32, u32),
+    /// Row of the image `rows` starts at.
+    pub top: u32,
+    pub rows: RgbaImage,
+    /// Number of finished interlacing passes.
+    pub pass: usize,
 }
 
 pub enum OperationEvent {
-    ThumbnailLoaded((PathBuf, std::io::Result<RgbaImage>)),
-    ImageLoaded((PathBuf, std::io::Result<RgbaImage>)),
+    /// Thumbnail with the dimensions of the full image, and how long it
+    /// took to decode.
+    ThumbnailLoaded((ImageSource, std::io::Result<SizedThumbnail>, Duration)),
+    /// All pages of the image, single page images have one, what they were
+    /// decoded from and how long decoding took.
+    ImageLoaded(
+        (
+            ImageSource,
+            std::io::Result<(Vec<RgbaImage>, SourceInfo)>,
+            Duration,
+        ),
+    ),
+    AlignmentEstimated((ImageSource, std::io::Result<Alignment>)),
+    AlphaAnomaliesFound((ImageSource, AlphaAnomalies)),
+    RegionStatsComputed((ImageSource, RegionStats)),
+    /// Annotations read from the sidecar file of the image.
+    AnnotationsRead((ImageSource, std::io::Result<Vec<Annotation>>)),
+    /// Annotations written to the sidecar file of the image.
+    AnnotationsWritten((ImageSource, std::io::Result<Vec<Annotation>>)),
+    /// File that `FileSystem::rename` couldn't rename.
+    RenameFailed((PathBuf, std::io::Error)),
+    /// How much the two files of a pair differ, see
+    /// `FileSystem::compare_pairs`.
+    PairCompared(((PathBuf, PathBuf), std::io::Result<PairStats>)),
+    /// Content hash of a file, see `FileSystem::hash_files`.
+    FileHashed((PathBuf, std::io::Result<FileHash>)),
+    /// Files found by `FileSystem::add_paths`.
+    PathsAdded(Vec<ImageSource>),
+    /// Folder all the opened paths belong to, at start or in
+    /// `FileSystem::add_paths`.
+    RootResolved(PathBuf),
+    /// Folders rescanned by `FileSystem::set_filter` and the images in them
+    /// the new filter lists.
+    Rescanned((Vec<PathBuf>, HashSet<PathBuf>)),
+    ImageSaved((PathBuf, std::io::Result<()>)),
+    MontageProgress((PathBuf, usize, usize)),
+    MontageSaved((PathBuf, std::io::Result<usize>)),
+    #[cfg(feature = "video")]
+    VideoFrameLoaded((ImageSource, std::io::Result<VideoFrame>)),
 }
 
 enum InternalFSEvent {
@@ -62,50 +431,222 @@ enum InternalFSEvent {
 }
 
 impl InternalFSEvent {
-    fn image_loaded(path: PathBuf, image: std::io::Result<RgbaImage>) -> Self {
-        InternalFSEvent::Op(OperationEvent::ImageLoaded((path, image)))
+    fn image_loaded(
+        source: ImageSource,
+        image: std::io::Result<(Vec<RgbaImage>, SourceInfo)>,
+        started: Instant,
+    ) -> Self {
+        let elapsed = started.elapsed();
+        InternalFSEvent::Op(OperationEvent::ImageLoaded((source, image, elapsed)))
     }
-    fn thumbnail_loaded(path: PathBuf, image: std::io::Result<RgbaImage>) -> Self {
-        InternalFSEvent::Op(OperationEvent::ThumbnailLoaded((path, image)))
+    fn thumbnail_loaded(
+        source: ImageSource,
+        image: std::io::Result<(RgbaImage, (u32, u32))>,
+        started: Instant,
+    ) -> Self {
+        let elapsed = started.elapsed();
+        InternalFSEvent::Op(OperationEvent::ThumbnailLoaded((source, image, elapsed)))
+    }
+    fn image_saved(path: PathBuf, res: std::io::Result<()>) -> Self {
+        InternalFSEvent::Op(OperationEvent::ImageSaved((path, res)))
+    }
+    fn montage_progress(path: PathBuf, done: usize, total: usize) -> Self {
+        InternalFSEvent::Op(OperationEvent::MontageProgress((path, done, total)))
+    }
+    fn montage_saved(path: PathBuf, res: std::io::Result<usize>) -> Self {
+        InternalFSEvent::Op(OperationEvent::MontageSaved((path, res)))
+    }
+    fn alignment_estimated(source: ImageSource, alignment: std::io::Result<Alignment>) -> Self {
+        InternalFSEvent::Op(OperationEvent::AlignmentEstimated((source, alignment)))
+    }
+    fn alpha_anomalies_found(source: ImageSource, anomalies: AlphaAnomalies) -> Self {
+        InternalFSEvent::Op(OperationEvent::AlphaAnomaliesFound((source, anomalies)))
+    }
+    fn annotations_read(source: ImageSource, res: std::io::Result<Vec<Annotation>>) -> Self {
+        InternalFSEvent::Op(OperationEvent::AnnotationsRead((source, res)))
+    }
+    fn annotations_written(source: ImageSource, res: std::io::Result<Vec<Annotation>>) -> Self {
+        InternalFSEvent::Op(OperationEvent::AnnotationsWritten((source, res)))
+    }
+    fn rename_failed(path: PathBuf, err: std::io::Error) -> Self {
+        InternalFSEvent::Op(OperationEvent::RenameFailed((path, err)))
+    }
+    fn file_hashed(path: PathBuf, res: std::io::Result<FileHash>) -> Self {
+        InternalFSEvent::Op(OperationEvent::FileHashed((path, res)))
+    }
+    fn pair_compared(a: PathBuf, b: PathBuf, res: std::io::Result<PairStats>) -> Self {
+        InternalFSEvent::Op(OperationEvent::PairCompared(((a, b), res)))
+    }
+    fn root_resolved(root: PathBuf) -> Self {
+        InternalFSEvent::Op(OperationEvent::RootResolved(root))
+    }
+    fn paths_added(sources: Vec<ImageSource>) -> Self {
+        InternalFSEvent::Op(OperationEvent::PathsAdded(sources))
+    }
+    fn rescanned(roots: Vec<PathBuf>, files: HashSet<PathBuf>) -> Self {
+        InternalFSEvent::Op(OperationEvent::Rescanned((roots, files)))
+    }
+    fn region_stats_computed(source: ImageSource, stats: RegionStats) -> Self {
+        InternalFSEvent::Op(OperationEvent::RegionStatsComputed((source, stats)))
+    }
+    #[cfg(feature = "video")]
+    fn video_frame_loaded(path: PathBuf, frame: std::io::Result<VideoFrame>) -> Self {
+        let source = ImageSource::File(path);
+        InternalFSEvent::Op(OperationEvent::VideoFrameLoaded((source, frame)))
     }
 }
 
 pub enum FileSystemEvent {
     FileEvent(FileEvent),
     OperationEvent(OperationEvent),
+    /// Non-fatal problem the user should know about.
+    Warning(String),
+}
+
+/// Symlinked images whose targets live outside the watched folders, by
+/// target, so changes of a target reload the links reading it.
+#[derive(Default)]
+struct LinkTargets {
+    roots: Vec<PathBuf>,
+    links: HashMap<PathBuf, Vec<PathBuf>>,
+}
+
+impl LinkTargets {
+    fn new(roots: &[PathBuf], files: &HashSet<PathBuf>) -> Self {
+        let mut targets = Self {
+            roots: roots.to_vec(),
+            links: HashMap::new(),
+        };
+        for file in files.iter().filter(|f| links::is_link(f)) {
+            if let Ok(target) = links::resolve(file) {
+                if !targets.in_root(&target) {
+                    targets.links.entry(target).or_default().push(file.clone());
+                }
+            }
+        }
+        targets
+    }
+
+    fn in_root(&self, path: &Path) -> bool {
+        path.parent()
+            .is_some_and(|dir| self.roots.iter().any(|r| r == dir))
+    }
+
+    /// Folders of the targets, at most `MAX_LINK_TARGET_DIRS` of them.
+    fn dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = self
+            .links
+            .keys()
+            .filter_map(|t| t.parent())
+            .map(Path::to_path_buf)
+            .collect::<Vec<_>>();
+        dirs.sort();
+        dirs.dedup();
+        if dirs.len() > MAX_LINK_TARGET_DIRS {
+            warn!(
+                "Symlinks point into {} folders, only the first {} are watched",
+                dirs.len(),
+                MAX_LINK_TARGET_DIRS
+            );
+            dirs.truncate(MAX_LINK_TARGET_DIRS);
+        }
+        dirs
+    }
+
+    /// Links reading the paths of `event`, `None` when the event comes from
+    /// one of the roots and is handled like any other.
+    fn links_of(&self, event: &DebouncedEvent) -> Option<Vec<PathBuf>> {
+        if self.links.is_empty() {
+            return None;
+        }
+        let paths = match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Remove(path) => vec![path],
+            DebouncedEvent::Rename(old_path, new_path) => vec![old_path, new_path],
+            _ => return None,
+        };
+        if paths.iter().any(|p| self.in_root(p)) {
+            return None;
+        }
+        let links = paths
+            .into_iter()
+            .filter_map(|p| self.links.get(p))
+            .flatten()
+            .cloned()
+            .collect();
+        Some(links)
+    }
 }
 
 impl FileSystem {
-    pub fn start<F>(paths: Vec<PathBuf>, notifier: F) -> std::io::Result<Self>
+    /// Lists `paths` and watches the folders they are in. With
+    /// `watch_link_targets` the folders symlinked images point into are
+    /// watched too, so changes of the targets reload the links.
+    pub fn start<F>(
+        paths: Vec<PathBuf>,
+        pool_sizes: PoolSizes,
+        filter: ScanFilter,
+        watch_link_targets: bool,
+        notifier: F,
+    ) -> std::io::Result<Self>
     where
-        F: Fn() + Send + 'static,
+        F: Fn() + Send + Sync + 'static,
     {
+        let notifier = Arc::new(notifier);
+        let (partial_sender, partial_receiver) = bounded(PARTIAL_QUEUE_LEN);
         let (fs_sender, fs_receiver) = unbounded();
         let fs_sender_cl = fs_sender.clone();
         let (op_sender, op_receiver) = unbounded();
-        let (root, files) = Self::select_root_and_files(&paths)?;
-        let shutdown_flag = Arc::new(AtomicBool::new(false));
-        let notify = if root.is_some() {
-            trace!(
-                "Start watching directory: {}",
-                root.as_ref().unwrap().display()
-            );
-            Some(Self::start_notify(root.as_ref().unwrap())?)
+        let mut unsniffed = Vec::new();
+        let (roots, files, mut skipped) =
+            Self::select_roots_and_files(&paths, &filter, &mut unsniffed)?;
+        let entries = Self::collect_archive_entries(&paths, &mut skipped);
+        let listed_roots = if roots.len() == 1 {
+            roots.clone()
         } else {
-            None
+            let named = paths
+                .iter()
+                .filter_map(|p| links::absolute(p).ok())
+                .collect::<HashSet<_>>();
+            roots
+                .iter()
+                .filter(|r| named.contains(*r))
+                .cloned()
+                .collect()
         };
-
-        let (notify_reciver, notify_watcher) = if let Some(notify) = notify {
-            (Some(notify.reciver), Some(notify.watcher))
+        let link_targets = if watch_link_targets {
+            LinkTargets::new(&roots, &files)
         } else {
-            (None, None)
+            LinkTargets::default()
         };
+        let filter = Arc::new(RwLock::new(filter));
+        let scan_filter = Arc::clone(&filter);
+        let shutdown_flag = Arc::new(AtomicBool::new(false));
+        let mut notifies = Vec::with_capacity(roots.len());
+        for root in roots.iter().chain(link_targets.dirs().iter()) {
+            trace!("Start watching directory: {}", root.display());
+            match Self::start_notify(root) {
+                Ok(notify) => notifies.push(notify),
+                Err(e) => warn!("Can't watch {}: {}", root.display(), e),
+            }
+        }
 
-        let notify_reciver = if let Some(nr) = notify_reciver {
-            let (s, r) = unbounded();
+        let mut threads = Vec::new();
+        let mut notify_watchers = Vec::with_capacity(notifies.len());
+        let (s, r) = unbounded();
+        for notify in notifies {
+            notify_watchers.push(notify.watcher);
+            let nr = notify.reciver;
+            let s = s.clone();
             let sfc = Arc::clone(&shutdown_flag);
-            std::thread::spawn(move || loop {
-                match nr.recv() {
+            threads.push(std::thread::spawn(move || loop {
+                match nr.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) => {
+                        if sfc.load(std::sync::atomic::Ordering::Acquire) {
+                            break;
+                        }
+                    }
                     Err(e) => {
                         if !sfc.load(std::sync::atomic::Ordering::Acquire) {
                             error!("Notify watcher trhead ended by reason: {}", e);
@@ -122,119 +663,592 @@ impl FileSystem {
                         }
                     },
                 }
-            });
-            r
-        } else {
+            }));
+        }
+        // The merged channel disconnects once every watcher thread ended.
+        drop(s);
+        let notify_reciver = if notify_watchers.is_empty() {
             never()
+        } else {
+            r
         };
 
-        let thumbs_thread_pool = ThreadPoolBuilder::new()
-            .num_threads(num_cpus::get().min(4))
-            .build()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-
-        let image_thread_pool = ThreadPoolBuilder::new()
-            .num_threads(num_cpus::get().min(4))
-            .build()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let thumbs_thread_pool = build_pool("thumbs", pool_sizes.thumb_threads, true)?;
+        let image_thread_pool = build_pool("decode", pool_sizes.decode_threads, false)?;
 
         {
             let sfc = Arc::clone(&shutdown_flag);
-            std::thread::spawn(move || {
-                let mut sel = Select::new();
-                sel.recv(&notify_reciver);
-                sel.recv(&op_receiver);
-                let rs = [&notify_reciver, &op_receiver];
-                loop {
-                    let idx = sel.ready();
-                    let res = rs[idx].try_recv();
-
-                    if let Err(e) = res {
-                        if e.is_empty() {
-                            continue;
-                        } else {
-                            if !sfc.load(std::sync::atomic::Ordering::Acquire) {
-                                error!("Internal receiver thread finished with error: {}", e);
-                            }
-                            break;
-                        }
-                    }
-                    let res = match res.unwrap() {
-                        InternalFSEvent::Notify(event) => {
-                            Self::process_notify_event(event, &fs_sender)
-                        }
-                        InternalFSEvent::Op(event) => {
-                            Self::process_operation_event(event, &fs_sender)
+            let notifier = Arc::clone(&notifier);
+            threads.push(std::thread::spawn(move || loop {
+                if sfc.load(std::sync::atomic::Ordering::Acquire) {
+                    break;
+                }
+                let res = select! {
+                    recv(notify_reciver) -> msg => msg,
+                    recv(op_receiver) -> msg => msg,
+                    default(SHUTDOWN_POLL_INTERVAL) => continue,
+                };
+                let event = match res {
+                    Ok(event) => event,
+                    Err(e) => {
+                        if !sfc.load(std::sync::atomic::Ordering::Acquire) {
+                            error!("Internal receiver thread finished with error: {}", e);
                         }
-                    };
-                    notifier();
-
-                    if let Err(_) = res {
                         break;
                     }
+                };
+                let res = match event {
+                    InternalFSEvent::Notify(event) => {
+                        let filter = filter.read().unwrap();
+                        Self::process_notify_event(event, &filter, &link_targets, &fs_sender)
+                    }
+                    InternalFSEvent::Op(event) => Self::process_operation_event(event, &fs_sender),
+                };
+                notifier();
+
+                if res.is_err() {
+                    break;
                 }
-            });
+            }));
         }
 
-        for file in files {
+        let sources = files.into_iter().map(ImageSource::File).chain(entries);
+        for source in sources {
             fs_sender_cl
-                .send(FileSystemEvent::FileEvent(FileEvent::Added(file)))
+                .send(FileSystemEvent::FileEvent(FileEvent::Added(source)))
                 .unwrap();
         }
+        Self::warn_skipped(&skipped, &fs_sender_cl);
+        if let [root] = roots.as_slice() {
+            fs_sender_cl
+                .send(FileSystemEvent::OperationEvent(
+                    OperationEvent::RootResolved(root.clone()),
+                ))
+                .unwrap();
+        }
+        // Sniffing reads every candidate, the listing doesn't wait for it.
+        if !unsniffed.is_empty() {
+            let sender = fs_sender_cl.clone();
+            image_thread_pool.spawn(move || {
+                for path in unsniffed.into_iter().filter(|p| decode::sniff_image(p)) {
+                    let source = ImageSource::File(path);
+                    if let Err(e) =
+                        sender.send(FileSystemEvent::FileEvent(FileEvent::Added(source)))
+                    {
+                        error!("Can't send sniffed image to main thread: {}", e);
+                    }
+                }
+            });
+        }
 
         Ok(Self {
             receiver: fs_receiver,
-            op_sender: op_sender,
-            thumbs_thread_pool: thumbs_thread_pool,
-            image_thread_pool: image_thread_pool,
-            notify_watcher: notify_watcher,
-            shutdown_flag: shutdown_flag,
+            partial_receiver,
+            partial_sender,
+            current: Arc::new(RwLock::new(None)),
+            notifier,
+            op_sender,
+            fs_sender: fs_sender_cl,
+            filter: scan_filter,
+            listed_roots,
+            thumbs_thread_pool,
+            image_thread_pool,
+            pool_sizes,
+            max_dimension: DEFAULT_MAX_DIMENSION,
+            max_memory: None,
+            notify_watchers,
+            shutdown_flag,
+            threads,
         })
     }
 
+    fn warn_skipped(skipped: &[PathBuf], sender: &Sender<FileSystemEvent>) {
+        if let Some(first) = skipped.first() {
+            let msg = format!(
+                "Skipped {} unreadable path(s), e.g. {}",
+                skipped.len(),
+                first.display()
+            );
+            if let Err(e) = sender.send(FileSystemEvent::Warning(msg)) {
+                error!("Can't send warning to main thread: {}", e);
+            }
+        }
+    }
+
+    /// Scans more files, folders or archives like the ones given at start.
+    /// The files given by name come first in the reported list, folders
+    /// aren't watched.
+    pub fn add_paths(&self, paths: Vec<PathBuf>) {
+        let sender = self.op_sender.clone();
+        let fs_sender = self.fs_sender.clone();
+        let filter = self.filter();
+        self.image_thread_pool.spawn(move || {
+            let mut unsniffed = Vec::new();
+            let selected = Self::select_roots_and_files(&paths, &filter, &mut unsniffed);
+            let (roots, mut files, mut skipped) = match selected {
+                Ok(selected) => selected,
+                Err(e) => {
+                    let msg = format!("Can't add files: {}", e);
+                    let _ = fs_sender.send(FileSystemEvent::Warning(msg));
+                    return;
+                }
+            };
+            files.extend(unsniffed.into_iter().filter(|p| decode::sniff_image(p)));
+            let named = paths
+                .iter()
+                .filter_map(|p| links::absolute(p).ok())
+                .filter(|p| files.contains(p))
+                .collect::<Vec<_>>();
+            let rest = files
+                .into_iter()
+                .filter(|p| !named.contains(p))
+                .collect::<Vec<_>>();
+            let entries = Self::collect_archive_entries(&paths, &mut skipped);
+            let sources = named
+                .into_iter()
+                .chain(rest)
+                .map(ImageSource::File)
+                .chain(entries)
+                .collect();
+            Self::warn_skipped(&skipped, &fs_sender);
+            if let [root] = roots.as_slice() {
+                if let Err(e) = sender.send(InternalFSEvent::root_resolved(root.clone())) {
+                    error!("Can't send root folder to main thread: {}", e);
+                }
+            }
+            if let Err(e) = sender.send(InternalFSEvent::paths_added(sources)) {
+                error!("Can't send added files to main thread: {}", e);
+            }
+        });
+    }
+
+    /// Lists the files among `paths` whose first bytes are those of an
+    /// image, they come back with `FileEvent::Created`.
+    pub fn sniff_files(&self, paths: Vec<PathBuf>) {
+        let sender = self.fs_sender.clone();
+        self.image_thread_pool.spawn(move || {
+            for path in paths.into_iter().filter(|p| decode::sniff_image(p)) {
+                if let Err(e) = sender.send(FileSystemEvent::FileEvent(FileEvent::Created(path))) {
+                    error!("Can't send sniffed image to main thread: {}", e);
+                }
+            }
+        });
+    }
+
+    pub fn filter(&self) -> ScanFilter {
+        self.filter.read().unwrap().clone()
+    }
+
+    /// Filters the scans and watcher events from now on with `filter`, and
+    /// rescans the folders listed in full. Their images come back with
+    /// `OperationEvent::Rescanned`.
+    pub fn set_filter(&self, filter: ScanFilter) {
+        *self.filter.write().unwrap() = filter.clone();
+        let sender = self.op_sender.clone();
+        let fs_sender = self.fs_sender.clone();
+        let roots = self.listed_roots.clone();
+        self.image_thread_pool.spawn(move || {
+            let mut skipped = Vec::new();
+            let mut unsniffed = Vec::new();
+            let mut files = roots
+                .iter()
+                .flat_map(|root| Self::collect_files(root, &filter, &mut skipped, &mut unsniffed))
+                .collect::<HashSet<_>>();
+            files.extend(unsniffed.into_iter().filter(|p| decode::sniff_image(p)));
+            Self::warn_skipped(&skipped, &fs_sender);
+            if let Err(e) = sender.send(InternalFSEvent::rescanned(roots, files)) {
+                error!("Can't send rescanned files to main thread: {}", e);
+            }
+        });
+    }
+
+    /// Reads the annotations saved next to the file of `source`.
+    pub fn read_annotations(&self, source: &ImageSource, path: &Path) {
+        let sender = self.op_sender.clone();
+        let source = source.clone();
+        let path = path.to_path_buf();
+        self.image_thread_pool.spawn(move || {
+            let res = read_sidecar(&path);
+            if let Err(e) = sender.send(InternalFSEvent::annotations_read(source, res)) {
+                error!("Can't send annotations to main thread: {}", e);
+            }
+        });
+    }
+
+    /// Saves `items` next to the file of `source`, they come back with
+    /// `OperationEvent::AnnotationsWritten` once written.
+    pub fn write_annotations(&self, source: &ImageSource, path: &Path, items: Vec<Annotation>) {
+        let sender = self.op_sender.clone();
+        let source = source.clone();
+        let path = path.to_path_buf();
+        self.image_thread_pool.spawn(move || {
+            let res = write_sidecar(&path, &items).map(|_| items);
+            if let Err(e) = sender.send(InternalFSEvent::annotations_written(source, res)) {
+                error!("Can't send annotations save result to main thread: {}", e);
+            }
+        });
+    }
+
+    /// Hashes the content of each file on the thumbnail pool, a file comes
+    /// back with `OperationEvent::FileHashed` once hashed. The hash given
+    /// with a file is reused when the file wasn't modified since.
+    pub fn hash_files(&self, files: Vec<(PathBuf, Option<FileHash>)>) {
+        for (path, cached) in files {
+            let sender = self.op_sender.clone();
+            self.thumbs_thread_pool.spawn(move || {
+                let res = hash_file(&path, cached);
+                if let Err(e) = sender.send(InternalFSEvent::file_hashed(path, res)) {
+                    error!("Can't send file hash to main thread: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Renames `path` to `new_name` within its folder. Success is reported
+    /// as `FileEvent::Renamed` like renames seen by the watcher, failure as
+    /// `OperationEvent::RenameFailed`.
+    pub fn rename(&self, path: &Path, new_name: &str) {
+        let sender = self.op_sender.clone();
+        let fs_sender = self.fs_sender.clone();
+        let path = path.to_path_buf();
+        let new_name = new_name.to_owned();
+        self.image_thread_pool.spawn(move || {
+            let res = rename_target(&path, &new_name)
+                .and_then(|target| std::fs::rename(&path, &target).map(|_| target));
+            if let Ok(target) = res.as_ref() {
+                let sidecar = sidecar_path(&path);
+                if sidecar.exists() {
+                    if let Err(e) = std::fs::rename(&sidecar, sidecar_path(target)) {
+                        warn!("Can't rename {}: {}", sidecar.display(), e);
+                    }
+                }
+            }
+            let sent = match res {
+                Ok(target) => {
+                    let event = FileEvent::Renamed(path, target);
+                    fs_sender.send(FileSystemEvent::FileEvent(event)).is_ok()
+                }
+                Err(e) => sender.send(InternalFSEvent::rename_failed(path, e)).is_ok(),
+            };
+            if !sent {
+                error!("Can't send rename result to main thread");
+            }
+        });
+    }
+
     pub fn read_file(&self, path: &Path) {
+        #[cfg(feature = "video")]
+        if video::is_video(path) {
+            return self.read_video_frame(path, 0.0);
+        }
         let sender = self.op_sender.clone();
+        let partial_sender = self.partial_sender.clone();
+        let current = Arc::clone(&self.current);
+        let notifier = Arc::clone(&self.notifier);
         let path = path.to_path_buf();
+        let max_dimension = self.max_dimension;
+        let max_memory = self.max_memory;
         self.image_thread_pool.spawn(move || {
-            let res = ImageReader::open(&path).and_then(|r| {
-                r.decode()
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-                    .map(|i| i.to_rgba8())
+            let started = Instant::now();
+            // Only the image shown is worth the slower progressive decode.
+            let progressive = current.read().unwrap().as_deref() == Some(path.as_path());
+            // First row the main thread hasn't got yet.
+            let mut unsent = 0;
+            let mut was_current = true;
+            let mut on_partial = |img: &RgbaImage, rows: std::ops::Range<u32>, pass| {
+                if current.read().unwrap().as_deref() != Some(path.as_path()) {
+                    was_current = false;
+                    return;
+                }
+                // The main thread dropped the rows it had when it left the image.
+                if !was_current {
+                    unsent = 0;
+                    was_current = true;
+                }
+                let top = unsent.min(rows.start);
+                let partial = PartialImage {
+                    source: ImageSource::File(path.clone()),
+                    size: img.dimensions(),
+                    top,
+                    rows: crop_imm(img, 0, top, img.width(), rows.end - top).to_image(),
+                    pass,
+                };
+                match partial_sender.try_send(partial) {
+                    Ok(_) => {
+                        unsent = rows.end;
+                        notifier();
+                    }
+                    Err(TrySendError::Full(_)) => (),
+                    Err(TrySendError::Disconnected(_)) => {
+                        error!("Can't send partial image to main thread")
+                    }
+                }
+            };
+            let res = links::resolve(&path).and_then(|target| {
+                retry_locked(&path, || {
+                    let on_partial = progressive.then_some(&mut on_partial as _);
+                    decode_pages(&target, max_dimension, max_memory, on_partial)
+                })
             });
-            match sender.send(InternalFSEvent::image_loaded(path, res)) {
+            let res = res.map(|pages| (pages, decode::probe(&path)));
+            let source = ImageSource::File(path);
+            match sender.send(InternalFSEvent::image_loaded(source, res, started)) {
                 Ok(_) => (),
                 Err(e) => error!("Can't send image to main thread: {}", e),
             }
         });
     }
 
-    pub fn shutdown(&self) {
-        self.shutdown_flag
-            .store(true, std::sync::atomic::Ordering::Release);
+    /// Decodes the image stored as `entry` of the ZIP archive at `path`.
+    pub fn read_archive_entry(&self, path: &Path, entry: &str) {
+        let sender = self.op_sender.clone();
+        let path = path.to_path_buf();
+        let entry = entry.to_string();
+        let max_dimension = self.max_dimension;
+        let max_memory = self.max_memory;
+        self.image_thread_pool.spawn(move || {
+            let started = Instant::now();
+            let res = archive::decode_entry(&path, &entry, max_dimension, max_memory)
+                .map(|(img, info)| (vec![img], info));
+            let source = ImageSource::Archive {
+                archive: path,
+                entry,
+            };
+            match sender.send(InternalFSEvent::image_loaded(source, res, started)) {
+                Ok(_) => (),
+                Err(e) => error!("Can't send image to main thread: {}", e),
+            }
+        });
+    }
+
+    /// Decodes the first page of both files of a compare pair, they become
+    /// the two pages of the loaded image.
+    pub fn read_pair(&self, a: &Path, b: &Path) {
+        let sender = self.op_sender.clone();
+        let a = a.to_path_buf();
+        let b = b.to_path_buf();
+        let max_dimension = self.max_dimension;
+        let max_memory = self.max_memory;
+        self.image_thread_pool.spawn(move || {
+            let started = Instant::now();
+            let first_page = |path: &Path| first_page(path, max_dimension, max_memory);
+            let res = first_page(&a)
+                .and_then(|img_a| Ok(vec![img_a, first_page(&b)?]))
+                .map(|pages| (pages, decode::probe(&a)));
+            let source = ImageSource::Pair { a, b };
+            match sender.send(InternalFSEvent::image_loaded(source, res, started)) {
+                Ok(_) => (),
+                Err(e) => error!("Can't send image to main thread: {}", e),
+            }
+        });
     }
 
-    fn to_thumbnail(img: RgbaImage, size: u32) -> RgbaImage {
-        let (w, h) = img.dimensions();
-        let ws = size as f32 / w as f32;
-        let hs = size as f32 / h as f32;
-        let s = ws.min(hs);
+    /// Decodes the first page of both files of each pair and reports how
+    /// much they differ as `OperationEvent::PairCompared`.
+    pub fn compare_pairs(&self, pairs: Vec<(PathBuf, PathBuf)>) {
+        let max_dimension = self.max_dimension;
+        let max_memory = self.max_memory;
+        for (a, b) in pairs {
+            let sender = self.op_sender.clone();
+            self.image_thread_pool.spawn(move || {
+                let first_page = |path: &Path| first_page(path, max_dimension, max_memory);
+                let res =
+                    first_page(&a).and_then(|img_a| report::compare(&img_a, &first_page(&b)?));
+                if let Err(e) = sender.send(InternalFSEvent::pair_compared(a, b, res)) {
+                    error!("Can't send pair stats to main thread: {}", e);
+                }
+            });
+        }
+    }
 
-        let w = (w as f32 * s).floor() as u32;
-        let h = (h as f32 * s).floor() as u32;
+    /// Decodes the video frame at `timestamp` seconds on the image pool.
+    #[cfg(feature = "video")]
+    pub fn read_video_frame(&self, path: &Path, timestamp: f64) {
+        let sender = self.op_sender.clone();
+        let path = path.to_path_buf();
+        self.image_thread_pool.spawn(move || {
+            let res = video::decode_frame(&path, timestamp);
+            match sender.send(InternalFSEvent::video_frame_loaded(path, res)) {
+                Ok(_) => (),
+                Err(e) => error!("Can't send video frame to main thread: {}", e),
+            }
+        });
+    }
 
-        image::imageops::thumbnail(&img, w, h)
+    pub fn save_image(&self, path: &Path, img: RgbaImage) {
+        let sender = self.op_sender.clone();
+        let path = path.to_path_buf();
+        self.image_thread_pool.spawn(move || {
+            let res = img.save(&path).map_err(std::io::Error::other);
+            match sender.send(InternalFSEvent::image_saved(path, res)) {
+                Ok(_) => (),
+                Err(e) => error!("Can't send save result to main thread: {}", e),
+            }
+        });
     }
 
-    pub fn read_thumbnail(&self, path: &Path, size: u32) {
+    pub fn save_montage(
+        &self,
+        path: &Path,
+        items: Vec<(String, RgbaImage)>,
+        settings: MontageSettings,
+    ) {
+        let sender = self.op_sender.clone();
         let path = path.to_path_buf();
+        self.image_thread_pool.spawn(move || {
+            let pages = settings.page_count(items.len());
+            let mut res = Ok(pages);
+            for (page, chunk) in items.chunks(settings.per_page()).enumerate() {
+                let img = render_page(chunk, &settings);
+                if let Err(e) = img.save(page_path(&path, page, pages)) {
+                    res = Err(std::io::Error::other(e));
+                    break;
+                }
+                let progress = InternalFSEvent::montage_progress(path.clone(), page + 1, pages);
+                if let Err(e) = sender.send(progress) {
+                    error!("Can't send montage progress to main thread: {}", e);
+                }
+            }
+            match sender.send(InternalFSEvent::montage_saved(path, res)) {
+                Ok(_) => (),
+                Err(e) => error!("Can't send montage result to main thread: {}", e),
+            }
+        });
+    }
+
+    pub fn estimate_alignment(&self, source: &ImageSource, one: RgbaImage, two: RgbaImage) {
+        let sender = self.op_sender.clone();
+        let source = source.clone();
+        self.image_thread_pool.spawn(move || {
+            let res = estimate_translation(&one, &two);
+            match sender.send(InternalFSEvent::alignment_estimated(source, res)) {
+                Ok(_) => (),
+                Err(e) => error!("Can't send alignment to main thread: {}", e),
+            }
+        });
+    }
+
+    /// Scans `img` for color hidden under alpha below `threshold`.
+    pub fn find_alpha_anomalies(&self, source: &ImageSource, img: RgbaImage, threshold: u8) {
+        let sender = self.op_sender.clone();
+        let source = source.clone();
+        self.image_thread_pool.spawn(move || {
+            let res = find_alpha_anomalies(&img, threshold);
+            match sender.send(InternalFSEvent::alpha_anomalies_found(source, res)) {
+                Ok(_) => (),
+                Err(e) => error!("Can't send alpha anomalies to main thread: {}", e),
+            }
+        });
+    }
+
+    /// Computes the statistics of a region cropped from the image and from
+    /// the difference shown for it.
+    pub fn compute_region_stats(
+        &self,
+        source: &ImageSource,
+        key: RegionKey,
+        img: RgbaImage,
+        diff: Option<RgbaImage>,
+    ) {
+        let sender = self.op_sender.clone();
+        let source = source.clone();
+        self.image_thread_pool.spawn(move || {
+            let stats = region_stats(key, &img, diff.as_ref());
+            match sender.send(InternalFSEvent::region_stats_computed(source, stats)) {
+                Ok(_) => (),
+                Err(e) => error!("Can't send region statistics to main thread: {}", e),
+            }
+        });
+    }
+
+    pub fn pool_sizes(&self) -> PoolSizes {
+        self.pool_sizes
+    }
+
+    /// Caps the images decoded for viewing and for thumbnails, see
+    /// `decode::decode_pages`.
+    pub fn set_max_dimension(&mut self, max_dimension: u32) {
+        self.max_dimension = max_dimension.max(1);
+    }
+
+    /// Partial images of `read_file` are only sent for `path`, the image
+    /// shown.
+    pub fn set_current(&self, path: Option<&Path>) {
+        *self.current.write().unwrap() = path.map(Path::to_path_buf);
+    }
+
+    /// Caps the memory a decode may allocate, images that need more fail
+    /// with `ImViewError::MemoryLimit`.
+    pub fn set_max_memory(&mut self, max_memory: Option<u64>) {
+        self.max_memory = max_memory;
+    }
+
+    /// Replaces the worker pools with pools of the given sizes. Jobs already
+    /// queued on the old pools still run to completion.
+    pub fn set_pool_sizes(&mut self, pool_sizes: PoolSizes) -> std::io::Result<()> {
+        if pool_sizes == self.pool_sizes {
+            return Ok(());
+        }
+        self.thumbs_thread_pool = build_pool("thumbs", pool_sizes.thumb_threads, true)?;
+        self.image_thread_pool = build_pool("decode", pool_sizes.decode_threads, false)?;
+        self.pool_sizes = pool_sizes;
+        Ok(())
+    }
+
+    /// Stops the background threads and waits up to `SHUTDOWN_TIMEOUT` for
+    /// them to finish, threads still running after that are left detached.
+    pub fn shutdown(&mut self) {
+        self.shutdown_flag
+            .store(true, std::sync::atomic::Ordering::Release);
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        for handle in self.threads.drain(..) {
+            while !handle.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            if !handle.is_finished() {
+                trace!("Filesystem thread did not stop in time");
+            } else if handle.join().is_err() {
+                error!("Filesystem thread panicked");
+            }
+        }
+    }
+
+    /// Loads the thumbnail of a file or archive entry, in-memory images
+    /// have no source to load it from and are skipped.
+    pub fn read_thumbnail(&self, source: &ImageSource, size: u32, scaling: ThumbnailScaling) {
+        if let ImageSource::Memory { .. } = source {
+            return;
+        }
+        let source = source.clone();
         let sender = self.op_sender.clone();
+        let max_dimension = self.max_dimension;
+        let max_memory = self.max_memory;
+        // Images `read_file` would refuse aren't decoded for a thumbnail
+        // either, their header decides before anything is allocated.
+        let thumbnail = move |path: &Path| {
+            decode::check_pixel_budget(path, max_dimension, max_memory)?;
+            decode_thumbnail(path, size, scaling)
+        };
         self.thumbs_thread_pool.spawn(move || {
-            let res = ImageReader::open(&path).and_then(|r| {
-                r.decode()
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
-                    .map(|i| Self::to_thumbnail(i.to_rgba8(), size))
-            });
-            match sender.send(InternalFSEvent::thumbnail_loaded(path, res)) {
+            let started = Instant::now();
+            let res = match &source {
+                #[cfg(feature = "video")]
+                ImageSource::File(path) if video::is_video(path) => {
+                    video::decode_thumbnail(path, size, scaling)
+                }
+                ImageSource::File(path) | ImageSource::Pair { a: path, .. } => links::resolve(path)
+                    .and_then(|target| retry_locked(path, || thumbnail(&target))),
+                ImageSource::Archive { archive, entry } => archive::decode_thumbnail(
+                    archive,
+                    entry,
+                    size,
+                    scaling,
+                    max_dimension,
+                    max_memory,
+                ),
+                ImageSource::Memory { .. } => unreachable!(),
+            };
+            match sender.send(InternalFSEvent::thumbnail_loaded(source, res, started)) {
                 Ok(_) => (),
                 Err(err) => error!("Can't send thumbnail to main thread: {}", err),
             }
@@ -243,31 +1257,46 @@ impl FileSystem {
 
     fn process_notify_event(
         event: DebouncedEvent,
+        filter: &ScanFilter,
+        link_targets: &LinkTargets,
         sender: &Sender<FileSystemEvent>,
-    ) -> Result<(), crossbeam::channel::SendError<FileSystemEvent>> {
-        let event = match event {
-            DebouncedEvent::Create(path) => {
-                if is_image(&path) {
-                    Some(FileEvent::Added(path))
-                } else {
-                    None
-                }
-            }
-            DebouncedEvent::Write(path) => {
-                if is_image(&path) {
-                    Some(FileEvent::Modified(path))
-                } else {
-                    None
-                }
+    ) -> Result<(), Box<crossbeam::channel::SendError<FileSystemEvent>>> {
+        if let Some(links) = link_targets.links_of(&event) {
+            for link in links {
+                sender.send(FileSystemEvent::FileEvent(FileEvent::Modified(link)))?;
             }
+            return Ok(());
+        }
+        // Names that don't tell are sniffed by a worker, not on this thread.
+        let event = match event {
+            DebouncedEvent::Create(path) => match filter.check(&path) {
+                NameCheck::Image => Some(FileEvent::Created(path)),
+                NameCheck::Unknown => Some(FileEvent::Unsniffed(path)),
+                NameCheck::NotImage => None,
+            },
+            DebouncedEvent::Write(path) => match filter.check(&path) {
+                NameCheck::Image => Some(FileEvent::Modified(path)),
+                NameCheck::Unknown => Some(FileEvent::Unsniffed(path)),
+                NameCheck::NotImage => None,
+            },
             DebouncedEvent::Remove(path) => Some(FileEvent::Removed(path)),
             DebouncedEvent::Rename(old_path, new_path) => {
-                Some(FileEvent::Renamed(old_path, new_path))
+                match (filter.accepts(&old_path), filter.check(&new_path)) {
+                    (true, NameCheck::Image | NameCheck::Unknown) => {
+                        Some(FileEvent::Renamed(old_path, new_path))
+                    }
+                    (true, NameCheck::NotImage) => Some(FileEvent::Removed(old_path)),
+                    (false, NameCheck::Image) => Some(FileEvent::Created(new_path)),
+                    (false, NameCheck::Unknown) => Some(FileEvent::Unsniffed(new_path)),
+                    (false, NameCheck::NotImage) => None,
+                }
             }
             _ => None,
         };
         if let Some(event) = event {
-            sender.send(FileSystemEvent::FileEvent(event))
+            sender
+                .send(FileSystemEvent::FileEvent(event))
+                .map_err(Box::new)
         } else {
             Ok(())
         }
@@ -276,8 +1305,10 @@ impl FileSystem {
     fn process_operation_event(
         event: OperationEvent,
         sender: &Sender<FileSystemEvent>,
-    ) -> Result<(), crossbeam::channel::SendError<FileSystemEvent>> {
-        sender.send(FileSystemEvent::OperationEvent(event))
+    ) -> Result<(), Box<crossbeam::channel::SendError<FileSystemEvent>>> {
+        sender
+            .send(FileSystemEvent::OperationEvent(event))
+            .map_err(Box::new)
     }
 
     fn start_notify(dir: &PathBuf) -> std::io::Result<Notify> {
@@ -297,54 +1328,126 @@ impl FileSystem {
         let mut files = Vec::with_capacity(paths.len());
         let mut dirs = Vec::with_capacity(paths.len());
         for path in paths {
-            if path.is_file() {
-                files.push(path);
-            } else if path.is_dir() {
+            if path.is_dir() {
                 dirs.push(path);
+            } else if path.is_file() || links::is_link(&path) {
+                files.push(path);
             }
         }
         (files, dirs)
     }
 
-    fn collect_files(dir: &PathBuf) -> std::io::Result<Vec<PathBuf>> {
+    /// Collects the images in `dir`. Unreadable directories and entries are
+    /// logged, added to `skipped` and left out instead of failing the scan.
+    /// Files whose names don't tell go to `unsniffed` for the content to
+    /// decide.
+    fn collect_files(
+        dir: &PathBuf,
+        filter: &ScanFilter,
+        skipped: &mut Vec<PathBuf>,
+        unsniffed: &mut Vec<PathBuf>,
+    ) -> Vec<PathBuf> {
         let mut files = Vec::new();
-        let entries = std::fs::read_dir(dir)?;
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Can't read directory {}: {}", dir.display(), e);
+                skipped.push(dir.clone());
+                return files;
+            }
+        };
         for entry in entries {
-            let entry = entry?;
-            let path = entry.path().canonicalize()?;
-            if path.is_file() && is_image(&path) {
-                files.push(path);
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Can't read entry in {}: {}", dir.display(), e);
+                    skipped.push(dir.clone());
+                    continue;
+                }
+            };
+            // Links stay links, broken ones too so they show why they fail.
+            let path = dir.join(entry.file_name());
+            let check = filter.check(&path);
+            if check == NameCheck::NotImage {
+                continue;
+            }
+            if path.is_file() || (links::is_link(&path) && !path.is_dir()) {
+                match check {
+                    NameCheck::Image => files.push(path),
+                    _ => unsniffed.push(path),
+                }
             }
         }
-        Ok(files)
+        files
     }
 
-    fn select_root_and_files(
-        paths: &Vec<PathBuf>,
-    ) -> std::io::Result<(Option<PathBuf>, HashSet<PathBuf>)> {
-        if paths.len() == 0 {
-            return Ok((None, HashSet::new()));
+    /// Lists the images inside the archives among `paths`. Archives are only
+    /// opened when passed explicitly and aren't watched for changes.
+    fn collect_archive_entries(paths: &[PathBuf], skipped: &mut Vec<PathBuf>) -> Vec<ImageSource> {
+        let mut entries = Vec::new();
+        let archives = paths
+            .iter()
+            .filter(|p| archive::is_archive(p))
+            .filter_map(|p| p.canonicalize().ok())
+            .filter(|p| p.is_file());
+        for path in archives {
+            match archive::list_images(&path) {
+                Ok(names) => entries.extend(names.into_iter().map(|entry| ImageSource::Archive {
+                    archive: path.clone(),
+                    entry,
+                })),
+                Err(e) => {
+                    warn!("Can't read archive {}: {}", path.display(), e);
+                    skipped.push(path.clone());
+                }
+            }
         }
+        entries
+    }
 
-        let (files, dirs) = Self::drain_files_dirs(
-            paths
-                .iter()
-                .map(|p| p.canonicalize())
-                .collect::<Result<Vec<_>, _>>()?,
-        );
+    /// Image files among `paths` and in the folders of `paths`, the folders
+    /// the files live in as the roots to watch, and the unreadable paths. A
+    /// single root is listed in full, siblings of the named files included.
+    /// The files left to sniff go to `unsniffed`, named ones always do.
+    fn select_roots_and_files(
+        paths: &[PathBuf],
+        filter: &ScanFilter,
+        unsniffed: &mut Vec<PathBuf>,
+    ) -> std::io::Result<(Vec<PathBuf>, HashSet<PathBuf>, Vec<PathBuf>)> {
+        let mut skipped = Vec::new();
+        if paths.is_empty() {
+            return Ok((Vec::new(), HashSet::new(), skipped));
+        }
 
-        let mut files = files
-            .into_iter()
-            .filter(|p| is_image(&p))
+        let paths = paths
+            .iter()
+            .filter_map(|p| match links::absolute(p) {
+                Ok(path) => Some(path),
+                Err(e) => {
+                    warn!("Can't open {}: {}", p.display(), e);
+                    skipped.push(p.clone());
+                    None
+                }
+            })
             .collect::<Vec<_>>();
+        let (named, dirs) = Self::drain_files_dirs(paths);
+
+        let mut files = Vec::with_capacity(named.len());
+        for path in named {
+            match check_name(&path) {
+                NameCheck::Image => files.push(path),
+                NameCheck::Unknown => unsniffed.push(path),
+                NameCheck::NotImage => (),
+            }
+        }
 
         for dir in dirs.iter() {
-            let new_files = Self::collect_files(&dir)?;
+            let new_files = Self::collect_files(dir, filter, &mut skipped, unsniffed);
             files.extend(new_files);
         }
 
-        let mut dirs: HashSet<PathBuf> = HashSet::from_iter(dirs.into_iter());
-        for file in files.iter() {
+        let mut dirs: HashSet<PathBuf> = HashSet::from_iter(dirs);
+        for file in files.iter().chain(unsniffed.iter()) {
             if let Some(parent) = file.parent() {
                 dirs.insert(parent.to_path_buf());
             }
@@ -352,18 +1455,129 @@ impl FileSystem {
 
         if dirs.len() == 1 {
             for dir in dirs.iter() {
-                let new_files = Self::collect_files(&dir)?;
+                let new_files = Self::collect_files(dir, filter, &mut skipped, unsniffed);
                 files.extend(new_files);
             }
         }
 
         let files = HashSet::from_iter(files);
+        unsniffed.sort();
+        unsniffed.dedup();
 
-        let dirs = if dirs.len() == 1 {
-            dirs.into_iter().next()
-        } else {
-            None
-        };
-        Ok((dirs, files))
+        let mut dirs = dirs.into_iter().collect::<Vec<_>>();
+        dirs.sort();
+        skipped.sort();
+        skipped.dedup();
+        Ok((dirs, files, skipped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::temp_path;
+    use std::fs::File;
+
+    /// Opens `path` the way a program writing it would, Windows then
+    /// refuses other reads of it.
+    fn hold(path: &Path) -> File {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true);
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            options.share_mode(0);
+        }
+        options.open(path).unwrap()
+    }
+
+    #[test]
+    fn retry_locked_reads_a_file_once_it_is_released() {
+        let path = temp_path("held");
+        std::fs::write(&path, b"image").unwrap();
+        let held = hold(&path);
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(LOCKED_RETRY_DELAY * 2);
+            drop(held);
+        });
+        let mut reads = 0;
+        let res = retry_locked(&path, || {
+            reads += 1;
+            std::fs::read(&path)
+        });
+        release.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(res.unwrap(), b"image");
+        // Only Windows locks files open for writing.
+        assert!(cfg!(windows) == (reads > 1), "{} reads", reads);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn file_that_stays_held_is_locked() {
+        let path = temp_path("locked");
+        std::fs::write(&path, b"image").unwrap();
+        let held = hold(&path);
+        let res = retry_locked(&path, || std::fs::read(&path));
+        drop(held);
+        std::fs::remove_file(&path).unwrap();
+        let err = ImViewError::from(&res.unwrap_err());
+        assert!(matches!(err, ImViewError::Locked(_)), "{:?}", err);
+    }
+
+    #[test]
+    fn retry_locked_passes_other_errors_on() {
+        let path = temp_path("missing");
+        let mut reads = 0;
+        let res = retry_locked(&path, || {
+            reads += 1;
+            std::fs::read(&path)
+        });
+        assert_eq!(reads, 1);
+        assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn first_pages_are_read_through_links() {
+        let (path, link) = (temp_path("pair.png"), temp_path("pair-link.png"));
+        RgbaImage::new(3, 2).save(&path).unwrap();
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        let page = first_page(&link, 1024, None);
+        std::fs::remove_file(&path).unwrap();
+        let broken = first_page(&link, 1024, None);
+        std::fs::remove_file(&link).unwrap();
+        assert_eq!(page.unwrap().dimensions(), (3, 2));
+        let err = broken.unwrap_err().to_string();
+        assert!(err.contains("pair.png"), "{}", err);
+    }
+
+    #[test]
+    fn image_names_are_checked_ignoring_case() {
+        for name in ["a.png", "a.PNG", "b.jpeg", "b.JPG", "c.tIf", "d.webp"] {
+            assert_eq!(check_name(Path::new(name)), NameCheck::Image, "{}", name);
+        }
+        for name in [
+            "a.webp.part",
+            "a.PNG.crdownload",
+            "notes.txt",
+            "a.png.txt.bak",
+        ] {
+            assert_eq!(check_name(Path::new(name)), NameCheck::NotImage, "{}", name);
+        }
+        for name in ["scan", "a.png.bak", "a.JPEG.old"] {
+            assert_eq!(check_name(Path::new(name)), NameCheck::Unknown, "{}", name);
+        }
+    }
+
+    #[test]
+    fn unknown_names_need_content_sniffing() {
+        let mut filter = ScanFilter::new();
+        assert_eq!(filter.check(Path::new("scan")), NameCheck::Unknown);
+        assert_eq!(filter.check(Path::new("Thumbs.db")), NameCheck::NotImage);
+        assert_eq!(filter.check(Path::new(".hidden.png")), NameCheck::NotImage);
+        filter.sniff_content = false;
+        assert_eq!(filter.check(Path::new("scan")), NameCheck::NotImage);
+        assert_eq!(filter.check(Path::new("a.png")), NameCheck::Image);
     }
 }
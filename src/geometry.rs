@@ -0,0 +1,458 @@
+use crate::image_ui_state::{DiffMode, ImageUIState};
+use arrayvec::ArrayVec;
+use eframe::egui::*;
+
+/// Maps between image pixel coordinates and screen coordinates of a view
+/// that shows the `uv` part of an image of `image_size` inside `rect`.
+#[derive(Clone, Copy, Debug)]
+pub struct ViewTransform {
+    pub rect: Rect,
+    pub uv: Rect,
+    pub image_size: Vec2,
+}
+
+impl ViewTransform {
+    pub fn new(rect: Rect, uv: Rect, image_size: Vec2) -> Self {
+        Self {
+            rect,
+            uv,
+            image_size,
+        }
+    }
+
+    /// Screen points per image pixel along each axis.
+    pub fn pixel_size(&self) -> Vec2 {
+        vec2(
+            self.rect.width() / (self.uv.width() * self.image_size.x),
+            self.rect.height() / (self.uv.height() * self.image_size.y),
+        )
+    }
+
+    pub fn to_screen(self, image_pos: Pos2) -> Pos2 {
+        let u = pos2(
+            image_pos.x / self.image_size.x,
+            image_pos.y / self.image_size.y,
+        );
+        pos2(
+            self.rect.left() + (u.x - self.uv.left()) / self.uv.width() * self.rect.width(),
+            self.rect.top() + (u.y - self.uv.top()) / self.uv.height() * self.rect.height(),
+        )
+    }
+
+    pub fn to_image(self, screen_pos: Pos2) -> Pos2 {
+        let u = pos2(
+            self.uv.left()
+                + (screen_pos.x - self.rect.left()) / self.rect.width() * self.uv.width(),
+            self.uv.top()
+                + (screen_pos.y - self.rect.top()) / self.rect.height() * self.uv.height(),
+        );
+        pos2(u.x * self.image_size.x, u.y * self.image_size.y)
+    }
+}
+
+/// Screen and texture rectangles of the parts of an image a view draws.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ViewLayout {
+    /// Fit scale in screen points per image pixel.
+    pub scale: f32,
+    /// Texture coordinates of each drawn part.
+    pub uvs: ArrayVec<Rect, 3>,
+    /// Screen rectangle of each drawn part, in the order of `uvs`.
+    pub rects: ArrayVec<Rect, 3>,
+}
+
+/// Size of one compared part of an image of `image_size`, split modes show
+/// half of the image on each side of the seam.
+pub fn part_size(mode: DiffMode, image_size: Vec2) -> Vec2 {
+    match mode {
+        DiffMode::VSplit | DiffMode::VColorDiff | DiffMode::Triptych => {
+            vec2(image_size.x * 0.5, image_size.y)
+        }
+        DiffMode::HSplit | DiffMode::HColorDiff => vec2(image_size.x, image_size.y * 0.5),
+        DiffMode::Full | DiffMode::PageDiff | DiffMode::PreviousDiff | DiffMode::ReferenceDiff => {
+            image_size
+        }
+    }
+}
+
+/// Room for one part in a view of `view_size`, the triptych puts three
+/// parts in a row.
+pub fn part_view_size(mode: DiffMode, view_size: Vec2) -> Vec2 {
+    match mode {
+        DiffMode::Triptych => vec2(view_size.x / 3.0, view_size.y),
+        _ => view_size,
+    }
+}
+
+/// Outlines of the shown part of each compared half on a preview of the
+/// whole image drawn in `in_rect`.
+pub fn view_part_rects(state: &ImageUIState, in_rect: Rect) -> ArrayVec<Rect, 3> {
+    let uv = state.uv_full();
+    match state.diff_mode {
+        DiffMode::Full | DiffMode::PageDiff | DiffMode::PreviousDiff | DiffMode::ReferenceDiff => {
+            let mut r = ArrayVec::new();
+            let size = vec2(in_rect.width() * uv.width(), in_rect.height() * uv.height());
+            let center = pos2(
+                in_rect.left() + in_rect.width() * uv.center().x,
+                in_rect.top() + in_rect.height() * uv.center().y,
+            );
+            r.push(Rect::from_center_size(center, size));
+            r
+        }
+        DiffMode::VSplit | DiffMode::VColorDiff | DiffMode::Triptych => {
+            let mut r = ArrayVec::new();
+            let size = vec2(
+                in_rect.width() / 2.0 * uv.width(),
+                in_rect.height() * uv.height(),
+            );
+            let top = in_rect.top() + in_rect.height() * uv.center().y;
+            let left = in_rect.width() / 2.0 * uv.center().x;
+            let center_l = pos2(in_rect.left() + left, top);
+            let center_r = pos2((in_rect.left() + in_rect.right()) / 2.0 + left, top);
+            r.push(Rect::from_center_size(center_l, size));
+            r.push(Rect::from_center_size(center_r, size));
+            r
+        }
+        DiffMode::HSplit | DiffMode::HColorDiff => {
+            let mut r = ArrayVec::new();
+            let size = vec2(
+                in_rect.width() * uv.width(),
+                in_rect.height() / 2.0 * uv.height(),
+            );
+            let left = in_rect.left() + in_rect.width() * uv.center().x;
+            let top = in_rect.height() / 2.0 * uv.center().y;
+            let center_l = pos2(left, in_rect.top() + top);
+            let center_r = pos2(left, (in_rect.top() + in_rect.bottom()) / 2.0 + top);
+            r.push(Rect::from_center_size(center_l, size));
+            r.push(Rect::from_center_size(center_r, size));
+            r
+        }
+    }
+}
+
+/// Largest scale, at most `max_scale`, that fits `part` into `view_size`.
+/// Degenerate sizes give 1.0.
+pub fn fit_scale(part: Vec2, view_size: Vec2, max_scale: f32) -> f32 {
+    if part.x <= 0.0 || part.y <= 0.0 {
+        return 1.0;
+    }
+    let scale = (view_size.x / part.x)
+        .min(view_size.y / part.y)
+        .min(max_scale);
+    if scale.is_finite() && scale > 0.0 {
+        scale
+    } else {
+        1.0
+    }
+}
+
+/// Scales along each axis that stretch `part` over `view_size`, ignoring
+/// its aspect ratio. Degenerate sizes give 1.0.
+pub fn fill_scale(part: Vec2, view_size: Vec2) -> Vec2 {
+    let fill = |part: f32, view: f32| {
+        let scale = view / part;
+        if part > 0.0 && scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            1.0
+        }
+    };
+    vec2(fill(part.x, view_size.x), fill(part.y, view_size.y))
+}
+
+/// Screen sizes of the drawn parts when `part` is shown at `scale` along
+/// each axis.
+pub fn display_sizes(state: &ImageUIState, part: Vec2, scale: Vec2) -> ArrayVec<Vec2, 3> {
+    let (w, h) = (part.x * scale.x, part.y * scale.y);
+    let mut r = ArrayVec::new();
+    match state.diff_mode {
+        DiffMode::Full
+        | DiffMode::VColorDiff
+        | DiffMode::HColorDiff
+        | DiffMode::PageDiff
+        | DiffMode::PreviousDiff
+        | DiffMode::ReferenceDiff => {
+            r.push(vec2(w, h));
+        }
+        DiffMode::VSplit => {
+            r.push(vec2(w * state.vsplit_factor, h));
+            r.push(vec2(w * (1.0 - state.vsplit_factor), h));
+        }
+        DiffMode::HSplit => {
+            r.push(vec2(w, h * state.hsplit_factor));
+            r.push(vec2(w, h * (1.0 - state.hsplit_factor)));
+        }
+        DiffMode::Triptych => {
+            r.extend([vec2(w, h); 3]);
+        }
+    }
+    r
+}
+
+/// Texture coordinates of the drawn parts for the zoom and pan of `state`.
+pub fn view_uvs(state: &ImageUIState) -> ArrayVec<Rect, 3> {
+    match state.diff_mode {
+        DiffMode::Full
+        | DiffMode::VColorDiff
+        | DiffMode::HColorDiff
+        | DiffMode::PageDiff
+        | DiffMode::PreviousDiff
+        | DiffMode::ReferenceDiff => {
+            let mut r = ArrayVec::new();
+            r.push(state.uv_full());
+            r
+        }
+        DiffMode::VSplit => state.uv_vsplit(state.vsplit_factor).into_iter().collect(),
+        DiffMode::HSplit => state.uv_hsplit(state.hsplit_factor).into_iter().collect(),
+        DiffMode::Triptych => {
+            let [left, right] = state.uv_halves();
+            ArrayVec::from([left, right, state.uv_full()])
+        }
+    }
+}
+
+/// Total size of the drawn parts, split and triptych parts are placed side
+/// by side.
+pub fn total_size(mode: DiffMode, sizes: &[Vec2]) -> Vec2 {
+    match mode {
+        DiffMode::Full
+        | DiffMode::VColorDiff
+        | DiffMode::HColorDiff
+        | DiffMode::PageDiff
+        | DiffMode::PreviousDiff
+        | DiffMode::ReferenceDiff => sizes[0],
+        DiffMode::VSplit | DiffMode::Triptych => vec2(sizes.iter().map(|s| s.x).sum(), sizes[0].y),
+        DiffMode::HSplit => vec2(sizes[0].x, sizes[0].y + sizes[1].y),
+    }
+}
+
+/// Point of the view space `state` zooms and pans in that lies under the
+/// screen position `pos`. `parts` are screen rectangles that each show the
+/// whole view, the one containing `pos` is used, else the first.
+pub fn view_point(state: &ImageUIState, parts: &[Rect], pos: Pos2) -> Option<Pos2> {
+    let part = parts
+        .iter()
+        .find(|r| r.contains(pos))
+        .or_else(|| parts.first())?;
+    if part.width() <= 0.0 || part.height() <= 0.0 {
+        return None;
+    }
+    let f = (pos - part.min) / part.size();
+    let uv = state.uv_full();
+    Some(uv.min + f * uv.size())
+}
+
+/// Splits `rect`, which has the total size of `sizes`, into the screen
+/// rectangles of the drawn parts.
+pub fn mesh_rects(mode: DiffMode, rect: Rect, sizes: &[Vec2]) -> ArrayVec<Rect, 3> {
+    let mut result = ArrayVec::new();
+    match mode {
+        DiffMode::Full
+        | DiffMode::HColorDiff
+        | DiffMode::VColorDiff
+        | DiffMode::PageDiff
+        | DiffMode::PreviousDiff
+        | DiffMode::ReferenceDiff => {
+            result.push(rect);
+        }
+        DiffMode::VSplit => {
+            let seam = rect.left() + sizes[0].x;
+            result.push(Rect::from_min_max(rect.min, pos2(seam, rect.bottom())));
+            result.push(Rect::from_min_max(pos2(seam, rect.top()), rect.max));
+        }
+        DiffMode::HSplit => {
+            let seam = rect.top() + sizes[0].y;
+            result.push(Rect::from_min_max(rect.min, pos2(rect.right(), seam)));
+            result.push(Rect::from_min_max(pos2(rect.left(), seam), rect.max));
+        }
+        DiffMode::Triptych => {
+            let mut left = rect.left();
+            for size in sizes {
+                let right = left + size.x;
+                result.push(Rect::from_min_max(
+                    pos2(left, rect.top()),
+                    pos2(right, rect.bottom()),
+                ));
+                left = right;
+            }
+        }
+    }
+    result
+}
+
+/// Computes what a view of `view_rect` draws for `state` and an image of
+/// `image_size` without touching the GPU. The parts are centered in the
+/// view and fitted at no more than `max_scale`.
+#[allow(dead_code)]
+pub fn view_layout(
+    state: &ImageUIState,
+    image_size: Vec2,
+    view_rect: Rect,
+    max_scale: f32,
+) -> ViewLayout {
+    let part = part_size(state.diff_mode, image_size);
+    let part_view = part_view_size(state.diff_mode, view_rect.size());
+    let scale = fit_scale(part, part_view, max_scale);
+    let sizes = display_sizes(state, part, Vec2::splat(scale));
+    let rect = Rect::from_center_size(view_rect.center(), total_size(state.diff_mode, &sizes));
+    ViewLayout {
+        scale,
+        uvs: view_uvs(state),
+        rects: mesh_rects(state.diff_mode, rect, &sizes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-4;
+
+    fn assert_rect_eq(a: Rect, b: Rect) {
+        assert!(
+            (a.min - b.min).length() < EPS && (a.max - b.max).length() < EPS,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    fn view() -> Rect {
+        Rect::from_min_size(Pos2::ZERO, vec2(400.0, 200.0))
+    }
+
+    #[test]
+    fn fully_zoomed_out_fills_the_view() {
+        let state = ImageUIState::new();
+        let layout = view_layout(&state, vec2(200.0, 100.0), view(), 10.0);
+        assert_eq!(layout.scale, 2.0);
+        assert_rect_eq(
+            layout.uvs[0],
+            Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0)),
+        );
+        assert_rect_eq(layout.rects[0], view());
+
+        // Small images aren't blown up beyond the largest scale.
+        let layout = view_layout(&state, vec2(20.0, 10.0), view(), 1.0);
+        assert_eq!(layout.scale, 1.0);
+        assert_rect_eq(
+            layout.rects[0],
+            Rect::from_center_size(view().center(), vec2(20.0, 10.0)),
+        );
+    }
+
+    #[test]
+    fn extreme_pan_stops_at_the_edge() {
+        let mut state = ImageUIState::new();
+        state.restore_view(Some(ImageUIState::ZOOM_MIN), pos2(-5.0, 7.0));
+        let layout = view_layout(&state, vec2(200.0, 100.0), view(), 10.0);
+        let uv = layout.uvs[0];
+        assert!((uv.left() - 0.0).abs() < EPS && (uv.bottom() - 1.0).abs() < EPS);
+        assert!((uv.width() - ImageUIState::ZOOM_MIN).abs() < EPS);
+        assert!((uv.height() - ImageUIState::ZOOM_MIN).abs() < EPS);
+        assert_rect_eq(layout.rects[0], view());
+    }
+
+    #[test]
+    fn split_factors_at_the_ends_give_one_empty_part() {
+        for mode in [DiffMode::VSplit, DiffMode::HSplit] {
+            for factor in [0.0, 1.0] {
+                let mut state = ImageUIState::new();
+                state.diff_mode = mode;
+                state.vsplit_factor = factor;
+                state.hsplit_factor = factor;
+                let layout = view_layout(&state, vec2(200.0, 100.0), view(), 10.0);
+                let (a, b) = (layout.rects[0], layout.rects[1]);
+                let [ua, ub] = [layout.uvs[0], layout.uvs[1]];
+                let empty = if factor == 0.0 { (a, ua) } else { (b, ub) };
+                let (full, full_uv) = if factor == 0.0 { (b, ub) } else { (a, ua) };
+                assert_eq!(layout.scale, 2.0);
+                match mode {
+                    DiffMode::VSplit => {
+                        assert!(empty.0.width().abs() < EPS && empty.1.width().abs() < EPS);
+                        assert!((full.width() - 200.0).abs() < EPS);
+                        assert!((full_uv.width() - 0.5).abs() < EPS);
+                        assert!((a.right() - b.left()).abs() < EPS);
+                    }
+                    _ => {
+                        assert!(empty.0.height().abs() < EPS && empty.1.height().abs() < EPS);
+                        assert!((full.height() - 100.0).abs() < EPS);
+                        assert!((full_uv.height() - 0.5).abs() < EPS);
+                        assert!((a.bottom() - b.top()).abs() < EPS);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn triptych_puts_the_halves_and_the_whole_in_a_row() {
+        let mut state = ImageUIState::new();
+        state.diff_mode = DiffMode::Triptych;
+        let view = Rect::from_min_size(Pos2::ZERO, vec2(600.0, 100.0));
+        let layout = view_layout(&state, vec2(200.0, 100.0), view, 10.0);
+        assert_eq!(layout.scale, 1.0);
+        for (i, rect) in layout.rects.iter().enumerate() {
+            let left = 150.0 + 100.0 * i as f32;
+            assert_rect_eq(
+                *rect,
+                Rect::from_min_size(pos2(left, 0.0), vec2(100.0, 100.0)),
+            );
+        }
+        assert_rect_eq(
+            layout.uvs[0],
+            Rect::from_min_max(Pos2::ZERO, pos2(0.5, 1.0)),
+        );
+        assert_rect_eq(
+            layout.uvs[1],
+            Rect::from_min_max(pos2(0.5, 0.0), pos2(1.0, 1.0)),
+        );
+        assert_rect_eq(
+            layout.uvs[2],
+            Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0)),
+        );
+    }
+
+    #[test]
+    fn zoomed_triptych_shares_the_view_between_the_panels() {
+        let mut state = ImageUIState::new();
+        state.diff_mode = DiffMode::Triptych;
+        state.restore_view(Some(0.25), pos2(0.3, 0.6));
+        let view = Rect::from_min_size(pos2(10.0, 20.0), vec2(300.0, 50.0));
+        let layout = view_layout(&state, vec2(200.0, 100.0), view, 10.0);
+        assert_eq!(layout.rects.len(), 3);
+        assert_eq!(layout.uvs.len(), 3);
+        for pair in layout.rects.windows(2) {
+            assert!((pair[0].right() - pair[1].left()).abs() < EPS);
+            assert!((pair[0].size() - pair[1].size()).length() < EPS);
+        }
+        let full = state.uv_full();
+        let in_half = |r: Rect, offset: f32| {
+            Rect::from_min_max(
+                pos2((r.min.x - offset) * 2.0, r.min.y),
+                pos2((r.max.x - offset) * 2.0, r.max.y),
+            )
+        };
+        assert_rect_eq(in_half(layout.uvs[0], 0.0), full);
+        assert_rect_eq(in_half(layout.uvs[1], 0.5), full);
+        assert_rect_eq(layout.uvs[2], full);
+
+        // The preview outlines the window in both halves.
+        let preview = Rect::from_min_size(Pos2::ZERO, vec2(200.0, 100.0));
+        let outlines = view_part_rects(&state, preview);
+        assert_eq!(outlines.len(), 2);
+        assert!((outlines[1].center().x - outlines[0].center().x - 100.0).abs() < EPS);
+    }
+
+    #[test]
+    fn empty_image_gives_finite_geometry() {
+        let mut state = ImageUIState::new();
+        state.diff_mode = DiffMode::VSplit;
+        let layout = view_layout(&state, Vec2::ZERO, view(), 10.0);
+        assert_eq!(layout.scale, 1.0);
+        for rect in layout.rects {
+            assert!(rect.min.is_finite() && rect.max.is_finite(), "{:?}", rect);
+        }
+    }
+}
@@ -1,66 +1,437 @@
-use crate::image_ui_state::DiffMode;
-use crate::utils::make_color_image;
+use crate::alignment::{apply_translation, Alignment, AlignmentStatus};
+use crate::alpha_anomalies::AlphaAnomalies;
+use crate::decode::SourceInfo;
+use crate::delta_e::{delta_e_image, DeltaEStats};
+use crate::diff_regions::find_diff_regions;
+use crate::error::ImViewError;
+use crate::image_source::ImageSource;
+use crate::image_ui_state::{
+    Adjustments, AlphaMode, DiffMetric, DiffMode, Edit, EditHistory, ImageUIState, ProfileLine,
+    SeamGuide, TextureFilter,
+};
+use crate::pixel_ops::{self, AlphaStats, Colormap, ScalarStats};
+use crate::reference::Reference;
+use crate::region_stats::{RegionKey, RegionStats};
+use crate::utils::{make_color_image, mip_chain};
 use eframe::egui::*;
-use image::imageops::crop_imm;
-use image::RgbaImage;
-use std::path::Path;
+use image::imageops::{
+    crop_imm, flip_horizontal, flip_vertical, resize, rotate270, rotate90, FilterType,
+};
+use image::{Rgba, RgbaImage};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Maximal number of transforms kept for undo.
+const MAX_UNDO_DEPTH: usize = 32;
+
+/// Channel difference above which a pixel counts as changed when looking
+/// for the changed regions.
+const DIFF_REGION_THRESHOLD: u8 = 8;
+
+/// Number of images made so far, it tells apart the textures of an image
+/// reloaded from the same source.
+static TEXTURE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Prefix of the texture names of a new image of `source`.
+fn texture_base_name(source: &ImageSource) -> String {
+    let generation = TEXTURE_GENERATION.fetch_add(1, Ordering::Relaxed);
+    format!("{}#{}", source, generation)
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Transform {
+    RotateLeft,
+    RotateRight,
+    FlipHorizontal,
+    FlipVertical,
+}
+
+impl Transform {
+    fn apply(&self, img: &RgbaImage) -> RgbaImage {
+        match self {
+            Transform::RotateLeft => rotate270(img),
+            Transform::RotateRight => rotate90(img),
+            Transform::FlipHorizontal => flip_horizontal(img),
+            Transform::FlipVertical => flip_vertical(img),
+        }
+    }
+}
+
+/// Position of the frame shown from a video, all values in seconds except
+/// the frame rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VideoPosition {
+    pub timestamp: f64,
+    pub duration: f64,
+    pub frame_rate: f64,
+}
+
+/// How the shown image is made from the image data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DisplayMapping {
+    unpremultiply: bool,
+    adjustments: Adjustments,
+    /// Colormap and the value range spread over it.
+    colormap: Option<(Colormap, (u8, u8))>,
+}
+
+/// Luma histograms of the two compared images.
+pub type HistogramPair = ([u32; 256], [u32; 256]);
+
+/// Pixels along a profile line of the image, and of the second compared
+/// image in the diff modes.
+pub type LineProfile = (Vec<[u8; 4]>, Option<Vec<[u8; 4]>>);
+
+/// Difference of the two compared images before gamma is applied.
+struct ColorDiff {
+    image: RgbaImage,
+    /// Set for `DiffMetric::DeltaE`.
+    delta_e: Option<DeltaEStats>,
+    histograms: HistogramPair,
+    /// Changed areas in texture coordinates, see `find_diff_regions`.
+    regions: Vec<Rect>,
+}
+
 pub struct ImageData {
     base_name: String,
     image: Option<RgbaImage>,
+    scalar_stats: Option<ScalarStats>,
+    /// Read by `AlphaMode::Auto`, none for images without pixels.
+    alpha_stats: Option<AlphaStats>,
+    /// Adjusted or colormapped copy of `image` shown instead of it, with the
+    /// mapping it was made with.
+    mapped_image: Option<RgbaImage>,
+    mapping: Option<DisplayMapping>,
     width: f32,
     height: f32,
-    color_diff_vsplited: Option<RgbaImage>,
-    color_diff_hsplited: Option<RgbaImage>,
+    color_diff_vsplited: Option<ColorDiff>,
+    color_diff_hsplited: Option<ColorDiff>,
+    color_diff_pages: Option<ColorDiff>,
+    color_diff_previous: Option<ColorDiff>,
+    color_diff_reference: Option<ColorDiff>,
     texture_handle: Option<TextureHandle>,
     cd_texture_handle: Option<TextureHandle>,
-    pub error_msg: Option<String>,
+    cd_texture_key: Option<(DiffMode, f32)>,
+    mip_textures: Vec<TextureHandle>,
+    nearest_texture: Option<(u32, TextureHandle)>,
+    /// The image is too large for an upscale by the factor its zoom needs,
+    /// so it's drawn partly or fully smoothed.
+    nearest_limited: bool,
+    /// Halves interleaved for `SeamGuide::Comb`, made for a split mode.
+    comb_texture: Option<(DiffMode, TextureHandle)>,
+    pages: Vec<RgbaImage>,
+    /// Image loaded before the file last changed, for
+    /// `DiffMode::PreviousDiff`.
+    previous: Option<RgbaImage>,
+    current_page: usize,
+    video: Option<VideoPosition>,
+    diff_pages: (usize, usize),
+    /// Generated image compared with in `DiffMode::ReferenceDiff`.
+    reference: Reference,
+    diff_metric: DiffMetric,
+    vsplit_factor: f32,
+    hsplit_factor: f32,
+    gamma_lut: Option<(f32, [u8; 256])>,
+    valignment: Option<Alignment>,
+    halignment: Option<Alignment>,
+    pub alignment_status: AlignmentStatus,
+    alpha_anomalies: Option<AlphaAnomalies>,
+    anomaly_texture: Option<TextureHandle>,
+    anomaly_scan_running: bool,
+    region_stats: Option<RegionStats>,
+    region_stats_running: bool,
+    /// The running region statistics are for pixels replaced since.
+    region_stats_outdated: bool,
+    /// The image changed while a scan was running, its result is dropped.
+    anomaly_scan_outdated: bool,
+    original: Option<RgbaImage>,
+    transforms: Vec<Transform>,
+    redo_transforms: Vec<Transform>,
+    /// How long the image took to decode, set for full images.
+    decode_time: Option<Duration>,
+    /// What a full image was decoded from.
+    source_info: Option<SourceInfo>,
+    pub error: Option<ImViewError>,
 }
 
 impl ImageData {
-    pub fn thumbnail(path: &Path, img: RgbaImage, cc: &Context) -> Self {
-        let name = format!("{}_thmb", path.display());
+    pub fn thumbnail(source: &ImageSource, img: RgbaImage, cc: &Context) -> Self {
+        let base_name = texture_base_name(source);
+        let name = format!("{}_thmb", base_name);
         let texture_handle = cc.load_texture(name, make_color_image(&img));
         Self {
-            base_name: path.display().to_string(),
-            image: None,
+            base_name,
             width: img.width() as _,
             height: img.height() as _,
+            image: Some(img),
+            scalar_stats: None,
+            alpha_stats: None,
+            mapped_image: None,
+            mapping: None,
             color_diff_vsplited: None,
             color_diff_hsplited: None,
+            color_diff_pages: None,
+            color_diff_previous: None,
+            color_diff_reference: None,
             texture_handle: Some(texture_handle),
             cd_texture_handle: None,
-            error_msg: None,
+            cd_texture_key: None,
+            mip_textures: Vec::new(),
+            nearest_texture: None,
+            nearest_limited: false,
+            comb_texture: None,
+            pages: Vec::new(),
+            previous: None,
+            current_page: 0,
+            video: None,
+            diff_pages: (0, 1),
+            reference: Reference::new(),
+            diff_metric: DiffMetric::Encoded,
+            vsplit_factor: 0.5,
+            hsplit_factor: 0.5,
+            gamma_lut: None,
+            valignment: None,
+            halignment: None,
+            alignment_status: AlignmentStatus::None,
+            alpha_anomalies: None,
+            anomaly_texture: None,
+            anomaly_scan_running: false,
+            region_stats: None,
+            region_stats_running: false,
+            region_stats_outdated: false,
+            anomaly_scan_outdated: false,
+            original: None,
+            transforms: Vec::new(),
+            redo_transforms: Vec::new(),
+            decode_time: None,
+            source_info: None,
+            error: None,
         }
     }
 
-    pub fn error(err: &dyn std::error::Error) -> Self {
+    pub fn error(err: &std::io::Error) -> Self {
         Self {
             base_name: String::new(),
             image: None,
+            scalar_stats: None,
+            alpha_stats: None,
+            mapped_image: None,
+            mapping: None,
             width: 0.0,
             height: 0.0,
             color_diff_vsplited: None,
             color_diff_hsplited: None,
+            color_diff_pages: None,
+            color_diff_previous: None,
+            color_diff_reference: None,
             texture_handle: None,
             cd_texture_handle: None,
-            error_msg: Some(format!("{}", err)),
+            cd_texture_key: None,
+            mip_textures: Vec::new(),
+            nearest_texture: None,
+            nearest_limited: false,
+            comb_texture: None,
+            pages: Vec::new(),
+            previous: None,
+            current_page: 0,
+            video: None,
+            diff_pages: (0, 1),
+            reference: Reference::new(),
+            diff_metric: DiffMetric::Encoded,
+            vsplit_factor: 0.5,
+            hsplit_factor: 0.5,
+            gamma_lut: None,
+            valignment: None,
+            halignment: None,
+            alignment_status: AlignmentStatus::None,
+            alpha_anomalies: None,
+            anomaly_texture: None,
+            anomaly_scan_running: false,
+            region_stats: None,
+            region_stats_running: false,
+            region_stats_outdated: false,
+            anomaly_scan_outdated: false,
+            original: None,
+            transforms: Vec::new(),
+            redo_transforms: Vec::new(),
+            decode_time: None,
+            source_info: None,
+            error: Some(ImViewError::from(err)),
         }
     }
 
-    pub fn full_image(path: &Path, img: RgbaImage, cc: &Context) -> Self {
-        let name = format!("{}_full", path.display());
+    /// Full image showing the first of `pages`, which must not be empty.
+    pub fn full_image(source: &ImageSource, mut pages: Vec<RgbaImage>, cc: &Context) -> Self {
+        let img = if pages.len() > 1 {
+            pages[0].clone()
+        } else {
+            pages.pop().unwrap()
+        };
+        let base_name = texture_base_name(source);
+        let name = format!("{}_full", base_name);
         let texture_handle = cc.load_texture(name, make_color_image(&img));
+        let scalar_stats = pixel_ops::scalar_stats(&img);
+        let alpha_stats = pixel_ops::alpha_stats(&img);
         Self {
-            base_name: path.display().to_string(),
+            base_name,
             width: img.width() as _,
             height: img.height() as _,
             image: Some(img),
+            scalar_stats: Some(scalar_stats),
+            alpha_stats: Some(alpha_stats),
+            mapped_image: None,
+            mapping: None,
             color_diff_vsplited: None,
             color_diff_hsplited: None,
+            color_diff_pages: None,
+            color_diff_previous: None,
+            color_diff_reference: None,
             texture_handle: Some(texture_handle),
             cd_texture_handle: None,
-            error_msg: None,
+            cd_texture_key: None,
+            mip_textures: Vec::new(),
+            nearest_texture: None,
+            nearest_limited: false,
+            comb_texture: None,
+            pages,
+            previous: None,
+            current_page: 0,
+            video: None,
+            diff_pages: (0, 1),
+            reference: Reference::new(),
+            diff_metric: DiffMetric::Encoded,
+            vsplit_factor: 0.5,
+            hsplit_factor: 0.5,
+            gamma_lut: None,
+            valignment: None,
+            halignment: None,
+            alignment_status: AlignmentStatus::None,
+            alpha_anomalies: None,
+            anomaly_texture: None,
+            anomaly_scan_running: false,
+            region_stats: None,
+            region_stats_running: false,
+            region_stats_outdated: false,
+            anomaly_scan_outdated: false,
+            original: None,
+            transforms: Vec::new(),
+            redo_transforms: Vec::new(),
+            decode_time: None,
+            source_info: None,
+            error: None,
+        }
+    }
+
+    /// Texture-only image used to show an image of `size` while it's still
+    /// decoding, transparent until `update_partial` fills it in.
+    pub fn partial(source: &ImageSource, size: (u32, u32), cc: &Context) -> Self {
+        let base_name = texture_base_name(source);
+        let name = format!("{}_partial", base_name);
+        let blank = ColorImage::new([size.0 as _, size.1 as _], Color32::TRANSPARENT);
+        let texture_handle = cc.load_texture(name, blank);
+        Self {
+            base_name,
+            width: size.0 as _,
+            height: size.1 as _,
+            image: None,
+            scalar_stats: None,
+            alpha_stats: None,
+            mapped_image: None,
+            mapping: None,
+            color_diff_vsplited: None,
+            color_diff_hsplited: None,
+            color_diff_pages: None,
+            color_diff_previous: None,
+            color_diff_reference: None,
+            texture_handle: Some(texture_handle),
+            cd_texture_handle: None,
+            cd_texture_key: None,
+            mip_textures: Vec::new(),
+            nearest_texture: None,
+            nearest_limited: false,
+            comb_texture: None,
+            pages: Vec::new(),
+            previous: None,
+            current_page: 0,
+            video: None,
+            diff_pages: (0, 1),
+            reference: Reference::new(),
+            diff_metric: DiffMetric::Encoded,
+            vsplit_factor: 0.5,
+            hsplit_factor: 0.5,
+            gamma_lut: None,
+            valignment: None,
+            halignment: None,
+            alignment_status: AlignmentStatus::None,
+            alpha_anomalies: None,
+            anomaly_texture: None,
+            anomaly_scan_running: false,
+            region_stats: None,
+            region_stats_running: false,
+            region_stats_outdated: false,
+            anomaly_scan_outdated: false,
+            original: None,
+            transforms: Vec::new(),
+            redo_transforms: Vec::new(),
+            decode_time: None,
+            source_info: None,
+            error: None,
+        }
+    }
+
+    /// Uploads `rows` of a partial image starting at row `top`, the rest of
+    /// the texture stays as it is.
+    pub fn update_partial(&mut self, top: u32, rows: &RgbaImage) {
+        if let Some(handle) = self.texture_handle.as_mut() {
+            handle.set_partial([0, top as _], make_color_image(rows));
+        }
+    }
+
+    pub fn pixels(&self) -> Option<&RgbaImage> {
+        self.image.as_ref()
+    }
+
+    /// Color of the pixel at `x`, `y` of the shown image.
+    pub fn pixel_at(&self, x: u32, y: u32) -> Option<Rgba<u8>> {
+        let img = self.image.as_ref()?;
+        if x < img.width() && y < img.height() {
+            Some(*img.get_pixel(x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Average color of the square of `2 * radius + 1` pixels centered at
+    /// `x`, `y`, the square is cut to the image bounds.
+    pub fn average_at(&self, x: u32, y: u32, radius: u32) -> Option<[u8; 4]> {
+        let img = self.image.as_ref()?;
+        if x >= img.width() || y >= img.height() {
+            return None;
+        }
+        let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let x1 = (x + radius).min(img.width() - 1);
+        let y1 = (y + radius).min(img.height() - 1);
+        let mut sum = [0u32; 4];
+        for py in y0..=y1 {
+            for px in x0..=x1 {
+                for (s, v) in sum.iter_mut().zip(img.get_pixel(px, py).0) {
+                    *s += v as u32;
+                }
+            }
         }
+        let count = (x1 - x0 + 1) * (y1 - y0 + 1);
+        Some(sum.map(|s| ((s + count / 2) / count) as u8))
+    }
+
+    /// Whether every pixel of the image is gray, so it holds single
+    /// channel data.
+    pub fn is_single_channel(&self) -> bool {
+        matches!(self.scalar_stats, Some(stats) if stats.gray)
+    }
+
+    /// Lowest and highest single channel value in the image.
+    pub fn scalar_range(&self) -> Option<(u8, u8)> {
+        self.scalar_stats.map(|stats| (stats.min, stats.max))
     }
 
     pub fn size(&self) -> Vec2 {
@@ -75,6 +446,35 @@ impl ImageData {
         self.height
     }
 
+    pub fn decode_time(&self) -> Option<Duration> {
+        self.decode_time
+    }
+
+    pub fn set_decode_time(&mut self, time: Duration) {
+        self.decode_time = Some(time);
+    }
+
+    pub fn source_info(&self) -> Option<&SourceInfo> {
+        self.source_info.as_ref()
+    }
+
+    pub fn set_source_info(&mut self, info: SourceInfo) {
+        self.source_info = Some(info);
+    }
+
+    /// Bytes taken by the decoded RGBA pixels, of the shown image and of the
+    /// pages kept for multi-page images.
+    pub fn pixel_memory(&self) -> usize {
+        let pages = self.pages.iter().map(|p| p.as_raw().len()).sum::<usize>();
+        pages + self.image.as_ref().map_or(0, |img| img.as_raw().len())
+    }
+
+    /// Whether the color texture is loaded, it's dropped by
+    /// `release_display_resources`.
+    pub fn has_color_texture(&self) -> bool {
+        self.texture_handle.is_some()
+    }
+
     pub fn color_texture_handle(&self) -> &TextureHandle {
         self.texture_handle.as_ref().unwrap()
     }
@@ -83,88 +483,1339 @@ impl ImageData {
         self.cd_texture_handle.as_ref().unwrap()
     }
 
+    /// Texture of the left to right color difference once it's loaded, the
+    /// last panel of the triptych.
+    pub fn vertical_diff_texture(&self) -> Option<&TextureHandle> {
+        match self.cd_texture_key {
+            Some((DiffMode::VColorDiff, _)) => self.cd_texture_handle.as_ref(),
+            _ => None,
+        }
+    }
+
     pub fn texture_handle(&self, diff_mode: DiffMode) -> &TextureHandle {
         match diff_mode {
-            DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit => self.color_texture_handle(),
-            DiffMode::VColorDiff | DiffMode::HColorDiff => self.color_diff_texture_handle(),
+            DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit | DiffMode::Triptych => {
+                self.color_texture_handle()
+            }
+            DiffMode::VColorDiff
+            | DiffMode::HColorDiff
+            | DiffMode::PageDiff
+            | DiffMode::PreviousDiff
+            | DiffMode::ReferenceDiff => self.color_diff_texture_handle(),
+        }
+    }
+
+    /// Returns the texture to draw for `diff_mode` when one texel is shown on
+    /// `texel_scale` screen pixels. Minified color images use the mip level
+    /// closest to the displayed size, so fine detail doesn't alias.
+    pub fn display_texture(&self, diff_mode: DiffMode, texel_scale: f32) -> &TextureHandle {
+        let is_color = matches!(
+            diff_mode,
+            DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit | DiffMode::Triptych
+        );
+        if is_color && texel_scale > 1.0 {
+            if let Some((_, handle)) = self.nearest_texture.as_ref() {
+                return handle;
+            }
+        }
+        if is_color && texel_scale > 0.0 && !self.mip_textures.is_empty() {
+            let level = (1.0 / texel_scale).log2().floor();
+            if level >= 1.0 {
+                let idx = (level as usize).min(self.mip_textures.len()) - 1;
+                return &self.mip_textures[idx];
+            }
+        }
+        self.texture_handle(diff_mode)
+    }
+
+    /// Checks whether the image is large enough to be split for `diff_mode`,
+    /// each half needs at least one pixel along the split direction.
+    pub fn can_split(&self, diff_mode: DiffMode) -> bool {
+        match diff_mode {
+            DiffMode::Full => true,
+            DiffMode::VSplit | DiffMode::VColorDiff | DiffMode::Triptych => self.width >= 2.0,
+            DiffMode::HSplit | DiffMode::HColorDiff => self.height >= 2.0,
+            DiffMode::PageDiff => self.pages.len() > 1,
+            DiffMode::PreviousDiff => self.previous.is_some(),
+            DiffMode::ReferenceDiff => true,
+        }
+    }
+
+    /// Pixels along `line` of the image, or of both compared images in the
+    /// color difference modes. `None` when the line is outside of them.
+    pub fn line_profile(&self, diff_mode: DiffMode, line: ProfileLine) -> Option<LineProfile> {
+        let img = self.image.as_ref()?;
+        let (full_w, full_h) = img.dimensions();
+        match diff_mode {
+            DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit => {
+                Some((sample_line(img, (0, 0), (full_w, full_h), line)?, None))
+            }
+            DiffMode::VColorDiff | DiffMode::HColorDiff | DiffMode::Triptych
+                if self.can_split(diff_mode) =>
+            {
+                let (size, second) = if diff_mode != DiffMode::HColorDiff {
+                    let (w, right) = split_extent(full_w, self.vsplit_factor);
+                    ((w, full_h), (right, 0))
+                } else {
+                    let (h, bottom) = split_extent(full_h, self.hsplit_factor);
+                    ((full_w, h), (0, bottom))
+                };
+                let one = sample_line(img, (0, 0), size, line)?;
+                let two = sample_line(img, second, size, line)?;
+                Some((one, Some(two)))
+            }
+            DiffMode::PageDiff if self.can_split(diff_mode) => {
+                let (a, b) = (
+                    &self.pages[self.diff_pages.0],
+                    &self.pages[self.diff_pages.1],
+                );
+                let size = (a.width().min(b.width()), a.height().min(b.height()));
+                let one = sample_line(a, (0, 0), size, line)?;
+                let two = sample_line(b, (0, 0), size, line)?;
+                Some((one, Some(two)))
+            }
+            DiffMode::PreviousDiff if self.can_split(diff_mode) => {
+                let a = self.previous.as_ref().unwrap();
+                let size = (a.width().min(full_w), a.height().min(full_h));
+                let one = sample_line(a, (0, 0), size, line)?;
+                let two = sample_line(img, (0, 0), size, line)?;
+                Some((one, Some(two)))
+            }
+            DiffMode::ReferenceDiff => {
+                let reference = self.reference.render(full_w, full_h);
+                let one = sample_line(img, (0, 0), (full_w, full_h), line)?;
+                let two = sample_line(&reference, (0, 0), (full_w, full_h), line)?;
+                Some((one, Some(two)))
+            }
+            DiffMode::VColorDiff
+            | DiffMode::HColorDiff
+            | DiffMode::PageDiff
+            | DiffMode::PreviousDiff
+            | DiffMode::Triptych => None,
         }
     }
 
-    fn create_hdiff_image(&self) -> RgbaImage {
-        let w = self.width as _;
-        let h = (self.height / 2.0) as _;
+    fn hsplit_halves(&self) -> (RgbaImage, RgbaImage) {
         let img = self.image.as_ref().unwrap();
-        let left_img = crop_imm(img, 0, 0, w, h).to_image();
-        let right_img = crop_imm(img, 0, h, w, h).to_image();
-        Self::image_diff(left_img, right_img)
+        let (w, full_h) = img.dimensions();
+        let (h, bottom) = split_extent(full_h, self.hsplit_factor);
+        let top_img = crop_imm(img, 0, 0, w, h).to_image();
+        let bottom_img = crop_imm(img, 0, bottom, w, h).to_image();
+        (top_img, bottom_img)
     }
 
-    fn create_vdiff_image(&self) -> RgbaImage {
-        let w = (self.width / 2.0) as _;
-        let h = self.height as _;
+    fn vsplit_halves(&self) -> (RgbaImage, RgbaImage) {
         let img = self.image.as_ref().unwrap();
+        let (full_w, h) = img.dimensions();
+        let (w, right) = split_extent(full_w, self.vsplit_factor);
         let left_img = crop_imm(img, 0, 0, w, h).to_image();
-        let right_img = crop_imm(img, w, 0, w, h).to_image();
-        Self::image_diff(left_img, right_img)
-    }
-
-    fn image_diff(mut one: RgbaImage, two: RgbaImage) -> RgbaImage {
-        let (w, h) = one.dimensions();
-        for y in 0..h {
-            for x in 0..w {
-                let op = one.get_pixel_mut(x, y);
-                let tp = two.get_pixel(x, y);
-                for c in 0..3 {
-                    let diff = (op[c] as i32 - tp[c] as i32).abs() as u8;
-                    op[c] = diff;
-                }
+        let right_img = crop_imm(img, right, 0, w, h).to_image();
+        (left_img, right_img)
+    }
+
+    /// Returns the two compared halves for the split direction of `diff_mode`.
+    ///
+    /// The seam lies at the split factor set by `set_split_factors`. Both
+    /// halves always have the same size, that of the smaller side of the
+    /// seam, and each stays anchored to its own image edge. For the default
+    /// 0.5 factor and an odd split dimension this leaves the middle column
+    /// (or row) out as a separator, so no phantom difference appears along
+    /// the far edge.
+    pub fn split_halves(&self, diff_mode: DiffMode) -> Option<(RgbaImage, RgbaImage)> {
+        if self.image.is_none() || !self.can_split(diff_mode) {
+            return None;
+        }
+        match diff_mode {
+            DiffMode::VSplit | DiffMode::VColorDiff | DiffMode::Triptych => {
+                Some(self.vsplit_halves())
             }
+            DiffMode::HSplit | DiffMode::HColorDiff => Some(self.hsplit_halves()),
+            DiffMode::Full
+            | DiffMode::PageDiff
+            | DiffMode::PreviousDiff
+            | DiffMode::ReferenceDiff => None,
         }
-        one
     }
 
-    fn image_gamma(mut img: RgbaImage, gamma: f32) -> RgbaImage {
-        let inv_gamma = 1.0 / gamma;
-        let (width, height) = img.dimensions();
-        for y in 0..height {
-            for x in 0..width {
-                let p = img.get_pixel_mut(x, y);
-                for c in 0..3 {
-                    let v = p[c] as f32;
-                    let v = (v / 255.0).powf(inv_gamma) * 255.0;
-                    let v = v as u8;
-                    p[c] = v
-                }
+    fn create_hdiff_image(&self) -> ColorDiff {
+        let (top_img, bottom_img) = self.hsplit_halves();
+        let bottom_img = match self.halignment.as_ref() {
+            Some(a) => apply_translation(&bottom_img, a),
+            None => bottom_img,
+        };
+        self.image_diff(top_img, bottom_img)
+    }
+
+    fn create_vdiff_image(&self) -> ColorDiff {
+        let (left_img, right_img) = self.vsplit_halves();
+        let right_img = match self.valignment.as_ref() {
+            Some(a) => apply_translation(&right_img, a),
+            None => right_img,
+        };
+        self.image_diff(left_img, right_img)
+    }
+
+    /// Difference of the two compared pages, cropped to the area they share.
+    fn create_page_diff_image(&self) -> ColorDiff {
+        let (a, b) = (
+            &self.pages[self.diff_pages.0],
+            &self.pages[self.diff_pages.1],
+        );
+        let w = a.width().min(b.width());
+        let h = a.height().min(b.height());
+        let one = crop_imm(a, 0, 0, w, h).to_image();
+        let two = crop_imm(b, 0, 0, w, h).to_image();
+        self.image_diff(one, two)
+    }
+
+    /// Difference of the image and the reference rendered at its size.
+    fn create_reference_diff_image(&self) -> ColorDiff {
+        let img = self.image.as_ref().unwrap();
+        let reference = self.reference.render(img.width(), img.height());
+        self.image_diff(img.clone(), reference)
+    }
+
+    /// Difference of the previous version and the image, cropped to the
+    /// area they share when the size changed.
+    fn create_previous_diff_image(&self) -> ColorDiff {
+        let (a, b) = (
+            self.previous.as_ref().unwrap(),
+            self.image.as_ref().unwrap(),
+        );
+        let w = a.width().min(b.width());
+        let h = a.height().min(b.height());
+        let one = crop_imm(a, 0, 0, w, h).to_image();
+        let two = crop_imm(b, 0, 0, w, h).to_image();
+        self.image_diff(one, two)
+    }
+
+    fn image_diff(&self, one: RgbaImage, two: RgbaImage) -> ColorDiff {
+        let histograms = (
+            pixel_ops::luma_histogram(&one),
+            pixel_ops::luma_histogram(&two),
+        );
+        let (image, delta_e) = match self.diff_metric {
+            DiffMetric::Encoded => (pixel_ops::image_diff(one, &two), None),
+            DiffMetric::Linear => (pixel_ops::image_diff_linear(one, &two), None),
+            DiffMetric::DeltaE => {
+                let (image, stats) = delta_e_image(one, &two);
+                (image, Some(stats))
+            }
+        };
+        let size = vec2(image.width() as f32, image.height() as f32);
+        let regions = find_diff_regions(&image, DIFF_REGION_THRESHOLD)
+            .into_iter()
+            .map(|r| {
+                let min = pos2(r.x as f32 / size.x, r.y as f32 / size.y);
+                Rect::from_min_size(min, vec2(r.width as f32, r.height as f32) / size)
+            })
+            .collect();
+        ColorDiff {
+            image,
+            delta_e,
+            histograms,
+            regions,
+        }
+    }
+
+    /// Maps ΔE differences through a colormap, the other metrics are shown
+    /// as they are.
+    fn shade_diff(&self, img: RgbaImage) -> RgbaImage {
+        match self.diff_metric {
+            DiffMetric::DeltaE => {
+                pixel_ops::apply_colormap(&img, &pixel_ops::colormap_lut(Colormap::Turbo, 0, 255))
             }
+            DiffMetric::Encoded | DiffMetric::Linear => img,
         }
-        img
+    }
+
+    fn cached_diff(&self, diff_mode: DiffMode) -> Option<&ColorDiff> {
+        match diff_mode {
+            DiffMode::VColorDiff | DiffMode::Triptych => self.color_diff_vsplited.as_ref(),
+            DiffMode::HColorDiff => self.color_diff_hsplited.as_ref(),
+            DiffMode::PageDiff => self.color_diff_pages.as_ref(),
+            DiffMode::PreviousDiff => self.color_diff_previous.as_ref(),
+            DiffMode::ReferenceDiff => self.color_diff_reference.as_ref(),
+            DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit => None,
+        }
+    }
+
+    /// Mean and 99th percentile ΔE of the difference shown in `diff_mode`,
+    /// once it's computed with `DiffMetric::DeltaE`.
+    pub fn delta_e_stats(&self, diff_mode: DiffMode) -> Option<DeltaEStats> {
+        self.cached_diff(diff_mode).and_then(|d| d.delta_e)
+    }
+
+    /// Changed areas of the difference shown in `diff_mode` in texture
+    /// coordinates, available while the difference is cached.
+    pub fn diff_regions(&self, diff_mode: DiffMode) -> Option<&[Rect]> {
+        self.cached_diff(diff_mode).map(|d| d.regions.as_slice())
+    }
+
+    /// Histograms of the two images compared in `diff_mode`, available
+    /// while their difference is cached.
+    pub fn diff_histograms(&self, diff_mode: DiffMode) -> Option<&HistogramPair> {
+        self.cached_diff(diff_mode).map(|d| &d.histograms)
+    }
+
+    fn image_gamma(img: RgbaImage, gamma: f32) -> RgbaImage {
+        pixel_ops::apply_lut(img, &pixel_ops::gamma_lut(gamma))
     }
 
     fn create_color_diff_texture(&mut self, cc: &Context, image: RgbaImage) {
-        let egui_image = make_color_image(&image);
+        // Dropped before the upload, so the old difference is freed first.
+        self.cd_texture_handle = None;
+        let egui_image = make_color_image(&self.shade_diff(image));
         self.cd_texture_handle =
             Some(cc.load_texture(format!("{}_color_diff", self.base_name), egui_image));
     }
 
+    fn apply_gamma(&mut self, img: RgbaImage, gamma: f32) -> RgbaImage {
+        match self.gamma_lut.as_ref() {
+            Some((g, _)) if *g == gamma => (),
+            _ => self.gamma_lut = Some((gamma, pixel_ops::gamma_lut(gamma))),
+        }
+        pixel_ops::apply_lut(img, &self.gamma_lut.as_ref().unwrap().1)
+    }
+
+    fn is_color_diff_loaded(&self, diff_mode: DiffMode, gamma: f32) -> bool {
+        self.cd_texture_handle.is_some() && self.cd_texture_key == Some((diff_mode, gamma))
+    }
+
     pub fn switch_to_horizontal_color_diff(&mut self, ctx: &Context, gamma: f32) {
+        if self.is_color_diff_loaded(DiffMode::HColorDiff, gamma)
+            || !self.can_split(DiffMode::HColorDiff)
+        {
+            return;
+        }
         if self.color_diff_hsplited.is_none() {
             self.color_diff_hsplited = Some(self.create_hdiff_image())
         }
-        let img = Self::image_gamma(self.color_diff_hsplited.as_ref().unwrap().clone(), gamma);
+        let img = self.apply_gamma(
+            self.color_diff_hsplited.as_ref().unwrap().image.clone(),
+            gamma,
+        );
         self.create_color_diff_texture(ctx, img);
+        self.cd_texture_key = Some((DiffMode::HColorDiff, gamma));
     }
 
     pub fn switch_to_vertical_color_diff(&mut self, ctx: &Context, gamma: f32) {
+        if self.is_color_diff_loaded(DiffMode::VColorDiff, gamma)
+            || !self.can_split(DiffMode::VColorDiff)
+        {
+            return;
+        }
         if self.color_diff_vsplited.is_none() {
             self.color_diff_vsplited = Some(self.create_vdiff_image())
         }
 
-        let img = Self::image_gamma(self.color_diff_vsplited.as_ref().unwrap().clone(), gamma);
+        let img = self.apply_gamma(
+            self.color_diff_vsplited.as_ref().unwrap().image.clone(),
+            gamma,
+        );
         self.create_color_diff_texture(ctx, img);
+        self.cd_texture_key = Some((DiffMode::VColorDiff, gamma));
+    }
+
+    pub fn switch_to_page_diff(&mut self, ctx: &Context, gamma: f32) {
+        if self.is_color_diff_loaded(DiffMode::PageDiff, gamma)
+            || !self.can_split(DiffMode::PageDiff)
+        {
+            return;
+        }
+        if self.color_diff_pages.is_none() {
+            self.color_diff_pages = Some(self.create_page_diff_image())
+        }
+
+        let img = self.apply_gamma(self.color_diff_pages.as_ref().unwrap().image.clone(), gamma);
+        self.create_color_diff_texture(ctx, img);
+        self.cd_texture_key = Some((DiffMode::PageDiff, gamma));
+    }
+
+    pub fn switch_to_previous_diff(&mut self, ctx: &Context, gamma: f32) {
+        if self.is_color_diff_loaded(DiffMode::PreviousDiff, gamma)
+            || !self.can_split(DiffMode::PreviousDiff)
+            || self.image.is_none()
+        {
+            return;
+        }
+        if self.color_diff_previous.is_none() {
+            self.color_diff_previous = Some(self.create_previous_diff_image())
+        }
+
+        let img = self.apply_gamma(
+            self.color_diff_previous.as_ref().unwrap().image.clone(),
+            gamma,
+        );
+        self.create_color_diff_texture(ctx, img);
+        self.cd_texture_key = Some((DiffMode::PreviousDiff, gamma));
+    }
+
+    pub fn switch_to_reference_diff(&mut self, ctx: &Context, gamma: f32) {
+        if self.is_color_diff_loaded(DiffMode::ReferenceDiff, gamma) || self.image.is_none() {
+            return;
+        }
+        if self.color_diff_reference.is_none() {
+            self.color_diff_reference = Some(self.create_reference_diff_image())
+        }
+
+        let img = self.apply_gamma(
+            self.color_diff_reference.as_ref().unwrap().image.clone(),
+            gamma,
+        );
+        self.create_color_diff_texture(ctx, img);
+        self.cd_texture_key = Some((DiffMode::ReferenceDiff, gamma));
+    }
+
+    pub fn applied_alignment(&self, diff_mode: DiffMode) -> Option<&Alignment> {
+        match diff_mode {
+            DiffMode::VSplit | DiffMode::VColorDiff | DiffMode::Triptych => {
+                self.valignment.as_ref()
+            }
+            DiffMode::HSplit | DiffMode::HColorDiff => self.halignment.as_ref(),
+            DiffMode::Full
+            | DiffMode::PageDiff
+            | DiffMode::PreviousDiff
+            | DiffMode::ReferenceDiff => None,
+        }
+    }
+
+    fn set_alignment(&mut self, cc: &Context, state: &ImageUIState, alignment: Option<Alignment>) {
+        match state.diff_mode {
+            DiffMode::VSplit | DiffMode::VColorDiff | DiffMode::Triptych => {
+                self.valignment = alignment;
+                self.color_diff_vsplited = None;
+            }
+            DiffMode::HSplit | DiffMode::HColorDiff => {
+                self.halignment = alignment;
+                self.color_diff_hsplited = None;
+            }
+            DiffMode::Full
+            | DiffMode::PageDiff
+            | DiffMode::PreviousDiff
+            | DiffMode::ReferenceDiff => return,
+        }
+        self.cd_texture_handle = None;
+        self.restore_display_resources(cc, state);
+    }
+
+    pub fn accept_alignment(&mut self, cc: &Context, state: &ImageUIState) {
+        if let AlignmentStatus::Estimated(mode, alignment) = self.alignment_status {
+            if mode == state.diff_mode {
+                self.alignment_status = AlignmentStatus::None;
+                self.set_alignment(cc, state, Some(alignment));
+            }
+        }
+    }
+
+    pub fn revert_alignment(&mut self, cc: &Context, state: &ImageUIState) {
+        self.set_alignment(cc, state, None);
+    }
+
+    /// Returns the pixels to scan for alpha anomalies below `threshold`
+    /// unless that scan is done already. Only one scan runs at a time, a
+    /// changed threshold is picked up once the running one finishes.
+    pub fn start_anomaly_scan(&mut self, threshold: u8) -> Option<RgbaImage> {
+        let img = self.image.as_ref()?;
+        let done = matches!(&self.alpha_anomalies, Some(a) if a.threshold == threshold);
+        if done || self.anomaly_scan_running {
+            return None;
+        }
+        self.anomaly_scan_running = true;
+        Some(img.clone())
+    }
+
+    pub fn is_anomaly_scan_running(&self) -> bool {
+        self.anomaly_scan_running
+    }
+
+    pub fn set_alpha_anomalies(&mut self, anomalies: AlphaAnomalies) {
+        self.anomaly_scan_running = false;
+        if std::mem::take(&mut self.anomaly_scan_outdated) {
+            return;
+        }
+        self.alpha_anomalies = Some(anomalies);
+        self.anomaly_texture = None;
+    }
+
+    pub fn alpha_anomalies(&self) -> Option<&AlphaAnomalies> {
+        self.alpha_anomalies.as_ref()
+    }
+
+    /// Returns the pixels of the image and of the cached difference inside
+    /// the region of `key`, unless its statistics are done already. One
+    /// computation runs at a time like the anomaly scan.
+    pub fn start_region_stats(&mut self, key: RegionKey) -> Option<(RgbaImage, Option<RgbaImage>)> {
+        let img = self.image.as_ref()?;
+        let done = matches!(&self.region_stats, Some(s) if s.key == key);
+        if done || self.region_stats_running {
+            return None;
+        }
+        let crop = |img: &RgbaImage| {
+            let r = key.region.clip(img.width(), img.height())?;
+            Some(crop_imm(img, r.x, r.y, r.width, r.height).to_image())
+        };
+        let region = crop(img)?;
+        let diff = self.cached_diff(key.diff_mode).and_then(|d| crop(&d.image));
+        self.region_stats_running = true;
+        Some((region, diff))
+    }
+
+    pub fn is_region_stats_running(&self) -> bool {
+        self.region_stats_running
+    }
+
+    pub fn set_region_stats(&mut self, stats: RegionStats) {
+        self.region_stats_running = false;
+        if std::mem::take(&mut self.region_stats_outdated) {
+            return;
+        }
+        self.region_stats = Some(stats);
+    }
+
+    pub fn region_stats(&self) -> Option<&RegionStats> {
+        self.region_stats.as_ref()
+    }
+
+    /// Overlay marking the alpha anomalies, made from the last scan.
+    pub fn anomaly_texture(&self) -> Option<&TextureHandle> {
+        self.anomaly_texture.as_ref()
+    }
+
+    /// Untransformed pixels of the shown page, kept as the previous
+    /// version when the file changes.
+    pub fn loaded_image(&self) -> Option<&RgbaImage> {
+        self.original.as_ref().or(self.image.as_ref())
+    }
+
+    pub fn has_previous_version(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    /// Keeps `img` to compare against in `DiffMode::PreviousDiff`.
+    pub fn set_previous_version(&mut self, img: RgbaImage) {
+        self.previous = Some(img);
+        self.color_diff_previous = None;
+    }
+
+    pub fn clear_previous_version(&mut self) {
+        self.previous = None;
+        self.color_diff_previous = None;
+        if matches!(self.cd_texture_key, Some((DiffMode::PreviousDiff, _))) {
+            self.cd_texture_handle = None;
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len().max(1)
+    }
+
+    /// Zero-based index of the shown page.
+    pub fn current_page(&self) -> usize {
+        self.current_page
+    }
+
+    /// Shows another page. Transforms and alignments belong to the page they
+    /// were made on, so they are dropped together with the cached diffs.
+    pub fn set_page(&mut self, cc: &Context, state: &ImageUIState, page: usize) {
+        if page >= self.pages.len() || page == self.current_page {
+            return;
+        }
+        self.current_page = page;
+        self.original = None;
+        self.transforms.clear();
+        self.redo_transforms.clear();
+        let img = self.pages[page].clone();
+        self.set_transformed_image(cc, state, img);
+    }
+
+    /// Frame image of a video showing the frame at `position`.
+    #[cfg(feature = "video")]
+    pub fn video_frame(
+        source: &ImageSource,
+        img: RgbaImage,
+        position: VideoPosition,
+        cc: &Context,
+    ) -> Self {
+        let mut data = Self::full_image(source, vec![img], cc);
+        data.video = Some(position);
+        data
+    }
+
+    pub fn video(&self) -> Option<VideoPosition> {
+        self.video
+    }
+
+    /// Shows another frame of the video, dropping transforms like `set_page`.
+    #[cfg(feature = "video")]
+    pub fn set_video_frame(
+        &mut self,
+        cc: &Context,
+        state: &ImageUIState,
+        img: RgbaImage,
+        position: VideoPosition,
+    ) {
+        self.video = Some(position);
+        self.original = None;
+        self.transforms.clear();
+        self.redo_transforms.clear();
+        self.set_transformed_image(cc, state, img);
+    }
+
+    /// Whether an edit of `history` can be undone, transforms of another
+    /// page or frame don't count.
+    pub fn can_undo(&self, history: &EditHistory) -> bool {
+        history.undo.iter().any(|edit| match edit {
+            Edit::Transform => !self.transforms.is_empty(),
+            Edit::Annotations(_) => true,
+        })
+    }
+
+    pub fn can_redo(&self, history: &EditHistory) -> bool {
+        history.redo.iter().any(|edit| match edit {
+            Edit::Transform => !self.redo_transforms.is_empty(),
+            Edit::Annotations(_) => true,
+        })
+    }
+
+    /// Takes back the latest transform or annotation edit of `state`.
+    pub fn undo(&mut self, cc: &Context, state: &mut ImageUIState) {
+        while let Some(edit) = state.history.undo.pop() {
+            match edit {
+                // Left over from a page or frame that is no longer shown.
+                Edit::Transform if self.transforms.is_empty() => continue,
+                Edit::Transform => self.undo_transform(cc, state),
+                Edit::Annotations(items) => {
+                    let items = state.annotations.restore(items);
+                    state.history.redo.push(Edit::Annotations(items));
+                    return;
+                }
+            }
+            state.history.redo.push(Edit::Transform);
+            return;
+        }
+    }
+
+    /// Makes the latest undone edit of `state` again.
+    pub fn redo(&mut self, cc: &Context, state: &mut ImageUIState) {
+        while let Some(edit) = state.history.redo.pop() {
+            match edit {
+                Edit::Transform if self.redo_transforms.is_empty() => continue,
+                Edit::Transform => self.redo_transform(cc, state),
+                Edit::Annotations(items) => {
+                    let items = state.annotations.restore(items);
+                    state.history.undo.push(Edit::Annotations(items));
+                    return;
+                }
+            }
+            state.history.undo.push(Edit::Transform);
+            return;
+        }
+    }
+
+    /// Transforms the shown image, recording the edit in the history of
+    /// `state`.
+    pub fn apply_transform(
+        &mut self,
+        cc: &Context,
+        state: &mut ImageUIState,
+        transform: Transform,
+    ) {
+        if self.image.is_none() {
+            return;
+        }
+        state.history.push(Edit::Transform);
+        if self.original.is_none() {
+            self.original = self.image.clone();
+        }
+        if self.transforms.len() == MAX_UNDO_DEPTH {
+            let oldest = self.transforms.remove(0);
+            self.original = self.original.as_ref().map(|img| oldest.apply(img));
+        }
+        self.transforms.push(transform);
+        self.redo_transforms.clear();
+        let img = transform.apply(self.image.as_ref().unwrap());
+        self.set_transformed_image(cc, state, img);
+    }
+
+    fn undo_transform(&mut self, cc: &Context, state: &ImageUIState) {
+        if let Some(transform) = self.transforms.pop() {
+            self.redo_transforms.push(transform);
+            self.replay_transforms(cc, state);
+        }
+    }
+
+    fn redo_transform(&mut self, cc: &Context, state: &ImageUIState) {
+        if let Some(transform) = self.redo_transforms.pop() {
+            self.transforms.push(transform);
+            self.replay_transforms(cc, state);
+        }
+    }
+
+    fn replay_transforms(&mut self, cc: &Context, state: &ImageUIState) {
+        let img = self
+            .transforms
+            .iter()
+            .fold(self.original.clone().unwrap(), |img, t| t.apply(&img));
+        self.set_transformed_image(cc, state, img);
+    }
+
+    fn set_transformed_image(&mut self, cc: &Context, state: &ImageUIState, img: RgbaImage) {
+        self.width = img.width() as _;
+        self.height = img.height() as _;
+        self.scalar_stats = Some(pixel_ops::scalar_stats(&img));
+        self.alpha_stats = Some(pixel_ops::alpha_stats(&img));
+        self.image = Some(img);
+        self.release_display_resources();
+        self.valignment = None;
+        self.halignment = None;
+        self.alignment_status = AlignmentStatus::None;
+        self.alpha_anomalies = None;
+        self.anomaly_scan_outdated = self.anomaly_scan_running;
+        self.region_stats = None;
+        self.region_stats_outdated = self.region_stats_running;
+        self.restore_display_resources(cc, state);
+    }
+
+    /// Returns the image shown by the view for the state's diff mode on CPU.
+    pub fn display_image(&self, state: &ImageUIState) -> Option<RgbaImage> {
+        if self.image.is_none() || !self.can_split(state.diff_mode) {
+            return None;
+        }
+        match state.diff_mode {
+            DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit | DiffMode::Triptych => {
+                Some(self.shown_image().clone())
+            }
+            DiffMode::VColorDiff => Some(self.vertical_diff_image(state)),
+            DiffMode::HColorDiff => {
+                let diff = match self.color_diff_hsplited.as_ref() {
+                    Some(diff) => diff.image.clone(),
+                    None => self.create_hdiff_image().image,
+                };
+                let diff = Self::image_gamma(diff, state.color_diff_hsplite_gamma);
+                Some(self.shade_diff(diff))
+            }
+            DiffMode::PageDiff => {
+                let diff = match self.color_diff_pages.as_ref() {
+                    Some(diff) => diff.image.clone(),
+                    None => self.create_page_diff_image().image,
+                };
+                let diff = Self::image_gamma(diff, state.page_diff_gamma);
+                Some(self.shade_diff(diff))
+            }
+            DiffMode::PreviousDiff => {
+                let diff = match self.color_diff_previous.as_ref() {
+                    Some(diff) => diff.image.clone(),
+                    None => self.create_previous_diff_image().image,
+                };
+                let diff = Self::image_gamma(diff, state.previous_diff_gamma);
+                Some(self.shade_diff(diff))
+            }
+            DiffMode::ReferenceDiff => {
+                let diff = match self.color_diff_reference.as_ref() {
+                    Some(diff) => diff.image.clone(),
+                    None => self.create_reference_diff_image().image,
+                };
+                let diff = Self::image_gamma(diff, state.reference_diff_gamma);
+                Some(self.shade_diff(diff))
+            }
+        }
+    }
+
+    /// The left to right color difference as the view shows it, the last
+    /// panel of the triptych.
+    pub fn vertical_diff_image(&self, state: &ImageUIState) -> RgbaImage {
+        let diff = match self.color_diff_vsplited.as_ref() {
+            Some(diff) => diff.image.clone(),
+            None => self.create_vdiff_image().image,
+        };
+        let diff = Self::image_gamma(diff, state.color_diff_vsplite_gamma);
+        self.shade_diff(diff)
+    }
+
+    pub fn release_display_resources(&mut self) {
+        self.mapped_image = None;
+        self.mapping = None;
+        self.texture_handle = None;
+        self.mip_textures.clear();
+        self.nearest_texture = None;
+        self.comb_texture = None;
+        self.anomaly_texture = None;
+        self.cd_texture_handle = None;
+        self.color_diff_vsplited = None;
+        self.color_diff_hsplited = None;
+        self.color_diff_pages = None;
+        self.color_diff_previous = None;
+        self.color_diff_reference = None;
+    }
+
+    /// Moves the seams used to crop the compared halves, dropping the cached
+    /// color differences that were computed for the old position.
+    pub fn set_split_factors(&mut self, vsplit_factor: f32, hsplit_factor: f32) {
+        if self.vsplit_factor != vsplit_factor {
+            self.vsplit_factor = vsplit_factor;
+            self.color_diff_vsplited = None;
+            if matches!(self.cd_texture_key, Some((DiffMode::VColorDiff, _))) {
+                self.cd_texture_handle = None;
+            }
+        }
+        if self.hsplit_factor != hsplit_factor {
+            self.hsplit_factor = hsplit_factor;
+            self.color_diff_hsplited = None;
+            if matches!(self.cd_texture_key, Some((DiffMode::HColorDiff, _))) {
+                self.cd_texture_handle = None;
+            }
+        }
+    }
+
+    /// Selects the two pages compared in `DiffMode::PageDiff`, indices past
+    /// the last page are clamped to it.
+    pub fn set_diff_pages(&mut self, pages: (usize, usize)) {
+        let last = self.page_count() - 1;
+        let pages = (pages.0.min(last), pages.1.min(last));
+        if self.diff_pages != pages {
+            self.diff_pages = pages;
+            self.color_diff_pages = None;
+            if matches!(self.cd_texture_key, Some((DiffMode::PageDiff, _))) {
+                self.cd_texture_handle = None;
+            }
+        }
+    }
+
+    /// Chooses how color differences are measured, dropping the ones
+    /// computed another way.
+    pub fn set_diff_metric(&mut self, metric: DiffMetric) {
+        if self.diff_metric != metric {
+            self.diff_metric = metric;
+            self.color_diff_vsplited = None;
+            self.color_diff_hsplited = None;
+            self.color_diff_pages = None;
+            self.color_diff_previous = None;
+            self.color_diff_reference = None;
+            self.cd_texture_handle = None;
+        }
+    }
+
+    /// Chooses the generated image compared with in
+    /// `DiffMode::ReferenceDiff`. Its size follows the image, so the
+    /// difference is made again whenever the image is replaced.
+    pub fn set_reference(&mut self, reference: Reference) {
+        if self.reference != reference {
+            self.reference = reference;
+            self.color_diff_reference = None;
+            if matches!(self.cd_texture_key, Some((DiffMode::ReferenceDiff, _))) {
+                self.cd_texture_handle = None;
+            }
+        }
+    }
+
+    pub fn restore_display_resources(&mut self, cc: &Context, state: &ImageUIState) {
+        if self.image.is_none() {
+            return;
+        }
+        self.set_split_factors(state.vsplit_factor, state.hsplit_factor);
+        self.set_diff_pages(state.diff_pages);
+        self.set_diff_metric(state.diff_metric);
+        self.set_reference(state.reference);
+        self.update_mapped_image(state);
+        self.switch_to_color_image(cc);
+        if self.mip_textures.is_empty() {
+            self.create_mip_textures(cc);
+        }
+        self.update_nearest_texture(cc, state);
+        self.update_comb_texture(cc, state);
+        if self.anomaly_texture.is_none() {
+            if let Some(anomalies) = self.alpha_anomalies.as_ref() {
+                let name = format!("{}_alpha_anomalies", self.base_name);
+                let texture = cc.load_texture(name, make_color_image(&anomalies.mask));
+                self.anomaly_texture = Some(texture);
+            }
+        }
+        if self.cd_texture_handle.is_none() {
+            match state.diff_mode {
+                DiffMode::VColorDiff => {
+                    self.switch_to_vertical_color_diff(cc, state.color_diff_vsplite_gamma)
+                }
+                DiffMode::HColorDiff => {
+                    self.switch_to_horizontal_color_diff(cc, state.color_diff_hsplite_gamma)
+                }
+                DiffMode::PageDiff => self.switch_to_page_diff(cc, state.page_diff_gamma),
+                DiffMode::PreviousDiff => {
+                    self.switch_to_previous_diff(cc, state.previous_diff_gamma)
+                }
+                DiffMode::ReferenceDiff => {
+                    self.switch_to_reference_diff(cc, state.reference_diff_gamma)
+                }
+                DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit | DiffMode::Triptych => (),
+            }
+        }
+        // The triptych draws the vertical difference next to the color
+        // image, another difference may have replaced it in the meantime.
+        if state.diff_mode == DiffMode::Triptych {
+            self.switch_to_vertical_color_diff(cc, state.color_diff_vsplite_gamma);
+        }
+    }
+
+    /// Mapping from values to colors the state asks for, `None` when the
+    /// image is shown as it is. Color images are only mapped when forced.
+    fn colormap_key(&self, state: &ImageUIState) -> Option<(Colormap, (u8, u8))> {
+        let stats = self.scalar_stats?;
+        if !stats.gray && !state.force_single_channel {
+            return None;
+        }
+        let range = if state.auto_stretch {
+            (stats.min, stats.max)
+        } else {
+            state.value_range
+        };
+        if stats.gray && state.colormap == Colormap::Grayscale && range == (0, 255) {
+            return None;
+        }
+        Some((state.colormap, range))
     }
 
+    /// Whether the pixels are read as premultiplied for `state.alpha_mode`.
+    pub fn is_premultiplied(&self, state: &ImageUIState) -> bool {
+        match state.alpha_mode {
+            AlphaMode::Auto => self.alpha_stats.is_some_and(|s| s.looks_premultiplied()),
+            AlphaMode::Straight => false,
+            AlphaMode::Premultiplied => true,
+        }
+    }
+
+    fn display_mapping(&self, state: &ImageUIState) -> Option<DisplayMapping> {
+        // Unpremultiplying opaque pixels would only copy them.
+        let changes = self.alpha_stats.is_some_and(|s| s.unpremultiply_changes);
+        let unpremultiply = self.is_premultiplied(state) && changes;
+        let colormap = self.colormap_key(state);
+        if !unpremultiply && colormap.is_none() && state.adjustments.is_identity() {
+            return None;
+        }
+        Some(DisplayMapping {
+            unpremultiply,
+            adjustments: state.adjustments,
+            colormap,
+        })
+    }
+
+    /// Rebuilds the shown image when the mapping changed, dropping the
+    /// textures made from the previous one. Premultiplied colors are made
+    /// straight first, then adjusted, so the colormap sees the final values.
+    fn update_mapped_image(&mut self, state: &ImageUIState) {
+        let mapping = self.display_mapping(state);
+        if mapping == self.mapping {
+            return;
+        }
+        self.mapping = mapping;
+        self.mapped_image = mapping.map(|mapping| {
+            let img = self.image.as_ref().unwrap();
+            let mut mapped = None;
+            if mapping.unpremultiply {
+                mapped = Some(pixel_ops::unpremultiply(img.clone()));
+            }
+            let a = mapping.adjustments;
+            if !a.is_identity() {
+                let lut = pixel_ops::adjustment_lut(a.exposure, a.brightness, a.contrast);
+                mapped = Some(pixel_ops::apply_lut(
+                    mapped.unwrap_or_else(|| img.clone()),
+                    &lut,
+                ));
+            }
+            if let Some((colormap, (low, high))) = mapping.colormap {
+                let lut = pixel_ops::colormap_lut(colormap, low, high);
+                mapped = Some(pixel_ops::apply_colormap(
+                    mapped.as_ref().unwrap_or(img),
+                    &lut,
+                ));
+            }
+            mapped.unwrap()
+        });
+        self.texture_handle = None;
+        self.mip_textures.clear();
+        self.nearest_texture = None;
+        self.comb_texture = None;
+    }
+
+    /// Image the color textures are made from, the mapped one if any.
+    fn shown_image(&self) -> &RgbaImage {
+        self.mapped_image.as_ref().or(self.image.as_ref()).unwrap()
+    }
+
+    fn create_mip_textures(&mut self, cc: &Context) {
+        let levels = mip_chain(self.shown_image());
+        self.mip_textures = levels
+            .iter()
+            .enumerate()
+            .map(|(idx, level)| {
+                let name = format!("{}_mip{}", self.base_name, idx + 1);
+                cc.load_texture(name, make_color_image(level))
+            })
+            .collect();
+    }
+
+    /// Keeps a copy of the image upscaled with nearest-neighbor sampling
+    /// while it's magnified with `TextureFilter::Nearest`. Textures are
+    /// always sampled linearly, so an integer upscale close to the displayed
+    /// size keeps pixel edges crisp. The factor is a power of two to avoid
+    /// uploads on every zoom step. The upscale stays within
+    /// `NEAREST_MAX_SIDE`, images too large for the factor the zoom needs
+    /// are reported by `nearest_limited`.
+    fn update_nearest_texture(&mut self, cc: &Context, state: &ImageUIState) {
+        const NEAREST_MAX_SIDE: u32 = 4096;
+        let img = self.shown_image();
+        let max_side = NEAREST_MAX_SIDE.min(cc.input().max_texture_side as u32);
+        let max_factor = max_side / img.width().max(img.height());
+        let wanted = if state.filter == TextureFilter::Nearest && state.texel_scale > 1.0 {
+            (state.texel_scale.ceil() as u32).next_power_of_two()
+        } else {
+            0
+        };
+        self.nearest_limited = wanted > max_factor;
+        let factor = wanted.min(max_factor);
+        if factor < 2 {
+            self.nearest_texture = None;
+            return;
+        }
+        if matches!(self.nearest_texture, Some((f, _)) if f == factor) {
+            return;
+        }
+        self.nearest_texture = None;
+        let img = self.shown_image();
+        let (w, h) = (img.width() * factor, img.height() * factor);
+        let upscaled = resize(img, w, h, FilterType::Nearest);
+        let name = format!("{}_nearest", self.base_name);
+        let handle = cc.load_texture(name, make_color_image(&upscaled));
+        self.nearest_texture = Some((factor, handle));
+    }
+
+    /// Keeps the comb of the split halves while the state asks for it.
+    fn update_comb_texture(&mut self, cc: &Context, state: &ImageUIState) {
+        let wanted = state.seam_guide == SeamGuide::Comb
+            && matches!(state.diff_mode, DiffMode::VSplit | DiffMode::HSplit)
+            && self.can_split(state.diff_mode);
+        if !wanted {
+            self.comb_texture = None;
+            return;
+        }
+        if matches!(self.comb_texture, Some((mode, _)) if mode == state.diff_mode) {
+            return;
+        }
+        self.comb_texture = None;
+        let comb = comb_image(self.shown_image(), state.diff_mode == DiffMode::HSplit);
+        let name = format!("{}_comb", self.base_name);
+        let handle = cc.load_texture(name, make_color_image(&comb));
+        self.comb_texture = Some((state.diff_mode, handle));
+    }
+
+    /// Whether the image is too large to be upscaled as far as its zoom
+    /// needs for crisp pixels, see `TextureFilter::Nearest`.
+    pub fn nearest_limited(&self) -> bool {
+        self.nearest_limited
+    }
+
+    /// Halves of the split view interleaved row by row, or column by column
+    /// for `DiffMode::HSplit`, see `SeamGuide::Comb`.
+    pub fn comb_texture(&self, diff_mode: DiffMode) -> Option<&TextureHandle> {
+        match self.comb_texture.as_ref() {
+            Some((mode, handle)) if *mode == diff_mode => Some(handle),
+            _ => None,
+        }
+    }
+
+    pub fn texture_memory(&self) -> usize {
+        [
+            &self.texture_handle,
+            &self.cd_texture_handle,
+            &self.anomaly_texture,
+        ]
+        .into_iter()
+        .filter_map(|h| h.as_ref())
+        .chain(self.mip_textures.iter())
+        .chain(self.nearest_texture.iter().map(|(_, h)| h))
+        .chain(self.comb_texture.iter().map(|(_, h)| h))
+        .map(|h| {
+            let [w, h] = h.size();
+            w * h * 4
+        })
+        .sum()
+    }
+
+    /// Uploads the color texture unless it's there already. Whatever changes
+    /// the shown image drops the texture, so an existing one is up to date.
     pub fn switch_to_color_image(&mut self, cc: &Context) {
-        let egui_image = make_color_image(self.image.as_ref().unwrap());
+        if self.texture_handle.is_some() {
+            return;
+        }
+        let egui_image = make_color_image(self.shown_image());
         self.texture_handle = Some(cc.load_texture(format!("{}_full", self.base_name), egui_image));
     }
 }
+
+/// Pixels along `line` of the `size` part of `img` starting at `origin`.
+fn sample_line(
+    img: &RgbaImage,
+    origin: (u32, u32),
+    size: (u32, u32),
+    line: ProfileLine,
+) -> Option<Vec<[u8; 4]>> {
+    let (x0, y0) = origin;
+    match line {
+        ProfileLine::Row(y) if y < size.1 => Some(
+            (0..size.0)
+                .map(|x| img.get_pixel(x0 + x, y0 + y).0)
+                .collect(),
+        ),
+        ProfileLine::Column(x) if x < size.0 => Some(
+            (0..size.1)
+                .map(|y| img.get_pixel(x0 + x, y0 + y).0)
+                .collect(),
+        ),
+        ProfileLine::Row(_) | ProfileLine::Column(_) => None,
+    }
+}
+
+/// Interleaves the halves a split view shows of `img`. Even rows of the
+/// result come from the left half and odd rows from the right one, or even
+/// and odd columns from the top and bottom halves when `horizontal`.
+fn comb_image(img: &RgbaImage, horizontal: bool) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    if horizontal {
+        let (len, second) = split_extent(h, 0.5);
+        RgbaImage::from_fn(w, len, |x, y| {
+            let y = if x % 2 == 0 { y } else { second + y };
+            *img.get_pixel(x, y)
+        })
+    } else {
+        let (len, second) = split_extent(w, 0.5);
+        RgbaImage::from_fn(len, h, |x, y| {
+            let x = if y % 2 == 0 { x } else { second + x };
+            *img.get_pixel(x, y)
+        })
+    }
+}
+
+/// Returns the length of both halves and the start of the second one when
+/// `full` is split at `factor`. The seam keeps at least one pixel on each
+/// side, so both halves are empty only when `full` is below two.
+fn split_extent(full: u32, factor: f32) -> (u32, u32) {
+    let seam = ((full as f32 * factor).round() as u32).clamp(1, full.saturating_sub(1).max(1));
+    let len = seam.min(full.saturating_sub(seam));
+    (len, full - len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::{Annotation, AnnotationShape};
+    use crate::geometry;
+
+    #[test]
+    fn split_extent_keeps_a_pixel_each_side() {
+        assert_eq!(split_extent(10, 0.5), (5, 5));
+        assert_eq!(split_extent(11, 0.5), (5, 6));
+        assert_eq!(split_extent(10, 0.3), (3, 7));
+        assert_eq!(split_extent(10, 0.0), (1, 9));
+        assert_eq!(split_extent(10, 1.0), (1, 9));
+        assert_eq!(split_extent(2, 1.0), (1, 1));
+        assert_eq!(split_extent(1, 0.5), (0, 1));
+        assert_eq!(split_extent(0, 0.5), (0, 0));
+    }
+
+    /// Image whose halves along `vertical` are equal apart from a white
+    /// middle column or row.
+    fn odd_halves(vertical: bool) -> RgbaImage {
+        let (w, h) = if vertical { (7, 3) } else { (3, 7) };
+        RgbaImage::from_fn(w, h, |x, y| {
+            let (along, across) = if vertical { (x, y) } else { (y, x) };
+            match along {
+                3 => Rgba([255, 255, 255, 255]),
+                _ => Rgba([(along % 4) as u8 * 40, across as u8 * 60, 100, 255]),
+            }
+        })
+    }
+
+    #[test]
+    fn odd_split_halves_show_no_phantom_edge() {
+        let cc = Context::default();
+        let source = ImageSource::File("odd.png".into());
+        for vertical in [true, false] {
+            let data = ImageData::full_image(&source, vec![odd_halves(vertical)], &cc);
+            let mode = if vertical {
+                DiffMode::VColorDiff
+            } else {
+                DiffMode::HColorDiff
+            };
+            let (one, two) = data.split_halves(mode).unwrap();
+            assert_eq!(one.dimensions(), (3, 3));
+            assert_eq!(one, two);
+            let diff = if vertical {
+                data.create_vdiff_image()
+            } else {
+                data.create_hdiff_image()
+            };
+            assert!(diff.regions.is_empty());
+            assert!(diff.image.pixels().all(|p| p.0[..3] == [0, 0, 0]));
+        }
+    }
+
+    /// Textures uploaded or changed since the last call.
+    fn uploads(cc: &Context) -> usize {
+        cc.tex_manager().write().take_delta().set.len()
+    }
+
+    #[test]
+    fn textures_are_uploaded_once_per_change() {
+        let cc = Context::default();
+        // The font texture.
+        uploads(&cc);
+        let source = ImageSource::File("uploads.png".into());
+        let img = RgbaImage::from_fn(8, 4, |x, y| Rgba([x as u8 * 30, y as u8 * 60, 0, 255]));
+        let mut data = ImageData::full_image(&source, vec![img], &cc);
+        assert_eq!(uploads(&cc), 1);
+        data.switch_to_color_image(&cc);
+        assert_eq!(uploads(&cc), 0);
+
+        // Only a new mode or gamma changes the difference texture.
+        for (mode, gamma, expected) in [
+            (DiffMode::VColorDiff, 2.2, 1),
+            (DiffMode::VColorDiff, 2.2, 0),
+            (DiffMode::HColorDiff, 2.2, 1),
+            (DiffMode::HColorDiff, 1.5, 1),
+            (DiffMode::HColorDiff, 1.5, 0),
+        ] {
+            match mode {
+                DiffMode::VColorDiff => data.switch_to_vertical_color_diff(&cc, gamma),
+                _ => data.switch_to_horizontal_color_diff(&cc, gamma),
+            }
+            data.switch_to_color_image(&cc);
+            assert_eq!(uploads(&cc), expected, "{:?} {}", mode, gamma);
+        }
+    }
+
+    #[test]
+    fn opaque_images_are_shown_as_they_are() {
+        let cc = Context::default();
+        let mut state = ImageUIState::new();
+        let source = ImageSource::File("alpha.png".into());
+        for (alpha, premultiplied) in [(255, false), (128, true)] {
+            let img = RgbaImage::from_pixel(4, 4, Rgba([100, 50, 0, alpha]));
+            let mut data = ImageData::full_image(&source, vec![img], &cc);
+            state.alpha_mode = AlphaMode::Auto;
+            assert_eq!(data.is_premultiplied(&state), premultiplied, "{}", alpha);
+            // Forced premultiplied, only translucent pixels need a copy.
+            state.alpha_mode = AlphaMode::Premultiplied;
+            data.update_mapped_image(&state);
+            assert_eq!(data.mapped_image.is_some(), premultiplied, "{}", alpha);
+        }
+    }
+
+    #[test]
+    fn images_too_large_to_upscale_are_reported() {
+        let cc = Context::default();
+        let mut state = ImageUIState::new();
+        state.texel_scale = 3.0;
+        let max_side = cc.input().max_texture_side as u32;
+        // Zoomed in 3 times the image is upscaled 4 times, a wide image only
+        // 2 times or not at all.
+        for (width, factor, limited) in [
+            (64, Some(4), false),
+            (max_side / 2, Some(2), true),
+            (max_side, None, true),
+        ] {
+            let source = ImageSource::File("nearest.png".into());
+            let img = RgbaImage::new(width, 4);
+            let mut data = ImageData::full_image(&source, vec![img], &cc);
+            data.restore_display_resources(&cc, &state);
+            assert_eq!(data.nearest_limited(), limited, "{}", width);
+            let upscale = data.nearest_texture.as_ref().map(|(f, _)| *f);
+            assert_eq!(upscale, factor, "{}", width);
+            // Up to 1:1 the image is drawn smoothed without an upscale.
+            state.texel_scale = 1.0;
+            data.restore_display_resources(&cc, &state);
+            assert!(!data.nearest_limited() && data.nearest_texture.is_none());
+            state.texel_scale = 3.0;
+        }
+    }
+
+    #[test]
+    fn gamma_lut_is_kept_for_the_same_gamma() {
+        let cc = Context::default();
+        let source = ImageSource::File("gamma.png".into());
+        let img = RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([(x * 16) as u8, (y * 16) as u8, 0, 255])
+        });
+        let mut data = ImageData::full_image(&source, vec![img.clone()], &cc);
+        for gamma in [2.2, 2.2, 1.5] {
+            let expected = ImageData::image_gamma(img.clone(), gamma);
+            assert_eq!(data.apply_gamma(img.clone(), gamma), expected);
+            assert_eq!(data.gamma_lut, Some((gamma, pixel_ops::gamma_lut(gamma))));
+        }
+    }
+
+    #[test]
+    fn tiny_images_go_through_the_pipeline() {
+        let cc = Context::default();
+        let source = ImageSource::File("tiny.png".into());
+        let view = Rect::from_min_size(Pos2::ZERO, vec2(400.0, 300.0));
+        for (w, h) in [(1, 1), (1, 10000), (10000, 1)] {
+            let data = ImageData::full_image(&source, vec![RgbaImage::new(w, h)], &cc);
+            for mode in [DiffMode::Full, DiffMode::VSplit, DiffMode::HSplit] {
+                let splits = match mode {
+                    DiffMode::VSplit => w >= 2,
+                    DiffMode::HSplit => h >= 2,
+                    _ => true,
+                };
+                assert_eq!(data.can_split(mode), splits, "{}x{} {:?}", w, h, mode);
+                if mode != DiffMode::Full {
+                    let halves = data.split_halves(mode);
+                    assert_eq!(halves.is_some(), splits);
+                    if let Some((one, two)) = halves {
+                        assert_eq!(one.dimensions(), two.dimensions());
+                        assert!(one.width() > 0 && one.height() > 0);
+                    }
+                }
+                let mut state = ImageUIState::new();
+                state.diff_mode = mode;
+                let layout = geometry::view_layout(&state, data.size(), view, 10.0);
+                assert!(layout.scale.is_finite() && layout.scale > 0.0);
+                for rect in layout.rects {
+                    assert!(rect.min.is_finite() && rect.max.is_finite(), "{:?}", rect);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn undo_takes_back_the_latest_edit() {
+        let cc = Context::default();
+        let source = ImageSource::File("undo.png".into());
+        let mut data = ImageData::full_image(&source, vec![RgbaImage::new(4, 2)], &cc);
+        let mut state = ImageUIState::new();
+        let pin = Annotation {
+            shape: AnnotationShape::Pin {
+                pos: [1.0, 1.0],
+                note: String::new(),
+            },
+            color: [255, 0, 0, 255],
+        };
+
+        data.apply_transform(&cc, &mut state, Transform::RotateLeft);
+        state.history.push(Edit::Annotations(Vec::new()));
+        state.annotations.items.push(pin.clone());
+        assert_eq!(data.size(), vec2(2.0, 4.0));
+
+        data.undo(&cc, &mut state);
+        assert!(state.annotations.items.is_empty());
+        assert_eq!(data.size(), vec2(2.0, 4.0));
+        data.undo(&cc, &mut state);
+        assert_eq!(data.size(), vec2(4.0, 2.0));
+        assert!(!data.can_undo(&state.history));
+
+        data.redo(&cc, &mut state);
+        assert_eq!(data.size(), vec2(2.0, 4.0));
+        assert!(state.annotations.items.is_empty());
+        data.redo(&cc, &mut state);
+        assert_eq!(state.annotations.items, vec![pin]);
+        assert!(!data.can_redo(&state.history));
+    }
+}
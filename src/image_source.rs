@@ -0,0 +1,94 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Identity of an entry in the image list. Files sort before archive
+/// entries, then compare pairs and in-memory images, each group in its
+/// natural order.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ImageSource {
+    File(PathBuf),
+    /// Image stored as `entry` of a ZIP archive.
+    Archive {
+        archive: PathBuf,
+        entry: String,
+    },
+    /// Two files from a `--from-file` line, loaded as the two pages of one
+    /// image so they can be compared page against page.
+    Pair {
+        a: PathBuf,
+        b: PathBuf,
+    },
+    /// Image without a backing file, like a pasted one. `id` tells apart
+    /// entries with the same name.
+    Memory {
+        name: String,
+        id: u64,
+    },
+}
+
+impl ImageSource {
+    /// File the image is stored in, the archive for archive entries.
+    pub fn file_path(&self) -> Option<&Path> {
+        match self {
+            ImageSource::File(path) => Some(path),
+            ImageSource::Archive { archive, .. } => Some(archive),
+            ImageSource::Pair { a, .. } => Some(a),
+            ImageSource::Memory { .. } => None,
+        }
+    }
+
+    pub fn file_name(&self) -> String {
+        match self {
+            ImageSource::File(path) => path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            ImageSource::Archive { entry, .. } => Path::new(entry)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            ImageSource::Pair { a, b } => format!(
+                "{} vs {}",
+                a.file_name().unwrap_or_default().to_string_lossy(),
+                b.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            ImageSource::Memory { name, .. } => name.clone(),
+        }
+    }
+
+    pub fn file_stem(&self) -> String {
+        match self {
+            ImageSource::File(path) => path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            ImageSource::Archive { entry, .. } => Path::new(entry)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            ImageSource::Pair { a, b } => format!(
+                "{}_vs_{}",
+                a.file_stem().unwrap_or_default().to_string_lossy(),
+                b.file_stem().unwrap_or_default().to_string_lossy()
+            ),
+            ImageSource::Memory { name, .. } => name.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ImageSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageSource::File(path) => write!(f, "{}", path.display()),
+            ImageSource::Archive { archive, entry } => {
+                write!(f, "{}#/{}", archive.display(), entry)
+            }
+            ImageSource::Pair { a, b } => write!(f, "{} vs {}", a.display(), b.display()),
+            ImageSource::Memory { name, .. } => write!(f, "{}", name),
+        }
+    }
+}
@@ -1,20 +1,252 @@
+use crate::annotations::{Annotation, Annotations};
+use crate::export::ExportResolution;
+use crate::pixel_ops::Colormap;
+use crate::reference::Reference;
 use eframe::egui::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq, Clone, Copy, Debug)]
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum DiffMode {
     Full,
     VSplit,
     VColorDiff,
     HSplit,
     HColorDiff,
+    /// Difference between two pages of a multi-page image.
+    PageDiff,
+    /// Difference to the version loaded before the file last changed.
+    PreviousDiff,
+    /// Left half, right half and their color difference in one row.
+    Triptych,
+    /// Difference to a generated image of the same size.
+    ReferenceDiff,
+}
+impl DiffMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DiffMode::Full => "Full image",
+            DiffMode::VSplit => "Vertical split",
+            DiffMode::VColorDiff => "Color difference vertical",
+            DiffMode::HSplit => "Horizontal split",
+            DiffMode::HColorDiff => "Color difference horizontal",
+            DiffMode::PageDiff => "Page difference",
+            DiffMode::PreviousDiff => "Previous version difference",
+            DiffMode::Triptych => "Triptych",
+            DiffMode::ReferenceDiff => "Reference difference",
+        }
+    }
+}
+
+/// How the color difference of two pixels is measured.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum DiffMetric {
+    /// Per channel difference of the sRGB encoded values.
+    Encoded,
+    /// Per channel difference of linear light, encoded back to sRGB.
+    Linear,
+    /// Perceptual CIEDE2000 difference, shown through a colormap.
+    DeltaE,
+}
+
+/// Row or column picked for the intensity profile, in image pixels.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ProfileLine {
+    Row(u32),
+    Column(u32),
+}
+
+impl ProfileLine {
+    /// The line moved by `step` pixels across itself, kept within a
+    /// `width`x`height` image.
+    pub fn moved(self, step: i64, width: u32, height: u32) -> Self {
+        let shift = |v: u32, len: u32| (v as i64 + step).clamp(0, len.max(1) as i64 - 1) as u32;
+        match self {
+            ProfileLine::Row(y) => ProfileLine::Row(shift(y, height)),
+            ProfileLine::Column(x) => ProfileLine::Column(shift(x, width)),
+        }
+    }
+}
+
+/// Action on the file behind an image, run by the app.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum FileAction {
+    /// Put the canonical path on the clipboard.
+    CopyPath,
+    /// Show the file in the system file browser.
+    Reveal,
+    /// Read the image again after it failed to load.
+    Reload,
+}
+
+/// Aid drawn at the seam of split views to check the halves line up.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum SeamGuide {
+    None,
+    /// A line with tick marks anchored to image pixels.
+    Line,
+    /// The line over a strip whose rows (or columns) alternate between the
+    /// halves, misalignment shows up as jagged edges.
+    Comb,
+}
+
+/// How magnified images are sampled.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum TextureFilter {
+    /// Smooth pixels at any zoom.
+    Linear,
+    /// Linear up to 1:1, switching to crisp pixels when zoomed in beyond,
+    /// the default.
+    Nearest,
+}
+
+/// How the color channels of pixels are read against their alpha.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AlphaMode {
+    /// Premultiplied when no color channel exceeds its alpha.
+    Auto,
+    Straight,
+    Premultiplied,
 }
+
+/// Maximal number of edits kept for undo.
+const MAX_EDITS: usize = 64;
+
+/// An edit of the image that can be undone.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Edit {
+    /// A rotation or flip, `ImageData` keeps the transforms themselves.
+    Transform,
+    /// Annotations as they were on the other side of the edit.
+    Annotations(Vec<Annotation>),
+}
+
+/// One undo history for the transforms and the annotations of an image, so
+/// that undo takes back the latest edit whichever it was. Undoing and
+/// redoing are done by `ImageData::undo` and `ImageData::redo`.
+#[derive(Default)]
+pub struct EditHistory {
+    pub undo: Vec<Edit>,
+    pub redo: Vec<Edit>,
+}
+
+impl EditHistory {
+    /// Records an edit about to be made, the undone ones can't be redone
+    /// after it.
+    pub fn push(&mut self, edit: Edit) {
+        if self.undo.len() == MAX_EDITS {
+            self.undo.remove(0);
+        }
+        self.undo.push(edit);
+        self.redo.clear();
+    }
+}
+
+/// Widget whose pointer input moves the view, see `ImageUIState::claim_view`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ViewInput {
+    /// The image view itself.
+    Main,
+    /// The preview in the controls.
+    Preview,
+}
+
+/// Non-destructive changes to how the image is shown, the image data and
+/// diffs keep the original values.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Adjustments {
+    /// In stops, each one doubles the light.
+    pub exposure: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+}
+
+impl Adjustments {
+    pub fn new() -> Self {
+        Self {
+            exposure: 0.0,
+            brightness: 0.0,
+            contrast: 1.0,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::new()
+    }
+}
+
 pub struct ImageUIState {
     pub diff_mode: DiffMode,
     pub color_diff_vsplite_gamma: f32,
     pub color_diff_hsplite_gamma: f32,
     pub vsplit_factor: f32,
     pub hsplit_factor: f32,
+    pub seam_guide: SeamGuide,
+    /// Width of the comb strip across the seam in image pixels.
+    pub comb_width: u32,
+    /// Zero-based pages compared in `DiffMode::PageDiff`.
+    pub diff_pages: (usize, usize),
+    pub page_diff_gamma: f32,
+    pub previous_diff_gamma: f32,
+    /// Image compared with in `DiffMode::ReferenceDiff`.
+    pub reference: Reference,
+    pub reference_diff_gamma: f32,
+    pub diff_metric: DiffMetric,
+    /// Plot the difference of the compared histograms instead of both.
+    pub histogram_difference: bool,
+    pub filter: TextureFilter,
+    /// Outline the pixels when zoomed in far enough, full image mode only.
+    pub pixel_grid: bool,
+    /// Fit the image into the view as is, otherwise it's stretched to fill
+    /// the view along both axes.
+    pub preserve_aspect: bool,
+    /// Screen pixels per image pixel the view was last drawn with.
+    pub texel_scale: f32,
+    /// Image pixel under the pointer, tracked in full image mode only.
+    pub hovered_pixel: Option<(u32, u32)>,
+    /// Side of the square averaged by the color picker, 1, 3 or 5.
+    pub sample_size: u32,
+    /// Video timestamp in seconds the user asked to see.
+    pub video_seek: Option<f64>,
+    pub colormap: Colormap,
+    /// Shows color images as single channel data, read as their luma.
+    pub force_single_channel: bool,
+    /// Spreads the value range found in the image over the whole colormap.
+    pub auto_stretch: bool,
+    /// Values mapped to the ends of the colormap without auto stretch.
+    pub value_range: (u8, u8),
+    pub adjustments: Adjustments,
+    pub alpha_mode: AlphaMode,
+    /// Marks color hidden under transparency in the view.
+    pub show_alpha_anomalies: bool,
+    /// Alpha below this value counts as transparent for the anomaly check.
+    pub alpha_threshold: u8,
+    /// Index of the anomaly region the view jumps to next.
+    pub next_anomaly: usize,
+    /// Changed region of the difference the view was last moved to, see
+    /// `step_difference`.
+    pub difference_index: Option<usize>,
+    pub alignment_requested: bool,
+    pub annotations: Annotations,
+    pub history: EditHistory,
+    /// Write the annotations to the sidecar file next to the image.
+    pub annotations_save_requested: bool,
+    pub export_resolution: ExportResolution,
+    pub export_requested: bool,
+    pub file_action_requested: Option<FileAction>,
+    /// Clicks on the view pick the profile line instead of editing
+    /// annotations.
+    pub profile_tool: bool,
+    pub profile_line: Option<ProfileLine>,
+    /// Image pixel a Ctrl+drag zoom selection started at.
+    pub zoom_drag_start: Option<Pos2>,
+    /// Input that moves the view until it lets go, see `claim_view`.
+    view_owner: Option<ViewInput>,
+    pub view_size: Vec2,
     scale: Option<f32>,
+    fit_scale: f32,
+    /// Magnification waiting for the fit scale to be known, see
+    /// `set_initial_magnification`.
+    initial_magnification: Option<f32>,
     view_center: Pos2,
 }
 
@@ -28,12 +260,62 @@ impl ImageUIState {
             color_diff_vsplite_gamma: 2.2,
             color_diff_hsplite_gamma: 2.2,
             scale: None,
+            fit_scale: 1.0,
             vsplit_factor: 0.5,
             hsplit_factor: 0.5,
+            seam_guide: SeamGuide::None,
+            comb_width: 32,
+            diff_pages: (0, 1),
+            page_diff_gamma: 2.2,
+            previous_diff_gamma: 2.2,
+            reference: Reference::new(),
+            reference_diff_gamma: 2.2,
+            diff_metric: DiffMetric::Encoded,
+            histogram_difference: false,
+            filter: TextureFilter::Nearest,
+            pixel_grid: true,
+            preserve_aspect: true,
+            texel_scale: 1.0,
+            hovered_pixel: None,
+            sample_size: 1,
+            video_seek: None,
+            colormap: Colormap::Grayscale,
+            force_single_channel: false,
+            auto_stretch: false,
+            value_range: (0, 255),
+            adjustments: Adjustments::new(),
+            alpha_mode: AlphaMode::Straight,
+            show_alpha_anomalies: false,
+            alpha_threshold: 1,
+            next_anomaly: 0,
+            difference_index: None,
+            alignment_requested: false,
+            annotations: Annotations::new(),
+            history: EditHistory::default(),
+            annotations_save_requested: false,
+            export_resolution: ExportResolution::Screen,
+            export_requested: false,
+            file_action_requested: None,
+            profile_tool: false,
+            profile_line: None,
+            zoom_drag_start: None,
+            view_owner: None,
+            view_size: Vec2::ZERO,
+            initial_magnification: None,
             view_center: Pos2::new(0.5, 0.5),
         }
     }
 
+    /// Whether the view shows adjusted pixels instead of the image data,
+    /// diff modes always show the original values.
+    pub fn is_adjusted(&self) -> bool {
+        !self.adjustments.is_identity()
+            && matches!(
+                self.diff_mode,
+                DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit
+            )
+    }
+
     pub fn scale(&self) -> f32 {
         self.scale.unwrap_or(1.0)
     }
@@ -43,6 +325,48 @@ impl ImageUIState {
         self.fix_bounds()
     }
 
+    /// Screen pixels per image pixel when the whole image fits the view.
+    pub fn fit_scale(&self) -> f32 {
+        self.fit_scale
+    }
+
+    pub fn set_fit_scale(&mut self, fit_scale: f32) {
+        self.fit_scale = fit_scale;
+        if let Some(magnification) = self.initial_magnification.take() {
+            self.set_magnification(magnification);
+        }
+    }
+
+    /// Zooms to `magnification` the first time the image is laid out, before
+    /// that the fit scale it's relative to isn't known.
+    pub fn set_initial_magnification(&mut self, magnification: f32) {
+        self.initial_magnification = Some(magnification);
+    }
+
+    /// Screen pixels per image pixel at the current zoom, 1.0 means 1:1.
+    pub fn magnification(&self) -> f32 {
+        self.fit_scale / self.scale()
+    }
+
+    pub fn set_magnification(&mut self, magnification: f32) {
+        self.set_scale(self.fit_scale / magnification);
+    }
+
+    /// Switches between actual size and the whole image in view.
+    pub fn toggle_actual_size(&mut self) {
+        if (self.magnification() - 1.0).abs() < 1e-3 {
+            self.set_scale(1.0);
+        } else {
+            self.set_magnification(1.0);
+        }
+    }
+
+    /// Goes back to the view the image opened with, centered at the zoom
+    /// the next layout picks.
+    pub fn reset_view(&mut self) {
+        self.restore_view(None, Pos2::new(0.5, 0.5));
+    }
+
     pub fn set_scale_if_none(&mut self, scale: f32) {
         if self.scale.is_none() {
             self.set_scale(scale)
@@ -59,6 +383,76 @@ impl ImageUIState {
         self.fix_bounds();
     }
 
+    /// Zoom, if set yet, and center of the view, see `restore_view`.
+    pub fn view(&self) -> (Option<f32>, Pos2) {
+        (self.scale, self.view_center)
+    }
+
+    /// Puts back a view taken with `view`, it wins over a pending initial
+    /// magnification.
+    pub fn restore_view(&mut self, scale: Option<f32>, center: Pos2) {
+        self.scale = scale;
+        self.view_center = center;
+        if scale.is_some() {
+            self.initial_magnification = None;
+        }
+        self.fix_bounds();
+    }
+
+    /// Moves the view to `center`, given in texture coordinates.
+    pub fn set_center(&mut self, center: Pos2) {
+        self.view_center = center;
+        self.fix_bounds();
+    }
+
+    /// Moves the view to the region `step` places from the one visited last,
+    /// wrapping around. `regions` are in texture coordinates, each is shown
+    /// with a margin of half its size.
+    pub fn step_difference(&mut self, regions: &[Rect], step: i64) {
+        if regions.is_empty() {
+            return;
+        }
+        let count = regions.len() as i64;
+        let index = match self.difference_index {
+            Some(index) => (index as i64 + step).rem_euclid(count),
+            None if step > 0 => 0,
+            None => count - 1,
+        } as usize;
+        self.difference_index = Some(index);
+        let region = regions[index];
+        self.zoom_to(region.expand(region.size().max_elem() / 2.0));
+    }
+
+    /// Whether `input` may move the view this frame, `active` while it's
+    /// dragged, pinched or scrolled. An active input takes the view when no
+    /// other holds it and keeps it until it's no longer active, the other
+    /// input is ignored meanwhile. Once no button is held the other input
+    /// may take over, so a holder that stopped being shown can't block it.
+    pub fn claim_view(&mut self, input: ViewInput, active: bool, pointer_down: bool) -> bool {
+        match self.view_owner {
+            Some(owner) if owner != input && pointer_down => false,
+            _ => {
+                self.view_owner = active.then_some(input);
+                true
+            }
+        }
+    }
+
+    /// Zooms in by `factor` while the view point `anchor` stays in place on
+    /// the screen, see `geometry::view_point`.
+    pub fn zoom_about(&mut self, anchor: Pos2, factor: f32) {
+        let (scale, center) = (self.scale(), self.view_center);
+        self.set_scale(scale / factor);
+        let ratio = self.scale() / scale;
+        self.set_center(anchor + (center - anchor) * ratio);
+    }
+
+    /// Zooms so that `uv`, given in texture coordinates, fills the view.
+    pub fn zoom_to(&mut self, uv: Rect) {
+        self.view_center = uv.center();
+        self.set_scale(uv.width().max(uv.height()));
+    }
+
     fn fix_bounds(&mut self) {
         if self.scale.is_some() {
             self.scale = Some(self.scale.unwrap().clamp(Self::ZOOM_MIN, Self::ZOOM_MAX));
@@ -95,36 +489,211 @@ impl ImageUIState {
     }
 
     pub fn uv_full(&self) -> Rect {
-        let r = Rect::from_min_max(
+        Rect::from_min_max(
             pos2(self.left(), self.top()),
             pos2(self.right(), self.bottom()),
-        );
-        r
+        )
+    }
+
+    /// Maps a viewport coordinate within one half of the image to texture
+    /// coordinates of the first (`second == false`) or second half. Both
+    /// halves use the same mapping, so equal inputs show matching pixels.
+    fn in_half(v: f32, second: bool) -> f32 {
+        v / 2.0 + if second { 0.5 } else { 0.0 }
     }
 
+    /// Texture rects of the left and right halves for the current viewport.
+    /// The left panel shows the viewport up to the seam at `ratio` of its
+    /// width, the right panel the same window of the other half from there.
     pub fn uv_vsplit(&self, ratio: f32) -> [Rect; 2] {
-        let s = self.scale.unwrap_or(1.0) / 2.0;
-        let lr = Rect::from_min_max(
-            pos2(self.left() / 2.0, self.top()),
-            pos2(self.right() / 2.0 - (1.0 - ratio) * s, self.bottom()),
-        );
-        let rr = Rect::from_min_max(
-            pos2(self.left() / 2.0 + 0.5 + ratio * s, self.top()),
-            pos2(self.right() / 2.0 + 0.5, self.bottom()),
-        );
-        [lr, rr]
+        let seam = self.left() + ratio * self.scale();
+        let (top, bottom) = (self.top(), self.bottom());
+        [
+            Rect::from_min_max(
+                pos2(Self::in_half(self.left(), false), top),
+                pos2(Self::in_half(seam, false), bottom),
+            ),
+            Rect::from_min_max(
+                pos2(Self::in_half(seam, true), top),
+                pos2(Self::in_half(self.right(), true), bottom),
+            ),
+        ]
     }
 
+    /// Texture rects of the whole viewport in the left and in the right
+    /// half, the source panels of the triptych.
+    pub fn uv_halves(&self) -> [Rect; 2] {
+        let (top, bottom) = (self.top(), self.bottom());
+        [false, true].map(|second| {
+            Rect::from_min_max(
+                pos2(Self::in_half(self.left(), second), top),
+                pos2(Self::in_half(self.right(), second), bottom),
+            )
+        })
+    }
+
+    /// Same as `uv_vsplit` for the top and bottom halves.
     pub fn uv_hsplit(&self, ratio: f32) -> [Rect; 2] {
-        let s = self.scale.unwrap_or(1.0) / 2.0;
-        let lr = Rect::from_min_max(
-            pos2(self.left(), self.top() / 2.0),
-            pos2(self.right(), self.bottom() / 2.0 - (1.0 - ratio) * s),
-        );
-        let rr = Rect::from_min_max(
-            pos2(self.left(), self.top() / 2.0 + 0.5 + ratio * s),
-            pos2(self.right(), self.bottom() / 2.0 + 0.5),
+        let seam = self.top() + ratio * self.scale();
+        let (left, right) = (self.left(), self.right());
+        [
+            Rect::from_min_max(
+                pos2(left, Self::in_half(self.top(), false)),
+                pos2(right, Self::in_half(seam, false)),
+            ),
+            Rect::from_min_max(
+                pos2(left, Self::in_half(seam, true)),
+                pos2(right, Self::in_half(self.bottom(), true)),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f32 = 1e-6;
+
+    /// Default view, fully zoomed in, and zoomed in against each corner.
+    fn split_states() -> Vec<ImageUIState> {
+        let mut states = vec![ImageUIState::new()];
+        for center in [
+            pos2(0.5, 0.5),
+            pos2(0.0, 0.0),
+            pos2(1.0, 1.0),
+            pos2(0.0, 1.0),
+        ] {
+            for scale in [ImageUIState::ZOOM_MIN, 0.3] {
+                let mut state = ImageUIState::new();
+                state.restore_view(Some(scale), center);
+                states.push(state);
+            }
+        }
+        states
+    }
+
+    fn assert_in_unit(r: Rect) {
+        assert!(r.min.x >= -EPS && r.min.y >= -EPS, "{:?}", r);
+        assert!(r.max.x <= 1.0 + EPS && r.max.y <= 1.0 + EPS, "{:?}", r);
+        assert!(
+            r.min.x <= r.max.x + EPS && r.min.y <= r.max.y + EPS,
+            "{:?}",
+            r
         );
-        [lr, rr]
+    }
+
+    #[test]
+    fn uv_vsplit_stays_in_its_half() {
+        for state in split_states() {
+            for ratio in [0.0, 0.5, 1.0] {
+                let [a, b] = state.uv_vsplit(ratio);
+                assert_in_unit(a);
+                assert_in_unit(b);
+                assert!(a.max.x <= 0.5 + EPS && b.min.x >= 0.5 - EPS);
+                // The right panel goes on where the left one stops.
+                assert!((a.max.x + 0.5 - b.min.x).abs() < EPS);
+                assert!((a.width() + b.width() - state.scale() / 2.0).abs() < EPS);
+            }
+        }
+    }
+
+    #[test]
+    fn uv_hsplit_stays_in_its_half() {
+        for state in split_states() {
+            for ratio in [0.0, 0.5, 1.0] {
+                let [a, b] = state.uv_hsplit(ratio);
+                assert_in_unit(a);
+                assert_in_unit(b);
+                assert!(a.max.y <= 0.5 + EPS && b.min.y >= 0.5 - EPS);
+                assert!((a.max.y + 0.5 - b.min.y).abs() < EPS);
+                assert!((a.height() + b.height() - state.scale() / 2.0).abs() < EPS);
+            }
+        }
+    }
+
+    /// Views over a grid of zooms and centers, including centers the
+    /// bounds move back inside.
+    fn view_grid() -> impl Iterator<Item = ImageUIState> {
+        let scales = [
+            ImageUIState::ZOOM_MIN,
+            0.1,
+            0.25,
+            0.5,
+            0.75,
+            ImageUIState::ZOOM_MAX,
+        ];
+        scales.into_iter().flat_map(|scale| {
+            (0..=6).flat_map(move |i| {
+                (0..=6).map(move |j| {
+                    let mut state = ImageUIState::new();
+                    let center = pos2(i as f32 / 4.0 - 0.25, j as f32 / 4.0 - 0.25);
+                    state.restore_view(Some(scale), center);
+                    state
+                })
+            })
+        })
+    }
+
+    #[test]
+    fn split_panels_show_one_window_of_both_halves() {
+        for state in view_grid() {
+            let full = state.uv_full();
+            for i in 0..=10 {
+                let ratio = i as f32 / 10.0;
+                // Within-half coordinates, 0 to 1 across each half.
+                let [a, b] = state.uv_vsplit(ratio);
+                let (a, b) = (
+                    (a.min.x * 2.0, a.max.x * 2.0),
+                    ((b.min.x - 0.5) * 2.0, (b.max.x - 0.5) * 2.0),
+                );
+                assert!((a.1 - b.0).abs() < EPS, "{:?} {:?} at {}", a, b, ratio);
+                assert!((a.0 - full.left()).abs() < EPS && (b.1 - full.right()).abs() < EPS);
+                let [a, b] = state.uv_hsplit(ratio);
+                let (a, b) = (
+                    (a.min.y * 2.0, a.max.y * 2.0),
+                    ((b.min.y - 0.5) * 2.0, (b.max.y - 0.5) * 2.0),
+                );
+                assert!((a.1 - b.0).abs() < EPS, "{:?} {:?} at {}", a, b, ratio);
+                assert!((a.0 - full.top()).abs() < EPS && (b.1 - full.bottom()).abs() < EPS);
+            }
+        }
+    }
+
+    #[test]
+    fn claim_view_is_held_while_active() {
+        let mut state = ImageUIState::new();
+        assert!(state.claim_view(ViewInput::Main, true, true));
+        assert_eq!(state.view_owner, Some(ViewInput::Main));
+        assert!(state.claim_view(ViewInput::Main, true, true));
+        assert!(!state.claim_view(ViewInput::Preview, true, true));
+        assert_eq!(state.view_owner, Some(ViewInput::Main));
+    }
+
+    #[test]
+    fn claim_view_is_released_when_inactive() {
+        let mut state = ImageUIState::new();
+        assert!(state.claim_view(ViewInput::Main, true, true));
+        assert!(state.claim_view(ViewInput::Main, false, false));
+        assert_eq!(state.view_owner, None);
+        assert!(state.claim_view(ViewInput::Preview, true, true));
+        assert_eq!(state.view_owner, Some(ViewInput::Preview));
+    }
+
+    #[test]
+    fn claim_view_competing_input_waits_for_buttons_up() {
+        let mut state = ImageUIState::new();
+        assert!(state.claim_view(ViewInput::Preview, true, true));
+        // The holder is no longer shown, so it never releases the view.
+        assert!(!state.claim_view(ViewInput::Main, true, true));
+        assert!(state.claim_view(ViewInput::Main, false, false));
+        assert_eq!(state.view_owner, None);
+    }
+
+    #[test]
+    fn claim_view_inactive_input_takes_nothing() {
+        let mut state = ImageUIState::new();
+        assert!(state.claim_view(ViewInput::Main, false, false));
+        assert_eq!(state.view_owner, None);
     }
 }
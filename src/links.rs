@@ -0,0 +1,59 @@
+//! Symlinked images are listed under the path of the link, only reading
+//! them and watching for their changes goes through the target.
+
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Links followed in a row before the chain counts as a cycle, the limit
+/// Linux puts on path resolution.
+const MAX_LINK_DEPTH: usize = 40;
+
+pub fn is_link(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Absolute form of `path` that keeps a symlinked file as the link, only
+/// the folders above it are resolved. Anything else is canonicalized.
+pub fn absolute(path: &Path) -> std::io::Result<PathBuf> {
+    if is_link(path) && !path.is_dir() {
+        if let (Some(dir), Some(name)) = (path.parent(), path.file_name()) {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            return Ok(dir.canonicalize()?.join(name));
+        }
+    }
+    path.canonicalize()
+}
+
+/// File the contents of `path` are read from, its canonical form unless
+/// it's a symlink. Broken links and link cycles are errors that name the
+/// missing target or the link the cycle returns to.
+pub fn resolve(path: &Path) -> std::io::Result<PathBuf> {
+    let mut current = path.to_path_buf();
+    let mut seen = HashSet::new();
+    loop {
+        match std::fs::symlink_metadata(&current) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                if !seen.insert(current.clone()) || seen.len() > MAX_LINK_DEPTH {
+                    let msg = format!("Symlink cycle through {}", current.display());
+                    return Err(Error::new(ErrorKind::InvalidData, msg));
+                }
+                let target = std::fs::read_link(&current)?;
+                current = match current.parent() {
+                    Some(dir) => dir.join(target),
+                    None => target,
+                };
+            }
+            Ok(_) => return current.canonicalize(),
+            Err(e) if e.kind() == ErrorKind::NotFound && current != path => {
+                let msg = format!("Broken link, {} doesn't exist", current.display());
+                return Err(Error::new(ErrorKind::NotFound, msg));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
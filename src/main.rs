@@ -1,43 +1,470 @@
+mod alignment;
+mod alpha_anomalies;
+mod annotations;
+mod archive;
+mod decode;
+mod delta_e;
+mod diff_regions;
+mod error;
+mod export;
 mod filesystem;
+mod geometry;
 mod image_data;
+mod image_source;
 mod image_ui_state;
+mod links;
+mod montage;
+mod pixel_ops;
+mod raster;
+mod reference;
+mod region_stats;
+mod report;
+mod session;
+mod single_instance;
+mod theme;
 mod utils;
+#[cfg(feature = "video")]
+mod video;
 mod widgets;
 
+use alignment::AlignmentStatus;
+use decode::{ThumbnailFilter, ThumbnailScaling};
+use image::RgbaImage;
 use image_data::ImageData;
-use image_ui_state::{DiffMode, ImageUIState};
+use image_source::ImageSource;
+use image_ui_state::{DiffMode, FileAction, ImageUIState};
+use montage::MontageSettings;
+use region_stats::{Region, RegionKey};
+use session::{Session, SessionImage, ViewState};
+use single_instance::{Instance, PrimaryInstance};
+use theme::Theme;
 
 use cached::{Cached, SizedCache};
 use clap::Parser;
+use crossbeam::channel::Receiver;
 use eframe::egui::{self, Context};
 use egui_extras::{Size, StripBuilder};
-use filesystem::{FileSystem, FileSystemEvent};
-use log::{trace, warn};
+use filesystem::{
+    rename_target, FileHash, FileSystem, FileSystemEvent, PartialImage, PoolSizes, ScanFilter,
+};
+use log::{error, info, trace, warn};
 use simple_logger::SimpleLogger;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use widgets::{ImageControls, ImageView, Thumbnail};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use utils::{file_size, format_bytes, local_timestamp, reveal_in_file_browser, spawn_with_path};
+use widgets::{
+    file_action_menu, CompareReport, CompareReportAction, HoverPreview, ImageControls, ImageView,
+    PointerBindings, QuickOpen, QuickOpenAction, Thumbnail, Toast,
+};
+
+const CLI_EXAMPLES: &str = "EXAMPLES:
+    imview renders/
+    imview --mode vcolordiff --gamma 3.0 --zoom 200 stitched.png
+    imview --mode hsplit --split 0.25 before_after.png";
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about)]
+#[clap(author, version, about, after_help = CLI_EXAMPLES)]
 struct CliArguments {
     #[clap(min_values(1))]
     path: Vec<PathBuf>,
+    /// Number of threads decoding full images [default: number of CPUs]
+    #[clap(long)]
+    decode_threads: Option<usize>,
+    /// Number of threads loading thumbnails [default: half of the CPUs]
+    #[clap(long)]
+    thumb_threads: Option<usize>,
+    /// List files and folders whose names start with a dot
+    #[clap(long)]
+    show_hidden: bool,
+    /// Don't read the first bytes of files without an image extension to
+    /// tell if they are images, for slow network folders
+    #[clap(long)]
+    no_sniff: bool,
+    /// Glob pattern of file or folder names, or full paths, to leave out of
+    /// the list on top of the ones in the settings, can be repeated
+    #[clap(long, value_name = "PATTERN", multiple_occurrences(true), parse(try_from_str = parse_ignore))]
+    ignore: Vec<String>,
+    /// Also watch the folders symlinked images point into, up to 16, so
+    /// changes of the targets reload the links
+    #[clap(long)]
+    watch_link_targets: bool,
+    /// Switch to images as they are created or changed in the watched folder
+    #[clap(long)]
+    follow: bool,
+    /// Milliseconds a changed file has to stay unchanged before it's reloaded
+    #[clap(long, default_value_t = 300)]
+    settle_ms: u64,
+    /// Open the paths in an already running window instead of a new one
+    #[clap(long)]
+    single_instance: bool,
+    /// Session file to reopen the review from, and to save it to on exit,
+    /// instead of the last session
+    #[clap(long, value_name = "FILE")]
+    session: Option<PathBuf>,
+    /// Shell command run with the path of each image shown, e.g. "echo"
+    #[clap(long, value_name = "CMD")]
+    on_select: Option<String>,
+    /// Longest image side in pixels, longer images are downscaled and ones
+    /// with more pixels than this squared are refused
+    #[clap(long, value_name = "PX", default_value_t = filesystem::DEFAULT_MAX_DIMENSION)]
+    max_dimension: u32,
+    /// Memory in MiB decoding an image may allocate at once, images needing
+    /// more are refused [default: the decoders' own limits]
+    #[clap(long, value_name = "MIB")]
+    max_memory: Option<u64>,
+    /// Log how long each image and thumbnail takes to decode
+    #[clap(long)]
+    timings: bool,
+    /// Filter thumbnails are shrunk with, box averages in linear light
+    #[clap(long, arg_enum, ignore_case = true, default_value_t = ThumbnailFilter::Box)]
+    thumb_filter: ThumbnailFilter,
+    /// Look of the app, changeable in the settings [default: the last one
+    /// used, dark at first]
+    #[clap(long, arg_enum, ignore_case = true)]
+    theme: Option<Theme>,
+    /// Text file listing an image path per line, or two tab separated paths
+    /// to compare as a pair. Relative paths start at the file's folder,
+    /// lines starting with # are skipped
+    #[clap(long, value_name = "FILE")]
+    from_file: Option<PathBuf>,
+    /// Diff mode the images open in
+    #[clap(long, arg_enum, ignore_case = true, default_value_t = StartMode::Full)]
+    mode: StartMode,
+    /// Gamma of the difference modes, from 1 to 5 [default: 2.2]
+    #[clap(long, parse(try_from_str = parse_gamma))]
+    gamma: Option<f32>,
+    /// Share of the image left of the vertical and above the horizontal
+    /// split, from 0 to 1 [default: 0.5]
+    #[clap(long, value_name = "FACTOR", parse(try_from_str = parse_split))]
+    split: Option<f32>,
+    /// Zoom the images open at, in percent of their actual size [default:
+    /// fit the window]
+    #[clap(long, value_name = "PERCENT", parse(try_from_str = parse_zoom))]
+    zoom: Option<f32>,
+}
+
+/// Diff modes `--mode` accepts, the previous version difference needs a
+/// file that changed so it can't start in it.
+#[derive(clap::ArgEnum, Clone, Copy, Debug)]
+#[clap(rename_all = "lower")]
+enum StartMode {
+    Full,
+    VSplit,
+    VColorDiff,
+    HSplit,
+    HColorDiff,
+    PageDiff,
+    Triptych,
+}
+
+impl From<StartMode> for DiffMode {
+    fn from(mode: StartMode) -> Self {
+        match mode {
+            StartMode::Full => DiffMode::Full,
+            StartMode::VSplit => DiffMode::VSplit,
+            StartMode::VColorDiff => DiffMode::VColorDiff,
+            StartMode::HSplit => DiffMode::HSplit,
+            StartMode::HColorDiff => DiffMode::HColorDiff,
+            StartMode::PageDiff => DiffMode::PageDiff,
+            StartMode::Triptych => DiffMode::Triptych,
+        }
+    }
+}
+
+fn parse_in_range(value: &str, min: f32, max: f32) -> Result<f32, String> {
+    let value = value.parse::<f32>().map_err(|e| e.to_string())?;
+    if value >= min && value <= max {
+        Ok(value)
+    } else {
+        Err(format!("must be between {} and {}", min, max))
+    }
+}
+
+fn parse_gamma(value: &str) -> Result<f32, String> {
+    parse_in_range(value, 1.0, 5.0)
+}
+
+fn parse_split(value: &str) -> Result<f32, String> {
+    parse_in_range(value, 0.0, 1.0)
+}
+
+fn parse_ignore(value: &str) -> Result<String, String> {
+    filesystem::parse_glob(value)
+        .map(|_| value.to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_zoom(value: &str) -> Result<f32, String> {
+    let value = value.parse::<f32>().map_err(|e| e.to_string())?;
+    if value > 0.0 && value.is_finite() {
+        Ok(value)
+    } else {
+        Err("must be a positive percentage".to_string())
+    }
+}
+
+/// View settings from the command line every listed image starts with.
+struct ViewDefaults {
+    diff_mode: DiffMode,
+    gamma: Option<f32>,
+    split: Option<f32>,
+    /// Magnification, 1.0 is actual size.
+    zoom: Option<f32>,
+}
+
+impl ViewDefaults {
+    fn new() -> Self {
+        Self {
+            diff_mode: DiffMode::Full,
+            gamma: None,
+            split: None,
+            zoom: None,
+        }
+    }
+
+    fn image_state(&self) -> ImageUIState {
+        let mut state = ImageUIState::new();
+        state.diff_mode = self.diff_mode;
+        if let Some(gamma) = self.gamma {
+            state.color_diff_vsplite_gamma = gamma;
+            state.color_diff_hsplite_gamma = gamma;
+            state.page_diff_gamma = gamma;
+            state.previous_diff_gamma = gamma;
+            state.reference_diff_gamma = gamma;
+        }
+        if let Some(split) = self.split {
+            state.vsplit_factor = split;
+            state.hsplit_factor = split;
+        }
+        if let Some(zoom) = self.zoom {
+            state.set_initial_magnification(zoom);
+        }
+        state
+    }
+}
+
+/// Paths listed in a `--from-file` manifest, single images and compare
+/// pairs.
+struct Manifest {
+    paths: Vec<PathBuf>,
+    pairs: Vec<(PathBuf, PathBuf)>,
+}
+
+fn read_manifest(path: &Path) -> std::io::Result<Manifest> {
+    let text = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut manifest = Manifest {
+        paths: Vec::new(),
+        pairs: Vec::new(),
+    };
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line
+            .split('\t')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(|f| dir.join(f))
+            .collect::<Vec<_>>();
+        match <[PathBuf; 2]>::try_from(fields) {
+            Ok([a, b]) => manifest.pairs.push((a, b)),
+            Err(mut fields) if fields.len() == 1 => manifest.paths.append(&mut fields),
+            Err(fields) => warn!(
+                "{}:{}: expected one or two paths, found {}",
+                path.display(),
+                number + 1,
+                fields.len()
+            ),
+        }
+    }
+    Ok(manifest)
 }
 
 fn main() {
     SimpleLogger::new().init().unwrap();
-    let args = CliArguments::parse();
-    let mut options = eframe::NativeOptions::default();
-    options.initial_window_size = Some(egui::Vec2::new(800 as _, 600 as _));
-    options.maximized = true;
+    let mut args = CliArguments::parse();
+    let mut pairs = Vec::new();
+    if let Some(manifest) = args.from_file.as_ref() {
+        match read_manifest(manifest) {
+            Ok(mut manifest) => {
+                args.path.append(&mut manifest.paths);
+                pairs = manifest.pairs;
+            }
+            Err(e) => {
+                error!("Can't read {}: {}", manifest.display(), e);
+                return;
+            }
+        }
+    }
+    let mut pool_sizes = PoolSizes::new();
+    if let Some(threads) = args.decode_threads {
+        pool_sizes.decode_threads = threads.max(1);
+    }
+    if let Some(threads) = args.thumb_threads {
+        pool_sizes.thumb_threads = threads.max(1);
+    }
+    let mut filter = ScanFilter::new();
+    filter.show_hidden = args.show_hidden;
+    #[cfg(feature = "video")]
+    if let Err(e) = video::init() {
+        warn!("Video support is unavailable: {}", e);
+    }
+    let instance = if args.single_instance {
+        match single_instance::acquire(&args.path) {
+            Ok(Instance::Forwarded) => return,
+            Ok(Instance::Primary(instance)) => Some(instance),
+            Err(e) => {
+                warn!("Can't reach a running instance, opening a new one: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let options = eframe::NativeOptions {
+        initial_window_size: Some(egui::Vec2::new(800 as _, 600 as _)),
+        maximized: true,
+        ..Default::default()
+    };
     eframe::run_native(
         "iMView",
         options,
-        Box::new(|cc| {
+        Box::new(move |cc| {
             let egui_ctx = cc.egui_ctx.clone();
-            let fs = FileSystem::start(args.path, move || egui_ctx.request_repaint());
-            let app = IMViewApp::new(fs.unwrap(), cc.egui_ctx.clone());
+            let recent_locations = cc
+                .storage
+                .and_then(|s| s.get_string(RECENT_LOCATIONS_KEY))
+                .and_then(|s| serde_json::from_str::<Vec<PathBuf>>(&s).ok())
+                .unwrap_or_default();
+            let session = match args.session.as_ref() {
+                Some(file) if file.exists() => Some(Session::read(file)),
+                Some(_) => None,
+                None => cc
+                    .storage
+                    .and_then(|s| s.get_string(SESSION_KEY))
+                    .map(|json| Session::from_json(&json)),
+            };
+            let mut session = match session {
+                Some(Ok(session)) => Some(session),
+                Some(Err(e)) => {
+                    warn!("Can't read the session: {}", e);
+                    None
+                }
+                None => None,
+            };
+            if let Some(session) = session.as_mut() {
+                session.prune();
+            }
+            let mut paths = args.path;
+            let mut restored_location = None;
+            if paths.is_empty() && pairs.is_empty() {
+                match session.as_ref().filter(|s| !s.paths.is_empty()) {
+                    Some(session) => paths = session.paths.clone(),
+                    None => {
+                        restored_location =
+                            recent_locations.first().filter(|d| d.is_dir()).cloned();
+                        paths.extend(restored_location.clone());
+                    }
+                }
+            }
+            let opened_paths = paths
+                .iter()
+                .map(|p| links::absolute(p).unwrap_or_else(|_| p.clone()))
+                .collect();
+            let nothing_to_open = paths.is_empty() && pairs.is_empty();
+            let ignore_patterns = cc
+                .storage
+                .and_then(|s| s.get_string(IGNORE_PATTERNS_KEY))
+                .and_then(|s| serde_json::from_str::<Vec<String>>(&s).ok())
+                .unwrap_or_else(|| filter.ignored().to_vec());
+            let all_patterns = ignore_patterns.iter().chain(args.ignore.iter()).cloned();
+            if let Err(e) = filter.set_ignored(all_patterns.collect()) {
+                warn!("Ignoring the saved ignore patterns: {}", e);
+                let defaults = filter.ignored().iter().chain(args.ignore.iter()).cloned();
+                filter.set_ignored(defaults.collect()).unwrap();
+            }
+            let sniff_content = cc
+                .storage
+                .and_then(|s| s.get_string(SNIFF_CONTENT_KEY))
+                .map_or(true, |s| s == "true");
+            filter.sniff_content = sniff_content && !args.no_sniff;
+            let watch_link_targets = args.watch_link_targets;
+            let fs = FileSystem::start(paths, pool_sizes, filter, watch_link_targets, move || {
+                egui_ctx.request_repaint()
+            });
+            let mut fs = fs.unwrap();
+            fs.set_max_dimension(args.max_dimension);
+            fs.set_max_memory(args.max_memory.map(|mib| mib.saturating_mul(1024 * 1024)));
+            let mut app = IMViewApp::new(fs, cc.egui_ctx.clone());
+            app.follow = args.follow;
+            app.log_timings = args.timings;
+            app.thumbnail_scaling.filter = args.thumb_filter;
+            app.view_defaults = ViewDefaults {
+                diff_mode: args.mode.into(),
+                gamma: args.gamma,
+                split: args.split,
+                zoom: args.zoom.map(|z| z / 100.0),
+            };
+            app.recent_locations = recent_locations;
+            app.ignore_patterns_text = ignore_patterns.join("\n");
+            app.ignore_patterns = ignore_patterns;
+            app.sniff_content = sniff_content;
+            app.logarithmic_zoom = cc
+                .storage
+                .and_then(|s| s.get_string(LOGARITHMIC_ZOOM_KEY))
+                .map_or(true, |s| s == "true");
+            let saved_theme = cc
+                .storage
+                .and_then(|s| s.get_string(THEME_KEY))
+                .and_then(|s| Theme::from_name(&s));
+            app.theme = args.theme.or(saved_theme).unwrap_or(Theme::Dark);
+            cc.egui_ctx.set_visuals(app.theme.visuals());
+            app.viewport_background = cc
+                .storage
+                .and_then(|s| s.get_string(VIEWPORT_BACKGROUND_KEY))
+                .and_then(|s| serde_json::from_str::<Option<[u8; 3]>>(&s).ok())
+                .flatten()
+                .map(|[r, g, b]| egui::Color32::from_rgb(r, g, b));
+            app.pointer_bindings = cc
+                .storage
+                .and_then(|s| s.get_string(POINTER_BINDINGS_KEY))
+                .and_then(|s| serde_json::from_str(&s).ok())
+                .unwrap_or_default();
+            app.extra_ignore_patterns = args.ignore;
+            app.restored_location = restored_location;
+            app.opened_paths = opened_paths;
+            app.session_file = args.session;
+            if let Some(session) = session {
+                app.restore_session(session);
+            }
+            app.nothing_to_open = nothing_to_open;
+            for (a, b) in pairs {
+                app.add_pair(a, b);
+            }
+            if let Some(size) = cc.storage.and_then(|s| s.get_string(THUMBNAIL_SIZE_KEY)) {
+                app.thumbnail_size = size.parse::<f32>().map_or(DEFAULT_THUMBNAIL_SIZE, |s| {
+                    s.clamp(MIN_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE)
+                });
+            }
+            app.settle_time = Duration::from_millis(args.settle_ms);
+            if let Some(command) = args.on_select {
+                app.on_select(move |path| {
+                    if let Err(e) = spawn_with_path(&command, path) {
+                        warn!("Can't run {}: {}", command, e);
+                    }
+                });
+            }
+            if let Some(instance) = instance {
+                let egui_ctx = cc.egui_ctx.clone();
+                match instance.listen(move || egui_ctx.request_repaint()) {
+                    Ok(receiver) => app.forwarded_paths = Some(receiver),
+                    Err(e) => warn!("Can't receive paths from other invocations: {}", e),
+                }
+                app.instance = Some(instance);
+            }
             Box::new(app)
         }),
     );
@@ -46,30 +473,643 @@ fn main() {
 struct IMViewApp {
     cc: Context,
     file_system: FileSystem,
-    current_image: Option<PathBuf>,
-    image_files: Vec<PathBuf>,
-    image_states: HashMap<PathBuf, ImageUIState>,
-    thumbnails_cache: HashMap<PathBuf, ImageData>,
-    full_images_cache: SizedCache<PathBuf, ImageData>,
+    current_image: Option<ImageSource>,
+    image_files: Vec<ImageSource>,
+    image_states: HashMap<ImageSource, ImageUIState>,
+    thumbnails_cache: HashMap<ImageSource, ImageData>,
+    /// Size the thumbnails are shown at, changed with Ctrl+scroll over the
+    /// strip.
+    thumbnail_size: f32,
+    /// Largest size in pixels a thumbnail was requested at, and the size the
+    /// cached one was made for, see `request_thumbnail`.
+    thumbnail_requested: HashMap<ImageSource, u32>,
+    thumbnail_resolutions: HashMap<ImageSource, u32>,
+    full_images_cache: SizedCache<ImageSource, ImageData>,
+    partial_image: Option<(ImageSource, ImageData)>,
+    /// Pixels of the `ImageSource::Memory` entries.
+    in_memory_images: HashMap<ImageSource, RgbaImage>,
+    next_memory_id: u64,
+    recent_images: VecDeque<ImageSource>,
+    toast: Option<Toast>,
+    montage_settings: MontageSettings,
+    montage_window_open: bool,
+    montage_job: Option<MontageJob>,
+    settings_window_open: bool,
+    /// Lists the live textures, opened from the texture memory in the
+    /// status bar.
+    textures_window_open: bool,
+    /// How newly listed images are shown at first, see `ViewDefaults`.
+    view_defaults: ViewDefaults,
+    /// Folders images were opened from, most recent first.
+    recent_locations: Vec<PathBuf>,
+    /// Last opened folder, reopened because no paths were given.
+    restored_location: Option<PathBuf>,
+    /// Files and folders opened at start or later, kept in the session.
+    opened_paths: Vec<PathBuf>,
+    /// Set with `--session`, the session is saved there on exit instead of
+    /// in the app storage.
+    session_file: Option<PathBuf>,
+    /// Views and current image of the restored session, applied as the
+    /// files get listed.
+    restored_views: HashMap<PathBuf, ViewState>,
+    restored_current: Option<PathBuf>,
+    /// Started without paths and without a folder to restore, the empty
+    /// window offers the recent locations instead of waiting for images.
+    nothing_to_open: bool,
+    /// Ignore patterns of the settings, kept between runs, and the ones
+    /// given with `--ignore` for this run.
+    ignore_patterns: Vec<String>,
+    extra_ignore_patterns: Vec<String>,
+    /// Patterns being edited in the settings, one per line.
+    ignore_patterns_text: String,
+    /// Sniff files without an image extension, kept between runs,
+    /// `--no-sniff` turns it off for one run only.
+    sniff_content: bool,
+    hover_preview: HoverPreview,
+    /// Search over the image names, opened with Ctrl+P.
+    quick_open: Option<QuickOpen>,
+    /// Differences of the A/B pairs among the listed files, while its
+    /// window is open.
+    compare_report: Option<CompareReport>,
+    pool_sizes: PoolSizes,
+    /// How thumbnails are shrunk, see `decode::to_thumbnail`.
+    thumbnail_scaling: ThumbnailScaling,
+    /// File stems under the thumbnails, in this font size.
+    thumbnail_captions: bool,
+    caption_size: f32,
+    /// Zoom slider steps by factors instead of percent, kept between runs.
+    logarithmic_zoom: bool,
+    theme: Theme,
+    /// Fill behind the images, the theme's background when `None`.
+    viewport_background: Option<egui::Color32>,
+    /// Pointer gestures of the image view and the preview, kept between runs.
+    pointer_bindings: PointerBindings,
+    /// Log the decode time of each image, set with `--timings`.
+    log_timings: bool,
+    compact_mode: bool,
+    overlay_shown_at: Instant,
+    /// Images being decoded, requested once until they load, see
+    /// `request_full_image`.
+    loading_images: HashSet<ImageSource>,
+    /// Files changed on disk, marked until their reloaded version was shown
+    /// and the user moved on.
+    modified_files: HashSet<ImageSource>,
+    /// Changed files waiting to settle, with the time and size they were
+    /// last seen changing at, see `process_settled_files`.
+    unsettled_files: HashMap<PathBuf, (Instant, Option<u64>)>,
+    settle_time: Duration,
+    window_title: String,
+    /// Show files as they are created or changed, see `follow_file`.
+    follow: bool,
+    /// When the user last picked an image, clicked, scrolled or pressed a
+    /// key.
+    interacted_at: Option<Instant>,
+    /// Files that appeared while following was paused, oldest first.
+    follow_missed: Vec<ImageSource>,
+    /// Images kept in the quick access row, in the order they were pinned.
+    pinned: Vec<ImageSource>,
+    /// Region of which image was last seen changing and when, see
+    /// `process_region_stats_request`.
+    region_changed: Option<(ImageSource, RegionKey, Instant)>,
+    /// File being renamed from the thumbnail strip.
+    rename_edit: Option<RenameEdit>,
+    /// Content hashes of the listed files, reused while they stay
+    /// unmodified.
+    file_hashes: HashMap<PathBuf, FileHash>,
+    /// Files hashed so far and in total by the running duplicate scan.
+    duplicate_scan: Option<(usize, usize)>,
+    /// Listed files with identical content, and the group of each file.
+    duplicate_groups: Vec<Vec<ImageSource>>,
+    duplicate_group_of: HashMap<ImageSource, usize>,
+    /// Show one image per duplicate group in the strip, the current one
+    /// when it's in the group.
+    one_per_duplicate_group: bool,
+    /// Dimensions of the full images, reported along with the thumbnails.
+    image_dimensions: HashMap<ImageSource, (u32, u32)>,
+    sort_order: SortOrder,
+    /// Dimensions arrived for an image since the list was last sorted.
+    resort_requested: bool,
+    /// Label the dimension groups in the strip when sorted by dimensions.
+    dimension_labels: bool,
+    /// Image shown before the current file changed, waiting for the new
+    /// version to load, see `DiffMode::PreviousDiff`.
+    previous_version: Option<(ImageSource, RgbaImage)>,
+    /// Called with the file of each image that becomes current.
+    select_hooks: Vec<SelectHook>,
+    /// Set in `--single-instance` mode when this process owns the window.
+    instance: Option<PrimaryInstance>,
+    /// Paths later invocations handed over, see `process_forwarded_paths`.
+    forwarded_paths: Option<Receiver<Vec<PathBuf>>>,
+    /// Video and timestamp of the frame being decoded, one seek at a time.
+    #[cfg(feature = "video")]
+    video_seek_in_flight: Option<(ImageSource, f64)>,
+}
+
+/// Name being typed for a file renamed from the thumbnail strip.
+struct RenameEdit {
+    path: PathBuf,
+    name: String,
+    /// Why the name can't be used, shown in place of the caption.
+    error: Option<String>,
+    focus_requested: bool,
+}
+
+impl RenameEdit {
+    fn new(path: PathBuf) -> Self {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self {
+            path,
+            name,
+            error: None,
+            focus_requested: true,
+        }
+    }
+
+    fn validate(&mut self) {
+        self.error = rename_target(&self.path, &self.name)
+            .err()
+            .map(|e| e.to_string());
+    }
+
+    /// Draws the name field over the bottom of the thumbnail at `rect`.
+    /// Returns `Some(true)` once Enter confirms a valid name and
+    /// `Some(false)` when the edit is left otherwise, like with Esc.
+    fn ui(&mut self, ui: &mut egui::Ui, rect: egui::Rect) -> Option<bool> {
+        let height = ui.spacing().interact_size.y;
+        let field =
+            egui::Rect::from_min_max(rect.left_bottom() - egui::vec2(0.0, height), rect.max);
+        let resp = ui.put(
+            field,
+            egui::TextEdit::singleline(&mut self.name).id_source("rename"),
+        );
+        if std::mem::take(&mut self.focus_requested) {
+            resp.request_focus();
+        }
+        if resp.changed() {
+            self.validate();
+        }
+        if let Some(error) = self.error.as_ref() {
+            ui.painter().text(
+                field.left_top() - egui::vec2(0.0, 2.0),
+                egui::Align2::LEFT_BOTTOM,
+                error,
+                egui::FontId::proportional(12.0),
+                egui::Color32::RED,
+            );
+        }
+        if !resp.lost_focus() {
+            return None;
+        }
+        if ui.input().key_pressed(egui::Key::Enter) && self.error.is_none() {
+            return Some(true);
+        }
+        if ui.input().key_pressed(egui::Key::Enter) {
+            // Keep editing until the name is fixed or the edit is left.
+            resp.request_focus();
+            return None;
+        }
+        Some(false)
+    }
+}
+
+/// Lists the recent folders as buttons, the ones that no longer exist are
+/// disabled. Returns the clicked folder.
+fn recent_locations_ui(ui: &mut egui::Ui, locations: &[PathBuf]) -> Option<PathBuf> {
+    let mut picked = None;
+    for location in locations {
+        let exists = location.is_dir();
+        let resp = ui
+            .add_enabled(exists, egui::Button::new(location.display().to_string()))
+            .on_disabled_hover_text("The folder no longer exists");
+        if resp.clicked() {
+            picked = Some(location.clone());
+            ui.close_menu();
+        }
+    }
+    if locations.is_empty() {
+        ui.weak("No recent locations");
+    }
+    picked
+}
+
+/// Color telling the duplicate groups apart, consecutive groups get distant
+/// hues.
+fn group_color(group: usize) -> egui::Color32 {
+    const GOLDEN_RATIO: f32 = 0.618_034;
+    let hue = (group as f32 * GOLDEN_RATIO).fract();
+    egui::color::Hsva::new(hue, 0.6, 0.9, 1.0).into()
+}
+
+/// Removes `path` from `files` and returns the current image after it. A
+/// removed current image passes to the file that took its place, or the
+/// one before it at the end of the list.
+fn remove_listed(
+    files: &mut Vec<ImageSource>,
+    path: &ImageSource,
+    current: Option<&ImageSource>,
+) -> Option<ImageSource> {
+    let index = files.iter().position(|p| p == path);
+    files.retain(|p| p != path);
+    if current != Some(path) {
+        return current.cloned();
+    }
+    let i = index?.min(files.len().checked_sub(1)?);
+    Some(files[i].clone())
+}
+
+/// Puts `new` in place of `old` in `files`, returns whether `old` was
+/// listed.
+fn rename_listed(files: &mut [ImageSource], old: &ImageSource, new: &ImageSource) -> bool {
+    match files.iter_mut().find(|p| *p == old) {
+        Some(p) => {
+            *p = new.clone();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Runs on the UI thread, so anything slow belongs on another thread.
+type SelectHook = Box<dyn FnMut(&Path)>;
+
+/// Order of the image list.
+#[derive(Clone, Copy, PartialEq)]
+enum SortOrder {
+    Name,
+    /// Grouped by the dimensions of the full images, smallest first.
+    Dimensions,
+}
+
+impl SortOrder {
+    fn label(&self) -> &'static str {
+        match self {
+            SortOrder::Name => "By name",
+            SortOrder::Dimensions => "By dimensions",
+        }
+    }
+}
+
+struct MontageJob {
+    target: PathBuf,
+    sources: Vec<ImageSource>,
+    started: bool,
+    progress: (usize, usize),
 }
 
-const THUMBNAIL_SIZE: u32 = 150;
+const DEFAULT_THUMBNAIL_SIZE: f32 = 150.0;
+const MIN_THUMBNAIL_SIZE: f32 = 48.0;
+const MAX_THUMBNAIL_SIZE: f32 = 512.0;
+/// Most physical pixels per point thumbnails are made with, extreme scale
+/// factors would make huge thumbnails.
+const MAX_THUMBNAIL_PIXEL_SCALE: f32 = 3.0;
+/// Storage key the thumbnail size is kept under between runs.
+const THUMBNAIL_SIZE_KEY: &str = "thumbnail_size";
+/// Storage key of the folders opened lately, most recent first.
+const RECENT_LOCATIONS_KEY: &str = "recent_locations";
+const MAX_RECENT_LOCATIONS: usize = 10;
+/// Storage key of the glob patterns of the entries left out of the list.
+const IGNORE_PATTERNS_KEY: &str = "ignore_patterns";
+/// Storage key of the last session, unless `--session` names a file.
+const SESSION_KEY: &str = "session";
+/// Storage key of whether files without an image extension are sniffed.
+const SNIFF_CONTENT_KEY: &str = "sniff_content";
+/// Storage key of whether the zoom slider is logarithmic.
+const LOGARITHMIC_ZOOM_KEY: &str = "logarithmic_zoom";
+/// Storage key of the theme name, see `Theme::name`.
+const THEME_KEY: &str = "theme";
+/// Storage key of the image view background as `[r, g, b]`, or `null` for
+/// the one of the theme.
+const VIEWPORT_BACKGROUND_KEY: &str = "viewport_background";
+/// Storage key of the `PointerBindings` as JSON.
+const POINTER_BINDINGS_KEY: &str = "pointer_bindings";
+const PINNED_THUMBNAIL_SIZE: f32 = 64.0;
+/// Number of image switches a cached full image keeps its textures for.
+const KEEP_TEXTURES_SWITCHES: usize = 3;
+/// Seconds the compact mode overlay stays fully visible after an image change.
+const COMPACT_OVERLAY_SECS: f32 = 2.0;
+const COMPACT_OVERLAY_FADE_SECS: f32 = 0.5;
+/// Seconds a selected region has to stay put before its statistics are
+/// computed.
+const REGION_STATS_DEBOUNCE_SECS: f32 = 0.3;
+/// Seconds following files stays paused after the user interacted.
+const FOLLOW_GRACE_SECS: f32 = 10.0;
 
 impl IMViewApp {
     fn new(fs: FileSystem, cc: Context) -> Self {
+        let pool_sizes = fs.pool_sizes();
         Self {
-            cc: cc,
+            cc,
             file_system: fs,
             current_image: None,
             image_files: Vec::new(),
             image_states: HashMap::new(),
             thumbnails_cache: HashMap::new(),
+            thumbnail_size: DEFAULT_THUMBNAIL_SIZE,
+            thumbnail_requested: HashMap::new(),
+            thumbnail_resolutions: HashMap::new(),
             full_images_cache: SizedCache::with_size(10),
+            partial_image: None,
+            in_memory_images: HashMap::new(),
+            next_memory_id: 0,
+            recent_images: VecDeque::new(),
+            toast: None,
+            montage_settings: MontageSettings::new(),
+            montage_window_open: false,
+            montage_job: None,
+            settings_window_open: false,
+            textures_window_open: false,
+            view_defaults: ViewDefaults::new(),
+            recent_locations: Vec::new(),
+            restored_location: None,
+            opened_paths: Vec::new(),
+            session_file: None,
+            restored_views: HashMap::new(),
+            restored_current: None,
+            nothing_to_open: false,
+            ignore_patterns: Vec::new(),
+            extra_ignore_patterns: Vec::new(),
+            ignore_patterns_text: String::new(),
+            sniff_content: true,
+            hover_preview: HoverPreview::new(),
+            quick_open: None,
+            compare_report: None,
+            pool_sizes,
+            thumbnail_scaling: ThumbnailScaling {
+                filter: ThumbnailFilter::Box,
+                linear: true,
+            },
+            thumbnail_captions: true,
+            caption_size: 12.0,
+            logarithmic_zoom: true,
+            theme: Theme::Dark,
+            viewport_background: None,
+            pointer_bindings: PointerBindings::new(),
+            log_timings: false,
+            compact_mode: false,
+            overlay_shown_at: Instant::now(),
+            loading_images: HashSet::new(),
+            modified_files: HashSet::new(),
+            unsettled_files: HashMap::new(),
+            settle_time: Duration::from_millis(300),
+            window_title: String::new(),
+            follow: false,
+            interacted_at: None,
+            follow_missed: Vec::new(),
+            pinned: Vec::new(),
+            region_changed: None,
+            rename_edit: None,
+            file_hashes: HashMap::new(),
+            duplicate_scan: None,
+            duplicate_groups: Vec::new(),
+            duplicate_group_of: HashMap::new(),
+            one_per_duplicate_group: false,
+            image_dimensions: HashMap::new(),
+            sort_order: SortOrder::Name,
+            resort_requested: false,
+            dimension_labels: true,
+            previous_version: None,
+            select_hooks: Vec::new(),
+            instance: None,
+            forwarded_paths: None,
+            #[cfg(feature = "video")]
+            video_seek_in_flight: None,
+        }
+    }
+
+    /// Uploads the rows of the current image decoded since the previous
+    /// partial image.
+    fn partial_image_loaded(&mut self, partial: PartialImage) {
+        let path = partial.source;
+        if self.current_image.as_ref() != Some(&path)
+            || self.full_images_cache.cache_get(&path).is_some()
+        {
+            return;
+        }
+        trace!("Image partially loaded: {} (pass {})", path, partial.pass);
+        let (width, height) = partial.size;
+        let size = egui::vec2(width as _, height as _);
+        match self.partial_image.as_mut() {
+            Some((p, data)) if *p == path && data.size() == size => {
+                data.update_partial(partial.top, &partial.rows)
+            }
+            _ => {
+                let mut data = ImageData::partial(&path, partial.size, &self.cc);
+                data.update_partial(partial.top, &partial.rows);
+                self.partial_image = Some((path, data));
+            }
+        }
+    }
+
+    fn set_current_image(&mut self, path: ImageSource) {
+        self.file_system.set_current(path.file_path());
+        if self.partial_image.as_ref().is_some_and(|(p, _)| p != &path) {
+            self.partial_image = None;
+        }
+        self.request_full_image(&path);
+        if self.current_image.as_ref() != Some(&path) {
+            if let Some(old) = self.current_image.clone() {
+                self.forget_previous_version(&old);
+                // The marker stays until the reloaded version was shown.
+                if self.full_images_cache.cache_get(&old).is_some() {
+                    self.modified_files.remove(&old);
+                }
+            }
+            if let Some(file) = path.file_path() {
+                for hook in self.select_hooks.iter_mut() {
+                    hook(file);
+                }
+            }
+        }
+        self.current_image = Some(path.clone());
+        self.overlay_shown_at = Instant::now();
+        self.recent_images.retain(|p| p != &path);
+        self.recent_images.push_front(path);
+        while self.recent_images.len() > KEEP_TEXTURES_SWITCHES {
+            let old = self.recent_images.pop_back().unwrap();
+            if let Some(data) = self.full_images_cache.cache_get_mut(&old) {
+                trace!("Release textures: {}", old);
+                data.release_display_resources();
+            }
+        }
+    }
+
+    /// Drops the version `source` had before its file changed, leaving the
+    /// comparison with it.
+    fn forget_previous_version(&mut self, source: &ImageSource) {
+        if self
+            .previous_version
+            .as_ref()
+            .is_some_and(|(p, _)| p == source)
+        {
+            self.previous_version = None;
+        }
+        if let Some(data) = self.full_images_cache.cache_get_mut(source) {
+            data.clear_previous_version();
+        }
+        if let Some(state) = self.image_states.get_mut(source) {
+            if state.diff_mode == DiffMode::PreviousDiff {
+                state.diff_mode = DiffMode::Full;
+            }
+        }
+    }
+
+    /// Registers `hook` to be called with the file of each image that
+    /// becomes current. Images not stored in a file are skipped, for
+    /// archive entries the archive is passed.
+    fn on_select(&mut self, hook: impl FnMut(&Path) + 'static) {
+        self.select_hooks.push(Box::new(hook));
+    }
+
+    /// Shows an image the user picked, pausing `follow` for a while.
+    fn select_image(&mut self, path: ImageSource) {
+        self.interacted_at = Some(Instant::now());
+        self.set_current_image(path);
+    }
+
+    /// Switches to a created or changed file when following the folder.
+    /// Less than `FOLLOW_GRACE_SECS` after the user interacted the file is
+    /// only counted, see `resume_follow`.
+    fn follow_file(&mut self, path: ImageSource) {
+        if !self.follow || self.current_image.as_ref() == Some(&path) {
+            return;
         }
+        let paused = self
+            .interacted_at
+            .is_some_and(|t| t.elapsed().as_secs_f32() < FOLLOW_GRACE_SECS);
+        if paused {
+            trace!("Follow paused, missed: {}", path);
+            self.follow_missed.retain(|p| *p != path);
+            self.follow_missed.push(path);
+        } else {
+            trace!("Following: {}", path);
+            self.follow_missed.clear();
+            self.set_current_image(path);
+        }
+    }
+
+    /// Ends a pause of `follow` and shows the newest file it missed.
+    fn resume_follow(&mut self) {
+        self.interacted_at = None;
+        if let Some(newest) = self.follow_missed.pop() {
+            self.set_current_image(newest);
+        }
+        self.follow_missed.clear();
+    }
+
+    /// Clicks, scrolls and key presses pause `follow`, pointer moves don't.
+    fn note_interaction(&mut self, ctx: &egui::Context) {
+        let interacted = {
+            let input = ctx.input();
+            input.pointer.any_down()
+                || input.scroll_delta != egui::Vec2::ZERO
+                || input
+                    .events
+                    .iter()
+                    .any(|e| matches!(e, egui::Event::Key { pressed: true, .. }))
+        };
+        if interacted {
+            self.interacted_at = Some(Instant::now());
+        }
+    }
+
+    /// Starts loading the full image, in-memory images are rebuilt from
+    /// their pixels since there's no file to read them from.
+    fn request_full_image(&mut self, source: &ImageSource) {
+        // The view asks again on every frame until the image is loaded.
+        let stored = matches!(source, ImageSource::Memory { .. });
+        if !stored && !self.loading_images.insert(source.clone()) {
+            return;
+        }
+        match source {
+            ImageSource::File(path) => self.file_system.read_file(path),
+            ImageSource::Archive { archive, entry } => {
+                self.file_system.read_archive_entry(archive, entry)
+            }
+            ImageSource::Pair { a, b } => self.file_system.read_pair(a, b),
+            ImageSource::Memory { .. } => {
+                let img = match self.in_memory_images.get(source) {
+                    Some(img) => img,
+                    None => return,
+                };
+                if self.full_images_cache.cache_get(source).is_none() {
+                    let data = ImageData::full_image(source, vec![img.clone()], &self.cc);
+                    self.full_images_cache.cache_set(source.clone(), data);
+                }
+            }
+        }
+    }
+
+    /// Opens the image on the clipboard as a new entry kept in memory.
+    fn paste_image(&mut self) {
+        let pasted = arboard::Clipboard::new().and_then(|mut c| c.get_image());
+        let img = match pasted {
+            Ok(img) => RgbaImage::from_raw(img.width as _, img.height as _, img.bytes.into_owned())
+                .filter(|img| img.width() > 0 && img.height() > 0),
+            Err(err) => {
+                warn!("Can't paste image: {}", err);
+                None
+            }
+        };
+        let img = match img {
+            Some(img) => img,
+            None => {
+                self.toast = Some(Toast::error("No image on the clipboard"));
+                return;
+            }
+        };
+        let source = ImageSource::Memory {
+            name: format!("clipboard-{}", local_timestamp()),
+            id: self.next_memory_id,
+        };
+        self.next_memory_id += 1;
+        trace!("Pasted image: {}", source);
+        self.in_memory_images.insert(source.clone(), img);
+        self.request_thumbnail(&source);
+        self.image_files.push(source.clone());
+        self.sort_files();
+        self.image_states
+            .insert(source.clone(), self.view_defaults.image_state());
+        self.select_image(source);
+    }
+
+    /// Saves an in-memory image, which has no file of its own, to a chosen
+    /// path.
+    fn save_in_memory_image(&mut self, source: &ImageSource) {
+        let img = match self.in_memory_images.get(source) {
+            Some(img) => img.clone(),
+            None => return,
+        };
+        let target = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name(&format!("{}.png", source.file_stem()))
+            .save_file();
+        if let Some(target) = target {
+            self.file_system.save_image(&target, img);
+        }
+    }
+
+    fn texture_memory(&self) -> usize {
+        let full: usize = self
+            .full_images_cache
+            .value_order()
+            .map(|d| d.texture_memory())
+            .sum();
+        let thumbs: usize = self
+            .thumbnails_cache
+            .values()
+            .map(|d| d.texture_memory())
+            .sum();
+        full + thumbs
     }
 
     fn process_fs_events(&mut self) {
         let mut was_file_events = false;
+        while let Ok(partial) = self.file_system.partial_receiver.try_recv() {
+            self.partial_image_loaded(partial);
+        }
         while let Ok(event) = self.file_system.receiver.try_recv() {
             match event {
                 FileSystemEvent::FileEvent(event) => {
@@ -77,25 +1117,35 @@ impl IMViewApp {
                     self.process_file_event(event);
                 }
                 FileSystemEvent::OperationEvent(event) => self.process_operation_event(event),
+                FileSystemEvent::Warning(msg) => {
+                    warn!("{}", msg);
+                    self.toast = Some(Toast::error(msg));
+                }
             }
         }
-        if was_file_events {
-            self.image_files.sort();
-            if self.current_image.is_none() && self.image_files.len() >= 1 {
-                self.current_image = Some(self.image_files[0].clone());
-                self.file_system.read_file(&self.image_files[0])
+        if was_file_events || self.resort_requested {
+            self.sort_files();
+            if self.current_image.is_none() && !self.image_files.is_empty() {
+                self.set_current_image(self.image_files[0].clone());
             }
-            if self.image_files.len() == 0 {
+            if self.image_files.is_empty() {
                 self.current_image = None;
+                self.file_system.set_current(None);
             }
         }
     }
 
     fn process_file_event(&mut self, event: filesystem::FileEvent) {
         match event {
-            filesystem::FileEvent::Added(path) => {
-                trace!("File added: {:?}", path);
-                self.add_file(path);
+            filesystem::FileEvent::Added(source) => {
+                trace!("File added: {}", source);
+                self.add_file(source);
+            }
+            filesystem::FileEvent::Created(path) => {
+                trace!("File created: {:?}", path);
+                let source = ImageSource::File(path);
+                self.add_file(source.clone());
+                self.follow_file(source);
             }
             filesystem::FileEvent::Removed(path) => {
                 trace!("File removed: {:?}", path);
@@ -103,147 +1153,2177 @@ impl IMViewApp {
             }
             filesystem::FileEvent::Modified(path) => {
                 trace!("File modified: {:?}", path);
-                self.invalidate_file_data(path);
+                let size = file_size(&path);
+                self.unsettled_files.insert(path, (Instant::now(), size));
             }
             filesystem::FileEvent::Renamed(old_path, new_path) => {
                 trace!("File renamed: {:?} -> {:?}", old_path, new_path);
                 self.rename_file(old_path, new_path);
             }
+            filesystem::FileEvent::Unsniffed(path) => {
+                if self
+                    .image_states
+                    .contains_key(&ImageSource::File(path.clone()))
+                {
+                    trace!("File modified: {:?}", path);
+                    let size = file_size(&path);
+                    self.unsettled_files.insert(path, (Instant::now(), size));
+                } else {
+                    self.file_system.sniff_files(vec![path]);
+                }
+            }
+        }
+    }
+
+    /// Opens the paths later `--single-instance` invocations handed over.
+    /// The window can't be raised with this eframe version, the new images
+    /// are selected instead.
+    fn process_forwarded_paths(&mut self) {
+        let paths = match self.forwarded_paths.as_ref() {
+            Some(receiver) => receiver.try_iter().flatten().collect::<Vec<_>>(),
+            None => return,
+        };
+        if !paths.is_empty() {
+            self.note_opened(&paths);
+            self.file_system.add_paths(paths);
+        }
+    }
+
+    fn note_opened(&mut self, paths: &[PathBuf]) {
+        for path in paths {
+            let path = links::absolute(path).unwrap_or_else(|_| path.clone());
+            if !self.opened_paths.contains(&path) {
+                self.opened_paths.push(path);
+            }
+        }
+    }
+
+    /// Takes over the sort order of `session`, its views and current image
+    /// are applied once their files are listed.
+    fn restore_session(&mut self, session: Session) {
+        self.sort_order = match session.sort_by_dimensions {
+            true => SortOrder::Dimensions,
+            false => SortOrder::Name,
+        };
+        self.restored_views = session
+            .images
+            .into_iter()
+            .map(|i| (i.path, i.view))
+            .collect();
+        self.restored_current = session.current;
+    }
+
+    /// The review as it is now, images viewed as the defaults are left out.
+    fn session(&self) -> Session {
+        let defaults = ViewState::new(&self.view_defaults.image_state());
+        let mut images = self
+            .image_states
+            .iter()
+            .filter_map(|(source, state)| match source {
+                ImageSource::File(path) => Some(SessionImage {
+                    path: path.clone(),
+                    view: ViewState::new(state),
+                }),
+                _ => None,
+            })
+            .filter(|i| i.view != defaults)
+            .collect::<Vec<_>>();
+        images.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut session = Session::new();
+        session.paths = self.opened_paths.clone();
+        session.current = match self.current_image.as_ref() {
+            Some(ImageSource::File(path)) => Some(path.clone()),
+            _ => None,
+        };
+        session.sort_by_dimensions = self.sort_order == SortOrder::Dimensions;
+        session.images = images;
+        session
+    }
+
+    fn add_file(&mut self, source: ImageSource) {
+        if self.image_states.contains_key(&source) {
+            return;
+        }
+        self.request_thumbnail(&source);
+        self.image_files.push(source.clone());
+        let mut state = self.view_defaults.image_state();
+        if let ImageSource::File(path) = &source {
+            if let Some(view) = self.restored_views.remove(path) {
+                view.apply(&mut state);
+            }
+        }
+        self.image_states.insert(source.clone(), state);
+        let restored = match &source {
+            ImageSource::File(path) => self.restored_current.as_ref() == Some(path),
+            _ => false,
+        };
+        if restored {
+            self.restored_current = None;
+            self.set_current_image(source);
+        }
+    }
+
+    /// Lists the two files as one image, opened on the difference of its
+    /// pages.
+    fn add_pair(&mut self, a: PathBuf, b: PathBuf) {
+        let source = ImageSource::Pair { a, b };
+        if self.image_states.contains_key(&source) {
+            return;
+        }
+        self.add_file(source.clone());
+        if let Some(state) = self.image_states.get_mut(&source) {
+            state.diff_mode = DiffMode::PageDiff;
+        }
+        self.sort_files();
+        if self.current_image.is_none() {
+            self.set_current_image(source);
+        }
+    }
+
+    /// Orders the list by `sort_order`. Images whose dimensions aren't
+    /// known yet go last and move into place as their thumbnails arrive,
+    /// the current image stays selected wherever it ends up.
+    fn sort_files(&mut self) {
+        self.resort_requested = false;
+        match self.sort_order {
+            SortOrder::Name => self.image_files.sort(),
+            SortOrder::Dimensions => {
+                let dimensions = &self.image_dimensions;
+                let key = |s: &ImageSource| {
+                    let d = dimensions.get(s);
+                    (d.is_none(), d.map(|&(w, h)| (w as u64 * h as u64, w, h)))
+                };
+                self.image_files
+                    .sort_by(|a, b| key(a).cmp(&key(b)).then_with(|| a.cmp(b)));
+            }
+        }
+    }
+
+    /// Hashes the listed files to find the ones with identical content,
+    /// files not modified since the last scan aren't read again.
+    fn start_duplicate_scan(&mut self) {
+        let files = self
+            .image_files
+            .iter()
+            .filter_map(|s| match s {
+                ImageSource::File(path) => {
+                    Some((path.clone(), self.file_hashes.get(path).copied()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if files.is_empty() {
+            return;
+        }
+        self.duplicate_scan = Some((0, files.len()));
+        self.file_system.hash_files(files);
+    }
+
+    /// Pairs the listed files by their names and compares each pair, the
+    /// results fill the compare report as they arrive.
+    fn start_compare_report(&mut self) {
+        let files = self
+            .image_files
+            .iter()
+            .filter_map(|s| match s {
+                ImageSource::File(path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let pairs = report::find_pairs(&files);
+        self.file_system.compare_pairs(pairs.clone());
+        self.compare_report = Some(CompareReport::new(pairs));
+    }
+
+    fn finish_duplicate_scan(&mut self) {
+        self.duplicate_scan = None;
+        let mut by_hash: HashMap<blake3::Hash, Vec<ImageSource>> = HashMap::new();
+        let mut order = Vec::new();
+        for source in self.image_files.iter() {
+            let hash = match source {
+                ImageSource::File(path) => self.file_hashes.get(path).map(|h| h.hash),
+                _ => None,
+            };
+            if let Some(hash) = hash {
+                let members = by_hash.entry(hash).or_default();
+                if members.is_empty() {
+                    order.push(hash);
+                }
+                members.push(source.clone());
+            }
         }
+        let groups = order
+            .into_iter()
+            .filter_map(|h| by_hash.remove(&h))
+            .collect::<Vec<_>>();
+        self.set_duplicate_groups(groups);
+        self.toast = Some(match self.duplicate_groups.len() {
+            0 => Toast::info("No duplicates found"),
+            n => Toast::info(format!("{} group(s) of duplicates found", n)),
+        });
+    }
+
+    /// Keeps the groups with more than one member.
+    fn set_duplicate_groups(&mut self, mut groups: Vec<Vec<ImageSource>>) {
+        groups.retain(|g| g.len() > 1);
+        self.duplicate_group_of = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, g)| g.iter().map(move |s| (s.clone(), i)))
+            .collect();
+        self.duplicate_groups = groups;
+    }
+
+    /// Takes a removed or changed file out of its duplicate group.
+    fn forget_duplicate(&mut self, source: &ImageSource) {
+        if !self.duplicate_group_of.contains_key(source) {
+            return;
+        }
+        let mut groups = std::mem::take(&mut self.duplicate_groups);
+        for group in groups.iter_mut() {
+            group.retain(|s| s != source);
+        }
+        self.set_duplicate_groups(groups);
+    }
+
+    /// Side in physical pixels of thumbnails shown at `thumbnail_size`
+    /// points, so they stay crisp on HiDPI screens.
+    fn thumbnail_pixels(&self) -> u32 {
+        let scale = self
+            .cc
+            .pixels_per_point()
+            .clamp(1.0, MAX_THUMBNAIL_PIXEL_SCALE);
+        (self.thumbnail_size * scale).ceil() as u32
     }
 
-    fn add_file(&mut self, path: PathBuf) {
-        self.image_files.push(path.clone());
-        self.image_states.insert(path.clone(), ImageUIState::new());
-        self.file_system.read_thumbnail(&path, THUMBNAIL_SIZE)
+    /// Loads the thumbnail of `source` at the size it's shown at. In-memory
+    /// images have no file to load it from, so theirs is made right away.
+    fn request_thumbnail(&mut self, source: &ImageSource) {
+        let size = self.thumbnail_pixels();
+        self.thumbnail_requested.insert(source.clone(), size);
+        match self.in_memory_images.get(source) {
+            Some(img) => {
+                let thumb = decode::to_thumbnail(img.clone(), size, self.thumbnail_scaling);
+                let thumb = ImageData::thumbnail(source, thumb, &self.cc);
+                self.thumbnails_cache.insert(source.clone(), thumb);
+                self.thumbnail_resolutions.insert(source.clone(), size);
+            }
+            None => self
+                .file_system
+                .read_thumbnail(source, size, self.thumbnail_scaling),
+        }
     }
 
+    /// Forgets a removed file. If it was the current image, the next one in
+    /// the list (or the previous one, for the last) becomes current.
     fn remove_file(&mut self, path: PathBuf) {
-        self.image_files.retain(|p| p != &path);
+        self.unsettled_files.remove(&path);
+        if self.rename_edit.as_ref().is_some_and(|e| e.path == path) {
+            self.rename_edit = None;
+        }
+        self.file_hashes.remove(&path);
+        let path = ImageSource::File(path);
+        self.forget_duplicate(&path);
+        self.pinned.retain(|p| p != &path);
+        self.follow_missed.retain(|p| p != &path);
+        self.clear_partial_image(&path);
+        let current = remove_listed(&mut self.image_files, &path, self.current_image.as_ref());
+        self.recent_images.retain(|p| p != &path);
+        self.modified_files.remove(&path);
+        self.loading_images.remove(&path);
         self.image_states.remove(&path);
         self.thumbnails_cache.remove(&path);
+        self.thumbnail_requested.remove(&path);
+        self.thumbnail_resolutions.remove(&path);
+        self.image_dimensions.remove(&path);
         self.full_images_cache.cache_remove(&path);
+        if self.current_image != current {
+            self.current_image = None;
+            self.file_system.set_current(None);
+            if let Some(neighbor) = current {
+                self.set_current_image(neighbor);
+            }
+        }
+    }
+
+    fn clear_partial_image(&mut self, path: &ImageSource) {
+        if self.partial_image.as_ref().is_some_and(|(p, _)| p == path) {
+            self.partial_image = None;
+        }
+    }
+
+    /// Reloads the changed files whose size stayed the same for
+    /// `settle_time`, so a file written in several passes isn't read while
+    /// it's half written.
+    fn process_settled_files(&mut self, ctx: &Context) {
+        if self.unsettled_files.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let mut settled = Vec::new();
+        for (path, (changed_at, size)) in self.unsettled_files.iter_mut() {
+            let current = file_size(path);
+            if current != *size {
+                *size = current;
+                *changed_at = now;
+            } else if now - *changed_at >= self.settle_time {
+                settled.push(path.clone());
+            }
+        }
+        for path in settled {
+            trace!("File settled: {:?}", path);
+            self.unsettled_files.remove(&path);
+            self.invalidate_file_data(path.clone());
+            self.follow_file(ImageSource::File(path));
+        }
+        if !self.unsettled_files.is_empty() {
+            ctx.request_repaint();
+        }
     }
 
     fn invalidate_file_data(&mut self, path: PathBuf) {
+        let path = ImageSource::File(path);
+        self.request_thumbnail(&path);
+        self.clear_partial_image(&path);
+        self.forget_duplicate(&path);
+        self.modified_files.insert(path.clone());
+        self.loading_images.remove(&path);
         self.thumbnails_cache.remove(&path);
-        self.full_images_cache.cache_remove(&path);
-        self.file_system.read_thumbnail(&path, THUMBNAIL_SIZE);
+        let old = self.full_images_cache.cache_remove(&path);
+        // The current image keeps the version it showed to compare with.
+        if self.current_image.as_ref() == Some(&path) {
+            self.previous_version = old
+                .as_ref()
+                .and_then(|d| d.loaded_image())
+                .map(|img| (path, img.clone()));
+        }
     }
 
     fn rename_file(&mut self, old_path: PathBuf, new_path: PathBuf) {
-        let index = self
-            .image_files
-            .iter()
-            .position(|p| p == &old_path)
-            .unwrap();
-        self.image_files[index] = new_path.clone();
+        if let Some(entry) = self.unsettled_files.remove(&old_path) {
+            self.unsettled_files.insert(new_path.clone(), entry);
+        }
+        if let Some(edit) = self.rename_edit.as_mut().filter(|e| e.path == old_path) {
+            edit.path = new_path.clone();
+        }
+        let old_path = ImageSource::File(old_path);
+        let new_path = ImageSource::File(new_path);
+        // Renames done by the app are reported by the watcher again.
+        if !rename_listed(&mut self.image_files, &old_path, &new_path) {
+            return;
+        }
+        self.clear_partial_image(&old_path);
+        if self.current_image.as_ref() == Some(&old_path) {
+            self.current_image = Some(new_path.clone());
+            self.file_system.set_current(new_path.file_path());
+        }
+        for p in self.recent_images.iter_mut().filter(|p| *p == &old_path) {
+            *p = new_path.clone();
+        }
+        for p in self.pinned.iter_mut().filter(|p| *p == &old_path) {
+            *p = new_path.clone();
+        }
+        for p in self.follow_missed.iter_mut().filter(|p| *p == &old_path) {
+            *p = new_path.clone();
+        }
         let state = self.image_states.remove(&old_path).unwrap();
         self.image_states.insert(new_path.clone(), state);
         if let Some(data) = self.thumbnails_cache.remove(&old_path) {
             self.thumbnails_cache.insert(new_path.clone(), data);
         }
+        if let Some(size) = self.thumbnail_requested.remove(&old_path) {
+            self.thumbnail_requested.insert(new_path.clone(), size);
+        }
+        if let Some(size) = self.thumbnail_resolutions.remove(&old_path) {
+            self.thumbnail_resolutions.insert(new_path.clone(), size);
+        }
+        if let Some(dimensions) = self.image_dimensions.remove(&old_path) {
+            self.image_dimensions.insert(new_path.clone(), dimensions);
+        }
+        if let (ImageSource::File(old), ImageSource::File(new)) = (&old_path, &new_path) {
+            if let Some(hash) = self.file_hashes.remove(old) {
+                self.file_hashes.insert(new.clone(), hash);
+            }
+        }
+        if self.duplicate_group_of.contains_key(&old_path) {
+            let mut groups = std::mem::take(&mut self.duplicate_groups);
+            for p in groups.iter_mut().flatten().filter(|p| *p == &old_path) {
+                *p = new_path.clone();
+            }
+            self.set_duplicate_groups(groups);
+        }
         if let Some(data) = self.full_images_cache.cache_remove(&old_path) {
             self.full_images_cache.cache_set(new_path.clone(), data);
         }
+        if self.modified_files.remove(&old_path) {
+            self.modified_files.insert(new_path.clone());
+        }
+        // The decode under the old name can't be matched anymore.
+        self.loading_images.remove(&old_path);
     }
 
-    fn process_operation_event(&mut self, event: filesystem::OperationEvent) {
-        match event {
-            filesystem::OperationEvent::ThumbnailLoaded((path, img)) => {
-                if img.is_err() {
-                    let err = img.err().unwrap();
-                    warn!("Failed to load thumbnail for {}: {}", path.display(), err);
-                    let data = ImageData::error(&err);
-                    self.thumbnails_cache.insert(path, data);
-                } else {
-                    trace!("Thumbnail loaded: {}", path.display());
-                    let img = img.unwrap();
-                    let data = ImageData::thumbnail(&path, img, &self.cc);
-                    self.thumbnails_cache.insert(path, data);
-                }
-            }
-            filesystem::OperationEvent::ImageLoaded((path, img)) => {
-                if img.is_err() {
-                    let err = img.err().unwrap();
-                    warn!("Failed to load image for {}: {}", path.display(), err);
-                    let data = ImageData::error(&err);
-                    self.full_images_cache.cache_set(path, data);
-                } else {
-                    let img = img.unwrap();
-                    trace!("Image loaded: {}", path.display());
-                    let data = ImageData::full_image(&path, img, &self.cc);
-                    self.full_images_cache.cache_set(path, data);
-                }
-            }
+    /// Opens the name field on the thumbnail of `source`, files only.
+    fn start_rename(&mut self, source: &ImageSource) {
+        if let ImageSource::File(path) = source {
+            self.rename_edit = Some(RenameEdit::new(path.clone()));
         }
     }
-}
 
-impl eframe::App for IMViewApp {
-    fn on_exit_event(&mut self) -> bool {
-        trace!("Closing application");
-        self.file_system.shutdown();
-        true
+    fn finish_rename(&mut self, confirmed: bool) {
+        let edit = match self.rename_edit.take() {
+            Some(edit) => edit,
+            None => return,
+        };
+        let unchanged = edit.path.file_name() == Some(std::ffi::OsStr::new(&edit.name));
+        if confirmed && !unchanged {
+            self.file_system.rename(&edit.path, &edit.name);
+        }
     }
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        self.process_fs_events();
 
-        if let Some(ci) = self.current_image.clone() {
-            let title = format!("iMView - {}", ci.display());
-            if self.full_images_cache.cache_get(&ci).is_none() {
-                self.file_system.read_file(&ci);
+    fn process_file_action_request(&mut self, ctx: &Context, path: &ImageSource) {
+        let state = self.image_states.get_mut(path).unwrap();
+        if let Some(action) = state.file_action_requested.take() {
+            self.run_file_action(ctx, path, action);
+        }
+    }
+
+    /// Copies the canonical path of `source` or shows it in the file
+    /// browser. Archive entries act on the archive.
+    fn run_file_action(&mut self, ctx: &Context, source: &ImageSource, action: FileAction) {
+        if action == FileAction::Reload {
+            self.loading_images.remove(source);
+            self.full_images_cache.cache_remove(source);
+            self.thumbnails_cache.remove(source);
+            self.thumbnail_requested.remove(source);
+            self.request_full_image(source);
+            return;
+        }
+        let path = match source.file_path() {
+            Some(path) => path,
+            None => {
+                self.toast = Some(Toast::error("The image isn't stored in a file"));
+                return;
+            }
+        };
+        match action {
+            FileAction::CopyPath => {
+                let path = links::absolute(path).unwrap_or_else(|_| path.to_owned());
+                let text = match source {
+                    ImageSource::Archive { entry, .. } => {
+                        format!("{}#/{}", path.display(), entry)
+                    }
+                    _ => path.display().to_string(),
+                };
+                ctx.output().copied_text = text;
+                self.toast = Some(Toast::info("Path copied"));
+            }
+            FileAction::Reveal => {
+                if let Err(e) = reveal_in_file_browser(path) {
+                    self.toast = Some(Toast::error(format!("Can't open the file browser: {}", e)));
+                }
+            }
+            FileAction::Reload => {}
+        }
+    }
+
+    /// Reads the sidecar annotations of the image once and writes them when
+    /// asked to. Only images stored in their own file have a sidecar.
+    fn process_annotations_io(&mut self, source: &ImageSource) {
+        let state = self.image_states.get_mut(source).unwrap();
+        let annotations = &mut state.annotations;
+        let path = match source {
+            ImageSource::File(path) => Some(path),
+            _ => None,
+        };
+        if !annotations.load_requested {
+            annotations.load_requested = true;
+            if let Some(path) = path {
+                self.file_system.read_annotations(source, path);
+            }
+        }
+        if std::mem::take(&mut state.annotations_save_requested) {
+            match path {
+                Some(path) => {
+                    let items = state.annotations.items.clone();
+                    self.file_system.write_annotations(source, path, items);
+                }
+                None => {
+                    let msg = "Annotations are saved for image files only";
+                    self.toast = Some(Toast::error(msg));
+                }
+            }
+        }
+    }
+
+    fn process_export_request(&mut self, path: &ImageSource, pixels_per_point: f32) {
+        let state = self.image_states.get_mut(path).unwrap();
+        if !state.export_requested {
+            return;
+        }
+        state.export_requested = false;
+        let data = match self.full_images_cache.cache_get(path) {
+            Some(data) => data,
+            None => return,
+        };
+        let source = match data.display_image(state) {
+            Some(source) => source,
+            None => return,
+        };
+        let diff = match state.diff_mode {
+            DiffMode::Triptych => Some(data.vertical_diff_image(state)),
+            _ => None,
+        };
+        let file_name = format!("{}_view.png", path.file_stem());
+        let target = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name(&file_name)
+            .save_file();
+        if let Some(target) = target {
+            let mode = state.diff_mode;
+            let resolution = state.export_resolution;
+            let view_size = state.view_size;
+            let view = ImageView::new(state, Some(data), None);
+            let sizes = view.display_size(view_size);
+            let uvs = view.uvs();
+            let view = export::ExportView {
+                mode,
+                sizes: &sizes,
+                uvs: &uvs,
+                resolution,
+                pixels_per_point,
+            };
+            let img = export::render_view(&source, diff.as_ref(), &state.annotations.items, view);
+            self.file_system.save_image(&target, img);
+        }
+    }
+
+    fn start_montage(&mut self) {
+        let target = rfd::FileDialog::new()
+            .add_filter("PNG", &["png"])
+            .set_file_name("contact_sheet.png")
+            .save_file();
+        if let Some(target) = target {
+            let missing = self
+                .image_files
+                .iter()
+                .filter(|p| !self.thumbnails_cache.contains_key(p))
+                .cloned()
+                .collect::<Vec<_>>();
+            for source in missing {
+                self.request_thumbnail(&source);
+            }
+            self.montage_job = Some(MontageJob {
+                target,
+                sources: self.image_files.clone(),
+                started: false,
+                progress: (0, 0),
+            });
+        }
+    }
+
+    fn process_montage_job(&mut self) {
+        let job = match self.montage_job.as_mut() {
+            Some(job) if !job.started => job,
+            _ => return,
+        };
+        // Files removed or renamed meanwhile are left out, thumbnails
+        // dropped by a reload are asked for again.
+        let listed = self.image_files.iter().collect::<HashSet<_>>();
+        job.sources.retain(|p| listed.contains(p));
+        if job.sources.is_empty() {
+            self.montage_job = None;
+            self.toast = Some(Toast::error("No files left for the contact sheet"));
+            return;
+        }
+        let missing = job
+            .sources
+            .iter()
+            .filter(|p| !self.thumbnails_cache.contains_key(p))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            for source in missing {
+                if !self.thumbnail_requested.contains_key(&source) {
+                    self.request_thumbnail(&source);
+                }
+            }
+            return;
+        }
+        let job = self.montage_job.as_mut().unwrap();
+        let items = job
+            .sources
+            .iter()
+            .map(|p| {
+                let name = p.file_name();
+                let thumb = self.thumbnails_cache[p]
+                    .pixels()
+                    .cloned()
+                    .unwrap_or_default();
+                (name, thumb)
+            })
+            .collect();
+        job.started = true;
+        self.file_system
+            .save_montage(&job.target, items, self.montage_settings);
+    }
+
+    /// Every texture egui holds, largest first, so leaked ones stand out.
+    fn textures_ui(&mut self, ctx: &egui::Context) {
+        if !self.textures_window_open {
+            return;
+        }
+        let mut open = true;
+        let mut textures = ctx
+            .tex_manager()
+            .read()
+            .allocated()
+            .map(|(_, meta)| (meta.name.clone(), meta.size, meta.bytes_used()))
+            .collect::<Vec<_>>();
+        textures.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+        egui::Window::new("Textures")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let total = textures.iter().map(|t| t.2).sum::<usize>();
+                ui.label(format!(
+                    "{} textures, {}",
+                    textures.len(),
+                    format_bytes(total)
+                ));
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("textures").striped(true).show(ui, |ui| {
+                        for (name, [w, h], bytes) in textures.iter() {
+                            ui.label(name);
+                            ui.label(format!("{}×{}", w, h));
+                            ui.label(format_bytes(*bytes));
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        self.textures_window_open = open;
+    }
+
+    fn montage_ui(&mut self, ctx: &egui::Context) {
+        let mut open = self.montage_window_open;
+        let mut export = false;
+        egui::Window::new("Contact sheet")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let settings = &mut self.montage_settings;
+                ui.horizontal(|ui| {
+                    ui.label("Columns:");
+                    ui.add(egui::DragValue::new(&mut settings.columns).clamp_range(1..=32));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Cell size:");
+                    ui.add(egui::DragValue::new(&mut settings.cell_size).clamp_range(32..=512));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Rows per page:");
+                    ui.add(egui::DragValue::new(&mut settings.rows_per_page).clamp_range(1..=100));
+                });
+                ui.label(format!(
+                    "{} image(s), {} page(s)",
+                    self.image_files.len(),
+                    settings.page_count(self.image_files.len())
+                ));
+                if ui
+                    .add_enabled(self.montage_job.is_none(), egui::Button::new("Export…"))
+                    .clicked()
+                {
+                    export = true;
+                }
+            });
+        self.montage_window_open = open;
+        if export {
+            self.start_montage();
+        }
+    }
+
+    fn settings_ui(&mut self, ctx: &egui::Context) {
+        let mut open = self.settings_window_open;
+        let mut apply = false;
+        let mut apply_patterns = None;
+        let mut apply_sniff = None;
+        let max_threads = 4 * num_cpus::get();
+        egui::Window::new("Settings")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let sizes = &mut self.pool_sizes;
+                ui.horizontal(|ui| {
+                    ui.label("Decode threads:");
+                    ui.add(
+                        egui::DragValue::new(&mut sizes.decode_threads)
+                            .clamp_range(1..=max_threads),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Thumbnail threads:");
+                    ui.add(
+                        egui::DragValue::new(&mut sizes.thumb_threads).clamp_range(1..=max_threads),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Thumbnail filter:");
+                    let filter = &mut self.thumbnail_scaling.filter;
+                    egui::ComboBox::from_id_source("thumbnail_filter")
+                        .selected_text(filter.label())
+                        .show_ui(ui, |ui| {
+                            for f in [
+                                ThumbnailFilter::Box,
+                                ThumbnailFilter::Nearest,
+                                ThumbnailFilter::Triangle,
+                                ThumbnailFilter::CatmullRom,
+                                ThumbnailFilter::Lanczos,
+                            ] {
+                                ui.selectable_value(filter, f, f.label());
+                            }
+                        })
+                        .response
+                        .on_hover_text("Applies to thumbnails loaded from now on");
+                });
+                ui.add_enabled(
+                    self.thumbnail_scaling.filter == ThumbnailFilter::Box,
+                    egui::Checkbox::new(
+                        &mut self.thumbnail_scaling.linear,
+                        "Linear light thumbnails",
+                    ),
+                )
+                .on_hover_text("Slower, keeps fine detail from darkening. Applies to thumbnails loaded from now on");
+                ui.horizontal(|ui| {
+                    ui.label("Thumbnail size:");
+                    ui.add(
+                        egui::Slider::new(
+                            &mut self.thumbnail_size,
+                            MIN_THUMBNAIL_SIZE..=MAX_THUMBNAIL_SIZE,
+                        )
+                        .suffix(" px"),
+                    )
+                    .on_hover_text("Ctrl+scroll over the thumbnails");
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.thumbnail_captions, "Thumbnail captions");
+                    ui.add_enabled(
+                        self.thumbnail_captions,
+                        egui::DragValue::new(&mut self.caption_size)
+                            .clamp_range(8.0..=24.0)
+                            .suffix(" pt"),
+                    );
+                });
+                ui.checkbox(&mut self.logarithmic_zoom, "Logarithmic zoom slider")
+                    .on_hover_text("Off makes the slider linear, for fine steps around 100%");
+                ui.horizontal(|ui| {
+                    ui.label("Theme:");
+                    for theme in [Theme::Dark, Theme::Light] {
+                        if ui
+                            .selectable_value(&mut self.theme, theme, theme.label())
+                            .changed()
+                        {
+                            ctx.set_visuals(theme.visuals());
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Viewport:");
+                    let mut custom = self.viewport_background.is_some();
+                    if ui
+                        .checkbox(&mut custom, "Custom background")
+                        .on_hover_text("Fill behind the images, a mid grey helps to judge colors")
+                        .changed()
+                    {
+                        self.viewport_background = custom.then_some(theme::MID_GREY);
+                    }
+                    if let Some(color) = self.viewport_background.as_mut() {
+                        ui.color_edit_button_srgba(color);
+                        if ui.button("Mid grey").clicked() {
+                            *color = theme::MID_GREY;
+                        }
+                    }
+                });
+                ui.collapsing("Pointer", |ui| {
+                    let bindings = &mut self.pointer_bindings;
+                    ui.horizontal(|ui| {
+                        ui.label("Pan with:");
+                        ui.checkbox(&mut bindings.primary_pans, "Left drag")
+                            .on_hover_text("Holding space pans with a left drag anyway");
+                        ui.checkbox(&mut bindings.middle_pans, "Middle drag");
+                    });
+                    ui.checkbox(&mut bindings.secondary_zooms, "Right drag zooms")
+                        .on_hover_text("Drag up to zoom in, down to zoom out");
+                    ui.checkbox(&mut bindings.middle_click_resets, "Middle click resets the view");
+                    ui.checkbox(
+                        &mut bindings.double_click_toggles,
+                        "Double click toggles 100% and fit",
+                    );
+                });
+                let changed = *sizes != self.file_system.pool_sizes();
+                if ui
+                    .add_enabled(changed, egui::Button::new("Apply"))
+                    .clicked()
+                {
+                    apply = true;
+                }
+                ui.separator();
+                ui.label("Ignored names, one glob pattern per line:");
+                ui.add(egui::TextEdit::multiline(&mut self.ignore_patterns_text).desired_rows(3))
+                    .on_hover_text("Matched against the file or folder name and its full path");
+                if !self.extra_ignore_patterns.is_empty() {
+                    ui.label(format!(
+                        "Also ignored this run: {}",
+                        self.extra_ignore_patterns.join(", ")
+                    ));
+                }
+                let patterns = self
+                    .ignore_patterns_text
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                let edited = patterns != self.ignore_patterns;
+                if ui
+                    .add_enabled(edited, egui::Button::new("Apply patterns"))
+                    .clicked()
+                {
+                    apply_patterns = Some(patterns);
+                }
+                let mut sniff = self.file_system.filter().sniff_content;
+                if ui
+                    .checkbox(&mut sniff, "Detect images without an extension")
+                    .on_hover_text("Reads the first bytes of such files, turn off for slow network folders")
+                    .changed()
+                {
+                    apply_sniff = Some(sniff);
+                }
+            });
+        self.settings_window_open = open;
+        if apply {
+            if let Err(e) = self.file_system.set_pool_sizes(self.pool_sizes) {
+                self.toast = Some(Toast::error(format!("Can't resize worker pools: {}", e)));
+                self.pool_sizes = self.file_system.pool_sizes();
             }
+        }
+        if let Some(patterns) = apply_patterns {
+            self.set_ignore_patterns(patterns);
+        }
+        if let Some(sniff) = apply_sniff {
+            self.sniff_content = sniff;
+            let mut filter = self.file_system.filter();
+            filter.sniff_content = sniff;
+            self.file_system.set_filter(filter);
+        }
+    }
+
+    /// Filters the list with `patterns` and the `--ignore` ones, the
+    /// watched folders are rescanned for the images no longer ignored.
+    fn set_ignore_patterns(&mut self, patterns: Vec<String>) {
+        let mut filter = self.file_system.filter();
+        let all = patterns.iter().chain(self.extra_ignore_patterns.iter());
+        match filter.set_ignored(all.cloned().collect()) {
+            Ok(()) => {
+                self.ignore_patterns = patterns;
+                self.file_system.set_filter(filter);
+            }
+            Err(e) => self.toast = Some(Toast::error(format!("Invalid ignore pattern: {}", e))),
+        }
+    }
+
+    /// Returns the image picked in the quick open window, if it's open.
+    fn quick_open_ui(&mut self, ctx: &egui::Context) -> Option<ImageSource> {
+        let action = self.quick_open.as_mut()?.show(ctx, &self.image_files);
+        match action {
+            QuickOpenAction::None => None,
+            QuickOpenAction::Picked(source) => {
+                self.quick_open = None;
+                Some(source)
+            }
+            QuickOpenAction::Closed => {
+                self.quick_open = None;
+                None
+            }
+        }
+    }
+
+    /// Returns the pair picked in the compare report, listing it first.
+    fn compare_report_ui(&mut self, ctx: &egui::Context) -> Option<ImageSource> {
+        let action = self.compare_report.as_mut()?.show(ctx);
+        match action {
+            CompareReportAction::None => None,
+            CompareReportAction::Open(a, b) => {
+                self.add_pair(a.clone(), b.clone());
+                Some(ImageSource::Pair { a, b })
+            }
+            CompareReportAction::Closed => {
+                self.compare_report = None;
+                None
+            }
+        }
+    }
+
+    /// One-based position of `path` in the image list, 0 when it's not listed.
+    fn image_position(&self, path: &ImageSource) -> usize {
+        self.image_files
+            .iter()
+            .position(|p| p == path)
+            .map_or(0, |i| i + 1)
+    }
+
+    /// Names the current image in the title, its full path is in the status
+    /// bar.
+    fn update_window_title(&mut self, ci: &ImageSource, frame: &mut eframe::Frame) {
+        let index = self.image_position(ci);
+        let dirty = if self.modified_files.contains(ci) {
+            " *"
+        } else {
+            ""
+        };
+        let title = format!(
+            "iMView - {} ({}/{}){}",
+            ci.file_name(),
+            index,
+            self.image_files.len(),
+            dirty
+        );
+        self.set_window_title(title, frame);
+    }
+
+    fn set_window_title(&mut self, title: String, frame: &mut eframe::Frame) {
+        if title != self.window_title {
             frame.set_window_title(&title);
-            egui::CentralPanel::default().show(ctx, |ui| {
-                let thumbs_height = ui.spacing().item_spacing.y
-                    + ui.spacing().scroll_bar_width
-                    + THUMBNAIL_SIZE as f32;
-                StripBuilder::new(ui)
-                    .size(Size::remainder().at_least(100.0)) // top cell
-                    .size(Size::exact(thumbs_height)) // bottom cell
-                    .vertical(|mut strip| {
-                        strip.strip(|builder| {
-                            builder
-                                .size(Size::exact(300.0))
-                                .size(Size::remainder())
-                                .horizontal(|mut strip| {
-                                    strip.cell(|ui| {
-                                        ImageControls::new(
-                                            self.image_states.get_mut(&ci).unwrap(),
-                                            self.full_images_cache.cache_get_mut(&ci),
-                                        )
-                                        .ui(ui);
-                                    });
-                                    strip.cell(|ui| {
-                                        ImageView::new(
-                                            self.image_states.get_mut(&ci).unwrap(),
-                                            self.full_images_cache.cache_get(&ci),
-                                        )
-                                        .ui(ui);
+            self.window_title = title;
+        }
+    }
+
+    /// Scans a folder picked from the recent locations.
+    fn open_location(&mut self, location: PathBuf) {
+        trace!("Opening recent location: {}", location.display());
+        self.nothing_to_open = false;
+        self.note_opened(std::slice::from_ref(&location));
+        self.file_system.add_paths(vec![location]);
+    }
+
+    /// Shown while no image is listed, either waiting for the first ones or,
+    /// when there is nothing to open, offering the recent locations.
+    fn empty_state_ui(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let title = match self.restored_location.as_ref() {
+            Some(location) => format!("iMView - {}", location.display()),
+            None => "iMView".to_string(),
+        };
+        self.set_window_title(title, frame);
+        let mut picked = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if !self.nothing_to_open {
+                ui.label("Loading images...");
+                return;
+            }
+            ui.heading("No images open");
+            ui.label("Pass files or folders on the command line, or reopen a recent location:");
+            ui.add_space(8.0);
+            picked = recent_locations_ui(ui, &self.recent_locations);
+        });
+        if let Some(location) = picked {
+            self.open_location(location);
+        }
+    }
+
+    fn status_bar_ui(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut save_requested = None;
+        let mut duplicate_scan_requested = false;
+        let mut compare_report_requested = false;
+        let mut location_picked = None;
+        let mut follow_resumed = false;
+        let size = self
+            .current_image
+            .clone()
+            .and_then(|ci| self.full_images_cache.cache_get(&ci).map(|d| d.size()));
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.menu_button("File", |ui| {
+                    ui.menu_button("Recent locations", |ui| {
+                        location_picked = recent_locations_ui(ui, &self.recent_locations);
+                    });
+                });
+                if let Some(ci) = self.current_image.as_ref() {
+                    let index = self.image_position(ci);
+                    ui.label(format!("{}/{}", index, self.image_files.len()));
+                    ui.label(ci.file_name()).on_hover_text(ci.to_string());
+                    if let Some(size) = size {
+                        ui.label(format!("{}x{}", size.x, size.y));
+                    }
+                    let state = &self.image_states[ci];
+                    ui.label(format!("{:.0}%", 100.0 * state.magnification()));
+                    if let Some((x, y)) = state.hovered_pixel {
+                        ui.label(format!("({}, {})", x, y));
+                    }
+                    ui.label(state.diff_mode.label());
+                    if self.modified_files.contains(ci) {
+                        ui.label("modified on disk");
+                    }
+                    if self.image_states[ci].is_adjusted() {
+                        let color = theme::highlight_color(ui.visuals().window_fill());
+                        ui.colored_label(color, "adjusted view")
+                            .on_hover_text("Exposure, brightness or contrast is changed");
+                    }
+                    if self.in_memory_images.contains_key(ci) {
+                        ui.label("not saved");
+                        if ui.button("Save as…").clicked() {
+                            save_requested = Some(ci.clone());
+                        }
+                    }
+                    ui.separator();
+                }
+                let memory = format!("Texture memory: {}", format_bytes(self.texture_memory()));
+                if ui
+                    .add(egui::Label::new(memory).sense(egui::Sense::click()))
+                    .on_hover_text("Click to list the live textures")
+                    .clicked()
+                {
+                    self.textures_window_open = !self.textures_window_open;
+                }
+                ui.separator();
+                ui.checkbox(&mut self.follow, "Follow")
+                    .on_hover_text("Show images as they are created or changed");
+                if self.follow && !self.follow_missed.is_empty() {
+                    let chip = format!("Paused, {} new", self.follow_missed.len());
+                    if ui
+                        .button(chip)
+                        .on_hover_text("Show the newest image and follow again")
+                        .clicked()
+                    {
+                        follow_resumed = true;
+                    }
+                }
+                if ui.button("Contact sheet…").clicked() {
+                    self.montage_window_open = !self.montage_window_open;
+                }
+                if ui.button("Settings…").clicked() {
+                    self.settings_window_open = !self.settings_window_open;
+                }
+                if ui.button("Compact mode").on_hover_text("Tab").clicked() {
+                    self.set_compact_mode(true, frame);
+                }
+                let sort_order = self.sort_order;
+                egui::ComboBox::from_id_source("sort_order")
+                    .selected_text(self.sort_order.label())
+                    .show_ui(ui, |ui| {
+                        for order in [SortOrder::Name, SortOrder::Dimensions] {
+                            ui.selectable_value(&mut self.sort_order, order, order.label());
+                        }
+                    });
+                if self.sort_order != sort_order {
+                    self.resort_requested = true;
+                }
+                if self.sort_order == SortOrder::Dimensions {
+                    ui.checkbox(&mut self.dimension_labels, "Size labels")
+                        .on_hover_text("Label the groups of images with the same dimensions");
+                }
+                if ui
+                    .add_enabled(
+                        self.duplicate_scan.is_none(),
+                        egui::Button::new("Find duplicates"),
+                    )
+                    .on_hover_text("Group the files with identical content")
+                    .clicked()
+                {
+                    duplicate_scan_requested = true;
+                }
+                if !self.duplicate_groups.is_empty() {
+                    ui.checkbox(&mut self.one_per_duplicate_group, "One per group")
+                        .on_hover_text(format!(
+                            "Show one image of each of the {} duplicate group(s)",
+                            self.duplicate_groups.len()
+                        ));
+                }
+                if ui
+                    .add_enabled(
+                        self.compare_report.is_none(),
+                        egui::Button::new("Compare report"),
+                    )
+                    .on_hover_text("Compare every pair of files named like name_a and name_b")
+                    .clicked()
+                {
+                    compare_report_requested = true;
+                }
+                if let Some((done, total)) = self.duplicate_scan {
+                    ui.add(
+                        egui::ProgressBar::new(done as f32 / total.max(1) as f32)
+                            .desired_width(150.0)
+                            .text(format!("Duplicates {}/{}", done, total)),
+                    );
+                }
+                if let Some(job) = self.montage_job.as_ref() {
+                    let (done, total) = job.progress;
+                    let progress = if total > 0 {
+                        done as f32 / total as f32
+                    } else {
+                        0.0
+                    };
+                    ui.add(
+                        egui::ProgressBar::new(progress)
+                            .desired_width(150.0)
+                            .text(format!("Contact sheet {}/{}", done, total)),
+                    );
+                }
+            });
+        });
+        if let Some(path) = save_requested {
+            self.save_in_memory_image(&path);
+        }
+        if duplicate_scan_requested {
+            self.start_duplicate_scan();
+        }
+        if compare_report_requested {
+            self.start_compare_report();
+        }
+        if let Some(location) = location_picked {
+            self.open_location(location);
+        }
+        if follow_resumed {
+            self.resume_follow();
+        }
+    }
+
+    fn main_view_ui(&mut self, ctx: &egui::Context, ci: &ImageSource) -> Option<ImageSource> {
+        let mut clicked = None;
+        let mut pin_toggled = None;
+        let mut file_action = None;
+        let mut rename_started = None;
+        let mut rename_finished = None;
+        let mut outgrown = Vec::new();
+        let mut thumbnail_zoom = 1.0;
+        let mut hovered = None;
+        // Thumbnails made smaller than they're shown are loaded again, the
+        // ones shown smaller are just scaled down. Moving to a screen with
+        // more pixels per point outgrows them too.
+        let wanted_size = self.thumbnail_pixels();
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let caption_height = if self.thumbnail_captions {
+                Thumbnail::caption_height(self.caption_size)
+            } else {
+                0.0
+            };
+            let thumbs_height = ui.spacing().item_spacing.y
+                + ui.spacing().scroll_bar_width
+                + self.thumbnail_size
+                + caption_height;
+            let has_pins = !self.pinned.is_empty();
+            let mut builder = StripBuilder::new(ui).size(Size::remainder().at_least(100.0)); // top cell
+            if has_pins {
+                builder = builder.size(Size::exact(PINNED_THUMBNAIL_SIZE)); // pinned row
+            }
+            builder
+                .size(Size::exact(thumbs_height)) // bottom cell
+                .vertical(|mut strip| {
+                    strip.strip(|builder| {
+                        builder
+                            .size(Size::exact(300.0))
+                            .size(Size::remainder())
+                            .horizontal(|mut strip| {
+                                strip.cell(|ui| {
+                                    ImageControls::new(
+                                        self.image_states.get_mut(ci).unwrap(),
+                                        self.full_images_cache.cache_get_mut(ci),
+                                    )
+                                    .logarithmic_zoom(self.logarithmic_zoom)
+                                    .bindings(self.pointer_bindings)
+                                    .ui(ui);
+                                });
+                                strip.cell(|ui| {
+                                    ImageView::new(
+                                        self.image_states.get_mut(ci).unwrap(),
+                                        self.full_images_cache.cache_get(ci),
+                                        self.partial_image
+                                            .as_ref()
+                                            .filter(|(p, _)| p == ci)
+                                            .map(|(_, d)| d)
+                                            .or(self.thumbnails_cache.get(ci)),
+                                    )
+                                    .background(self.viewport_background)
+                                    .bindings(self.pointer_bindings)
+                                    .ui(ui);
+                                });
+                            });
+                    });
+                    if has_pins {
+                        strip.cell(|ui| {
+                            egui::containers::ScrollArea::horizontal()
+                                .id_source("pinned")
+                                .show(ui, |ui| {
+                                    ui.horizontal(|ui| {
+                                        for (i, img) in self.pinned.iter().enumerate() {
+                                            let data = self.thumbnails_cache.get(img);
+                                            let mut thumb = Thumbnail::new(
+                                                data,
+                                                PINNED_THUMBNAIL_SIZE,
+                                                ci == img,
+                                            );
+                                            if let Some(&r) = self.thumbnail_resolutions.get(img) {
+                                                thumb = thumb.resolution(r as _);
+                                            }
+                                            let resp = ui.add(thumb);
+                                            if resp.hovered() {
+                                                hovered = Some(img.clone());
+                                            }
+                                            let hint = match i {
+                                                0..=8 => format!("{} ({})", img.file_name(), i + 1),
+                                                _ => img.file_name(),
+                                            };
+                                            if resp.on_hover_text(hint).clicked() {
+                                                clicked = Some(img.clone());
+                                            }
+                                        }
                                     });
                                 });
                         });
-                        strip.cell(|ui| {
-                            egui::containers::ScrollArea::horizontal().show(ui, |ui| {
-                                ui.horizontal(|ui| {
-                                    for img in self.image_files.iter() {
-                                        let data = self.thumbnails_cache.get(img);
-                                        let is_current = &ci == img;
-                                        let thumb =
-                                            Thumbnail::new(data, THUMBNAIL_SIZE as _, is_current);
-                                        if ui.add(thumb).clicked() {
-                                            self.current_image = Some(img.clone());
-                                            self.file_system.read_file(&img);
+                    }
+                    strip.cell(|ui| {
+                        egui::containers::ScrollArea::horizontal().show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                let labeled = self.dimension_labels
+                                    && self.sort_order == SortOrder::Dimensions;
+                                let mut last_dimensions = None;
+                                for img in self.image_files.iter() {
+                                    let group = self
+                                        .duplicate_group_of
+                                        .get(img)
+                                        .map(|&g| (g, &self.duplicate_groups[g]));
+                                    if let Some((_, members)) = group {
+                                        let shown = match members.contains(ci) {
+                                            true => ci,
+                                            false => &members[0],
+                                        };
+                                        if self.one_per_duplicate_group && shown != img {
+                                            continue;
                                         }
                                     }
-                                });
+                                    let dimensions = self.image_dimensions.get(img);
+                                    if labeled && last_dimensions != Some(dimensions) {
+                                        ui.separator();
+                                        match dimensions {
+                                            Some((w, h)) => ui.label(format!("{}x{}", w, h)),
+                                            None => ui.weak("Unknown size"),
+                                        };
+                                        last_dimensions = Some(dimensions);
+                                    }
+                                    let data = self.thumbnails_cache.get(img);
+                                    let is_current = ci == img;
+                                    let pinned = self.pinned.contains(img);
+                                    let mut thumb =
+                                        Thumbnail::new(data, self.thumbnail_size, is_current)
+                                            .pinned(pinned)
+                                            .duplicate(group.map(|(g, _)| group_color(g)));
+                                    if let Some(&r) = self.thumbnail_resolutions.get(img) {
+                                        thumb = thumb.resolution(r as _);
+                                    }
+                                    if self.thumbnail_captions {
+                                        thumb = thumb.caption(img.file_stem(), self.caption_size);
+                                    }
+                                    let mut resp = ui.add(thumb);
+                                    if resp.hovered() {
+                                        hovered = Some(img.clone());
+                                    }
+                                    let stale = self
+                                        .thumbnail_requested
+                                        .get(img)
+                                        .is_some_and(|&size| size < wanted_size);
+                                    if stale && ui.is_rect_visible(resp.rect) {
+                                        outgrown.push(img.clone());
+                                    }
+                                    if self.thumbnail_captions {
+                                        resp = resp.on_hover_text(img.to_string());
+                                    }
+                                    let on_badge = resp.interact_pointer_pos().is_some_and(|p| {
+                                        Thumbnail::badge_rect(resp.rect).contains(p)
+                                    });
+                                    match group {
+                                        // The badge cycles through the group.
+                                        Some((_, members)) if resp.clicked() && on_badge => {
+                                            let from = if members.contains(ci) { ci } else { img };
+                                            let i = members.iter().position(|m| m == from);
+                                            let next = i.map_or(0, |i| (i + 1) % members.len());
+                                            clicked = Some(members[next].clone());
+                                        }
+                                        _ if resp.clicked() => clicked = Some(img.clone()),
+                                        _ => (),
+                                    }
+                                    let edit = self.rename_edit.as_mut().filter(
+                                        |e| matches!(img, ImageSource::File(p) if *p == e.path),
+                                    );
+                                    if let Some(edit) = edit {
+                                        rename_finished = edit.ui(ui, resp.rect);
+                                    }
+                                    resp.context_menu(|ui| {
+                                        let label = if pinned { "Unpin" } else { "Pin (P)" };
+                                        if ui.button(label).clicked() {
+                                            pin_toggled = Some(img.clone());
+                                            ui.close_menu();
+                                        }
+                                        let is_file = matches!(img, ImageSource::File(_));
+                                        if is_file && ui.button("Rename (R)").clicked() {
+                                            rename_started = Some(img.clone());
+                                            ui.close_menu();
+                                        }
+                                        if img.file_path().is_some() {
+                                            let mut action = None;
+                                            file_action_menu(ui, &mut action);
+                                            if let Some(action) = action {
+                                                file_action = Some((img.clone(), action));
+                                            }
+                                        }
+                                    });
+                                }
                             });
                         });
+                        if ui.rect_contains_pointer(ui.max_rect()) {
+                            thumbnail_zoom = ui.input().zoom_delta();
+                        }
                     });
+                });
+        });
+        if thumbnail_zoom != 1.0 {
+            self.thumbnail_size = (self.thumbnail_size * thumbnail_zoom)
+                .clamp(MIN_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE);
+        }
+        for img in outgrown {
+            self.request_thumbnail(&img);
+        }
+        self.hover_preview_ui(ctx, hovered.as_ref());
+        if let Some(img) = pin_toggled {
+            self.toggle_pin(&img);
+        }
+        if let Some(confirmed) = rename_finished {
+            self.finish_rename(confirmed);
+        }
+        if let Some(img) = rename_started {
+            self.start_rename(&img);
+        }
+        if let Some((img, action)) = file_action {
+            self.run_file_action(ctx, &img, action);
+        }
+        clicked
+    }
+
+    /// Pops up a larger view of the hovered thumbnail, from the full image
+    /// when it happens to be cached.
+    fn hover_preview_ui(&mut self, ctx: &egui::Context, hovered: Option<&ImageSource>) {
+        let source = match self.hover_preview.update(ctx, hovered) {
+            Some(source) => source,
+            None => return,
+        };
+        let full = self
+            .full_images_cache
+            .cache_get(source)
+            .filter(|d| d.has_color_texture());
+        let data = full
+            .or_else(|| self.thumbnails_cache.get(source))
+            .filter(|d| d.error.is_none() && d.has_color_texture());
+        if let Some(data) = data {
+            HoverPreview::show(ctx, data);
+        }
+    }
+
+    fn compact_view_ui(&mut self, ctx: &egui::Context, ci: &ImageSource) {
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none())
+            .show(ctx, |ui| {
+                ImageView::new(
+                    self.image_states.get_mut(ci).unwrap(),
+                    self.full_images_cache.cache_get(ci),
+                    self.partial_image
+                        .as_ref()
+                        .filter(|(p, _)| p == ci)
+                        .map(|(_, d)| d)
+                        .or(self.thumbnails_cache.get(ci)),
+                )
+                .background(self.viewport_background)
+                .bindings(self.pointer_bindings)
+                .ui(ui);
+            });
+
+        let elapsed = self.overlay_shown_at.elapsed().as_secs_f32();
+        let alpha =
+            1.0 - ((elapsed - COMPACT_OVERLAY_SECS) / COMPACT_OVERLAY_FADE_SECS).clamp(0.0, 1.0);
+        if alpha <= 0.0 {
+            return;
+        }
+        ctx.request_repaint();
+        let index = self.image_position(ci);
+        let zoom = self.image_states[ci].magnification() * 100.0;
+        let text = format!(
+            "{}  {}/{}  {:.0}%",
+            ci.file_name(),
+            index,
+            self.image_files.len(),
+            zoom
+        );
+        egui::Area::new("compact_overlay")
+            .anchor(egui::Align2::LEFT_TOP, egui::vec2(10.0, 10.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                let bg = egui::Color32::from_black_alpha((160.0 * alpha) as u8);
+                let fg = egui::Color32::from_white_alpha((255.0 * alpha) as u8);
+                egui::Frame::none()
+                    .fill(bg)
+                    .rounding(egui::Rounding::same(4.0))
+                    .inner_margin(egui::style::Margin::same(6.0))
+                    .show(ui, |ui| ui.label(egui::RichText::new(text).color(fg)));
+            });
+    }
+
+    fn set_compact_mode(&mut self, compact: bool, frame: &mut eframe::Frame) {
+        self.compact_mode = compact;
+        self.overlay_shown_at = Instant::now();
+        frame.set_decorations(!compact);
+    }
+
+    fn toggle_pin(&mut self, source: &ImageSource) {
+        match self.pinned.iter().position(|p| p == source) {
+            Some(index) => {
+                self.pinned.remove(index);
+            }
+            None => self.pinned.push(source.clone()),
+        }
+    }
+
+    fn move_profile_line(&mut self, step: i64) {
+        let ci = match self.current_image.as_ref() {
+            Some(ci) => ci,
+            None => return,
+        };
+        let size = match self.full_images_cache.cache_get(ci) {
+            Some(data) => (data.width() as u32, data.height() as u32),
+            None => return,
+        };
+        let state = self.image_states.get_mut(ci).unwrap();
+        if let Some(line) = state.profile_line {
+            state.profile_line = Some(line.moved(step, size.0, size.1));
+        }
+    }
+
+    fn select_relative(&mut self, offset: isize) {
+        if self.image_files.is_empty() {
+            return;
+        }
+        let current = self
+            .current_image
+            .as_ref()
+            .and_then(|ci| self.image_files.iter().position(|p| p == ci))
+            .unwrap_or(0) as isize;
+        let next = (current + offset).clamp(0, self.image_files.len() as isize - 1) as usize;
+        if next as isize != current {
+            self.select_image(self.image_files[next].clone());
+        }
+    }
+
+    fn process_keyboard(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        // Page keys page through multi-page images instead of the list.
+        let multi_page = self
+            .current_image
+            .clone()
+            .and_then(|ci| {
+                self.full_images_cache
+                    .cache_get(&ci)
+                    .map(|d| d.page_count() > 1)
+            })
+            .unwrap_or(false);
+        const PIN_KEYS: [egui::Key; 9] = [
+            egui::Key::Num1,
+            egui::Key::Num2,
+            egui::Key::Num3,
+            egui::Key::Num4,
+            egui::Key::Num5,
+            egui::Key::Num6,
+            egui::Key::Num7,
+            egui::Key::Num8,
+            egui::Key::Num9,
+        ];
+        let (pin, pinned_index, rename, quick_open) = {
+            let input = ctx.input();
+            (
+                input.key_pressed(egui::Key::P) && !input.modifiers.command,
+                PIN_KEYS.iter().position(|k| input.key_pressed(*k)),
+                input.key_pressed(egui::Key::R),
+                input.key_pressed(egui::Key::P) && input.modifiers.command,
+            )
+        };
+        // This egui has no bracket keys, they come as typed text.
+        let difference_step = ctx
+            .input()
+            .events
+            .iter()
+            .map(|e| match e {
+                egui::Event::Text(t) if t == "]" => 1,
+                egui::Event::Text(t) if t == "[" => -1,
+                _ => 0,
+            })
+            .sum::<i64>();
+        if difference_step != 0 {
+            self.step_difference(difference_step);
+        }
+        if quick_open {
+            self.quick_open = Some(QuickOpen::new());
+        }
+        // Arrow keys move a picked profile line instead of switching images.
+        let moves_profile = self.current_image.as_ref().is_some_and(|ci| {
+            let state = &self.image_states[ci];
+            state.profile_tool && state.profile_line.is_some()
+        });
+        let profile_step = {
+            let input = ctx.input();
+            let back =
+                input.key_pressed(egui::Key::ArrowUp) || input.key_pressed(egui::Key::ArrowLeft);
+            let forth =
+                input.key_pressed(egui::Key::ArrowDown) || input.key_pressed(egui::Key::ArrowRight);
+            forth as i64 - back as i64
+        };
+        let (prev, next, prev_page, next_page, toggle_compact, escape, copy_color, paste) = {
+            let input = ctx.input();
+            let page_up = input.key_pressed(egui::Key::PageUp);
+            let page_down = input.key_pressed(egui::Key::PageDown);
+            (
+                input.key_pressed(egui::Key::ArrowLeft) && !moves_profile || page_up && !multi_page,
+                input.key_pressed(egui::Key::ArrowRight) && !moves_profile
+                    || page_down && !multi_page,
+                page_up && multi_page,
+                page_down && multi_page,
+                input.key_pressed(egui::Key::Tab),
+                input.key_pressed(egui::Key::Escape),
+                input.key_pressed(egui::Key::C) && !input.modifiers.command,
+                input.key_pressed(egui::Key::V) && input.modifiers.command,
+            )
+        };
+        if prev {
+            self.select_relative(-1);
+        }
+        if next {
+            self.select_relative(1);
+        }
+        if prev_page || next_page {
+            let ci = self.current_image.clone().unwrap();
+            let state = &self.image_states[&ci];
+            if let Some(data) = self.full_images_cache.cache_get_mut(&ci) {
+                let page = if next_page {
+                    data.current_page() + 1
+                } else {
+                    data.current_page().saturating_sub(1)
+                };
+                data.set_page(ctx, state, page);
+            }
+        }
+        if moves_profile && profile_step != 0 {
+            self.move_profile_line(profile_step);
+        }
+        if let Some(ci) = self.current_image.clone().filter(|_| pin) {
+            self.toggle_pin(&ci);
+        }
+        if let Some(img) = pinned_index.and_then(|i| self.pinned.get(i)).cloned() {
+            self.select_image(img);
+        }
+        // The name field lives in the thumbnail strip.
+        if let Some(ci) = self
+            .current_image
+            .clone()
+            .filter(|_| rename && !self.compact_mode)
+        {
+            self.start_rename(&ci);
+        }
+        if copy_color {
+            self.copy_hovered_color(ctx);
+        }
+        if paste {
+            self.paste_image();
+        }
+        if toggle_compact {
+            self.set_compact_mode(!self.compact_mode, frame);
+        } else if escape && self.compact_mode {
+            self.set_compact_mode(false, frame);
+        }
+    }
+
+    /// Moves the view through the changed regions of the shown difference.
+    fn step_difference(&mut self, step: i64) {
+        let ci = match self.current_image.as_ref() {
+            Some(ci) => ci,
+            None => return,
+        };
+        let state = self.image_states.get_mut(ci).unwrap();
+        let regions = self
+            .full_images_cache
+            .cache_get(ci)
+            .and_then(|data| data.diff_regions(state.diff_mode));
+        match regions {
+            Some([]) => self.toast = Some(Toast::info("No changed regions")),
+            Some(regions) => state.step_difference(regions, step),
+            None => (),
+        }
+    }
+
+    /// Copies the color under the pointer to the clipboard as hex and CSS
+    /// `rgba(...)`, averaged over the picker's sample size.
+    fn copy_hovered_color(&mut self, ctx: &egui::Context) {
+        let ci = match self.current_image.as_ref() {
+            Some(ci) => ci,
+            None => return,
+        };
+        let state = &self.image_states[ci];
+        let (x, y) = match state.hovered_pixel {
+            Some(pos) => pos,
+            None => return,
+        };
+        let radius = state.sample_size / 2;
+        let color = self
+            .full_images_cache
+            .cache_get(ci)
+            .and_then(|data| data.average_at(x, y, radius));
+        if let Some([r, g, b, a]) = color {
+            let hex = format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a);
+            let css = format!("rgba({}, {}, {}, {:.3})", r, g, b, a as f32 / 255.0);
+            ctx.output().copied_text = format!("{} {}", hex, css);
+            self.toast = Some(Toast::info(format!("Copied {} at ({}, {})", hex, x, y)));
+        }
+    }
+
+    fn process_alignment_request(&mut self, path: &ImageSource) {
+        let state = self.image_states.get_mut(path).unwrap();
+        if !state.alignment_requested {
+            return;
+        }
+        state.alignment_requested = false;
+        if let Some(data) = self.full_images_cache.cache_get_mut(path) {
+            if let Some((one, two)) = data.split_halves(state.diff_mode) {
+                data.alignment_status = AlignmentStatus::Running(state.diff_mode);
+                self.file_system.estimate_alignment(path, one, two);
+            }
+        }
+    }
+
+    fn process_alpha_anomaly_request(&mut self, path: &ImageSource) {
+        let state = &self.image_states[path];
+        if !state.show_alpha_anomalies {
+            return;
+        }
+        let threshold = state.alpha_threshold;
+        if let Some(data) = self.full_images_cache.cache_get_mut(path) {
+            if let Some(img) = data.start_anomaly_scan(threshold) {
+                self.file_system.find_alpha_anomalies(path, img, threshold);
+            }
+        }
+    }
+
+    /// Computes the statistics of the selected box annotation once it has
+    /// stayed put for `REGION_STATS_DEBOUNCE_SECS`.
+    fn process_region_stats_request(&mut self, ctx: &Context, path: &ImageSource) {
+        let state = &self.image_states[path];
+        let data = match self.full_images_cache.cache_get_mut(path) {
+            Some(data) => data,
+            None => return,
+        };
+        let (w, h) = (data.width() as u32, data.height() as u32);
+        let key = state
+            .annotations
+            .selected_rectangle()
+            .and_then(|(min, max)| Region::covered(min, max, w, h))
+            .map(|region| RegionKey {
+                region,
+                diff_mode: state.diff_mode,
+                diff_metric: state.diff_metric,
             });
+        let key = match key {
+            Some(key) => key,
+            None => return,
+        };
+        let changed_at = match &self.region_changed {
+            Some((p, k, at)) if p == path && *k == key => *at,
+            _ => {
+                self.region_changed = Some((path.clone(), key, Instant::now()));
+                Instant::now()
+            }
+        };
+        if changed_at.elapsed().as_secs_f32() < REGION_STATS_DEBOUNCE_SECS {
+            ctx.request_repaint();
+            return;
+        }
+        if let Some((img, diff)) = data.start_region_stats(key) {
+            self.file_system.compute_region_stats(path, key, img, diff);
+        }
+    }
+
+    #[cfg(feature = "video")]
+    fn process_video_seek(&mut self, path: &ImageSource) {
+        if self.video_seek_in_flight.is_some() {
+            return;
+        }
+        if let (Some(timestamp), ImageSource::File(file)) =
+            (self.image_states[path].video_seek, path)
+        {
+            self.file_system.read_video_frame(file, timestamp);
+            self.video_seek_in_flight = Some((path.clone(), timestamp));
+        }
+    }
+
+    #[cfg(feature = "video")]
+    fn video_frame_loaded(&mut self, path: ImageSource, frame: std::io::Result<video::VideoFrame>) {
+        self.loading_images.remove(&path);
+        let requested = match self.video_seek_in_flight.take() {
+            Some((p, timestamp)) if p == path => Some(timestamp),
+            other => {
+                self.video_seek_in_flight = other;
+                None
+            }
+        };
+        let state = match self.image_states.get_mut(&path) {
+            Some(state) => state,
+            None => return,
+        };
+        // A newer position requested meanwhile stays pending.
+        if requested.is_some() && state.video_seek == requested {
+            state.video_seek = None;
+        }
+        match frame {
+            Ok(frame) => {
+                trace!(
+                    "Video frame loaded: {} at {:.3} s",
+                    path,
+                    frame.position.timestamp
+                );
+                match self.full_images_cache.cache_get_mut(&path) {
+                    Some(data) if data.video().is_some() => {
+                        data.set_video_frame(&self.cc, state, frame.image, frame.position)
+                    }
+                    _ => {
+                        let data =
+                            ImageData::video_frame(&path, frame.image, frame.position, &self.cc);
+                        self.full_images_cache.cache_set(path, data);
+                    }
+                }
+            }
+            Err(err) => {
+                warn!("Failed to decode video frame of {}: {}", path, err);
+                if requested.is_some() {
+                    state.video_seek = None;
+                    self.toast = Some(Toast::error(format!("Can't seek video: {}", err)));
+                } else if self.full_images_cache.cache_get(&path).is_none() {
+                    self.full_images_cache
+                        .cache_set(path, ImageData::error(&err));
+                }
+            }
+        }
+    }
+
+    fn process_operation_event(&mut self, event: filesystem::OperationEvent) {
+        match event {
+            filesystem::OperationEvent::ThumbnailLoaded((path, img, elapsed)) => {
+                if self.log_timings {
+                    info!(
+                        "Thumbnail of {} decoded in {} ms",
+                        path,
+                        elapsed.as_millis()
+                    );
+                }
+                match img {
+                    Err(err) => {
+                        warn!("Failed to load thumbnail for {}: {}", path, err);
+                        let data = ImageData::error(&err);
+                        self.thumbnails_cache.insert(path, data);
+                    }
+                    Ok((img, dimensions)) => {
+                        trace!("Thumbnail loaded: {}", path);
+                        if self.image_dimensions.insert(path.clone(), dimensions)
+                            != Some(dimensions)
+                            && self.sort_order == SortOrder::Dimensions
+                        {
+                            self.resort_requested = true;
+                        }
+                        let data = ImageData::thumbnail(&path, img, &self.cc);
+                        if let Some(&size) = self.thumbnail_requested.get(&path) {
+                            self.thumbnail_resolutions.insert(path.clone(), size);
+                        }
+                        self.thumbnails_cache.insert(path, data);
+                    }
+                }
+            }
+            #[cfg(feature = "video")]
+            filesystem::OperationEvent::VideoFrameLoaded((path, frame)) => {
+                self.video_frame_loaded(path, frame)
+            }
+            filesystem::OperationEvent::ImageLoaded((path, img, elapsed)) => {
+                if self.log_timings {
+                    info!("{} decoded in {} ms", path, elapsed.as_millis());
+                }
+                self.loading_images.remove(&path);
+                self.clear_partial_image(&path);
+                match img {
+                    Err(err) => {
+                        warn!("Failed to load image for {}: {}", path, err);
+                        let data = ImageData::error(&err);
+                        self.full_images_cache.cache_set(path, data);
+                    }
+                    Ok((pages, info)) => {
+                        trace!("Image loaded: {} ({} page(s))", path, pages.len());
+                        let mut data = ImageData::full_image(&path, pages, &self.cc);
+                        data.set_decode_time(elapsed);
+                        data.set_source_info(info);
+                        match self.previous_version.take() {
+                            Some((p, img)) if p == path => data.set_previous_version(img),
+                            other => self.previous_version = other,
+                        }
+                        self.full_images_cache.cache_set(path, data);
+                    }
+                }
+            }
+            filesystem::OperationEvent::ImageSaved((path, res)) => match res {
+                Ok(_) => {
+                    trace!("Image saved: {}", path.display());
+                    self.toast = Some(Toast::info(format!("Saved {}", path.display())));
+                }
+                Err(err) => {
+                    warn!("Failed to save {}: {}", path.display(), err);
+                    self.toast = Some(Toast::error(format!(
+                        "Failed to save {}: {}",
+                        path.display(),
+                        err
+                    )));
+                }
+            },
+            filesystem::OperationEvent::MontageProgress((_, done, total)) => {
+                if let Some(job) = self.montage_job.as_mut() {
+                    job.progress = (done, total);
+                }
+            }
+            filesystem::OperationEvent::MontageSaved((path, res)) => {
+                self.montage_job = None;
+                self.toast = Some(match res {
+                    Ok(pages) => Toast::info(format!(
+                        "Contact sheet saved: {} ({} page(s))",
+                        path.display(),
+                        pages
+                    )),
+                    Err(err) => {
+                        warn!("Failed to save contact sheet {}: {}", path.display(), err);
+                        Toast::error(format!("Failed to save contact sheet: {}", err))
+                    }
+                });
+            }
+            filesystem::OperationEvent::AlphaAnomaliesFound((path, anomalies)) => {
+                trace!("Alpha anomalies in {}: {}", path, anomalies.count);
+                if let Some(data) = self.full_images_cache.cache_get_mut(&path) {
+                    data.set_alpha_anomalies(anomalies);
+                }
+            }
+            filesystem::OperationEvent::AnnotationsRead((path, res)) => match res {
+                Ok(items) => {
+                    if let Some(state) = self.image_states.get_mut(&path) {
+                        state.annotations.set_loaded(items);
+                    }
+                }
+                Err(e) => {
+                    let msg = format!("Can't read the annotations of {}: {}", path, e);
+                    self.toast = Some(Toast::error(msg));
+                }
+            },
+            filesystem::OperationEvent::AnnotationsWritten((path, res)) => match res {
+                Ok(items) => {
+                    if let Some(state) = self.image_states.get_mut(&path) {
+                        state.annotations.set_saved(items);
+                    }
+                    self.toast = Some(Toast::info("Annotations saved"));
+                }
+                Err(e) => {
+                    let msg = format!("Can't save the annotations of {}: {}", path, e);
+                    self.toast = Some(Toast::error(msg));
+                }
+            },
+            filesystem::OperationEvent::RenameFailed((path, e)) => {
+                let msg = format!("Can't rename {}: {}", path.display(), e);
+                self.toast = Some(Toast::error(msg));
+            }
+            filesystem::OperationEvent::PairCompared(((a, b), res)) => {
+                if let Some(report) = self.compare_report.as_mut() {
+                    report.set_stats(&a, &b, res);
+                }
+            }
+            filesystem::OperationEvent::FileHashed((path, res)) => {
+                match res {
+                    Ok(hash) => {
+                        self.file_hashes.insert(path, hash);
+                    }
+                    Err(e) => warn!("Can't hash {}: {}", path.display(), e),
+                }
+                if let Some((done, total)) = self.duplicate_scan.as_mut() {
+                    *done += 1;
+                    if *done >= *total {
+                        self.finish_duplicate_scan();
+                    }
+                }
+            }
+            filesystem::OperationEvent::RootResolved(root) => {
+                trace!("Root folder: {}", root.display());
+                self.recent_locations.retain(|l| *l != root);
+                self.recent_locations.insert(0, root);
+                self.recent_locations.truncate(MAX_RECENT_LOCATIONS);
+            }
+            filesystem::OperationEvent::Rescanned((roots, files)) => {
+                let ignored = self
+                    .image_files
+                    .iter()
+                    .filter_map(|source| match source {
+                        ImageSource::File(path)
+                            if path.parent().is_some_and(|p| roots.iter().any(|r| r == p))
+                                && !files.contains(path) =>
+                        {
+                            Some(path.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                for path in ignored {
+                    self.remove_file(path);
+                }
+                for path in files {
+                    self.add_file(ImageSource::File(path));
+                }
+                self.resort_requested = true;
+            }
+            filesystem::OperationEvent::PathsAdded(sources) => {
+                let first = sources.first().cloned();
+                for source in sources {
+                    self.add_file(source);
+                }
+                if let Some(first) = first {
+                    self.select_image(first);
+                }
+            }
+            filesystem::OperationEvent::RegionStatsComputed((path, stats)) => {
+                if let Some(data) = self.full_images_cache.cache_get_mut(&path) {
+                    data.set_region_stats(stats);
+                }
+            }
+            filesystem::OperationEvent::AlignmentEstimated((path, alignment)) => {
+                if let Some(data) = self.full_images_cache.cache_get_mut(&path) {
+                    if let AlignmentStatus::Running(mode) = data.alignment_status {
+                        data.alignment_status = match alignment {
+                            Ok(a) => {
+                                trace!("Alignment estimated for {}: {:?}", path, a);
+                                AlignmentStatus::Estimated(mode, a)
+                            }
+                            Err(err) => {
+                                warn!("Failed to align {}: {}", path, err);
+                                AlignmentStatus::Failed(err.to_string())
+                            }
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl eframe::App for IMViewApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        storage.set_string(THUMBNAIL_SIZE_KEY, self.thumbnail_size.to_string());
+        match serde_json::to_string(&self.recent_locations) {
+            Ok(json) => storage.set_string(RECENT_LOCATIONS_KEY, json),
+            Err(e) => warn!("Can't save the recent locations: {}", e),
+        }
+        storage.set_string(SNIFF_CONTENT_KEY, self.sniff_content.to_string());
+        storage.set_string(LOGARITHMIC_ZOOM_KEY, self.logarithmic_zoom.to_string());
+        storage.set_string(THEME_KEY, self.theme.name().to_string());
+        let background = self.viewport_background.map(|c| [c.r(), c.g(), c.b()]);
+        match serde_json::to_string(&background) {
+            Ok(json) => storage.set_string(VIEWPORT_BACKGROUND_KEY, json),
+            Err(e) => warn!("Can't save the viewport background: {}", e),
+        }
+        match serde_json::to_string(&self.pointer_bindings) {
+            Ok(json) => storage.set_string(POINTER_BINDINGS_KEY, json),
+            Err(e) => warn!("Can't save the pointer bindings: {}", e),
+        }
+        if self.session_file.is_none() {
+            match self.session().to_json() {
+                Ok(json) => storage.set_string(SESSION_KEY, json),
+                Err(e) => warn!("Can't save the session: {}", e),
+            }
+        }
+        match serde_json::to_string(&self.ignore_patterns) {
+            Ok(json) => storage.set_string(IGNORE_PATTERNS_KEY, json),
+            Err(e) => warn!("Can't save the ignore patterns: {}", e),
+        }
+    }
+
+    /// Only the settings are kept, the window always opens maximized.
+    fn persist_native_window(&self) -> bool {
+        false
+    }
+
+    fn persist_egui_memory(&self) -> bool {
+        false
+    }
+
+    fn on_exit_event(&mut self) -> bool {
+        trace!("Closing application");
+        if let Some(file) = self.session_file.as_ref() {
+            if let Err(e) = self.session().write(file) {
+                error!("Can't save the session to {}: {}", file.display(), e);
+            }
+        }
+        self.file_system.shutdown();
+        if let Some(instance) = self.instance.as_ref() {
+            instance.release();
+        }
+        true
+    }
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.process_fs_events();
+        self.process_settled_files(ctx);
+        self.process_forwarded_paths();
+        self.note_interaction(ctx);
+        self.process_keyboard(ctx, frame);
+
+        if let Some(ci) = self.current_image.clone() {
+            if self.full_images_cache.cache_get(&ci).is_none() {
+                self.request_full_image(&ci);
+            }
+            self.update_window_title(&ci, frame);
+            if let Some(data) = self.full_images_cache.cache_get_mut(&ci) {
+                data.restore_display_resources(ctx, &self.image_states[&ci]);
+            }
+            let clicked = if self.compact_mode {
+                self.compact_view_ui(ctx, &ci);
+                None
+            } else {
+                self.status_bar_ui(ctx, frame);
+                self.montage_ui(ctx);
+                self.settings_ui(ctx);
+                self.textures_ui(ctx);
+                self.main_view_ui(ctx, &ci)
+            };
+            let picked = self.quick_open_ui(ctx);
+            let reported = self.compare_report_ui(ctx);
+            self.process_alignment_request(&ci);
+            self.process_alpha_anomaly_request(&ci);
+            self.process_file_action_request(ctx, &ci);
+            self.process_annotations_io(&ci);
+            self.process_region_stats_request(ctx, &ci);
+            #[cfg(feature = "video")]
+            self.process_video_seek(&ci);
+            self.process_montage_job();
+            self.process_export_request(&ci, ctx.pixels_per_point());
+            if let Some(path) = clicked.or(picked).or(reported) {
+                self.select_image(path);
+            }
         } else {
-            egui::CentralPanel::default().show(ctx, |ui| ui.label("Loading images..."));
+            self.empty_state_ui(ctx, frame);
         }
+
+        if let Some(toast) = self.toast.as_ref() {
+            if toast.is_expired() {
+                self.toast = None;
+            } else {
+                toast.show(ctx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str) -> ImageSource {
+        ImageSource::File(name.into())
+    }
+
+    fn files(names: &[&str]) -> Vec<ImageSource> {
+        names.iter().map(|n| file(n)).collect()
+    }
+
+    #[test]
+    fn removed_current_image_passes_to_a_neighbor() {
+        let removed = |index: usize| {
+            let mut list = files(&["a", "b", "c"]);
+            let path = list[index].clone();
+            let current = remove_listed(&mut list, &path, Some(&path));
+            assert_eq!(list.len(), 2);
+            let current = current.unwrap();
+            (list.iter().position(|p| *p == current), current)
+        };
+        assert_eq!(removed(0), (Some(0), file("b")));
+        assert_eq!(removed(1), (Some(1), file("c")));
+        assert_eq!(removed(2), (Some(1), file("b")));
+
+        let mut list = files(&["a"]);
+        let only = list[0].clone();
+        assert_eq!(remove_listed(&mut list, &only, Some(&only)), None);
+        assert!(list.is_empty());
+    }
+
+    fn memory(name: &str, id: u64) -> ImageSource {
+        ImageSource::Memory {
+            name: name.into(),
+            id,
+        }
+    }
+
+    #[test]
+    fn mixed_entries_sort_files_first() {
+        let mut list = vec![
+            memory("b", 1),
+            file("b"),
+            memory("a", 2),
+            file("a"),
+            memory("a", 1),
+        ];
+        list.sort();
+        let expected = vec![
+            file("a"),
+            file("b"),
+            memory("a", 1),
+            memory("a", 2),
+            memory("b", 1),
+        ];
+        assert_eq!(list, expected);
+    }
+
+    #[test]
+    fn mixed_entries_select_rename_and_evict() {
+        let pasted = memory("pasted", 1);
+        let mut list = vec![file("a"), pasted.clone(), file("c")];
+        assert_eq!(
+            remove_listed(&mut list, &pasted, Some(&pasted)),
+            Some(file("c"))
+        );
+        assert_eq!(
+            remove_listed(&mut list, &file("a"), Some(&pasted)),
+            Some(pasted.clone())
+        );
+
+        let mut list = vec![file("a"), pasted.clone()];
+        assert!(rename_listed(&mut list, &file("a"), &file("z")));
+        assert!(!rename_listed(&mut list, &file("a"), &file("y")));
+        list.sort();
+        assert_eq!(list, vec![file("z"), pasted.clone()]);
+
+        // Memory entries with the same name are evicted on their own.
+        let mut cache = SizedCache::with_size(2);
+        cache.cache_set(memory("pasted", 2), 2);
+        cache.cache_set(pasted.clone(), 1);
+        cache.cache_set(file("pasted"), 0);
+        assert_eq!(cache.cache_get(&memory("pasted", 2)), None);
+        assert_eq!(cache.cache_get(&pasted), Some(&1));
+        assert_eq!(cache.cache_get(&file("pasted")), Some(&0));
+    }
+
+    #[test]
+    fn removing_another_file_keeps_the_current_image() {
+        let mut list = files(&["a", "b", "c"]);
+        let (a, c) = (list[0].clone(), list[2].clone());
+        assert_eq!(remove_listed(&mut list, &a, Some(&c)), Some(c.clone()));
+        assert_eq!(list, files(&["b", "c"]));
+        assert_eq!(remove_listed(&mut list, &c, None), None);
+        assert_eq!(list, files(&["b"]));
     }
 }
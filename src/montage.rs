@@ -0,0 +1,106 @@
+use crate::raster::{draw_text, text_width};
+use eframe::egui::pos2;
+use image::imageops::{replace, resize, FilterType};
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+const CAPTION_SIZE: f32 = 14.0;
+const CAPTION_HEIGHT: u32 = 20;
+const PADDING: u32 = 8;
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const CAPTION_COLOR: [u8; 4] = [230, 230, 230, 255];
+
+#[derive(Clone, Copy, Debug)]
+pub struct MontageSettings {
+    pub columns: u32,
+    pub cell_size: u32,
+    pub rows_per_page: u32,
+}
+
+impl MontageSettings {
+    pub fn new() -> Self {
+        Self {
+            columns: 6,
+            cell_size: 150,
+            rows_per_page: 8,
+        }
+    }
+
+    pub fn per_page(&self) -> usize {
+        (self.columns.max(1) * self.rows_per_page.max(1)) as usize
+    }
+
+    pub fn page_count(&self, items: usize) -> usize {
+        items.div_ceil(self.per_page())
+    }
+}
+
+/// Path of the `page`-th output file; multi-page sheets get a numeric suffix.
+pub fn page_path(target: &Path, page: usize, pages: usize) -> PathBuf {
+    if pages <= 1 {
+        return target.to_path_buf();
+    }
+    let stem = target.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = target
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_else(|| "png".into());
+    target.with_file_name(format!("{}_{:03}.{}", stem, page + 1, ext))
+}
+
+fn fit_caption(name: &str, width: f32) -> String {
+    if text_width(name, CAPTION_SIZE) <= width {
+        return name.to_string();
+    }
+    let mut chars = name.chars().collect::<Vec<_>>();
+    while !chars.is_empty() {
+        chars.pop();
+        let caption = format!("{}…", chars.iter().collect::<String>());
+        if text_width(&caption, CAPTION_SIZE) <= width {
+            return caption;
+        }
+    }
+    String::new()
+}
+
+/// Lays out the captioned thumbnails into a grid of `settings.columns` columns.
+pub fn render_page(items: &[(String, RgbaImage)], settings: &MontageSettings) -> RgbaImage {
+    let columns = settings.columns.max(1);
+    let rows = (items.len() as u32).div_ceil(columns);
+    let cell_w = settings.cell_size + PADDING;
+    let cell_h = settings.cell_size + CAPTION_HEIGHT + PADDING;
+    let mut page = RgbaImage::from_pixel(
+        columns * cell_w + PADDING,
+        rows * cell_h + PADDING,
+        BACKGROUND,
+    );
+    for (idx, (name, thumb)) in items.iter().enumerate() {
+        let col = idx as u32 % columns;
+        let row = idx as u32 / columns;
+        let x = PADDING + col * cell_w;
+        let y = PADDING + row * cell_h;
+
+        let (w, h) = thumb.dimensions();
+        if w > 0 && h > 0 {
+            let s = settings.cell_size as f32 / w.max(h) as f32;
+            let tw = ((w as f32 * s).round() as u32).max(1);
+            let th = ((h as f32 * s).round() as u32).max(1);
+            let scaled = resize(thumb, tw, th, FilterType::Triangle);
+            let ox = x + (settings.cell_size - tw) / 2;
+            let oy = y + (settings.cell_size - th) / 2;
+            replace(&mut page, &scaled, ox as i64, oy as i64);
+        }
+
+        let caption = fit_caption(name, settings.cell_size as f32);
+        let cx = x as f32 + (settings.cell_size as f32 - text_width(&caption, CAPTION_SIZE)) / 2.0;
+        let cy = (y + settings.cell_size) as f32 + 2.0;
+        draw_text(
+            &mut page,
+            pos2(cx, cy),
+            &caption,
+            CAPTION_SIZE,
+            CAPTION_COLOR,
+        );
+    }
+    page
+}
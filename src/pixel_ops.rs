@@ -0,0 +1,513 @@
+//! Per-pixel kernels working on raw RGBA buffers.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+
+fn row_len(img: &RgbaImage) -> usize {
+    (img.width() as usize * 4).max(4)
+}
+
+/// Replaces the color channels of `one` with the absolute difference to
+/// `two`, keeping the alpha channel of `one`.
+pub fn image_diff(mut one: RgbaImage, two: &RgbaImage) -> RgbaImage {
+    let row = row_len(&one);
+    one.par_chunks_mut(row)
+        .zip(two.par_chunks(row))
+        .for_each(|(one_row, two_row)| {
+            for (op, tp) in one_row.chunks_exact_mut(4).zip(two_row.chunks_exact(4)) {
+                for c in 0..3 {
+                    op[c] = op[c].abs_diff(tp[c]);
+                }
+            }
+        });
+    one
+}
+
+fn srgb_decode(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_encode(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Linear light of every sRGB encoded value, in 16 bits.
+fn linear_lut() -> [u16; 256] {
+    let mut lut = [0u16; 256];
+    for (v, l) in lut.iter_mut().enumerate() {
+        *l = (srgb_decode(v as f32 / 255.0) * 65535.0).round() as u16;
+    }
+    lut
+}
+
+/// sRGB encoded value of every 16 bit linear light value.
+fn encode_lut() -> Vec<u8> {
+    (0..=u16::MAX)
+        .map(|v| (srgb_encode(v as f32 / 65535.0) * 255.0).round() as u8)
+        .collect()
+}
+
+/// Like `image_diff`, but the difference is taken between linear light
+/// values and encoded back to sRGB, so it follows the emitted light rather
+/// than the encoded values.
+pub fn image_diff_linear(mut one: RgbaImage, two: &RgbaImage) -> RgbaImage {
+    let (linear, encode) = (linear_lut(), encode_lut());
+    let row = row_len(&one);
+    one.par_chunks_mut(row)
+        .zip(two.par_chunks(row))
+        .for_each(|(one_row, two_row)| {
+            for (op, tp) in one_row.chunks_exact_mut(4).zip(two_row.chunks_exact(4)) {
+                for c in 0..3 {
+                    let diff = linear[op[c] as usize].abs_diff(linear[tp[c] as usize]);
+                    op[c] = encode[diff as usize];
+                }
+            }
+        });
+    one
+}
+
+/// Number of pixels of `img` at every luma value.
+pub fn luma_histogram(img: &RgbaImage) -> [u32; 256] {
+    img.par_chunks(row_len(img))
+        .fold(
+            || [0u32; 256],
+            |mut hist, row| {
+                for p in row.chunks_exact(4) {
+                    hist[scalar_value(p) as usize] += 1;
+                }
+                hist
+            },
+        )
+        .reduce(
+            || [0u32; 256],
+            |mut a, b| {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                a
+            },
+        )
+}
+
+/// Earth mover's distance between two histograms scaled to the same total,
+/// the average number of levels values move to turn one into the other.
+pub fn histogram_distance(a: &[u32; 256], b: &[u32; 256]) -> f32 {
+    let total_a: f64 = a.iter().map(|v| *v as f64).sum();
+    let total_b: f64 = b.iter().map(|v| *v as f64).sum();
+    if total_a == 0.0 || total_b == 0.0 {
+        return 0.0;
+    }
+    let (mut cdf_a, mut cdf_b, mut distance) = (0.0, 0.0, 0.0);
+    for (a, b) in a.iter().zip(b) {
+        cdf_a += *a as f64 / total_a;
+        cdf_b += *b as f64 / total_b;
+        distance += (cdf_a - cdf_b).abs();
+    }
+    distance as f32
+}
+
+/// Builds a lookup table mapping channel values through `1 / gamma`.
+pub fn gamma_lut(gamma: f32) -> [u8; 256] {
+    let inv_gamma = 1.0 / gamma;
+    let mut lut = [0u8; 256];
+    for (v, l) in lut.iter_mut().enumerate() {
+        *l = ((v as f32 / 255.0).powf(inv_gamma) * 255.0) as u8;
+    }
+    lut
+}
+
+/// Builds a lookup table for view adjustments. Exposure scales linear light
+/// by `2^exposure`, then contrast stretches around mid gray and brightness
+/// shifts the result, both on the encoded values.
+pub fn adjustment_lut(exposure: f32, brightness: f32, contrast: f32) -> [u8; 256] {
+    let gain = exposure.exp2();
+    let mut lut = [0u8; 256];
+    for (v, l) in lut.iter_mut().enumerate() {
+        let linear = (v as f32 / 255.0).powf(2.2) * gain;
+        let encoded = linear.powf(1.0 / 2.2);
+        let adjusted = (encoded - 0.5) * contrast + 0.5 + brightness;
+        *l = (adjusted.clamp(0.0, 1.0) * 255.0).round() as u8;
+    }
+    lut
+}
+
+/// Maps the color channels of `img` through `lut`, alpha is left untouched.
+pub fn apply_lut(mut img: RgbaImage, lut: &[u8; 256]) -> RgbaImage {
+    let row = row_len(&img);
+    img.par_chunks_mut(row).for_each(|img_row| {
+        for p in img_row.chunks_exact_mut(4) {
+            p[0] = lut[p[0] as usize];
+            p[1] = lut[p[1] as usize];
+            p[2] = lut[p[2] as usize];
+        }
+    });
+    img
+}
+
+/// How the colors of an image relate to its alpha, see `alpha_stats`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlphaStats {
+    /// No color channel of any pixel exceeds its alpha.
+    pub within_alpha: bool,
+    /// Some pixel is neither opaque nor fully transparent.
+    pub translucent: bool,
+    /// Some translucent pixel has color, `unpremultiply` changes it.
+    pub unpremultiply_changes: bool,
+}
+
+impl AlphaStats {
+    /// Whether the image likely holds premultiplied colors. Opaque images
+    /// fit either reading and count as straight.
+    pub fn looks_premultiplied(&self) -> bool {
+        self.within_alpha && self.translucent
+    }
+}
+
+pub fn alpha_stats(img: &RgbaImage) -> AlphaStats {
+    let empty = AlphaStats {
+        within_alpha: true,
+        translucent: false,
+        unpremultiply_changes: false,
+    };
+    img.par_chunks(row_len(img))
+        .map(|row| {
+            row.chunks_exact(4).fold(empty, |s, p| {
+                let translucent = p[3] != 0 && p[3] != 255;
+                AlphaStats {
+                    within_alpha: s.within_alpha && p[..3].iter().all(|c| *c <= p[3]),
+                    translucent: s.translucent || translucent,
+                    unpremultiply_changes: s.unpremultiply_changes
+                        || (translucent && p[..3].iter().any(|c| *c > 0)),
+                }
+            })
+        })
+        .reduce(
+            || empty,
+            |a, b| AlphaStats {
+                within_alpha: a.within_alpha && b.within_alpha,
+                translucent: a.translucent || b.translucent,
+                unpremultiply_changes: a.unpremultiply_changes || b.unpremultiply_changes,
+            },
+        )
+}
+
+/// Straight color of a premultiplied pixel, its color channels divided by
+/// alpha. Fully transparent pixels keep their channels.
+pub fn straight_color(mut p: [u8; 4]) -> [u8; 4] {
+    let a = p[3] as u32;
+    if a != 0 && a != 255 {
+        for c in p[..3].iter_mut() {
+            *c = ((*c as u32 * 255 + a / 2) / a).min(255) as u8;
+        }
+    }
+    p
+}
+
+/// Makes the colors of premultiplied `img` straight.
+pub fn unpremultiply(mut img: RgbaImage) -> RgbaImage {
+    let row = row_len(&img);
+    img.par_chunks_mut(row).for_each(|img_row| {
+        for p in img_row.chunks_exact_mut(4) {
+            let straight = straight_color([p[0], p[1], p[2], p[3]]);
+            p.copy_from_slice(&straight);
+        }
+    });
+    img
+}
+
+/// Value range of an image read as a single channel, and whether every pixel
+/// is gray so the image effectively is one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScalarStats {
+    pub gray: bool,
+    pub min: u8,
+    pub max: u8,
+}
+
+/// Single channel value of a pixel, its luma. Gray pixels keep their value.
+pub fn scalar_value(p: &[u8]) -> u8 {
+    ((p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114 + 500) / 1000) as u8
+}
+
+pub fn scalar_stats(img: &RgbaImage) -> ScalarStats {
+    let empty = ScalarStats {
+        gray: true,
+        min: u8::MAX,
+        max: u8::MIN,
+    };
+    let stats = img
+        .par_chunks(row_len(img))
+        .map(|row| {
+            row.chunks_exact(4).fold(empty, |s, p| {
+                let v = scalar_value(p);
+                ScalarStats {
+                    gray: s.gray && p[0] == p[1] && p[1] == p[2],
+                    min: s.min.min(v),
+                    max: s.max.max(v),
+                }
+            })
+        })
+        .reduce(
+            || empty,
+            |a, b| ScalarStats {
+                gray: a.gray && b.gray,
+                min: a.min.min(b.min),
+                max: a.max.max(b.max),
+            },
+        );
+    if stats.min > stats.max {
+        ScalarStats {
+            min: 0,
+            max: u8::MAX,
+            ..stats
+        }
+    } else {
+        stats
+    }
+}
+
+/// Colors single channel images are shown with.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Colormap {
+    Grayscale,
+    Viridis,
+    Turbo,
+}
+
+/// Color of `colormap` at `t` in `[0, 1]`. Viridis and turbo use their
+/// published polynomial fits.
+fn colormap_color(colormap: Colormap, t: f32) -> [u8; 3] {
+    const VIRIDIS: [[f32; 3]; 7] = [
+        [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+        [0.105_093_04, 1.404_613_5, 1.384_590_2],
+        [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+        [-4.634_230_5, -5.799_101, -19.332_44],
+        [6.228_27, 14.179_933, 56.690_55],
+        [4.776_385, -13.745_145, -65.353_03],
+        [-5.435_456, 4.645_852_6, 26.312_435],
+    ];
+    const TURBO: [[f32; 3]; 6] = [
+        [0.135_721_38, 0.091_402_61, 0.106_673_3],
+        [4.615_392_6, 2.194_188_4, 12.641_946],
+        [-42.660_32, 4.842_966_6, -60.582_05],
+        [132.131_08, -14.185_033, 110.362_77],
+        [-152.942_4, 4.277_298_6, -89.903_11],
+        [59.286_38, 2.829_566, 27.348_25],
+    ];
+    let coefs: &[[f32; 3]] = match colormap {
+        Colormap::Grayscale => return [(t * 255.0).round() as u8; 3],
+        Colormap::Viridis => &VIRIDIS,
+        Colormap::Turbo => &TURBO,
+    };
+    let mut color = [0.0f32; 3];
+    for coef in coefs.iter().rev() {
+        for (c, k) in color.iter_mut().zip(coef) {
+            *c = *c * t + k;
+        }
+    }
+    color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Builds a lookup table spreading values from `low` to `high` over
+/// `colormap`, values outside the range take the end colors.
+pub fn colormap_lut(colormap: Colormap, low: u8, high: u8) -> [[u8; 3]; 256] {
+    let span = (high as f32 - low as f32).max(1.0);
+    let mut lut = [[0u8; 3]; 256];
+    for (v, l) in lut.iter_mut().enumerate() {
+        let t = ((v as f32 - low as f32) / span).clamp(0.0, 1.0);
+        *l = colormap_color(colormap, t);
+    }
+    lut
+}
+
+/// Maps the single channel value of every pixel through `lut`, alpha is
+/// left untouched.
+pub fn apply_colormap(img: &RgbaImage, lut: &[[u8; 3]; 256]) -> RgbaImage {
+    let mut out = img.clone();
+    let row = row_len(&out);
+    out.par_chunks_mut(row).for_each(|out_row| {
+        for p in out_row.chunks_exact_mut(4) {
+            let color = lut[scalar_value(p) as usize];
+            p[..3].copy_from_slice(&color);
+        }
+    });
+    out
+}
+
+/// Shrinks `img` to `width`x`height` averaging the linear light of the
+/// source pixels each target pixel covers. Averaging encoded values instead
+/// darkens fine high contrast detail, like text or checkerboards.
+pub fn downscale_linear(img: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let mut out = RgbaImage::new(width, height);
+    let (src_w, src_h) = (img.width() as usize, img.height() as usize);
+    if out.is_empty() || src_w == 0 || src_h == 0 {
+        return out;
+    }
+    let (linear, encode) = (linear_lut(), encode_lut());
+    let span = |i: usize, src: usize, dst: usize| {
+        let start = i * src / dst;
+        (start, ((i + 1) * src / dst).max(start + 1))
+    };
+    let src_row = row_len(img);
+    out.par_chunks_mut(width as usize * 4)
+        .enumerate()
+        .for_each(|(y, out_row)| {
+            let (y0, y1) = span(y, src_h, height as usize);
+            for (x, p) in out_row.chunks_exact_mut(4).enumerate() {
+                let (x0, x1) = span(x, src_w, width as usize);
+                let mut sum = [0u64; 4];
+                for sy in y0..y1 {
+                    let row = &img.as_raw()[sy * src_row..][4 * x0..4 * x1];
+                    for sp in row.chunks_exact(4) {
+                        for c in 0..3 {
+                            sum[c] += linear[sp[c] as usize] as u64;
+                        }
+                        sum[3] += sp[3] as u64;
+                    }
+                }
+                let n = ((y1 - y0) * (x1 - x0)) as u64;
+                for c in 0..3 {
+                    p[c] = encode[((sum[c] + n / 2) / n) as usize];
+                }
+                p[3] = ((sum[3] + n / 2) / n) as u8;
+            }
+        });
+    out
+}
+
+/// Halves both dimensions of `img` by averaging 2x2 blocks, an odd last
+/// column or row is dropped.
+pub fn downsample_half(img: &RgbaImage) -> RgbaImage {
+    let (w, h) = (img.width() / 2, img.height() / 2);
+    let mut out = RgbaImage::new(w, h);
+    if w == 0 || h == 0 {
+        return out;
+    }
+    let src_row = row_len(img);
+    out.par_chunks_mut(w as usize * 4)
+        .enumerate()
+        .for_each(|(y, out_row)| {
+            let top = &img.as_raw()[2 * y * src_row..][..src_row];
+            let bottom = &img.as_raw()[(2 * y + 1) * src_row..][..src_row];
+            for (x, p) in out_row.chunks_exact_mut(4).enumerate() {
+                for c in 0..4 {
+                    let sum = top[8 * x + c] as u16
+                        + top[8 * x + 4 + c] as u16
+                        + bottom[8 * x + c] as u16
+                        + bottom[8 * x + 4 + c] as u16;
+                    p[c] = ((sum + 2) / 4) as u8;
+                }
+            }
+        });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    /// Odd-sized image with every channel varying, so rows don't line up
+    /// with any chunking.
+    fn test_image(seed: u32) -> RgbaImage {
+        RgbaImage::from_fn(37, 23, |x, y| {
+            let v = x.wrapping_mul(31) ^ y.wrapping_mul(17) ^ seed;
+            Rgba([v as u8, (v >> 3) as u8, (v >> 5) as u8, (v >> 2) as u8])
+        })
+    }
+
+    /// Applies `f` to the color channels of each pair of pixels in turn.
+    fn serial(mut one: RgbaImage, two: &RgbaImage, f: impl Fn(u8, u8) -> u8) -> RgbaImage {
+        for (op, tp) in one.pixels_mut().zip(two.pixels()) {
+            for c in 0..3 {
+                op[c] = f(op[c], tp[c]);
+            }
+        }
+        one
+    }
+
+    #[test]
+    fn parallel_diffs_match_serial() {
+        let (one, two) = (test_image(1), test_image(2));
+        let expected = serial(one.clone(), &two, |a, b| a.abs_diff(b));
+        assert_eq!(image_diff(one.clone(), &two), expected);
+
+        let (linear, encode) = (linear_lut(), encode_lut());
+        let expected = serial(one.clone(), &two, |a, b| {
+            encode[linear[a as usize].abs_diff(linear[b as usize]) as usize]
+        });
+        assert_eq!(image_diff_linear(one, &two), expected);
+    }
+
+    #[test]
+    fn parallel_lut_and_histogram_match_serial() {
+        let img = test_image(3);
+        let lut = gamma_lut(2.2);
+        let expected = serial(img.clone(), &img, |a, _| lut[a as usize]);
+        assert_eq!(apply_lut(img.clone(), &lut), expected);
+
+        let mut expected = [0u32; 256];
+        for p in img.pixels() {
+            expected[scalar_value(&p.0) as usize] += 1;
+        }
+        assert_eq!(luma_histogram(&img), expected);
+    }
+
+    #[test]
+    fn gamma_lut_matches_float_path() {
+        for gamma in [0.5, 1.0, 1.8, 2.2, 4.0] {
+            let lut = gamma_lut(gamma);
+            for v in 0..=255u8 {
+                let float = (v as f32 / 255.0).powf(1.0 / gamma) * 255.0;
+                let diff = (lut[v as usize] as f32 - float).abs();
+                assert!(
+                    diff < 1.0,
+                    "gamma {} value {}: {} vs {}",
+                    gamma,
+                    v,
+                    lut[v as usize],
+                    float
+                );
+            }
+            assert_eq!((lut[0], lut[255]), (0, 255));
+        }
+    }
+
+    #[test]
+    fn linear_diff_golden_values() {
+        let pixels = |values: [u8; 3]| {
+            RgbaImage::from_fn(3, 1, |x, _| {
+                let v = values[x as usize];
+                Rgba([v, v, v, 255])
+            })
+        };
+        let (one, two) = (pixels([255, 200, 0]), pixels([128, 100, 0]));
+        let encoded = image_diff(one.clone(), &two);
+        let linear = image_diff_linear(one, &two);
+        let channel = |img: &RgbaImage| img.pixels().map(|p| p[0]).collect::<Vec<_>>();
+        assert_eq!(channel(&encoded), [127, 100, 0]);
+        assert_eq!(channel(&linear), [229, 179, 0]);
+    }
+
+    #[test]
+    fn only_translucent_images_look_premultiplied() {
+        let stats = |pixels: [[u8; 4]; 2]| {
+            alpha_stats(&RgbaImage::from_fn(2, 1, |x, _| Rgba(pixels[x as usize])))
+        };
+        let opaque = stats([[200, 10, 0, 255], [0, 0, 0, 255]]);
+        assert!(!opaque.looks_premultiplied() && !opaque.unpremultiply_changes);
+        let premultiplied = stats([[100, 50, 0, 128], [0, 0, 0, 0]]);
+        assert!(premultiplied.looks_premultiplied() && premultiplied.unpremultiply_changes);
+        let straight = stats([[200, 50, 0, 128], [0, 0, 0, 255]]);
+        assert!(!straight.looks_premultiplied() && straight.unpremultiply_changes);
+        // Black shadows stay black when made straight.
+        let shadow = stats([[0, 0, 0, 128], [30, 30, 30, 255]]);
+        assert!(shadow.looks_premultiplied() && !shadow.unpremultiply_changes);
+    }
+}
@@ -0,0 +1,87 @@
+//! Simple CPU drawing helpers used when rasterizing overlays into images.
+
+use eframe::egui::*;
+use image::RgbaImage;
+
+pub fn segment_distance(p: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_sq() > 0.0 {
+        ((p - a).dot(ab) / ab.length_sq()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
+
+pub fn blend(img: &mut RgbaImage, x: i64, y: i64, color: [u8; 4], coverage: f32) {
+    if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+        return;
+    }
+    let a = color[3] as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+    let p = img.get_pixel_mut(x as u32, y as u32);
+    for c in 0..3 {
+        p[c] = (p[c] as f32 * (1.0 - a) + color[c] as f32 * a).round() as u8;
+    }
+    p[3] = p[3].max((a * 255.0) as u8);
+}
+
+pub fn draw_line(img: &mut RgbaImage, a: Pos2, b: Pos2, width: f32, color: [u8; 4]) {
+    let radius = (width / 2.0).max(0.5);
+    let min = a.min(b) - Vec2::splat(radius + 1.0);
+    let max = a.max(b) + Vec2::splat(radius + 1.0);
+    for y in min.y.floor() as i64..=max.y.ceil() as i64 {
+        for x in min.x.floor() as i64..=max.x.ceil() as i64 {
+            let d = segment_distance(pos2(x as f32 + 0.5, y as f32 + 0.5), a, b);
+            if d <= radius + 0.5 {
+                blend(img, x, y, color, radius + 0.5 - d);
+            }
+        }
+    }
+}
+
+fn with_font<R>(f: impl FnOnce(&ab_glyph::FontRef) -> R) -> Option<R> {
+    let fonts = FontDefinitions::default();
+    let data = fonts.font_data.get("Ubuntu-Light")?;
+    let font = ab_glyph::FontRef::try_from_slice(&data.font).ok()?;
+    Some(f(&font))
+}
+
+/// Width in pixels of `text` drawn by [`draw_text`] with the given size.
+pub fn text_width(text: &str, size: f32) -> f32 {
+    use ab_glyph::{Font, PxScale, ScaleFont};
+
+    with_font(|font| {
+        let scaled = font.as_scaled(PxScale::from(size));
+        text.chars()
+            .map(|ch| scaled.h_advance(scaled.glyph_id(ch)))
+            .sum()
+    })
+    .unwrap_or(0.0)
+}
+
+pub fn draw_text(img: &mut RgbaImage, pos: Pos2, text: &str, size: f32, color: [u8; 4]) {
+    use ab_glyph::{Font, PxScale, ScaleFont};
+
+    with_font(|font| {
+        let scaled = font.as_scaled(PxScale::from(size));
+        let mut caret = pos.x;
+        let baseline = pos.y + scaled.ascent();
+        for ch in text.chars() {
+            let id = scaled.glyph_id(ch);
+            let glyph = id.with_scale_and_position(size, ab_glyph::point(caret, baseline));
+            if let Some(outlined) = font.outline_glyph(glyph) {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, c| {
+                    blend(
+                        img,
+                        bounds.min.x as i64 + x as i64,
+                        bounds.min.y as i64 + y as i64,
+                        color,
+                        c,
+                    );
+                });
+            }
+            caret += scaled.h_advance(id);
+        }
+    });
+}
@@ -0,0 +1,80 @@
+//! Generated images an image can be compared with instead of a second file,
+//! so encoding artifacts show against a known signal.
+
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum ReferenceKind {
+    /// `Reference::color` everywhere.
+    Solid,
+    /// Black on the left to white on the right.
+    HorizontalGradient,
+    /// Black at the top to white at the bottom.
+    VerticalGradient,
+    /// Black and white squares of `Reference::cell` pixels.
+    Checkerboard,
+}
+
+impl ReferenceKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReferenceKind::Solid => "Solid color",
+            ReferenceKind::HorizontalGradient => "Horizontal gradient",
+            ReferenceKind::VerticalGradient => "Vertical gradient",
+            ReferenceKind::Checkerboard => "Checkerboard",
+        }
+    }
+}
+
+/// What the reference looks like, it's rendered at the size of the image
+/// it's compared with.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Reference {
+    pub kind: ReferenceKind,
+    /// Straight RGBA of the solid color.
+    pub color: [u8; 4],
+    /// Side of a checkerboard square in pixels.
+    pub cell: u32,
+}
+
+impl Reference {
+    pub const GREY: [u8; 4] = [128, 128, 128, 255];
+    pub const MAX_CELL: u32 = 256;
+
+    pub fn new() -> Self {
+        Self {
+            kind: ReferenceKind::Solid,
+            color: Self::GREY,
+            cell: 8,
+        }
+    }
+
+    pub fn render(&self, width: u32, height: u32) -> RgbaImage {
+        let grey = |v: u8| Rgba([v, v, v, 255]);
+        match self.kind {
+            ReferenceKind::Solid => RgbaImage::from_pixel(width, height, Rgba(self.color)),
+            ReferenceKind::HorizontalGradient => {
+                RgbaImage::from_fn(width, height, |x, _| grey(ramp(x, width)))
+            }
+            ReferenceKind::VerticalGradient => {
+                RgbaImage::from_fn(width, height, |_, y| grey(ramp(y, height)))
+            }
+            ReferenceKind::Checkerboard => {
+                let cell = self.cell.max(1);
+                RgbaImage::from_fn(width, height, |x, y| match (x / cell + y / cell) % 2 {
+                    0 => grey(255),
+                    _ => grey(0),
+                })
+            }
+        }
+    }
+}
+
+/// Level of pixel `i` of `n` on a ramp from 0 to 255 that hits both ends.
+fn ramp(i: u32, n: u32) -> u8 {
+    if n < 2 {
+        return 0;
+    }
+    ((i as u64 * 255 + (n as u64 - 1) / 2) / (n as u64 - 1)) as u8
+}
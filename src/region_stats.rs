@@ -0,0 +1,155 @@
+//! Statistics of a rectangular region of an image and of the difference
+//! shown for it.
+
+use crate::delta_e::DELTA_E_SCALE;
+use crate::image_ui_state::{DiffMetric, DiffMode};
+use image::RgbaImage;
+use rayon::prelude::*;
+use std::fmt::Write;
+
+/// Rectangle in image pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// Pixels covered by the rectangle from `min` to `max` within a
+    /// `width`x`height` image, `None` when it covers none.
+    pub fn covered(min: [f32; 2], max: [f32; 2], width: u32, height: u32) -> Option<Self> {
+        let x0 = min[0].min(max[0]).floor().clamp(0.0, width as f32) as u32;
+        let y0 = min[1].min(max[1]).floor().clamp(0.0, height as f32) as u32;
+        let x1 = min[0].max(max[0]).ceil().clamp(0.0, width as f32) as u32;
+        let y1 = min[1].max(max[1]).ceil().clamp(0.0, height as f32) as u32;
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+        Some(Self {
+            x: x0,
+            y: y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        })
+    }
+
+    /// Part of the region inside a `width`x`height` image.
+    pub fn clip(&self, width: u32, height: u32) -> Option<Self> {
+        let min = [self.x as f32, self.y as f32];
+        let max = [(self.x + self.width) as f32, (self.y + self.height) as f32];
+        Self::covered(min, max, width, height)
+    }
+}
+
+/// What statistics were computed for, they're redone once it changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RegionKey {
+    pub region: Region,
+    pub diff_mode: DiffMode,
+    pub diff_metric: DiffMetric,
+}
+
+/// Per channel statistics in RGBA order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelStats {
+    pub mean: [f32; 4],
+    pub min: [u8; 4],
+    pub max: [u8; 4],
+}
+
+pub struct RegionStats {
+    pub key: RegionKey,
+    pub image: ChannelStats,
+    /// Statistics of the difference inside the region, for color
+    /// difference modes.
+    pub diff: Option<ChannelStats>,
+}
+
+pub fn channel_stats(img: &RgbaImage) -> Option<ChannelStats> {
+    let count = img.width() as u64 * img.height() as u64;
+    if count == 0 {
+        return None;
+    }
+    let init = || ([0u64; 4], [u8::MAX; 4], [0u8; 4]);
+    let (sum, min, max) = img
+        .par_chunks(img.width() as usize * 4)
+        .fold(init, |(mut sum, mut min, mut max), row| {
+            for p in row.chunks_exact(4) {
+                for c in 0..4 {
+                    sum[c] += p[c] as u64;
+                    min[c] = min[c].min(p[c]);
+                    max[c] = max[c].max(p[c]);
+                }
+            }
+            (sum, min, max)
+        })
+        .reduce(init, |(mut sum, mut min, mut max), (s, lo, hi)| {
+            for c in 0..4 {
+                sum[c] += s[c];
+                min[c] = min[c].min(lo[c]);
+                max[c] = max[c].max(hi[c]);
+            }
+            (sum, min, max)
+        });
+    Some(ChannelStats {
+        mean: sum.map(|s| (s as f64 / count as f64) as f32),
+        min,
+        max,
+    })
+}
+
+/// Statistics of `img` and `diff`, both already cropped to the region.
+pub fn region_stats(key: RegionKey, img: &RgbaImage, diff: Option<&RgbaImage>) -> RegionStats {
+    let empty = ChannelStats {
+        mean: [0.0; 4],
+        min: [0; 4],
+        max: [0; 4],
+    };
+    RegionStats {
+        key,
+        image: channel_stats(img).unwrap_or(empty),
+        diff: diff.and_then(channel_stats),
+    }
+}
+
+fn write_channels(text: &mut String, stats: &ChannelStats) {
+    let _ = writeln!(text, "{:>6}{:>9}{:>9}{:>9}{:>9}", "", "R", "G", "B", "A");
+    let _ = write!(text, "{:>6}", "mean");
+    for v in stats.mean {
+        let _ = write!(text, "{:>9.2}", v);
+    }
+    let _ = write!(text, "\n{:>6}", "min");
+    for v in stats.min {
+        let _ = write!(text, "{:>9}", v);
+    }
+    let _ = write!(text, "\n{:>6}", "max");
+    for v in stats.max {
+        let _ = write!(text, "{:>9}", v);
+    }
+    text.push('\n');
+}
+
+impl RegionStats {
+    /// Plain text table of the numbers, for pasting elsewhere.
+    pub fn to_text(&self) -> String {
+        let r = self.key.region;
+        let mut text = format!("Region x {}, y {}, {}x{} px\n", r.x, r.y, r.width, r.height);
+        write_channels(&mut text, &self.image);
+        if let Some(diff) = self.diff.as_ref() {
+            let _ = writeln!(text, "{}:", self.key.diff_mode.label());
+            if self.key.diff_metric == DiffMetric::DeltaE {
+                let _ = writeln!(
+                    text,
+                    "ΔE mean {:.2}, max {:.1}",
+                    diff.mean[0] / DELTA_E_SCALE,
+                    diff.max[0] as f32 / DELTA_E_SCALE
+                );
+            } else {
+                write_channels(&mut text, diff);
+            }
+        }
+        text
+    }
+}
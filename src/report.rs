@@ -0,0 +1,90 @@
+//! A/B pairs among the listed files and how much each pair differs, the
+//! data behind the compare report.
+
+use image::RgbaImage;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Stem endings of the two files of a pair, like `button_a.png` and
+/// `button_b.png` in the same folder.
+pub const PAIR_SUFFIXES: [(&str, &str); 3] = [("_a", "_b"), ("-a", "-b"), ("_expected", "_actual")];
+
+/// Pairs `files` by `PAIR_SUFFIXES`, ordered by the first file. Their
+/// extensions may differ, with several candidates the first listed wins.
+pub fn find_pairs(files: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+    let key = |path: &Path| {
+        let stem = path.file_stem()?.to_string_lossy().into_owned();
+        Some((path.parent().map(Path::to_path_buf), stem))
+    };
+    let mut by_stem = HashMap::new();
+    for path in files {
+        if let Some(key) = key(path) {
+            by_stem.entry(key).or_insert(path);
+        }
+    }
+    let mut pairs = files
+        .iter()
+        .filter_map(|a| {
+            let (dir, stem) = key(a)?;
+            PAIR_SUFFIXES.iter().find_map(|(suffix_a, suffix_b)| {
+                let base = stem.strip_suffix(suffix_a)?;
+                let b = by_stem.get(&(dir.clone(), format!("{}{}", base, suffix_b)))?;
+                Some((a.clone(), b.to_path_buf()))
+            })
+        })
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs
+}
+
+/// Differences of the channel values of two images, over all four
+/// channels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PairStats {
+    pub max: u8,
+    pub mean: f32,
+    /// Peak signal-to-noise ratio in dB, infinite for identical images.
+    pub psnr: f32,
+}
+
+/// Compares two images of the same size.
+pub fn compare(a: &RgbaImage, b: &RgbaImage) -> std::io::Result<PairStats> {
+    if a.dimensions() != b.dimensions() {
+        let msg = format!(
+            "Sizes differ, {}x{} and {}x{}",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        );
+        return Err(Error::new(ErrorKind::InvalidData, msg));
+    }
+    let row = (a.width() as usize * 4).max(1);
+    let (max, sum, squares) = a
+        .as_raw()
+        .par_chunks(row)
+        .zip(b.as_raw().par_chunks(row))
+        .map(|(ra, rb)| {
+            ra.iter()
+                .zip(rb)
+                .fold((0u8, 0u64, 0u64), |(max, sum, squares), (x, y)| {
+                    let d = x.abs_diff(*y);
+                    (max.max(d), sum + d as u64, squares + d as u64 * d as u64)
+                })
+        })
+        .reduce(|| (0, 0, 0), |x, y| (x.0.max(y.0), x.1 + y.1, x.2 + y.2));
+    let count = a.as_raw().len().max(1) as f64;
+    let mse = squares as f64 / count;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+    Ok(PairStats {
+        max,
+        mean: (sum as f64 / count) as f32,
+        psnr: psnr as f32,
+    })
+}
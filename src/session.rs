@@ -0,0 +1,177 @@
+//! What a review looked like when the app closed: the opened paths, the
+//! current image, the sort order and how each image was viewed. Written as
+//! JSON and read back to reopen the review as it was left.
+
+use crate::annotations::Annotation;
+use crate::image_ui_state::{
+    Adjustments, DiffMetric, DiffMode, ImageUIState, SeamGuide, TextureFilter,
+};
+use crate::reference::Reference;
+use eframe::egui::Pos2;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Version written into new sessions. Older versions are upgraded in
+/// `Session::from_json`, newer ones are refused.
+pub const SESSION_VERSION: u32 = 1;
+
+fn invalid<E>(e: E) -> std::io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+/// View settings of one image, the part of `ImageUIState` worth keeping.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewState {
+    pub diff_mode: DiffMode,
+    pub vsplit_gamma: f32,
+    pub hsplit_gamma: f32,
+    pub page_diff_gamma: f32,
+    pub previous_diff_gamma: f32,
+    pub reference: Reference,
+    pub reference_diff_gamma: f32,
+    pub vsplit_factor: f32,
+    pub hsplit_factor: f32,
+    pub diff_pages: (usize, usize),
+    pub diff_metric: DiffMetric,
+    pub seam_guide: SeamGuide,
+    pub filter: TextureFilter,
+    pub pixel_grid: bool,
+    pub preserve_aspect: bool,
+    pub adjustments: Adjustments,
+    /// Visible fraction of the image, none until the view was zoomed.
+    pub scale: Option<f32>,
+    /// View center in texture coordinates.
+    pub center: [f32; 2],
+    /// Annotations as they were, saved to the sidecar file or not.
+    pub annotations: Vec<Annotation>,
+}
+
+impl ViewState {
+    pub fn new(state: &ImageUIState) -> Self {
+        let (scale, center) = state.view();
+        Self {
+            diff_mode: state.diff_mode,
+            vsplit_gamma: state.color_diff_vsplite_gamma,
+            hsplit_gamma: state.color_diff_hsplite_gamma,
+            page_diff_gamma: state.page_diff_gamma,
+            previous_diff_gamma: state.previous_diff_gamma,
+            reference: state.reference,
+            reference_diff_gamma: state.reference_diff_gamma,
+            vsplit_factor: state.vsplit_factor,
+            hsplit_factor: state.hsplit_factor,
+            diff_pages: state.diff_pages,
+            diff_metric: state.diff_metric,
+            seam_guide: state.seam_guide,
+            filter: state.filter,
+            pixel_grid: state.pixel_grid,
+            preserve_aspect: state.preserve_aspect,
+            adjustments: state.adjustments,
+            scale,
+            center: [center.x, center.y],
+            annotations: state.annotations.items.clone(),
+        }
+    }
+
+    pub fn apply(&self, state: &mut ImageUIState) {
+        state.diff_mode = self.diff_mode;
+        state.color_diff_vsplite_gamma = self.vsplit_gamma;
+        state.color_diff_hsplite_gamma = self.hsplit_gamma;
+        state.page_diff_gamma = self.page_diff_gamma;
+        state.previous_diff_gamma = self.previous_diff_gamma;
+        state.reference = self.reference;
+        state.reference_diff_gamma = self.reference_diff_gamma;
+        state.vsplit_factor = self.vsplit_factor;
+        state.hsplit_factor = self.hsplit_factor;
+        state.diff_pages = self.diff_pages;
+        state.diff_metric = self.diff_metric;
+        state.seam_guide = self.seam_guide;
+        state.filter = self.filter;
+        state.pixel_grid = self.pixel_grid;
+        state.preserve_aspect = self.preserve_aspect;
+        state.adjustments = self.adjustments;
+        state.restore_view(self.scale, Pos2::new(self.center[0], self.center[1]));
+        state.annotations.set_restored(self.annotations.clone());
+    }
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        Self::new(&ImageUIState::new())
+    }
+}
+
+/// How one listed file was viewed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionImage {
+    pub path: PathBuf,
+    pub view: ViewState,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub version: u32,
+    /// Files and folders that were open, listed again on restore.
+    pub paths: Vec<PathBuf>,
+    pub current: Option<PathBuf>,
+    /// `true` for the list ordered by dimensions.
+    pub sort_by_dimensions: bool,
+    /// Images whose view differs from the defaults.
+    pub images: Vec<SessionImage>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            version: SESSION_VERSION,
+            ..Default::default()
+        }
+    }
+
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json()?)
+    }
+
+    pub fn from_json(json: &str) -> std::io::Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).map_err(invalid)?;
+        let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+        if version > SESSION_VERSION as u64 {
+            return Err(invalid(format!(
+                "Session version {} is newer than the supported {}",
+                version, SESSION_VERSION
+            )));
+        }
+        // Fields added later take their defaults, changed ones get upgraded
+        // here from `version`.
+        let mut session: Session = serde_json::from_value(value).map_err(invalid)?;
+        session.version = SESSION_VERSION;
+        Ok(session)
+    }
+
+    pub fn to_json(&self) -> std::io::Result<String> {
+        serde_json::to_string_pretty(self).map_err(invalid)
+    }
+
+    /// Forgets the paths and images that no longer exist.
+    pub fn prune(&mut self) {
+        let before = self.paths.len() + self.images.len();
+        self.paths.retain(|p| p.exists());
+        self.images.retain(|i| i.path.exists());
+        if self.current.as_ref().is_some_and(|p| !p.exists()) {
+            self.current = None;
+        }
+        let pruned = before - self.paths.len() - self.images.len();
+        if pruned > 0 {
+            info!("Left {} missing path(s) out of the session", pruned);
+        }
+    }
+}
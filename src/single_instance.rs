@@ -0,0 +1,339 @@
+//! One window for all invocations: later ones hand their paths over to the
+//! running instance and exit.
+//!
+//! On Unix the running instance listens on a socket in a directory only the
+//! user can enter. Elsewhere it listens on a loopback port written to the
+//! instance file in the local app data of the user, with a random token
+//! that connecting invocations have to send first.
+//!
+//! The listener is bound before the window is up, so a second invocation
+//! started meanwhile queues its paths instead of opening another window.
+
+use crate::links;
+use crossbeam::channel::{unbounded, Receiver};
+use log::{error, trace, warn};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a connected invocation gets to send its paths.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub enum Instance {
+    /// This process runs the window and receives the paths of later
+    /// invocations.
+    Primary(PrimaryInstance),
+    /// The paths were handed to the running instance.
+    Forwarded,
+}
+
+/// Writes `paths` to a connection, one per line.
+fn send_paths(mut stream: impl Write, paths: &[PathBuf]) -> std::io::Result<()> {
+    for path in paths {
+        let path = links::absolute(path).unwrap_or_else(|_| path.clone());
+        writeln!(stream, "{}", path.display())?;
+    }
+    stream.flush()
+}
+
+/// Reads the paths of a connection, `None` when it doesn't start with the
+/// `token` expected.
+fn read_paths(stream: impl Read, token: Option<&str>) -> Option<Vec<PathBuf>> {
+    let mut lines = BufReader::new(stream).lines().map_while(Result::ok);
+    if let Some(token) = token {
+        if lines.next().as_deref() != Some(token) {
+            return None;
+        }
+    }
+    Some(
+        lines
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+    )
+}
+
+#[cfg(unix)]
+mod transport {
+    use log::warn;
+    use std::fs::{self, DirBuilder};
+    use std::io::{Error, ErrorKind};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+
+    pub type Listener = UnixListener;
+    pub type Stream = UnixStream;
+
+    /// Where the running instance listens, and the inode of its socket so
+    /// that a socket of another instance isn't removed.
+    pub struct Address {
+        socket: PathBuf,
+        inode: u64,
+    }
+
+    /// The runtime dir of the user, or a directory of the user in the temp
+    /// dir. Refused unless it's owned by the user and closed to others.
+    fn private_dir() -> std::io::Result<PathBuf> {
+        let uid = unsafe { libc::geteuid() };
+        let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let dir = std::env::temp_dir().join(format!("imview-{}", uid));
+                match DirBuilder::new().mode(0o700).create(&dir) {
+                    Ok(_) => (),
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+                    Err(e) => return Err(e),
+                }
+                dir
+            }
+        };
+        let meta = fs::symlink_metadata(&dir)?;
+        if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+            let msg = format!("{} is open to other users", dir.display());
+            return Err(Error::new(ErrorKind::PermissionDenied, msg));
+        }
+        Ok(dir)
+    }
+
+    /// Listens on the socket of the user, or connects to the instance
+    /// listening there. A socket nobody listens on is stale and replaced.
+    pub fn bind_or_connect() -> std::io::Result<Result<(Listener, Address), Stream>> {
+        let socket = private_dir()?.join("imview.sock");
+        loop {
+            match UnixListener::bind(&socket) {
+                Ok(listener) => {
+                    let inode = fs::symlink_metadata(&socket)?.ino();
+                    return Ok(Ok((listener, Address { socket, inode })));
+                }
+                Err(e) if e.kind() == ErrorKind::AddrInUse => (),
+                Err(e) => return Err(e),
+            }
+            match UnixStream::connect(&socket) {
+                Ok(stream) => return Ok(Err(stream)),
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                    warn!("Removing stale socket {}", socket.display());
+                    fs::remove_file(&socket)?;
+                }
+                // Removed between the two calls, try binding again.
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Connections are only possible for the user, no token needed.
+    pub fn token(_: &Address) -> Option<&str> {
+        None
+    }
+
+    /// Removes the socket, unless another instance took it over.
+    pub fn release(address: &Address) -> std::io::Result<()> {
+        match fs::symlink_metadata(&address.socket) {
+            Ok(meta) if meta.ino() == address.inode => fs::remove_file(&address.socket),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod transport {
+    use log::warn;
+    use std::collections::hash_map::RandomState;
+    use std::fs::{self, OpenOptions};
+    use std::hash::{BuildHasher, Hasher};
+    use std::io::{ErrorKind, Write};
+    use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+    use std::path::PathBuf;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    /// How long a starting instance gets to write its port.
+    const STARTUP_WAIT: Duration = Duration::from_secs(2);
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    pub type Listener = TcpListener;
+    pub type Stream = TcpStream;
+
+    /// The instance file and what the running instance wrote to it.
+    pub struct Address {
+        file: PathBuf,
+        port: u16,
+        token: String,
+    }
+
+    /// In the local app data, which only the user can read.
+    fn instance_file() -> PathBuf {
+        std::env::var_os("LOCALAPPDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+            .join("imview.instance")
+    }
+
+    /// 128 bits from hashers keyed by the random seeds of the standard
+    /// library, enough that another user can't guess it.
+    fn random_token() -> String {
+        (0..2u64)
+            .map(|i| {
+                let mut hasher = RandomState::new().build_hasher();
+                hasher.write_u64(i);
+                format!("{:016x}", hasher.finish())
+            })
+            .collect()
+    }
+
+    fn read_address(file: &PathBuf) -> std::io::Result<Option<(u16, String)>> {
+        let text = fs::read_to_string(file)?;
+        let mut words = text.split_whitespace();
+        let port = words.next().and_then(|p| p.parse().ok());
+        let token = words.next().map(str::to_string);
+        Ok(port.zip(token))
+    }
+
+    /// Listens on a loopback port written to the instance file, or
+    /// connects to the instance it names. The file is created empty before
+    /// the port is known, so invocations started meanwhile wait for it.
+    pub fn bind_or_connect() -> std::io::Result<Result<(Listener, Address), Stream>> {
+        let file = instance_file();
+        let started = Instant::now();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&file) {
+                Ok(mut f) => {
+                    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+                    let port = listener.local_addr()?.port();
+                    let token = random_token();
+                    write!(f, "{} {}", port, token)?;
+                    return Ok(Ok((listener, Address { file, port, token })));
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+                Err(e) => return Err(e),
+            }
+            match read_address(&file) {
+                Ok(Some((port, token))) => {
+                    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+                    match TcpStream::connect_timeout(&addr, STARTUP_WAIT) {
+                        Ok(mut stream) => {
+                            writeln!(stream, "{}", token)?;
+                            return Ok(Err(stream));
+                        }
+                        Err(e) => {
+                            warn!("Running instance on port {} is gone: {}", port, e);
+                            let _ = fs::remove_file(&file);
+                        }
+                    }
+                }
+                // Still starting up, or it died before writing the port.
+                Ok(None) if started.elapsed() < STARTUP_WAIT => sleep(POLL_INTERVAL),
+                Ok(None) => {
+                    warn!("Removing stale instance file {}", file.display());
+                    let _ = fs::remove_file(&file);
+                }
+                // Removed between the two calls, try creating it again.
+                Err(e) if e.kind() == ErrorKind::NotFound => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Any local user can connect to the port, only the token tells the
+    /// invocations of this user apart.
+    pub fn token(address: &Address) -> Option<&str> {
+        Some(&address.token)
+    }
+
+    /// Removes the instance file, unless another instance took it over.
+    pub fn release(address: &Address) -> std::io::Result<()> {
+        match read_address(&address.file) {
+            Ok(Some((port, token))) if port == address.port && token == address.token => {
+                fs::remove_file(&address.file)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct PrimaryInstance {
+    listener: transport::Listener,
+    address: transport::Address,
+}
+
+/// Becomes the primary instance, or forwards `paths` to the running one.
+pub fn acquire(paths: &[PathBuf]) -> std::io::Result<Instance> {
+    match transport::bind_or_connect()? {
+        Ok((listener, address)) => {
+            trace!("Single instance listening");
+            Ok(Instance::Primary(PrimaryInstance { listener, address }))
+        }
+        Err(stream) => {
+            // The token, if any, was sent on connecting.
+            send_paths(stream, paths)?;
+            Ok(Instance::Forwarded)
+        }
+    }
+}
+
+impl PrimaryInstance {
+    /// Receives the paths sent by later invocations, one batch per
+    /// invocation. `notifier` is called after each batch.
+    pub fn listen<F>(&self, notifier: F) -> std::io::Result<Receiver<Vec<PathBuf>>>
+    where
+        F: Fn() + Send + 'static,
+    {
+        let listener = self.listener.try_clone()?;
+        let token = transport::token(&self.address).map(str::to_string);
+        let (sender, receiver) = unbounded();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream: transport::Stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Can't accept forwarded paths: {}", e);
+                        continue;
+                    }
+                };
+                // A client that connects and never sends would block the
+                // later ones.
+                if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+                    warn!("Can't set the read timeout: {}", e);
+                    continue;
+                }
+                let paths = match read_paths(stream, token.as_deref()) {
+                    Some(paths) => paths,
+                    None => {
+                        warn!("Refused forwarded paths without the instance token");
+                        continue;
+                    }
+                };
+                trace!("Forwarded paths: {:?}", paths);
+                if sender.send(paths).is_err() {
+                    break;
+                }
+                notifier();
+            }
+        });
+        Ok(receiver)
+    }
+
+    /// Removes the socket or instance file, unless another instance took it
+    /// over.
+    pub fn release(&self) {
+        if let Err(e) = transport::release(&self.address) {
+            error!("Can't release the single instance: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_need_the_token_first() {
+        let sent = b"secret\n/a.png\n\n/b.png\n";
+        let paths = read_paths(&sent[..], Some("secret")).unwrap();
+        assert_eq!(paths, [PathBuf::from("/a.png"), PathBuf::from("/b.png")]);
+        assert!(read_paths(&sent[..], Some("guess")).is_none());
+        assert!(read_paths(&b""[..], Some("secret")).is_none());
+        assert_eq!(read_paths(&b"/a.png\n"[..], None).unwrap().len(), 1);
+    }
+}
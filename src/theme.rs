@@ -0,0 +1,58 @@
+//! Look of the app: egui's dark or light visuals and the colors drawn on
+//! top of them.
+
+use eframe::egui::{Color32, Visuals};
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ArgEnum)]
+#[clap(rename_all = "lower")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Theme::Dark => "Dark",
+            Theme::Light => "Light",
+        }
+    }
+
+    /// Name the theme is stored under, read back by `from_name`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Theme::Dark),
+            "light" => Some(Theme::Light),
+            _ => None,
+        }
+    }
+
+    pub fn visuals(&self) -> Visuals {
+        match self {
+            Theme::Dark => Visuals::dark(),
+            Theme::Light => Visuals::light(),
+        }
+    }
+}
+
+/// Background of the image view reviewers pick to judge colors neutrally.
+pub const MID_GREY: Color32 = Color32::from_rgb(128, 128, 128);
+
+/// Yellow of the notes and markers, turned into a dark amber over light
+/// backgrounds where plain yellow hardly shows.
+pub fn highlight_color(background: Color32) -> Color32 {
+    let [r, g, b, _] = background.to_array();
+    let luma = 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32;
+    if luma > 150.0 {
+        Color32::from_rgb(150, 100, 0)
+    } else {
+        Color32::YELLOW
+    }
+}
@@ -1,5 +1,13 @@
+use crate::pixel_ops::downsample_half;
 use eframe::egui::ColorImage;
 use image::RgbaImage;
+use std::path::Path;
+use std::process::Command;
+use time::macros::format_description;
+use time::OffsetDateTime;
+
+/// Mip levels stop once the larger side would get below this many pixels.
+const MIN_MIP_SIZE: u32 = 32;
 
 pub fn make_color_image(image: &RgbaImage) -> ColorImage {
     let w = image.width() as _;
@@ -9,3 +17,111 @@ pub fn make_color_image(image: &RgbaImage) -> ColorImage {
     let color_image = ColorImage::from_rgba_unmultiplied(size, pixels.as_slice());
     color_image
 }
+
+/// Builds the mip chain of `image` without the image itself, each level is
+/// half the size of the previous one.
+pub fn mip_chain(image: &RgbaImage) -> Vec<RgbaImage> {
+    let mut levels: Vec<RgbaImage> = Vec::new();
+    loop {
+        let prev = levels.last().unwrap_or(image);
+        let (w, h) = (prev.width() / 2, prev.height() / 2);
+        if w == 0 || h == 0 || w.max(h) < MIN_MIP_SIZE {
+            break;
+        }
+        let level = downsample_half(prev);
+        levels.push(level);
+    }
+    levels
+}
+
+/// Current local time like `2024-05-01T12:33:05`, UTC when the local offset
+/// can't be determined.
+pub fn local_timestamp() -> String {
+    let now = OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc());
+    now.format(format_description!(
+        "[year]-[month]-[day]T[hour]:[minute]:[second]"
+    ))
+    .unwrap_or_default()
+}
+
+/// Size of the file at `path`, `None` when it can't be read.
+pub fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|m| m.len())
+}
+
+/// Opens the system file browser at `path`, selecting the file where the
+/// browser supports it and showing its folder otherwise.
+pub fn reveal_in_file_browser(path: &Path) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("explorer");
+        command.arg(format!("/select,{}", path.display()));
+        command
+    } else if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(path.parent().unwrap_or(path));
+        command
+    };
+    let mut child = command.spawn()?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Runs the shell `command` with `path` as its last argument without
+/// waiting for it to finish. The path reaches the shell through a variable,
+/// so characters like `&` or `;` in file names stay part of the name.
+pub fn spawn_with_path(command: &str, path: &Path) -> std::io::Result<()> {
+    let mut child = shell_with_path(command, path).spawn()?;
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn shell_with_path(command: &str, path: &Path) -> Command {
+    use std::os::windows::process::CommandExt;
+    // cmd expands the variable before parsing the line and file names can't
+    // hold quotes, so the quoted value is never split or run. `raw_arg`
+    // keeps Rust from escaping the quotes with backslashes, which cmd
+    // doesn't understand.
+    let mut shell = Command::new("cmd");
+    shell
+        .env("IMVIEW_PATH", path)
+        .raw_arg(format!("/S /C \"{} \"%IMVIEW_PATH%\"\"", command));
+    shell
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell_with_path(command: &str, path: &Path) -> Command {
+    // `$0` is the name the script runs as, so the path becomes `$1`.
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(format!("{} \"$1\"", command))
+        .arg("imview")
+        .arg(path);
+    shell
+}
+
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Path in the temp dir for a test file, unique to this process.
+#[cfg(test)]
+pub fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("imview-{}-{}", std::process::id(), name))
+}
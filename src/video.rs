@@ -0,0 +1,140 @@
+//! Video frame decoding through FFmpeg, compiled with the `video` feature.
+
+use crate::decode::{to_thumbnail, ThumbnailScaling};
+use crate::image_data::VideoPosition;
+use ffmpeg::format::Pixel;
+use ffmpeg::media::Type;
+use ffmpeg::software::scaling::{context::Context as Scaler, flag::Flags};
+use ffmpeg::util::frame::video::Video;
+use ffmpeg_next as ffmpeg;
+use image::RgbaImage;
+use std::path::Path;
+
+const VIDEO_EXTENSIONS: [&str; 6] = ["mp4", "m4v", "mov", "mkv", "webm", "avi"];
+
+/// Frame rate assumed when the container doesn't report one.
+const DEFAULT_FRAME_RATE: f64 = 30.0;
+
+pub struct VideoFrame {
+    pub image: RgbaImage,
+    pub position: VideoPosition,
+}
+
+fn ffmpeg_err(e: ffmpeg::Error) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+pub fn init() -> std::io::Result<()> {
+    ffmpeg::init().map_err(ffmpeg_err)
+}
+
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| VIDEO_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn to_rgba_image(frame: &Video) -> RgbaImage {
+    let (w, h) = (frame.width(), frame.height());
+    let stride = frame.stride(0);
+    let row = w as usize * 4;
+    let mut pixels = Vec::with_capacity(row * h as usize);
+    for line in frame.data(0).chunks(stride).take(h as usize) {
+        pixels.extend_from_slice(&line[..row]);
+    }
+    RgbaImage::from_raw(w, h, pixels).unwrap()
+}
+
+/// Decodes the first frame shown at or after `timestamp` seconds. Seeking
+/// lands on the preceding keyframe, the frames up to the requested one are
+/// decoded and dropped.
+pub fn decode_frame(path: &Path, timestamp: f64) -> std::io::Result<VideoFrame> {
+    let mut input = ffmpeg::format::input(&path).map_err(ffmpeg_err)?;
+    let stream = input
+        .streams()
+        .best(Type::Video)
+        .ok_or_else(|| ffmpeg_err(ffmpeg::Error::StreamNotFound))?;
+    let index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let frame_rate = match f64::from(stream.avg_frame_rate()) {
+        r if r.is_finite() && r > 0.0 => r,
+        _ => DEFAULT_FRAME_RATE,
+    };
+    let context = ffmpeg::codec::context::Context::from_parameters(stream.parameters())
+        .map_err(ffmpeg_err)?;
+    let mut decoder = context.decoder().video().map_err(ffmpeg_err)?;
+    let duration = (input.duration().max(0) as f64 / f64::from(ffmpeg::ffi::AV_TIME_BASE)).max(0.0);
+
+    // The last frame starts a frame before the end.
+    let timestamp = timestamp.clamp(0.0, (duration - 1.0 / frame_rate).max(0.0));
+    if timestamp > 0.0 {
+        let target = (timestamp * f64::from(ffmpeg::ffi::AV_TIME_BASE)) as i64;
+        input.seek(target, ..target).map_err(ffmpeg_err)?;
+    }
+
+    let mut scaler = Scaler::get(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::RGBA,
+        decoder.width(),
+        decoder.height(),
+        Flags::BILINEAR,
+    )
+    .map_err(ffmpeg_err)?;
+
+    let mut decoded = Video::empty();
+    let mut receive =
+        |decoder: &mut ffmpeg::decoder::Video| -> std::io::Result<Option<VideoFrame>> {
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                let frame_ts = decoded.timestamp().unwrap_or(0) as f64 * time_base;
+                // Half a frame of tolerance, timestamps are rarely exact.
+                if frame_ts + 0.5 / frame_rate < timestamp {
+                    continue;
+                }
+                let mut rgba = Video::empty();
+                scaler.run(&decoded, &mut rgba).map_err(ffmpeg_err)?;
+                let position = VideoPosition {
+                    timestamp: frame_ts.max(0.0),
+                    duration,
+                    frame_rate,
+                };
+                return Ok(Some(VideoFrame {
+                    image: to_rgba_image(&rgba),
+                    position,
+                }));
+            }
+            Ok(None)
+        };
+
+    for (stream, packet) in input.packets() {
+        if stream.index() != index {
+            continue;
+        }
+        decoder.send_packet(&packet).map_err(ffmpeg_err)?;
+        if let Some(frame) = receive(&mut decoder)? {
+            return Ok(frame);
+        }
+    }
+    decoder.send_eof().map_err(ffmpeg_err)?;
+    match receive(&mut decoder)? {
+        Some(frame) => Ok(frame),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "No video frame at the requested position",
+        )),
+    }
+}
+
+/// Thumbnail made from the first keyframe of the video, and the frame size.
+pub fn decode_thumbnail(
+    path: &Path,
+    size: u32,
+    scaling: ThumbnailScaling,
+) -> std::io::Result<(RgbaImage, (u32, u32))> {
+    decode_frame(path, 0.0).map(|frame| {
+        let dimensions = frame.image.dimensions();
+        (to_thumbnail(frame.image, size, scaling), dimensions)
+    })
+}
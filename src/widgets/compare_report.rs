@@ -0,0 +1,208 @@
+use crate::report::PairStats;
+use eframe::egui::*;
+use std::cmp::Ordering;
+use std::path::PathBuf;
+
+/// What the user did with the compare report this frame.
+pub enum CompareReportAction {
+    None,
+    /// Open the pair in a diff mode.
+    Open(PathBuf, PathBuf),
+    Closed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Column {
+    Pair,
+    Max,
+    Mean,
+    Psnr,
+    Result,
+}
+
+impl Column {
+    fn label(&self) -> &'static str {
+        match self {
+            Column::Pair => "Pair",
+            Column::Max => "Max",
+            Column::Mean => "Mean",
+            Column::Psnr => "PSNR",
+            Column::Result => "Result",
+        }
+    }
+}
+
+struct Row {
+    a: PathBuf,
+    b: PathBuf,
+    /// `None` until the pair is compared.
+    stats: Option<Result<PairStats, String>>,
+}
+
+impl Row {
+    fn name(&self) -> String {
+        let name = |p: &PathBuf| {
+            p.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        };
+        format!("{} vs {}", name(&self.a), name(&self.b))
+    }
+
+    fn stats(&self) -> Option<&PairStats> {
+        self.stats.as_ref().and_then(|s| s.as_ref().ok())
+    }
+}
+
+/// Sortable table of the A/B pairs of the listed files with their
+/// differences, each passes when its largest difference is within the
+/// tolerance.
+pub struct CompareReport {
+    rows: Vec<Row>,
+    sort: Column,
+    descending: bool,
+    /// Largest channel difference that still passes.
+    tolerance: u8,
+}
+
+impl CompareReport {
+    pub fn new(pairs: Vec<(PathBuf, PathBuf)>) -> Self {
+        Self {
+            rows: pairs
+                .into_iter()
+                .map(|(a, b)| Row { a, b, stats: None })
+                .collect(),
+            sort: Column::Pair,
+            descending: false,
+            tolerance: 0,
+        }
+    }
+
+    /// Pairs the report waits for.
+    pub fn pending(&self) -> usize {
+        self.rows.iter().filter(|r| r.stats.is_none()).count()
+    }
+
+    pub fn set_stats(&mut self, a: &PathBuf, b: &PathBuf, stats: std::io::Result<PairStats>) {
+        if let Some(row) = self.rows.iter_mut().find(|r| r.a == *a && r.b == *b) {
+            row.stats = Some(stats.map_err(|e| e.to_string()));
+        }
+    }
+
+    fn passes(&self, row: &Row) -> Option<bool> {
+        row.stats().map(|s| s.max <= self.tolerance)
+    }
+
+    /// Compares by the sort column, pairs without stats go last.
+    fn compare(&self, x: &Row, y: &Row) -> Ordering {
+        let by_stats = |f: fn(&PairStats) -> f32| match (x.stats(), y.stats()) {
+            (Some(x), Some(y)) => f(x).partial_cmp(&f(y)).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        let order = match self.sort {
+            Column::Pair => x.a.cmp(&y.a),
+            Column::Max => by_stats(|s| s.max as f32),
+            Column::Mean => by_stats(|s| s.mean),
+            Column::Psnr => by_stats(|s| s.psnr),
+            Column::Result => self.passes(x).cmp(&self.passes(y)),
+        };
+        match self.descending {
+            true => order.reverse(),
+            false => order,
+        }
+    }
+
+    fn header_ui(&mut self, ui: &mut Ui, column: Column) {
+        let mut text = column.label().to_string();
+        if self.sort == column {
+            text.push_str(if self.descending { " ⏷" } else { " ⏶" });
+        }
+        if ui.selectable_label(self.sort == column, text).clicked() {
+            self.descending = self.sort == column && !self.descending;
+            self.sort = column;
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context) -> CompareReportAction {
+        let mut open = true;
+        let mut action = CompareReportAction::None;
+        let mut order = (0..self.rows.len()).collect::<Vec<_>>();
+        order.sort_by(|&x, &y| self.compare(&self.rows[x], &self.rows[y]));
+        Window::new("Compare report")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Tolerance:");
+                    ui.add(DragValue::new(&mut self.tolerance).clamp_range(0..=255))
+                        .on_hover_text("Largest channel difference of a passing pair");
+                    let failed = self
+                        .rows
+                        .iter()
+                        .filter(|r| self.passes(r) == Some(false))
+                        .count();
+                    ui.label(format!("{} pair(s), {} failed", self.rows.len(), failed));
+                    let pending = self.pending();
+                    if pending > 0 {
+                        ui.spinner();
+                        ui.label(format!("{} left", pending));
+                    }
+                });
+                if self.rows.is_empty() {
+                    ui.weak("No pairs, name the files like name_a.png and name_b.png");
+                    return;
+                }
+                ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("compare_report").striped(true).show(ui, |ui| {
+                        for column in [
+                            Column::Pair,
+                            Column::Max,
+                            Column::Mean,
+                            Column::Psnr,
+                            Column::Result,
+                        ] {
+                            self.header_ui(ui, column);
+                        }
+                        ui.end_row();
+                        for &i in order.iter() {
+                            let row = &self.rows[i];
+                            if ui
+                                .selectable_label(false, row.name())
+                                .on_hover_text("Open the pair")
+                                .clicked()
+                            {
+                                action = CompareReportAction::Open(row.a.clone(), row.b.clone());
+                            }
+                            match row.stats.as_ref() {
+                                None => {
+                                    ui.spinner();
+                                }
+                                Some(Ok(stats)) => {
+                                    ui.label(stats.max.to_string());
+                                    ui.label(format!("{:.3}", stats.mean));
+                                    ui.label(match stats.psnr.is_finite() {
+                                        true => format!("{:.2} dB", stats.psnr),
+                                        false => "∞".to_string(),
+                                    });
+                                    match stats.max <= self.tolerance {
+                                        true => ui.colored_label(Color32::GREEN, "pass"),
+                                        false => ui.colored_label(Color32::RED, "fail"),
+                                    };
+                                }
+                                Some(Err(e)) => {
+                                    ui.colored_label(Color32::RED, "error").on_hover_text(e);
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        if !open {
+            return CompareReportAction::Closed;
+        }
+        action
+    }
+}
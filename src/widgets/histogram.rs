@@ -0,0 +1,101 @@
+use eframe::egui::*;
+
+const COLOR_A: Color32 = Color32::from_rgba_premultiplied(128, 48, 32, 128);
+const COLOR_B: Color32 = Color32::from_rgba_premultiplied(32, 80, 128, 128);
+
+/// Two histograms drawn over each other, or their difference, each scaled
+/// to the same total.
+pub struct HistogramComparison<'a> {
+    a: &'a [u32; 256],
+    b: &'a [u32; 256],
+    size: Vec2,
+    labels: Option<(String, String)>,
+    difference: bool,
+}
+
+impl<'a> HistogramComparison<'a> {
+    pub fn new(a: &'a [u32; 256], b: &'a [u32; 256], size: Vec2) -> Self {
+        Self {
+            a,
+            b,
+            size,
+            labels: None,
+            difference: false,
+        }
+    }
+
+    /// Names of the two histograms, shown as a legend.
+    pub fn labels(mut self, labels: (String, String)) -> Self {
+        self.labels = Some(labels);
+        self
+    }
+
+    /// Plots `a - b` around the middle instead of both histograms.
+    pub fn difference(mut self, difference: bool) -> Self {
+        self.difference = difference;
+        self
+    }
+}
+
+fn normalized(hist: &[u32; 256]) -> Vec<f32> {
+    let total = hist.iter().map(|v| *v as f32).sum::<f32>().max(1.0);
+    hist.iter().map(|v| *v as f32 / total).collect()
+}
+
+impl Widget for HistogramComparison<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let (rect, resp) = ui.allocate_exact_size(self.size, Sense::hover());
+        if !ui.is_rect_visible(rect) {
+            return resp;
+        }
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, Rounding::none(), ui.visuals().extreme_bg_color);
+        let (a, b) = (normalized(self.a), normalized(self.b));
+        let bin_width = rect.width() / 256.0;
+        let bar = |i: usize, from: f32, to: f32, color: Color32| {
+            let x = rect.left() + i as f32 * bin_width;
+            let bar = Rect::from_x_y_ranges(x..=x + bin_width, from.min(to)..=from.max(to));
+            painter.rect_filled(bar, Rounding::none(), color);
+        };
+        if self.difference {
+            let diffs: Vec<f32> = a.iter().zip(&b).map(|(a, b)| a - b).collect();
+            let max = diffs.iter().fold(f32::EPSILON, |m, d| m.max(d.abs()));
+            let middle = rect.center().y;
+            for (i, d) in diffs.iter().enumerate() {
+                let color = if *d > 0.0 { COLOR_A } else { COLOR_B };
+                bar(i, middle, middle - d / max * rect.height() / 2.0, color);
+            }
+            painter.hline(
+                rect.x_range(),
+                middle,
+                ui.visuals().widgets.noninteractive.bg_stroke,
+            );
+        } else {
+            let max = a.iter().chain(&b).fold(f32::EPSILON, |m, v| m.max(*v));
+            for (hist, color) in [(&a, COLOR_A), (&b, COLOR_B)] {
+                for (i, v) in hist.iter().enumerate() {
+                    bar(
+                        i,
+                        rect.bottom(),
+                        rect.bottom() - v / max * rect.height(),
+                        color,
+                    );
+                }
+            }
+        }
+        if let Some((label_a, label_b)) = self.labels {
+            let font = FontId::proportional(12.0);
+            let pos = rect.left_top() + vec2(4.0, 2.0);
+            let a = painter.text(
+                pos,
+                Align2::LEFT_TOP,
+                label_a,
+                font.clone(),
+                COLOR_A.to_opaque(),
+            );
+            let pos = a.right_top() + vec2(8.0, 0.0);
+            painter.text(pos, Align2::LEFT_TOP, label_b, font, COLOR_B.to_opaque());
+        }
+        resp
+    }
+}
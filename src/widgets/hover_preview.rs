@@ -0,0 +1,92 @@
+use crate::image_source::ImageSource;
+use crate::{DiffMode, ImageData};
+use eframe::egui::*;
+use std::time::{Duration, Instant};
+
+/// How long a thumbnail has to be hovered before its preview pops up.
+const SHOW_DELAY: Duration = Duration::from_millis(500);
+/// How long the preview outlives the hover, so it stays up while the
+/// pointer crosses the gaps between thumbnails.
+const GRACE: Duration = Duration::from_millis(200);
+const PREVIEW_SIZE: f32 = 400.0;
+/// Distance between the pointer and the preview.
+const POINTER_GAP: f32 = 16.0;
+
+/// Larger view of the hovered thumbnail, drawn near the pointer without
+/// taking input or changing the current image.
+pub struct HoverPreview {
+    source: Option<ImageSource>,
+    hovered_since: Instant,
+    last_hovered: Instant,
+    shown: bool,
+}
+
+impl HoverPreview {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            source: None,
+            hovered_since: now,
+            last_hovered: now,
+            shown: false,
+        }
+    }
+
+    /// Tracks the thumbnail hovered this frame, returns the image to preview
+    /// once the delay is over. Moving to a neighbor while the preview is up
+    /// switches it right away.
+    pub fn update(&mut self, ctx: &Context, hovered: Option<&ImageSource>) -> Option<&ImageSource> {
+        let now = Instant::now();
+        match hovered {
+            Some(source) => {
+                if self.source.as_ref() != Some(source) {
+                    self.shown = self.shown && now - self.last_hovered < GRACE;
+                    self.source = Some(source.clone());
+                    self.hovered_since = now;
+                }
+                self.last_hovered = now;
+                if now - self.hovered_since >= SHOW_DELAY {
+                    self.shown = true;
+                }
+            }
+            None if now - self.last_hovered >= GRACE => {
+                self.source = None;
+                self.shown = false;
+            }
+            None => (),
+        }
+        if self.source.is_some() {
+            ctx.request_repaint();
+        }
+        self.source.as_ref().filter(|_| self.shown)
+    }
+
+    /// Draws `image` above the pointer, at most `PREVIEW_SIZE` on a side.
+    pub fn show(ctx: &Context, image: &ImageData) {
+        let pointer = match ctx.input().pointer.hover_pos() {
+            Some(pos) => pos,
+            None => return,
+        };
+        // Thumbnails smaller than the preview are scaled up.
+        let scale = PREVIEW_SIZE / image.width().max(image.height());
+        let size = image.size() * scale;
+        let texture = image.display_texture(DiffMode::Full, scale);
+        let margin = ctx.style().spacing.window_margin;
+        let outer = size + vec2(margin.left + margin.right, margin.top + margin.bottom);
+        let screen = ctx.input().screen_rect();
+        let pos = pos2(
+            (pointer.x + POINTER_GAP).min(screen.right() - outer.x),
+            pointer.y - POINTER_GAP - outer.y,
+        )
+        .max(screen.min);
+        Area::new("hover_preview")
+            .order(Order::Tooltip)
+            .fixed_pos(pos)
+            .interactable(false)
+            .show(ctx, |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.image(texture, size);
+                });
+            });
+    }
+}
@@ -1,34 +1,294 @@
-use crate::{DiffMode, ImageData, ImageUIState};
-use arrayvec::ArrayVec;
+use crate::annotations::AnnotationTool;
+use crate::error::ImViewError;
+use crate::export::ExportResolution;
+use crate::geometry;
+use crate::image_data::Transform;
+use crate::image_ui_state::{
+    Adjustments, AlphaMode, DiffMetric, FileAction, ProfileLine, SeamGuide, TextureFilter,
+    ViewInput,
+};
+use crate::pixel_ops::{self, Colormap};
+use crate::reference::{Reference, ReferenceKind};
+use crate::utils::format_bytes;
+use crate::widgets::histogram::HistogramComparison;
+use crate::widgets::interaction::{self, Navigation, PointerBindings};
+use crate::widgets::profile::ProfilePlot;
+use crate::{AlignmentStatus, DiffMode, ImageData, ImageUIState};
 use eframe::egui::*;
+use image::ColorType;
+
+/// Largest part of the image shown when jumping to an alpha anomaly.
+const ANOMALY_VIEW_SCALE: f32 = 0.25;
+const HISTOGRAM_HEIGHT: f32 = 100.0;
+const PROFILE_HEIGHT: f32 = 120.0;
 
 pub struct ImageControls<'a> {
     state: &'a mut ImageUIState,
     data: Option<&'a mut ImageData>,
+    logarithmic_zoom: bool,
+    bindings: PointerBindings,
 }
 
 impl<'a> ImageControls<'a> {
     pub fn new(state: &'a mut ImageUIState, data: Option<&'a mut ImageData>) -> Self {
-        Self { state, data }
+        Self {
+            state,
+            data,
+            logarithmic_zoom: true,
+            bindings: PointerBindings::new(),
+        }
+    }
+
+    /// Whether the zoom slider steps by factors, on by default. A linear
+    /// slider makes fine steps around 100% easier.
+    pub fn logarithmic_zoom(mut self, logarithmic: bool) -> Self {
+        self.logarithmic_zoom = logarithmic;
+        self
     }
 
-    fn zoom_ui(&mut self, ui: &mut Ui) {
-        let slider_min = 100.0 / ImageUIState::ZOOM_MAX;
-        let slider_max = 100.0 / ImageUIState::ZOOM_MIN;
-        let mut slider_val = 100.0 / self.state.scale();
+    /// Pointer gestures that move the view from the preview.
+    pub fn bindings(mut self, bindings: PointerBindings) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    fn zoom_ui(&mut self, ui: &mut Ui, fit_scale: f32) {
+        let slider_min = 100.0 * fit_scale / ImageUIState::ZOOM_MAX;
+        let slider_max = 100.0 * fit_scale / ImageUIState::ZOOM_MIN;
+        let mut slider_val = 100.0 * self.state.magnification();
         ui.horizontal_top(|ui| {
             ui.label("Zoom: ");
             if ui
                 .add(
                     widgets::Slider::new(&mut slider_val, slider_min..=slider_max)
-                        .logarithmic(true)
-                        .fixed_decimals(2),
+                        .logarithmic(self.logarithmic_zoom)
+                        .fixed_decimals(2)
+                        .suffix("%"),
                 )
+                .on_hover_text("Ctrl+drag on the image zooms into a rectangle")
                 .changed()
             {
-                self.state.set_scale(100.0 / slider_val);
+                self.state.set_magnification(slider_val / 100.0);
             }
         });
+        ui.horizontal(|ui| {
+            ui.label("Magnify: ");
+            ui.selectable_value(&mut self.state.filter, TextureFilter::Linear, "Linear")
+                .on_hover_text("Smooth pixels when zoomed in");
+            ui.selectable_value(&mut self.state.filter, TextureFilter::Nearest, "Nearest")
+                .on_hover_text("Crisp pixels when zoomed in beyond 100%");
+            if self.data.as_ref().is_some_and(|d| d.nearest_limited()) {
+                ui.weak("smoothed").on_hover_text(
+                    "The image is too large to be upscaled for crisp pixels at this zoom",
+                );
+            }
+        });
+        ui.checkbox(&mut self.state.pixel_grid, "Pixel grid")
+            .on_hover_text("Outline the pixels when zoomed in far enough to tell them apart");
+        ui.checkbox(&mut self.state.preserve_aspect, "Keep aspect ratio")
+            .on_hover_text("Off stretches the image to fill the view");
+    }
+
+    fn page_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_mut().unwrap();
+        let count = data.page_count();
+        if count < 2 {
+            return;
+        }
+        let mut page = data.current_page() + 1;
+        ui.horizontal(|ui| {
+            ui.label("Page:");
+            if ui
+                .add(widgets::DragValue::new(&mut page).clamp_range(1..=count))
+                .on_hover_text("PageUp / PageDown")
+                .changed()
+            {
+                data.set_page(ui.ctx(), self.state, page - 1);
+            }
+            ui.label(format!("of {}", count));
+        });
+    }
+
+    /// Scrub slider and frame steps for videos, the requested frame is
+    /// decoded in the background and shown once it arrives.
+    fn video_ui(&mut self, ui: &mut Ui) {
+        let position = match self.data.as_ref().unwrap().video() {
+            Some(position) => position,
+            None => return,
+        };
+        let frame = 1.0 / position.frame_rate;
+        let mut timestamp = self.state.video_seek.unwrap_or(position.timestamp);
+        ui.horizontal(|ui| {
+            ui.label("Time:");
+            if ui
+                .add(
+                    widgets::Slider::new(&mut timestamp, 0.0..=position.duration)
+                        .fixed_decimals(2)
+                        .suffix(" s"),
+                )
+                .changed()
+            {
+                self.state.video_seek = Some(timestamp);
+            }
+            if self.state.video_seek.is_some() {
+                ui.spinner();
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("◀ Frame").clicked() {
+                self.state.video_seek = Some((timestamp - frame).max(0.0));
+            }
+            if ui.button("Frame ▶").clicked() {
+                self.state.video_seek = Some((timestamp + frame).min(position.duration));
+            }
+        });
+    }
+
+    fn transform_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_mut().unwrap();
+        let mut transform = None;
+        ui.horizontal(|ui| {
+            if ui.button("Rotate left").clicked() {
+                transform = Some(Transform::RotateLeft);
+            }
+            if ui.button("Rotate right").clicked() {
+                transform = Some(Transform::RotateRight);
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Flip horizontal").clicked() {
+                transform = Some(Transform::FlipHorizontal);
+            }
+            if ui.button("Flip vertical").clicked() {
+                transform = Some(Transform::FlipVertical);
+            }
+        });
+        if let Some(transform) = transform {
+            data.apply_transform(ui.ctx(), self.state, transform);
+        }
+
+        // Transforms and annotations share the history, so the keys undo
+        // whichever edit came last.
+        let typing = ui.ctx().wants_keyboard_input();
+        let (undo_key, redo_key) = {
+            let input = ui.input();
+            let command = input.modifiers.command && !typing;
+            let undo = command && !input.modifiers.shift && input.key_pressed(Key::Z);
+            let redo = command
+                && (input.key_pressed(Key::Y)
+                    || input.modifiers.shift && input.key_pressed(Key::Z));
+            (undo, redo)
+        };
+        ui.horizontal(|ui| {
+            let can_undo = data.can_undo(&self.state.history);
+            let undo = ui.add_enabled(can_undo, widgets::Button::new("Undo"));
+            if (undo.clicked() || undo_key) && can_undo {
+                data.undo(ui.ctx(), self.state);
+            }
+            let can_redo = data.can_redo(&self.state.history);
+            let redo = ui.add_enabled(can_redo, widgets::Button::new("Redo"));
+            if (redo.clicked() || redo_key) && can_redo {
+                data.redo(ui.ctx(), self.state);
+            }
+        });
+    }
+
+    /// Colormap and value range for single channel data like depth maps and
+    /// masks. The mapped texture is rebuilt with the display resources.
+    fn colormap_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_ref().unwrap();
+        if data.is_single_channel() {
+            ui.label("Single channel image");
+        } else {
+            ui.checkbox(
+                &mut self.state.force_single_channel,
+                "Show as single channel",
+            )
+            .on_hover_text("Map the luma of a color image");
+            if !self.state.force_single_channel {
+                return;
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("Colormap:");
+            let colormap = &mut self.state.colormap;
+            ui.selectable_value(colormap, Colormap::Grayscale, "Grayscale");
+            ui.selectable_value(colormap, Colormap::Viridis, "Viridis");
+            ui.selectable_value(colormap, Colormap::Turbo, "Turbo");
+        });
+        ui.checkbox(&mut self.state.auto_stretch, "Auto range")
+            .on_hover_text("Spread the lowest to the highest value over the colormap");
+        if self.state.auto_stretch {
+            self.state.value_range = data.scalar_range().unwrap_or((0, 255));
+        }
+        let enabled = !self.state.auto_stretch;
+        let (low, high) = &mut self.state.value_range;
+        ui.horizontal(|ui| {
+            ui.label("Low:");
+            ui.add_enabled(enabled, widgets::Slider::new(low, 0..=*high));
+        });
+        ui.horizontal(|ui| {
+            ui.label("High:");
+            ui.add_enabled(enabled, widgets::Slider::new(high, *low..=255));
+        });
+    }
+
+    /// Exposure, brightness and contrast of the view, the image data is left
+    /// untouched.
+    fn adjust_ui(&mut self, ui: &mut Ui) {
+        let title = match self.state.is_adjusted() {
+            true => "Adjust (adjusted)",
+            false => "Adjust",
+        };
+        CollapsingHeader::new(title)
+            .id_source("adjust")
+            .show(ui, |ui| {
+                let adjustments = &mut self.state.adjustments;
+                ui.horizontal(|ui| {
+                    ui.label("Exposure:");
+                    ui.add(
+                        widgets::Slider::new(&mut adjustments.exposure, -5.0..=5.0)
+                            .fixed_decimals(1)
+                            .suffix(" EV"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Brightness:");
+                    ui.add(widgets::Slider::new(
+                        &mut adjustments.brightness,
+                        -0.5..=0.5,
+                    ));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Contrast:");
+                    ui.add(widgets::Slider::new(&mut adjustments.contrast, 0.0..=3.0));
+                });
+                if ui
+                    .add_enabled(!adjustments.is_identity(), widgets::Button::new("Reset"))
+                    .clicked()
+                {
+                    *adjustments = Adjustments::new();
+                }
+            });
+    }
+
+    /// Whether the color channels are read as straight or premultiplied by
+    /// alpha, premultiplied images are made straight for display.
+    fn alpha_ui(&mut self, ui: &mut Ui) {
+        let premultiplied = self.data.as_ref().unwrap().is_premultiplied(self.state);
+        ui.horizontal(|ui| {
+            ui.label("Alpha:");
+            let mode = &mut self.state.alpha_mode;
+            let auto = match (*mode, premultiplied) {
+                (AlphaMode::Auto, true) => "Auto (premultiplied)",
+                (AlphaMode::Auto, false) => "Auto (straight)",
+                _ => "Auto",
+            };
+            ui.selectable_value(mode, AlphaMode::Auto, auto)
+                .on_hover_text("Premultiplied for translucent images with no color above alpha");
+            ui.selectable_value(mode, AlphaMode::Straight, "Straight");
+            ui.selectable_value(mode, AlphaMode::Premultiplied, "Premultiplied");
+        });
     }
 
     fn diff_ui(&mut self, ui: &mut Ui) {
@@ -55,13 +315,23 @@ impl<'a> ImageControls<'a> {
             ui.label("Part: ");
             if ui
                 .add_enabled(
-                    self.state.diff_mode == DiffMode::VSplit,
+                    matches!(
+                        self.state.diff_mode,
+                        DiffMode::VSplit | DiffMode::VColorDiff | DiffMode::Triptych
+                    ),
                     widgets::Slider::new(&mut self.state.vsplit_factor, 0.0..=1.0)
                         .show_value(false),
                 )
                 .changed()
             {
-                data.switch_to_color_image(ui.ctx());
+                if matches!(
+                    self.state.diff_mode,
+                    DiffMode::VColorDiff | DiffMode::Triptych
+                ) {
+                    data.restore_display_resources(ui.ctx(), self.state);
+                } else {
+                    data.switch_to_color_image(ui.ctx());
+                }
             }
         });
 
@@ -75,11 +345,22 @@ impl<'a> ImageControls<'a> {
         {
             data.switch_to_vertical_color_diff(ui.ctx(), self.state.color_diff_vsplite_gamma);
         }
+        if ui
+            .radio_value(&mut self.state.diff_mode, DiffMode::Triptych, "Triptych")
+            .on_hover_text("Left half, right half and their color difference")
+            .changed()
+        {
+            data.switch_to_color_image(ui.ctx());
+            data.switch_to_vertical_color_diff(ui.ctx(), self.state.color_diff_vsplite_gamma);
+        }
         ui.horizontal(|ui| {
             ui.label("Gamma:");
             if ui
                 .add_enabled(
-                    self.state.diff_mode == DiffMode::VColorDiff,
+                    matches!(
+                        self.state.diff_mode,
+                        DiffMode::VColorDiff | DiffMode::Triptych
+                    ),
                     widgets::Slider::new(&mut self.state.color_diff_vsplite_gamma, 1.0..=5.0),
                 )
                 .changed()
@@ -102,13 +383,20 @@ impl<'a> ImageControls<'a> {
             ui.label("Part: ");
             if ui
                 .add_enabled(
-                    self.state.diff_mode == DiffMode::HSplit,
+                    matches!(
+                        self.state.diff_mode,
+                        DiffMode::HSplit | DiffMode::HColorDiff
+                    ),
                     widgets::Slider::new(&mut self.state.hsplit_factor, 0.0..=1.0)
                         .show_value(false),
                 )
                 .changed()
             {
-                data.switch_to_color_image(ui.ctx());
+                if self.state.diff_mode == DiffMode::HColorDiff {
+                    data.restore_display_resources(ui.ctx(), self.state);
+                } else {
+                    data.switch_to_color_image(ui.ctx());
+                }
             }
         });
         if ui
@@ -133,61 +421,494 @@ impl<'a> ImageControls<'a> {
                 data.switch_to_horizontal_color_diff(ui.ctx(), self.state.color_diff_hsplite_gamma);
             }
         });
+        self.seam_guide_ui(ui);
+        self.page_diff_ui(ui);
+        self.previous_diff_ui(ui);
+        self.reference_diff_ui(ui);
+        let changed = ui
+            .horizontal(|ui| {
+                ui.label("Metric:");
+                let metric = &mut self.state.diff_metric;
+                let encoded = ui
+                    .selectable_value(metric, DiffMetric::Encoded, "sRGB")
+                    .on_hover_text("Difference of the encoded values");
+                let linear = ui
+                    .selectable_value(metric, DiffMetric::Linear, "Linear light")
+                    .on_hover_text("Difference of the emitted light");
+                let delta_e = ui
+                    .selectable_value(metric, DiffMetric::DeltaE, "ΔE 2000")
+                    .on_hover_text("Perceptual CIEDE2000 difference, 1 is about just noticeable");
+                encoded.changed() || linear.changed() || delta_e.changed()
+            })
+            .inner;
+        if changed {
+            let data = self.data.as_mut().unwrap();
+            data.restore_display_resources(ui.ctx(), self.state);
+        }
     }
 
-    fn view_part_rect(&self, in_rect: Rect) -> ArrayVec<Rect, 2> {
-        let uv = self.state.uv_full();
-        match self.state.diff_mode {
-            DiffMode::Full => {
-                let mut r = ArrayVec::new();
-                let size = vec2(in_rect.width() * uv.width(), in_rect.height() * uv.height());
-                let center = pos2(
-                    in_rect.left() + in_rect.width() * uv.center().x,
-                    in_rect.top() + in_rect.height() * uv.center().y,
-                );
-                r.push(Rect::from_center_size(center, size));
-                r
-            }
-            DiffMode::VSplit | DiffMode::VColorDiff => {
-                let mut r = ArrayVec::new();
-                let size = vec2(
-                    in_rect.width() / 2.0 * uv.width(),
-                    in_rect.height() * uv.height(),
+    /// Steps through the changed regions of the shown difference.
+    fn difference_navigation_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_ref().unwrap();
+        let regions = match data.diff_regions(self.state.diff_mode) {
+            Some(regions) => regions,
+            None => return,
+        };
+        ui.horizontal(|ui| {
+            let label = match self.state.difference_index {
+                Some(i) if i < regions.len() => {
+                    format!("Changed region {}/{}", i + 1, regions.len())
+                }
+                _ => format!("Changed regions: {}", regions.len()),
+            };
+            ui.label(label);
+            let enabled = !regions.is_empty();
+            if ui
+                .add_enabled(enabled, widgets::Button::new("Previous"))
+                .on_hover_text("[")
+                .clicked()
+            {
+                self.state.step_difference(regions, -1);
+            }
+            if ui
+                .add_enabled(enabled, widgets::Button::new("Next"))
+                .on_hover_text("]")
+                .clicked()
+            {
+                self.state.step_difference(regions, 1);
+            }
+        });
+    }
+
+    /// Marks at the seam of the split modes to check the halves line up.
+    fn seam_guide_ui(&mut self, ui: &mut Ui) {
+        let enabled = matches!(self.state.diff_mode, DiffMode::VSplit | DiffMode::HSplit);
+        ui.add_enabled_ui(enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Seam:");
+                let guide = &mut self.state.seam_guide;
+                ui.selectable_value(guide, SeamGuide::None, "None");
+                ui.selectable_value(guide, SeamGuide::Line, "Line")
+                    .on_hover_text("Tick marks sit on the same rows of both halves");
+                ui.selectable_value(guide, SeamGuide::Comb, "Comb")
+                    .on_hover_text("Alternates the halves line by line, misalignment looks jagged");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Comb width:");
+                ui.add_enabled(
+                    self.state.seam_guide == SeamGuide::Comb,
+                    widgets::DragValue::new(&mut self.state.comb_width)
+                        .clamp_range(2..=512)
+                        .suffix(" px"),
                 );
-                let top = in_rect.top() + in_rect.height() * uv.center().y;
-                let left = in_rect.width() / 2.0 * uv.center().x;
-                let center_l = pos2(in_rect.left() + left, top);
-                let center_r = pos2((in_rect.left() + in_rect.right()) / 2.0 + left, top);
-                r.push(Rect::from_center_size(center_l, size));
-                r.push(Rect::from_center_size(center_r, size));
-                r
-            }
-            DiffMode::HSplit | DiffMode::HColorDiff => {
-                let mut r = ArrayVec::new();
-                let size = vec2(
-                    in_rect.width() * uv.width(),
-                    in_rect.height() / 2.0 * uv.height(),
+            });
+        });
+    }
+
+    /// Compares two pages of a multi-page image, hidden for single images.
+    fn page_diff_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_mut().unwrap();
+        let count = data.page_count();
+        if count < 2 {
+            return;
+        }
+        let mut changed = ui
+            .radio_value(
+                &mut self.state.diff_mode,
+                DiffMode::PageDiff,
+                "Page difference",
+            )
+            .changed();
+        let enabled = self.state.diff_mode == DiffMode::PageDiff;
+        ui.horizontal(|ui| {
+            let (a, b) = &mut self.state.diff_pages;
+            for (label, page) in [("Page A:", a), ("Page B:", b)] {
+                ui.label(label);
+                let mut shown = (*page).min(count - 1) + 1;
+                if ui
+                    .add_enabled(
+                        enabled,
+                        widgets::DragValue::new(&mut shown).clamp_range(1..=count),
+                    )
+                    .changed()
+                {
+                    *page = shown - 1;
+                    changed = true;
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Gamma:");
+            changed |= ui
+                .add_enabled(
+                    enabled,
+                    widgets::Slider::new(&mut self.state.page_diff_gamma, 1.0..=5.0),
+                )
+                .changed();
+        });
+        if changed {
+            data.set_diff_pages(self.state.diff_pages);
+            data.switch_to_page_diff(ui.ctx(), self.state.page_diff_gamma);
+        }
+    }
+
+    /// Compares the image with the version loaded before the file last
+    /// changed, hidden until it changes.
+    fn previous_diff_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_mut().unwrap();
+        if !data.has_previous_version() {
+            return;
+        }
+        let mut changed = ui
+            .radio_value(
+                &mut self.state.diff_mode,
+                DiffMode::PreviousDiff,
+                "Previous version difference",
+            )
+            .on_hover_text("What changed when the file was last written")
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Gamma:");
+            changed |= ui
+                .add_enabled(
+                    self.state.diff_mode == DiffMode::PreviousDiff,
+                    widgets::Slider::new(&mut self.state.previous_diff_gamma, 1.0..=5.0),
+                )
+                .changed();
+        });
+        if changed {
+            data.switch_to_previous_diff(ui.ctx(), self.state.previous_diff_gamma);
+        }
+    }
+
+    /// Compares the image with a generated one of its size, so encoding
+    /// artifacts show against a known signal.
+    fn reference_diff_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_mut().unwrap();
+        let mut changed = ui
+            .radio_value(
+                &mut self.state.diff_mode,
+                DiffMode::ReferenceDiff,
+                "Reference difference",
+            )
+            .on_hover_text("Difference to a generated image of the same size")
+            .changed();
+        let enabled = self.state.diff_mode == DiffMode::ReferenceDiff;
+        let reference = &mut self.state.reference;
+        let before = *reference;
+        ui.add_enabled_ui(enabled, |ui| {
+            ComboBox::from_id_source("reference_kind")
+                .selected_text(reference.kind.label())
+                .show_ui(ui, |ui| {
+                    for kind in [
+                        ReferenceKind::Solid,
+                        ReferenceKind::HorizontalGradient,
+                        ReferenceKind::VerticalGradient,
+                        ReferenceKind::Checkerboard,
+                    ] {
+                        ui.selectable_value(&mut reference.kind, kind, kind.label());
+                    }
+                });
+            ui.horizontal(|ui| match reference.kind {
+                ReferenceKind::Solid => {
+                    ui.label("Color:");
+                    ui.color_edit_button_srgba_unmultiplied(&mut reference.color);
+                    if ui.button("50% grey").clicked() {
+                        reference.color = Reference::GREY;
+                    }
+                }
+                ReferenceKind::Checkerboard => {
+                    ui.label("Cell:");
+                    ui.add(
+                        widgets::DragValue::new(&mut reference.cell)
+                            .clamp_range(1..=Reference::MAX_CELL)
+                            .suffix(" px"),
+                    );
+                }
+                ReferenceKind::HorizontalGradient | ReferenceKind::VerticalGradient => (),
+            });
+            ui.horizontal(|ui| {
+                ui.label("Gamma:");
+                changed |= ui
+                    .add(widgets::Slider::new(
+                        &mut self.state.reference_diff_gamma,
+                        1.0..=5.0,
+                    ))
+                    .changed();
+            });
+        });
+        if changed || *reference != before {
+            data.set_reference(*reference);
+            data.switch_to_reference_diff(ui.ctx(), self.state.reference_diff_gamma);
+        }
+    }
+
+    /// Luma histograms of the compared images, so tonal shifts show even
+    /// when the per pixel differences are small.
+    fn histogram_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_ref().unwrap();
+        let (a, b) = match data.diff_histograms(self.state.diff_mode) {
+            Some(pair) => pair,
+            None => return,
+        };
+        let labels = match self.state.diff_mode {
+            DiffMode::VColorDiff | DiffMode::Triptych => ("left".to_string(), "right".to_string()),
+            DiffMode::HColorDiff => ("top".to_string(), "bottom".to_string()),
+            DiffMode::PreviousDiff => ("previous".to_string(), "current".to_string()),
+            DiffMode::ReferenceDiff => ("image".to_string(), "reference".to_string()),
+            _ => {
+                let (one, two) = self.state.diff_pages;
+                (format!("page {}", one + 1), format!("page {}", two + 1))
+            }
+        };
+        CollapsingHeader::new("Histograms")
+            .id_source("histograms")
+            .show(ui, |ui| {
+                ui.checkbox(&mut self.state.histogram_difference, "Difference");
+                let width = ui.available_width();
+                ui.add(
+                    HistogramComparison::new(a, b, vec2(width, HISTOGRAM_HEIGHT))
+                        .labels(labels)
+                        .difference(self.state.histogram_difference),
                 );
-                let left = in_rect.left() + in_rect.width() * uv.center().x;
-                let top = in_rect.height() / 2.0 * uv.center().y;
-                let center_l = pos2(left, in_rect.top() + top);
-                let center_r = pos2(left, (in_rect.top() + in_rect.bottom()) / 2.0 + top);
-                r.push(Rect::from_center_size(center_l, size));
-                r.push(Rect::from_center_size(center_r, size));
-                r
+                ui.label(format!(
+                    "Earth mover's distance: {:.2} levels",
+                    pixel_ops::histogram_distance(a, b)
+                ));
+            });
+    }
+
+    fn alignment_ui(&mut self, ui: &mut Ui) {
+        if !matches!(
+            self.state.diff_mode,
+            DiffMode::VColorDiff | DiffMode::HColorDiff | DiffMode::Triptych
+        ) {
+            return;
+        }
+        let data = self.data.as_mut().unwrap();
+        let running = matches!(data.alignment_status, AlignmentStatus::Running(_));
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!running, widgets::Button::new("Align (feature-based)"))
+                .clicked()
+            {
+                self.state.alignment_requested = true;
+            }
+            if running {
+                ui.spinner();
+            }
+        });
+        match &data.alignment_status {
+            AlignmentStatus::Estimated(mode, a) if *mode == self.state.diff_mode => {
+                ui.label(format!(
+                    "Estimated offset: {}, {} (confidence {:.1})",
+                    a.dx, a.dy, a.confidence
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("Accept").clicked() {
+                        data.accept_alignment(ui.ctx(), self.state);
+                    }
+                    if ui.button("Discard").clicked() {
+                        data.alignment_status = AlignmentStatus::None;
+                    }
+                });
+            }
+            AlignmentStatus::Failed(msg) => {
+                ui.label(RichText::new(msg.as_str()).color(Color32::RED));
+            }
+            _ => (),
+        }
+        if let Some(a) = data.applied_alignment(self.state.diff_mode).copied() {
+            ui.horizontal(|ui| {
+                ui.label(format!("Applied offset: {}, {}", a.dx, a.dy));
+                if ui.button("Revert").clicked() {
+                    data.revert_alignment(ui.ctx(), self.state);
+                }
+            });
+        }
+    }
+
+    /// Numbers for the region of the selected box annotation.
+    fn region_stats_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_ref().unwrap();
+        let selected = self.state.annotations.selected_rectangle().is_some();
+        ui.collapsing("Region statistics", |ui| {
+            if !selected {
+                ui.label("Select a box annotation to measure its region");
+                return;
+            }
+            if data.is_region_stats_running() {
+                ui.spinner();
+            }
+            if let Some(stats) = data.region_stats() {
+                ui.label(RichText::new(stats.to_text()).monospace());
+                if ui.button("Copy as text").clicked() {
+                    ui.output().copied_text = stats.to_text();
+                }
+            }
+        });
+    }
+
+    fn annotations_ui(&mut self, ui: &mut Ui) {
+        let can_undo = self
+            .data
+            .as_ref()
+            .is_some_and(|data| data.can_undo(&self.state.history));
+        let annotations = &mut self.state.annotations;
+        let history = &mut self.state.history;
+        let mut undo = false;
+        ui.collapsing("Annotations", |ui| {
+            if self.state.diff_mode != DiffMode::Full {
+                ui.label("Annotations are edited in full image mode");
+                return;
+            }
+            ui.horizontal_wrapped(|ui| {
+                ui.selectable_value(&mut annotations.tool, AnnotationTool::None, "Off");
+                ui.selectable_value(&mut annotations.tool, AnnotationTool::Select, "Select");
+                ui.selectable_value(&mut annotations.tool, AnnotationTool::Rectangle, "Box");
+                ui.selectable_value(&mut annotations.tool, AnnotationTool::Arrow, "Arrow");
+                ui.selectable_value(&mut annotations.tool, AnnotationTool::Text, "Text");
+                ui.selectable_value(&mut annotations.tool, AnnotationTool::Pin, "Pin")
+                    .on_hover_text("Click to place a marker with the note next to it");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Color:");
+                let mut color = match annotations.selected {
+                    Some(idx) => annotations.items[idx].color,
+                    None => annotations.color,
+                };
+                if ui
+                    .color_edit_button_srgba_unmultiplied(&mut color)
+                    .changed()
+                {
+                    annotations.color = color;
+                    annotations.set_selected_color(color, history);
+                }
+            });
+            if matches!(annotations.tool, AnnotationTool::Text | AnnotationTool::Pin) {
+                ui.horizontal(|ui| {
+                    ui.label("Note:");
+                    ui.text_edit_singleline(&mut annotations.text);
+                });
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        annotations.selected.is_some(),
+                        widgets::Button::new("Delete"),
+                    )
+                    .clicked()
+                {
+                    annotations.delete_selected(history);
+                }
+                if ui
+                    .add_enabled(can_undo, widgets::Button::new("Undo"))
+                    .on_hover_text("Undo the latest edit, annotation or transform")
+                    .clicked()
+                {
+                    undo = true;
+                }
+                if ui
+                    .add_enabled(annotations.is_modified(), widgets::Button::new("Save"))
+                    .on_hover_text("Keep the annotations in <image>.imview.json")
+                    .clicked()
+                {
+                    self.state.annotations_save_requested = true;
+                }
+            });
+        });
+        if undo {
+            if let Some(data) = self.data.as_mut() {
+                data.undo(ui.ctx(), self.state);
             }
         }
     }
 
+    fn export_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Export view…").clicked() {
+                self.state.export_requested = true;
+            }
+            ui.radio_value(
+                &mut self.state.export_resolution,
+                ExportResolution::Screen,
+                "On-screen",
+            );
+            ui.radio_value(
+                &mut self.state.export_resolution,
+                ExportResolution::Source,
+                "Source",
+            );
+        });
+    }
+
+    /// Intensities along the picked row or column. The color difference
+    /// modes plot the luma of both compared images and their difference.
+    fn profile_ui(&mut self, ui: &mut Ui) {
+        let data = self.data.as_ref().unwrap();
+        let state = &mut *self.state;
+        ui.collapsing("Profile", |ui| {
+            ui.checkbox(&mut state.profile_tool, "Pick line")
+                .on_hover_text(
+                    "Click a row in the full image, Shift+click a column. Arrow keys move the line",
+                );
+            let line = match state.profile_line.filter(|_| state.profile_tool) {
+                Some(line) => line,
+                None => return,
+            };
+            let (one, two) = match data.line_profile(state.diff_mode, line) {
+                Some(profile) => profile,
+                None => {
+                    ui.label("The line is outside of the compared images");
+                    return;
+                }
+            };
+            let hovered = state.hovered_pixel;
+            let (name, cursor) = match line {
+                ProfileLine::Row(y) => (format!("Row {}", y), hovered.map(|p| p.0)),
+                ProfileLine::Column(x) => (format!("Column {}", x), hovered.map(|p| p.1)),
+            };
+            ui.label(name);
+            let channel = |pixels: &[[u8; 4]], c: usize| -> Vec<f32> {
+                pixels.iter().map(|p| p[c] as f32).collect()
+            };
+            let luma = |pixels: &[[u8; 4]]| -> Vec<f32> {
+                pixels
+                    .iter()
+                    .map(|p| pixel_ops::scalar_value(p) as f32)
+                    .collect()
+            };
+            let size = vec2(ui.available_width(), PROFILE_HEIGHT);
+            let plot = match two {
+                None => ProfilePlot::new(size)
+                    .line(channel(&one, 0), Color32::RED)
+                    .line(channel(&one, 1), Color32::GREEN)
+                    .line(channel(&one, 2), Color32::LIGHT_BLUE),
+                Some(two) => {
+                    let (a, b) = (luma(&one), luma(&two));
+                    let diff = a.iter().zip(&b).map(|(a, b)| (a - b).abs()).collect();
+                    ProfilePlot::new(size)
+                        .line(a, Color32::from_rgb(255, 96, 64))
+                        .line(b, Color32::from_rgb(64, 160, 255))
+                        .line(diff, Color32::WHITE)
+                }
+            };
+            ui.add(plot.cursor(cursor.map(|c| c as usize)));
+        });
+    }
+
     fn preview_ui(&mut self, ui: &mut Ui) {
         let width = ui.available_size_before_wrap().x;
         let data = self.data.as_mut().unwrap();
+        if data.width() <= 0.0 || data.height() <= 0.0 {
+            return;
+        }
         let height = data.height() * (width / data.width());
         let resp = ui
             .image(data.color_texture_handle(), vec2(width, height))
-            .interact(Sense::drag());
+            .interact(Sense::click_and_drag());
         let rect = resp.rect;
-        let rects = self.view_part_rect(rect);
+        let rects = geometry::view_part_rects(self.state, rect);
         for r in rects.iter() {
             ui.painter_at(rect).rect(
                 *r,
@@ -196,58 +917,212 @@ impl<'a> ImageControls<'a> {
                 Stroke::new(1.5, Color32::YELLOW),
             )
         }
-        if let Some(p) = resp.interact_pointer_pos() {
-            if rects.iter().any(|r| r.contains(p)) {
-                if resp.dragged_by(PointerButton::Primary) {
-                    let dd = resp.drag_delta();
-                    let dd = Vec2::new(dd.x / width, dd.y / height);
-                    self.state.set_center_diff(dd);
-                }
+        // Only the outlined parts react, like grabbing the view itself.
+        let on_view = resp
+            .interact_pointer_pos()
+            .or_else(|| resp.hover_pos())
+            .is_some_and(|p| rects.iter().any(|r| r.contains(p)));
+        let owns_view = interaction::claim_view(ui, &resp, self.state, ViewInput::Preview);
+        let pinching = interaction::pinch(ui, &resp, self.state, &rects, owns_view);
+        if on_view && owns_view && !pinching {
+            let nav = Navigation {
+                pan_per_point: vec2(1.0 / width, 1.0 / height),
+                zoom_per_scroll: -0.001,
+                primary_free: true,
+                hand_tool: false,
+            };
+            self.bindings.navigate(ui, &resp, self.state, nav);
+        }
+    }
+
+    fn info_ui(&mut self, ui: &mut Ui) {
+        let data = match self.data.as_ref() {
+            Some(d) => d,
+            None => return,
+        };
+        source_info_ui(ui, Some(data));
+        ui.horizontal(|ui| {
+            ui.label("Sample:");
+            for size in [1, 3, 5] {
+                ui.selectable_value(&mut self.state.sample_size, size, format!("{0}x{0}", size));
             }
+        });
+        if let Some(time) = data.decode_time() {
+            ui.label(format!("Decoded in {} ms", time.as_millis()));
         }
-        if let Some(p) = resp.hover_pos() {
-            if rects.iter().any(|r| r.contains(p)) {
-                let sd = ui.input().scroll_delta[1];
-                if sd != 0.0 {
-                    self.state.set_scale_diff(-0.001 * sd);
-                }
+        if let Some(stats) = data.delta_e_stats(self.state.diff_mode) {
+            ui.label(format!(
+                "ΔE mean: {:.2}, 99th percentile: {:.2}",
+                stats.mean, stats.p99
+            ));
+        }
+        if let Some((x, y)) = self.state.hovered_pixel {
+            let single_channel = data.is_single_channel() || self.state.force_single_channel;
+            let premultiplied = data.is_premultiplied(self.state);
+            if let Some(p) = data.pixel_at(x, y) {
+                let value = match (single_channel, premultiplied) {
+                    (true, _) => format!("{}", pixel_ops::scalar_value(&p.0)),
+                    (false, true) => format!(
+                        "{} (straight {})",
+                        hex_color(p.0),
+                        hex_color(pixel_ops::straight_color(p.0))
+                    ),
+                    (false, false) => hex_color(p.0),
+                };
+                ui.label(format!("Pixel ({}, {}): {}", x, y, value));
+            }
+            let size = self.state.sample_size;
+            if let Some(avg) = data.average_at(x, y, size / 2).filter(|_| size > 1) {
+                let value = match single_channel {
+                    true => format!("{}", pixel_ops::scalar_value(&avg)),
+                    false => hex_color(avg),
+                };
+                ui.label(format!("Average {0}x{0}: {1}", size, value));
             }
         }
+        self.alpha_anomalies_ui(ui);
     }
 
-    fn info_ui(&mut self, ui: &mut Ui) {
-        let (w, h) = match self.data.as_ref() {
-            Some(d) => (format!("{}", d.width()), format!("{}", d.height())),
-            None => ("-".into(), "-".into()),
-        };
+    /// Toggle and results of the check for color hidden under transparency.
+    fn alpha_anomalies_ui(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
-            ui.label(format!("Size: {}x{}", w, h));
+            ui.checkbox(
+                &mut self.state.show_alpha_anomalies,
+                "Mark color under alpha below",
+            )
+            .on_hover_text("RGB hidden by transparency shows up after resizing");
+            ui.add(widgets::DragValue::new(&mut self.state.alpha_threshold).clamp_range(1..=255));
         });
+        if !self.state.show_alpha_anomalies {
+            return;
+        }
+        let data = self.data.as_ref().unwrap();
+        if data.is_anomaly_scan_running() {
+            ui.horizontal(|ui| {
+                ui.label("Scanning…");
+                ui.spinner();
+            });
+        }
+        let anomalies = match data.alpha_anomalies() {
+            Some(anomalies) => anomalies,
+            None => return,
+        };
+        ui.label(format!(
+            "Anomalies: {} pixel(s) in {} region(s)",
+            anomalies.count,
+            anomalies.regions.len()
+        ));
+        let can_jump = !anomalies.regions.is_empty() && self.state.diff_mode == DiffMode::Full;
+        if ui
+            .add_enabled(can_jump, widgets::Button::new("Next anomaly"))
+            .clicked()
+        {
+            let index = self.state.next_anomaly % anomalies.regions.len();
+            let (x, y) = anomalies.regions[index];
+            self.state.next_anomaly = index + 1;
+            let center = pos2(
+                (x as f32 + 0.5) / data.width(),
+                (y as f32 + 0.5) / data.height(),
+            );
+            self.state
+                .set_scale(self.state.scale().min(ANOMALY_VIEW_SCALE));
+            self.state.set_center(center);
+        }
     }
 
-    fn data_load_error(&self, error: &str, ui: &mut Ui) {
-        let text = format!("Error loading data: {}", error);
-        ui.label(text);
+    fn data_load_error(&mut self, error: &ImViewError, ui: &mut Ui) {
+        ui.label(
+            RichText::new(error.short_label())
+                .strong()
+                .color(Color32::RED),
+        );
+        ui.label(error.to_string());
+        if let Some(hint) = error.hint() {
+            ui.label(RichText::new(hint).weak());
+        }
+        if error.can_retry() && ui.button("Retry").clicked() {
+            self.state.file_action_requested = Some(FileAction::Reload);
+        }
     }
 
     fn data_is_loading(&self, ui: &mut Ui) {
         ui.label("Loading data...");
         ui.spinner();
+        source_info_ui(ui, None);
     }
 
     pub fn ui(&mut self, ui: &mut Ui) {
         ui.vertical(|ui| match self.data.as_ref() {
             None => self.data_is_loading(ui),
             Some(d) => {
-                if let Some(em) = d.error_msg.as_ref() {
-                    self.data_load_error(em, ui);
+                if let Some(error) = d.error.clone() {
+                    self.data_load_error(&error, ui);
                 } else {
-                    self.zoom_ui(ui);
+                    self.zoom_ui(ui, self.state.fit_scale());
+                    self.page_ui(ui);
+                    self.video_ui(ui);
+                    self.transform_ui(ui);
+                    self.colormap_ui(ui);
+                    self.adjust_ui(ui);
+                    self.alpha_ui(ui);
                     self.diff_ui(ui);
+                    self.difference_navigation_ui(ui);
+                    self.histogram_ui(ui);
+                    self.alignment_ui(ui);
+                    self.annotations_ui(ui);
+                    self.region_stats_ui(ui);
+                    self.export_ui(ui);
                     self.preview_ui(ui);
+                    self.profile_ui(ui);
                     self.info_ui(ui);
                 }
             }
         });
     }
 }
+
+/// Rows describing what the image was decoded from, "-" for the values not
+/// known yet.
+fn source_info_ui(ui: &mut Ui, data: Option<&ImageData>) {
+    let info = data.and_then(|d| d.source_info());
+    let format = info
+        .and_then(|i| i.format)
+        .map(|f| format!("{:?}", f).to_uppercase());
+    let color = info.and_then(|i| i.color);
+    let rows = [
+        ("Format:", format),
+        ("Color:", color.map(color_label)),
+        (
+            "Alpha:",
+            color.map(|c| if c.has_alpha() { "yes" } else { "no" }.to_string()),
+        ),
+        (
+            "File size:",
+            info.and_then(|i| i.file_size).map(|s| format_bytes(s as _)),
+        ),
+        ("Memory:", data.map(|d| format_bytes(d.pixel_memory()))),
+    ];
+    Grid::new("source_info").num_columns(2).show(ui, |ui| {
+        for (label, value) in rows {
+            ui.label(label);
+            ui.label(value.as_deref().unwrap_or("-"));
+            ui.end_row();
+        }
+    });
+}
+
+/// Channels and bit depth, like "RGBA, 16 bit".
+fn color_label(color: ColorType) -> String {
+    let channels = match (color.has_color(), color.has_alpha()) {
+        (true, true) => "RGBA",
+        (true, false) => "RGB",
+        (false, true) => "Gray + alpha",
+        (false, false) => "Gray",
+    };
+    let bits = color.bits_per_pixel() / color.channel_count() as u16;
+    format!("{}, {} bit", channels, bits)
+}
+
+fn hex_color([r, g, b, a]: [u8; 4]) -> String {
+    format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+}
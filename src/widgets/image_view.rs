@@ -1,117 +1,520 @@
+use crate::geometry::{self, ViewTransform};
+use crate::image_ui_state::{FileAction, ProfileLine, SeamGuide, ViewInput};
+use crate::theme;
+use crate::widgets::interaction::{self, Navigation, PointerBindings};
 use crate::{DiffMode, ImageData, ImageUIState};
 use arrayvec::ArrayVec;
 use eframe::egui::*;
 
 use crate::widgets::splited_image::SplittedImage;
 
+/// Smallest gap in points between the tick marks along a seam.
+const MIN_TICK_GAP: f32 = 24.0;
+
+/// Screen points per image pixel from which the pixel grid is drawn.
+const PIXEL_GRID_MIN_ZOOM: f32 = 8.0;
+
+/// Largest radius in pixels sampled for the brightness under the grid.
+const PIXEL_GRID_SAMPLE_RADIUS: u32 = 64;
+
+/// Longest side in points of the overview shown while zoomed in.
+const MINIMAP_SIZE: f32 = 160.0;
+/// Gap in points between the overview and the corner of the view.
+const MINIMAP_MARGIN: f32 = 8.0;
+
+/// Smallest rubber band side in points that zooms, shorter drags are
+/// taken as slips of a click.
+const MIN_ZOOM_BAND: f32 = 4.0;
+
 pub struct ImageView<'a> {
     state: &'a mut ImageUIState,
     data: Option<&'a ImageData>,
+    placeholder: Option<&'a ImageData>,
+    background: Option<Color32>,
+    bindings: PointerBindings,
 }
 
 impl<'a> ImageView<'a> {
-    pub fn new(state: &'a mut ImageUIState, data: Option<&'a ImageData>) -> Self {
-        Self { state, data }
+    /// `placeholder` is shown scaled up to the view while `data` is not
+    /// loaded yet, usually it's the thumbnail of the image.
+    pub fn new(
+        state: &'a mut ImageUIState,
+        data: Option<&'a ImageData>,
+        placeholder: Option<&'a ImageData>,
+    ) -> Self {
+        Self {
+            state,
+            data,
+            placeholder,
+            background: None,
+            bindings: PointerBindings::new(),
+        }
     }
 
-    fn need_half_width(&self) -> bool {
-        self.state.diff_mode == DiffMode::VSplit || self.state.diff_mode == DiffMode::VColorDiff
+    /// Pointer gestures that move the view.
+    pub fn bindings(mut self, bindings: PointerBindings) -> Self {
+        self.bindings = bindings;
+        self
     }
 
-    fn need_half_height(&self) -> bool {
-        self.state.diff_mode == DiffMode::HSplit || self.state.diff_mode == DiffMode::HColorDiff
+    /// Fill behind the image, the panel's own one when `None`.
+    pub fn background(mut self, background: Option<Color32>) -> Self {
+        self.background = background;
+        self
     }
 
-    fn calc_scale(&self, in_size: Vec2) -> f32 {
-        let data = self.data.as_ref().unwrap();
-        let width = data.width() * if self.need_half_width() { 0.5 } else { 1.0 };
-        let height = data.height() * if self.need_half_height() { 0.5 } else { 1.0 };
-
-        let w_scale = in_size.x / width;
-        let h_scale = in_size.y / height;
-
-        let scale = w_scale.min(h_scale).min(1.0);
-        scale
+    fn is_placeholder(&self) -> bool {
+        self.data.is_none()
     }
 
-    fn display_size(&self, in_size: Vec2) -> ArrayVec<Vec2, 2> {
-        let data = self.data.as_ref().unwrap();
-        let width = data.width() * if self.need_half_width() { 0.5 } else { 1.0 };
-        let height = data.height() * if self.need_half_height() { 0.5 } else { 1.0 };
-
-        let scale = self.calc_scale(in_size);
+    fn shown_data(&self) -> &'a ImageData {
+        self.data.or(self.placeholder).unwrap()
+    }
 
-        let w = width * scale;
-        let h = height * scale;
+    /// Fit scale of the view, the larger of the two axis scales when the
+    /// aspect ratio isn't kept.
+    fn calc_scale(&self, in_size: Vec2) -> f32 {
+        let scales = self.calc_scales(in_size);
+        scales.x.max(scales.y)
+    }
 
-        match self.state.diff_mode {
-            DiffMode::Full | DiffMode::VColorDiff | DiffMode::HColorDiff => {
-                let mut r = ArrayVec::new();
-                r.push(vec2(w, h));
-                r
-            }
-            DiffMode::VSplit => {
-                let mut r = ArrayVec::new();
-                r.push(vec2(w * self.state.vsplit_factor, h));
-                r.push(vec2(w * (1.0 - self.state.vsplit_factor), h));
-                r
-            }
-            DiffMode::HSplit => {
-                let mut r = ArrayVec::new();
-                r.push(vec2(w, h * self.state.hsplit_factor));
-                r.push(vec2(w, h * (1.0 - self.state.hsplit_factor)));
-                r
-            }
+    /// Scales along each axis, equal unless the image is stretched to fill
+    /// the view.
+    fn calc_scales(&self, in_size: Vec2) -> Vec2 {
+        let data = self.shown_data();
+        let part = geometry::part_size(self.state.diff_mode, data.size());
+        let in_size = geometry::part_view_size(self.state.diff_mode, in_size);
+        // The placeholder is much smaller than the image it stands for, so
+        // it's stretched to the view instead of being shown at most 1:1.
+        let max_scale = if self.is_placeholder() {
+            f32::INFINITY
+        } else {
+            1.0
+        };
+        if !self.state.preserve_aspect {
+            return geometry::fill_scale(part, in_size);
         }
+        Vec2::splat(geometry::fit_scale(part, in_size, max_scale))
     }
 
-    fn uvs(&self) -> ArrayVec<Rect, 2> {
-        match self.state.diff_mode {
-            DiffMode::Full | DiffMode::VColorDiff | DiffMode::HColorDiff => {
-                let mut r = ArrayVec::new();
-                r.push(self.state.uv_full());
-                r
-            }
-            DiffMode::VSplit => ArrayVec::from(self.state.uv_vsplit(self.state.vsplit_factor)),
-            DiffMode::HSplit => ArrayVec::from(self.state.uv_hsplit(self.state.hsplit_factor)),
-        }
+    pub fn display_size(&self, in_size: Vec2) -> ArrayVec<Vec2, 3> {
+        let part = geometry::part_size(self.state.diff_mode, self.shown_data().size());
+        geometry::display_sizes(self.state, part, self.calc_scales(in_size))
+    }
+
+    pub fn uvs(&self) -> ArrayVec<Rect, 3> {
+        geometry::view_uvs(self.state)
     }
 
     fn data_exist_ui(&mut self, ui: &mut Ui) {
-        let data = self.data.as_ref().unwrap();
+        let data = self.shown_data();
+        let placeholder = self.is_placeholder();
+        if !data.can_split(self.state.diff_mode) {
+            let msg = match self.state.diff_mode {
+                DiffMode::PageDiff => "Image has a single page",
+                DiffMode::PreviousDiff => "No previous version, the file hasn't changed",
+                _ => "Image is too small for split modes",
+            };
+            let background = self
+                .background
+                .unwrap_or_else(|| ui.visuals().window_fill());
+            let color = theme::highlight_color(background);
+            ui.centered_and_justified(|ui| ui.label(RichText::new(msg).color(color)));
+            return;
+        }
         let av_size = ui.available_size_before_wrap();
-        self.state.set_scale_if_none(self.calc_scale(av_size));
+        self.state.view_size = av_size;
+        if !placeholder {
+            let fit_scale = self.calc_scale(av_size);
+            self.state.set_fit_scale(fit_scale);
+            self.state.set_scale_if_none(fit_scale);
+        }
         let sizes = self.display_size(av_size);
         let uvs = self.uvs();
+        let texel_scale =
+            self.calc_scale(av_size) * ui.ctx().pixels_per_point() / self.state.scale();
+        if !placeholder {
+            self.state.texel_scale = texel_scale;
+        }
+        let mut image_rect = Rect::NOTHING;
         let resp = ui.with_layout(
             Layout::centered_and_justified(Direction::LeftToRight),
             |ui| {
-                let img = SplittedImage::new(
-                    data.texture_handle(self.state.diff_mode),
-                    sizes,
-                    uvs,
+                let mut img = SplittedImage::new(
+                    data.display_texture(self.state.diff_mode, texel_scale),
+                    sizes.clone(),
+                    uvs.clone(),
                     self.state.diff_mode,
                 );
-                ui.add(img);
+                let overlay_shown = self.state.show_alpha_anomalies
+                    && !placeholder
+                    && matches!(
+                        self.state.diff_mode,
+                        DiffMode::Full | DiffMode::VSplit | DiffMode::HSplit
+                    );
+                if let Some(texture) = data.anomaly_texture().filter(|_| overlay_shown) {
+                    img = img.overlay(texture);
+                }
+                if self.state.diff_mode == DiffMode::Triptych {
+                    match data.vertical_diff_texture() {
+                        Some(texture) => img = img.diff_texture(texture),
+                        None => return,
+                    }
+                }
+                image_rect = ui.add(img).rect;
             },
         );
-        let resp = resp.response.interact(Sense::drag());
-        if let Some(_hover_pos) = resp.hover_pos() {
-            let scroll_delta = ui.input().scroll_delta[1];
-            if scroll_delta != 0.0 {
-                self.state.set_scale_diff(-0.0001 * scroll_delta)
+        let split = matches!(self.state.diff_mode, DiffMode::VSplit | DiffMode::HSplit);
+        if split && !placeholder && self.state.seam_guide != SeamGuide::None {
+            let rects = geometry::mesh_rects(self.state.diff_mode, image_rect, &sizes);
+            let part = geometry::part_size(self.state.diff_mode, data.size());
+            let comb = data
+                .comb_texture(self.state.diff_mode)
+                .filter(|_| self.state.seam_guide == SeamGuide::Comb)
+                .map(|texture| (texture, self.state.comb_width as f32));
+            let transposed = self.state.diff_mode == DiffMode::HSplit;
+            let painter = ui.painter_at(image_rect);
+            paint_seam_guide(
+                &painter,
+                [rects[0], rects[1]],
+                [uvs[0], uvs[1]],
+                part,
+                comb,
+                transposed,
+            );
+        }
+        let resp = resp.response.interact(Sense::click_and_drag());
+        let minimap = minimap_rect(resp.rect, data.size());
+        let minimap_opacity = ui.ctx().animate_bool(
+            resp.id.with("minimap"),
+            !placeholder && data.has_color_texture() && self.state.scale() < 1.0,
+        );
+        let on_minimap = minimap_opacity > 0.0
+            && ui
+                .input()
+                .pointer
+                .press_origin()
+                .is_some_and(|p| minimap.contains(p));
+        self.state.hovered_pixel = None;
+        // Holding space turns a left drag into panning, whatever tool is
+        // active. Not while typing a space into a text field.
+        // Input and memory share a lock, so they're read one at a time.
+        let space_down = ui.input().key_down(Key::Space);
+        let hand_tool = space_down && ui.memory().focus().is_none();
+        if hand_tool && resp.hovered() {
+            ui.output().cursor_icon = match resp.dragged() {
+                true => CursorIcon::Grabbing,
+                false => CursorIcon::Grab,
+            };
+        }
+        let parts = match self.state.diff_mode {
+            DiffMode::Triptych => geometry::mesh_rects(DiffMode::Triptych, image_rect, &sizes),
+            _ => [image_rect].into_iter().collect(),
+        };
+        let owns_view = interaction::claim_view(ui, &resp, self.state, ViewInput::Main);
+        let pinching = interaction::pinch(ui, &resp, self.state, &parts, owns_view);
+        let mut zooming = false;
+        if placeholder {
+            ui.painter().text(
+                image_rect.left_top() + vec2(8.0, 8.0),
+                Align2::LEFT_TOP,
+                "Loading full resolution…",
+                FontId::proportional(12.0),
+                Color32::from_white_alpha(160),
+            );
+        } else if self.state.diff_mode == DiffMode::Full {
+            let transform = ViewTransform::new(image_rect, self.state.uv_full(), data.size());
+            if let Some(pos) = resp.hover_pos().filter(|p| image_rect.contains(*p)) {
+                let p = transform.to_image(pos);
+                if p.x >= 0.0 && p.y >= 0.0 && p.x < data.width() && p.y < data.height() {
+                    self.state.hovered_pixel = Some((p.x as u32, p.y as u32));
+                }
+            }
+            let painter = ui.painter_at(image_rect);
+            if self.state.pixel_grid {
+                paint_pixel_grid(&painter, &transform, data);
+            }
+            zooming = ui.input().modifiers.command || self.state.zoom_drag_start.is_some();
+            if on_minimap {
+                // The overview pans, nothing under it reacts.
+            } else if hand_tool || pinching || self.bindings.navigating(&ui.input()) {
+                // The hand tool, pinches and the navigation buttons move the
+                // view whatever tool is active.
+            } else if zooming {
+                self.zoom_selection(&resp, &painter, &transform);
+            } else if self.state.profile_tool {
+                if let Some(p) = self.state.hovered_pixel.filter(|_| resp.clicked()) {
+                    self.state.profile_line = Some(match ui.input().modifiers.shift {
+                        true => ProfileLine::Column(p.0),
+                        false => ProfileLine::Row(p.1),
+                    });
+                }
+                if let Some(line) = self.state.profile_line {
+                    paint_profile_line(&painter, &transform, line, data.size());
+                }
+            } else {
+                self.state.annotations.interact(
+                    &resp,
+                    &painter,
+                    &transform,
+                    &mut self.state.history,
+                );
             }
+            self.state.annotations.paint(&painter, &transform);
+        }
+        if !owns_view {
+            // The preview moves the view, see `ImageUIState::claim_view`.
+        } else if on_minimap {
+            if let Some(p) = ui.input().pointer.interact_pos() {
+                let uv = ((p - minimap.min) / minimap.size()).to_pos2();
+                self.state
+                    .set_center(minimap_center(self.state.diff_mode, uv));
+            }
+        } else if !pinching {
+            let nav = Navigation {
+                pan_per_point: Vec2::splat(-self.state.scale() * 0.001),
+                zoom_per_scroll: -0.0001,
+                primary_free: !self.state.annotations.is_editing() && !zooming,
+                hand_tool,
+            };
+            self.bindings.navigate(ui, &resp, self.state, nav);
+        }
+        if minimap_opacity > 0.0 {
+            self.paint_minimap(ui, data, minimap, minimap_opacity);
+        }
+        resp.context_menu(|ui| file_action_menu(ui, &mut self.state.file_action_requested));
+    }
+
+    /// Draws the whole image in `rect` with the shown parts outlined.
+    fn paint_minimap(&self, ui: &Ui, data: &ImageData, rect: Rect, opacity: f32) {
+        let painter = ui.painter();
+        let alpha = (255.0 * opacity) as u8;
+        painter.rect_filled(
+            rect.expand(2.0),
+            Rounding::same(2.0),
+            Color32::from_black_alpha(alpha / 2),
+        );
+        let mut mesh = epaint::Mesh::with_texture(data.color_texture_handle().id());
+        let uv = Rect::from_min_max(Pos2::ZERO, pos2(1.0, 1.0));
+        mesh.add_rect_with_uv(rect, uv, Color32::from_white_alpha(alpha));
+        painter.add(Shape::mesh(mesh));
+        let stroke = Stroke::new(1.5, Color32::YELLOW.linear_multiply(opacity));
+        for part in geometry::view_part_rects(self.state, rect) {
+            painter.rect_stroke(part.intersect(rect), Rounding::none(), stroke);
+        }
+    }
+
+    /// Ctrl+drag draws a rubber band, on release the view zooms so the band
+    /// fills it.
+    fn zoom_selection(&mut self, resp: &Response, painter: &Painter, transform: &ViewTransform) {
+        if resp.drag_started() {
+            self.state.zoom_drag_start = resp.interact_pointer_pos().map(|p| transform.to_image(p));
+        }
+        let (start, current) = match (self.state.zoom_drag_start, resp.ctx.pointer_latest_pos()) {
+            (Some(start), Some(current)) => (start, current),
+            _ => return,
+        };
+        let band = Rect::from_two_pos(transform.to_screen(start), current);
+        if !resp.drag_released() {
+            painter.rect(
+                band,
+                Rounding::none(),
+                Color32::from_white_alpha(24),
+                Stroke::new(1.0, Color32::WHITE),
+            );
+            return;
         }
-        if resp.dragged_by(PointerButton::Primary) {
-            let dd = resp.drag_delta() * (-self.state.scale() * 0.001);
-            self.state.set_center_diff(dd);
+        self.state.zoom_drag_start = None;
+        if band.width() < MIN_ZOOM_BAND || band.height() < MIN_ZOOM_BAND {
+            return;
         }
+        let size = transform.image_size;
+        let end = transform.to_image(current);
+        let uv = Rect::from_two_pos(
+            pos2(start.x / size.x, start.y / size.y),
+            pos2(end.x / size.x, end.y / size.y),
+        );
+        self.state.zoom_to(uv);
     }
 
     pub fn ui(&mut self, ui: &mut Ui) {
-        match self.data {
-            None => (),
-            Some(_) => self.data_exist_ui(ui),
+        if let Some(background) = self.background {
+            let rect = ui.available_rect_before_wrap();
+            ui.painter().rect_filled(rect, Rounding::none(), background);
+        }
+        let placeholder_usable = self.placeholder.is_some_and(|p| p.error.is_none())
+            && !matches!(
+                self.state.diff_mode,
+                DiffMode::VColorDiff
+                    | DiffMode::HColorDiff
+                    | DiffMode::PageDiff
+                    | DiffMode::PreviousDiff
+                    | DiffMode::ReferenceDiff
+                    | DiffMode::Triptych
+            );
+        if self.data.is_some() || placeholder_usable {
+            self.data_exist_ui(ui);
+        } else {
+            ui.centered_and_justified(|ui| ui.add(widgets::Spinner::new()));
+        }
+    }
+}
+
+/// Bottom right corner of `view` holding an overview of an image of `size`.
+fn minimap_rect(view: Rect, size: Vec2) -> Rect {
+    let scale = MINIMAP_SIZE / size.max_elem().max(1.0);
+    let max = view.right_bottom() - vec2(MINIMAP_MARGIN, MINIMAP_MARGIN);
+    Rect::from_min_max(max - size * scale, max)
+}
+
+/// View center, in texture coordinates of a compared part, that puts the
+/// point `uv` of the whole image in the middle.
+fn minimap_center(mode: DiffMode, uv: Pos2) -> Pos2 {
+    let uv = uv.clamp(Pos2::ZERO, pos2(1.0, 1.0));
+    match mode {
+        DiffMode::VSplit | DiffMode::VColorDiff | DiffMode::Triptych => {
+            pos2((uv.x * 2.0).min(1.999).fract(), uv.y)
         }
+        DiffMode::HSplit | DiffMode::HColorDiff => pos2(uv.x, (uv.y * 2.0).min(1.999).fract()),
+        DiffMode::Full | DiffMode::PageDiff | DiffMode::PreviousDiff | DiffMode::ReferenceDiff => {
+            uv
+        }
+    }
+}
+
+/// Draws the picked profile line through the middle of its pixels.
+fn paint_profile_line(painter: &Painter, transform: &ViewTransform, line: ProfileLine, size: Vec2) {
+    let (a, b) = match line {
+        ProfileLine::Row(y) => (pos2(0.0, y as f32 + 0.5), pos2(size.x, y as f32 + 0.5)),
+        ProfileLine::Column(x) => (pos2(x as f32 + 0.5, 0.0), pos2(x as f32 + 0.5, size.y)),
+    };
+    painter.line_segment(
+        [transform.to_screen(a), transform.to_screen(b)],
+        Stroke::new(1.0, Color32::YELLOW),
+    );
+}
+
+/// Outlines the image pixels once they are at least `PIXEL_GRID_MIN_ZOOM`
+/// points wide. The lines are dark over bright images and light over dark
+/// ones, judged by the average of the pixels around the view center.
+fn paint_pixel_grid(painter: &Painter, transform: &ViewTransform, data: &ImageData) {
+    let pixel = transform.pixel_size();
+    if pixel.min_elem() < PIXEL_GRID_MIN_ZOOM {
+        return;
+    }
+    let clip = painter.clip_rect().intersect(transform.rect);
+    let min = transform.to_image(clip.min).max(Pos2::ZERO);
+    let max = transform.to_image(clip.max).min(data.size().to_pos2());
+    if min.x >= max.x || min.y >= max.y {
+        return;
+    }
+    let center = min + (max - min) / 2.0;
+    let radius = ((max - min).max_elem() / 2.0).ceil() as u32;
+    let bright = data
+        .average_at(
+            center.x as u32,
+            center.y as u32,
+            radius.min(PIXEL_GRID_SAMPLE_RADIUS),
+        )
+        .is_some_and(|[r, g, b, _]| 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32 > 128.0);
+    let color = match bright {
+        true => Color32::from_black_alpha(48),
+        false => Color32::from_white_alpha(32),
+    };
+    let stroke = Stroke::new(1.0, color);
+    for x in min.x.ceil() as u32..=max.x.floor() as u32 {
+        let a = transform.to_screen(pos2(x as f32, min.y));
+        let b = transform.to_screen(pos2(x as f32, max.y));
+        painter.line_segment([a, b], stroke);
+    }
+    for y in min.y.ceil() as u32..=max.y.floor() as u32 {
+        let a = transform.to_screen(pos2(min.x, y as f32));
+        let b = transform.to_screen(pos2(max.x, y as f32));
+        painter.line_segment([a, b], stroke);
+    }
+}
+
+fn transpose(p: Pos2, transposed: bool) -> Pos2 {
+    if transposed {
+        pos2(p.y, p.x)
+    } else {
+        p
+    }
+}
+
+fn transpose_rect(r: Rect, transposed: bool) -> Rect {
+    Rect::from_min_max(transpose(r.min, transposed), transpose(r.max, transposed))
+}
+
+/// Draws the seam between the two `rects` of a vertical split with tick
+/// marks on image rows, `uvs` and `part` are the texture rects and the
+/// size of a half in pixels. `comb` is the comb texture with the width of
+/// the strip to show of it. `transposed` swaps the axes of everything for
+/// horizontal splits.
+fn paint_seam_guide(
+    painter: &Painter,
+    rects: [Rect; 2],
+    uvs: [Rect; 2],
+    part: Vec2,
+    comb: Option<(&TextureHandle, f32)>,
+    transposed: bool,
+) {
+    let rects = rects.map(|r| transpose_rect(r, transposed));
+    let uvs = uvs.map(|r| transpose_rect(r, transposed));
+    let part = transpose(part.to_pos2(), transposed).to_vec2();
+    let at = |x: f32, y: f32| transpose(pos2(x, y), transposed);
+    let (seam, top, bottom) = (rects[0].right(), rects[0].top(), rects[0].bottom());
+    // Viewport within one half, in half widths.
+    let (view_left, view_right) = (uvs[0].left() * 2.0, (uvs[1].right() - 0.5) * 2.0);
+    let seam_u = uvs[0].right() * 2.0;
+    let width = rects[0].width() + rects[1].width();
+    if view_right <= view_left || width <= 0.0 {
+        return;
+    }
+    let points_per_u = width / (view_right - view_left);
+
+    if let Some((texture, comb_width)) = comb {
+        let half = comb_width / 2.0 / part.x;
+        let (u0, u1) = (
+            (seam_u - half).max(view_left),
+            (seam_u + half).min(view_right),
+        );
+        let screen = Rect::from_min_max(
+            at(seam + (u0 - seam_u) * points_per_u, top),
+            at(seam + (u1 - seam_u) * points_per_u, bottom),
+        );
+        let uv = Rect::from_min_max(at(u0, uvs[0].top()), at(u1, uvs[0].bottom()));
+        let mut mesh = epaint::Mesh::with_texture(texture.id());
+        mesh.add_rect_with_uv(screen, uv, Color32::WHITE);
+        painter.add(Shape::mesh(mesh));
+    }
+
+    let stroke = Stroke::new(1.0, Color32::YELLOW);
+    painter.line_segment([at(seam, top), at(seam, bottom)], stroke);
+    let points_per_pixel = (bottom - top) / (uvs[0].height() * part.y);
+    if points_per_pixel.is_nan() || points_per_pixel <= 0.0 {
+        return;
+    }
+    let gap = ((MIN_TICK_GAP / points_per_pixel).ceil() as u32).next_power_of_two() as f32;
+    let first = (uvs[0].top() * part.y / gap).ceil() * gap;
+    let last = uvs[0].bottom() * part.y;
+    let mut row = first;
+    while row <= last {
+        let y = top + (row - uvs[0].top() * part.y) * points_per_pixel;
+        painter.line_segment([at(seam - 4.0, y), at(seam + 4.0, y)], stroke);
+        row += gap;
+    }
+}
+
+/// Entries for the actions on the file behind an image, the picked one is
+/// stored in `action`.
+pub fn file_action_menu(ui: &mut Ui, action: &mut Option<FileAction>) {
+    if ui.button("Copy path").clicked() {
+        *action = Some(FileAction::CopyPath);
+        ui.close_menu();
+    }
+    if ui.button("Reveal in file browser").clicked() {
+        *action = Some(FileAction::Reveal);
+        ui.close_menu();
     }
 }
@@ -0,0 +1,180 @@
+//! Pointer navigation shared by the image view and the preview of the
+//! controls, so both follow the same `PointerBindings`.
+
+use crate::geometry;
+use crate::image_ui_state::ViewInput;
+use crate::ImageUIState;
+use eframe::egui::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Scale factor per point of a zoom drag, as an exponent so that dragging
+/// back returns to the same zoom.
+const DRAG_ZOOM_SPEED: f32 = 0.005;
+
+/// Which pointer gestures move the view, set in the settings.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PointerBindings {
+    /// A left drag pans when no tool uses it.
+    pub primary_pans: bool,
+    pub middle_pans: bool,
+    /// A vertical right drag zooms, dragging up zooms in.
+    pub secondary_zooms: bool,
+    /// A middle click goes back to the view the image opened with.
+    pub middle_click_resets: bool,
+    /// A double click switches between actual size and the whole image.
+    pub double_click_toggles: bool,
+}
+
+impl PointerBindings {
+    pub fn new() -> Self {
+        Self {
+            primary_pans: true,
+            middle_pans: true,
+            secondary_zooms: false,
+            middle_click_resets: false,
+            double_click_toggles: false,
+        }
+    }
+
+    /// A button bound to navigation is held, tools leave the pointer alone
+    /// meanwhile.
+    pub fn navigating(&self, input: &InputState) -> bool {
+        let pointer = &input.pointer;
+        (pointer.button_down(PointerButton::Middle)
+            && (self.middle_pans || self.middle_click_resets))
+            || (pointer.button_down(PointerButton::Secondary) && self.secondary_zooms)
+    }
+
+    /// Pans, zooms and resets the view of `state` for the pointer on `resp`.
+    pub fn navigate(&self, ui: &Ui, resp: &Response, state: &mut ImageUIState, nav: Navigation) {
+        if resp.hovered() {
+            let scroll_delta = ui.input().scroll_delta.y;
+            if scroll_delta != 0.0 {
+                state.set_scale_diff(nav.zoom_per_scroll * scroll_delta);
+            }
+        }
+        let primary_pans = nav.hand_tool || (self.primary_pans && nav.primary_free);
+        if (resp.dragged_by(PointerButton::Primary) && primary_pans)
+            || (resp.dragged_by(PointerButton::Middle) && self.middle_pans)
+        {
+            let dd = resp.drag_delta();
+            state.set_center_diff(vec2(dd.x * nav.pan_per_point.x, dd.y * nav.pan_per_point.y));
+        }
+        if resp.dragged_by(PointerButton::Secondary) && self.secondary_zooms {
+            let dy = resp.drag_delta().y;
+            state.set_scale(state.scale() * (DRAG_ZOOM_SPEED * dy).exp());
+        }
+        if resp.clicked_by(PointerButton::Middle) && self.middle_click_resets {
+            state.reset_view();
+        }
+        if resp.double_clicked() && self.double_click_toggles {
+            state.toggle_actual_size();
+        }
+    }
+}
+
+/// Claims the view of `state` for `input` while `resp` is dragged, pinched
+/// or scrolled, see `ImageUIState::claim_view`. Returns whether `input` may
+/// move the view this frame.
+pub fn claim_view(ui: &Ui, resp: &Response, state: &mut ImageUIState, input: ViewInput) -> bool {
+    let (scrolled, touched, pointer_down) = {
+        let input = ui.input();
+        (
+            input.scroll_delta != Vec2::ZERO,
+            input.multi_touch().is_some(),
+            input.pointer.any_down(),
+        )
+    };
+    let active = resp.dragged() || (resp.hovered() && (scrolled || touched));
+    state.claim_view(input, active, pointer_down)
+}
+
+impl Default for PointerBindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How a widget maps the pointer onto its view.
+pub struct Navigation {
+    /// Change of the view center per dragged point.
+    pub pan_per_point: Vec2,
+    /// Change of the scale per scrolled point.
+    pub zoom_per_scroll: f32,
+    /// Left drags may pan, off while a tool uses them.
+    pub primary_free: bool,
+    /// Space is held, left drags pan whatever the bindings and the tools.
+    pub hand_tool: bool,
+}
+
+/// Fingers on the screen by touch id. egui only tells how their center
+/// moves, a pinch zooms about the center itself.
+#[derive(Clone, Default)]
+struct Touches(BTreeMap<u64, Pos2>);
+
+/// Center of the fingers on the screen, tracked under `id` from the touch
+/// events of each frame.
+fn touch_center(ui: &Ui, id: Id) -> Option<Pos2> {
+    let (events, pressed) = {
+        let input = ui.input();
+        let events = input
+            .events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Touch { id, phase, pos, .. } => Some((id.0, *phase, *pos)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (events, input.pointer.any_down())
+    };
+    let mut data = ui.data();
+    let touches = data.get_temp_mut_or_default::<Touches>(id);
+    // Touches that ended while the view wasn't shown are never reported.
+    if events.is_empty() && !pressed {
+        touches.0.clear();
+    }
+    for (touch, phase, pos) in events {
+        match phase {
+            TouchPhase::Start | TouchPhase::Move => {
+                touches.0.insert(touch, pos);
+            }
+            TouchPhase::End | TouchPhase::Cancel => {
+                touches.0.remove(&touch);
+            }
+        }
+    }
+    let count = touches.0.len();
+    let sum = touches
+        .0
+        .values()
+        .fold(Vec2::ZERO, |sum, p| sum + p.to_vec2());
+    (count > 0).then(|| (sum / count as f32).to_pos2())
+}
+
+/// Zooms `state` with a pinch that started on `resp`, about the center of
+/// the fingers. `parts` are the screen rectangles that each show the whole
+/// view. Without `owns_view` the fingers are only tracked. Returns whether
+/// a pinch is going on, the pointer then follows a single finger and its
+/// drags shouldn't pan or reach the tools.
+pub fn pinch(
+    ui: &Ui,
+    resp: &Response,
+    state: &mut ImageUIState,
+    parts: &[Rect],
+    owns_view: bool,
+) -> bool {
+    let center = touch_center(ui, resp.id.with("touches"));
+    let touch = match ui.input().multi_touch() {
+        Some(touch) if resp.rect.contains(touch.start_pos) => touch,
+        _ => return false,
+    };
+    let center = center.unwrap_or(touch.start_pos);
+    if let Some(anchor) = geometry::view_point(state, parts, center) {
+        if owns_view && touch.zoom_delta != 1.0 {
+            state.zoom_about(anchor, touch.zoom_delta);
+        }
+    }
+    true
+}
@@ -1,8 +1,20 @@
+mod compare_report;
+mod histogram;
+mod hover_preview;
 mod image_controls;
 mod image_view;
+mod interaction;
+mod profile;
+mod quick_open;
 mod splited_image;
 mod thumbnail;
+mod toast;
 
+pub use compare_report::{CompareReport, CompareReportAction};
+pub use hover_preview::HoverPreview;
 pub use image_controls::ImageControls;
-pub use image_view::ImageView;
+pub use image_view::{file_action_menu, ImageView};
+pub use interaction::PointerBindings;
+pub use quick_open::{QuickOpen, QuickOpenAction};
 pub use thumbnail::Thumbnail;
+pub use toast::Toast;
@@ -0,0 +1,59 @@
+use crate::theme;
+use eframe::egui::*;
+
+/// Values between 0 and 255 along a line of pixels, drawn as polylines.
+pub struct ProfilePlot {
+    size: Vec2,
+    lines: Vec<(Vec<f32>, Color32)>,
+    cursor: Option<usize>,
+}
+
+impl ProfilePlot {
+    pub fn new(size: Vec2) -> Self {
+        Self {
+            size,
+            lines: Vec::new(),
+            cursor: None,
+        }
+    }
+
+    pub fn line(mut self, values: Vec<f32>, color: Color32) -> Self {
+        self.lines.push((values, color));
+        self
+    }
+
+    /// Index of the value to mark with a vertical line.
+    pub fn cursor(mut self, cursor: Option<usize>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+}
+
+impl Widget for ProfilePlot {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let (rect, resp) = ui.allocate_exact_size(self.size, Sense::hover());
+        if !ui.is_rect_visible(rect) {
+            return resp;
+        }
+        let painter = ui.painter_at(rect);
+        let background = ui.visuals().extreme_bg_color;
+        painter.rect_filled(rect, Rounding::none(), background);
+        let len = self.lines.iter().map(|(v, _)| v.len()).max().unwrap_or(0);
+        let step = rect.width() / len.saturating_sub(1).max(1) as f32;
+        let to_y = |v: f32| rect.bottom() - v / 255.0 * rect.height();
+        for (values, color) in self.lines {
+            let points = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| pos2(rect.left() + i as f32 * step, to_y(*v)))
+                .collect();
+            painter.add(Shape::line(points, Stroke::new(1.0, color)));
+        }
+        if let Some(i) = self.cursor.filter(|i| *i < len) {
+            let x = rect.left() + i as f32 * step;
+            let color = theme::highlight_color(background);
+            painter.vline(x, rect.y_range(), Stroke::new(1.0, color));
+        }
+        resp
+    }
+}
@@ -0,0 +1,135 @@
+use crate::image_source::ImageSource;
+use eframe::egui::*;
+
+/// Most matches listed at once.
+const MAX_MATCHES: usize = 50;
+
+/// What the user did with the quick open window this frame.
+pub enum QuickOpenAction {
+    None,
+    Picked(ImageSource),
+    Closed,
+}
+
+/// Searchable list of the image names, picks one to jump to.
+pub struct QuickOpen {
+    query: String,
+    selected: usize,
+    focus_requested: bool,
+}
+
+/// Scores how well `query` matches `name` as a case-insensitive
+/// subsequence, `None` when it doesn't. The matched characters are found
+/// greedily from the left. Runs of consecutive characters and
+/// matches at word starts score higher.
+fn fuzzy_score(query: &str, name: &str) -> Option<i32> {
+    let name = name.chars().collect::<Vec<_>>();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for q in query.chars().flat_map(char::to_lowercase) {
+        let found = (next..name.len()).find(|&i| name[i].to_lowercase().eq([q]))?;
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == found) {
+            score += 4;
+        }
+        let word_start =
+            found == 0 || !name[found - 1].is_alphanumeric() || name[found].is_uppercase();
+        if word_start {
+            score += 2;
+        }
+        previous = Some(found);
+        next = found + 1;
+    }
+    if query.is_empty() {
+        // Everything matches, keep the list order.
+        return Some(0);
+    }
+    // Shorter names win ties.
+    Some(score * 256 - name.len().min(255) as i32)
+}
+
+impl QuickOpen {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            selected: 0,
+            focus_requested: true,
+        }
+    }
+
+    /// Images matching the query, best first.
+    fn matches<'a>(&self, files: &'a [ImageSource]) -> Vec<&'a ImageSource> {
+        let mut scored = files
+            .iter()
+            .filter_map(|f| fuzzy_score(&self.query, &f.file_name()).map(|s| (s, f)))
+            .collect::<Vec<_>>();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        scored.truncate(MAX_MATCHES);
+        scored.into_iter().map(|(_, f)| f).collect()
+    }
+
+    pub fn show(&mut self, ctx: &Context, files: &[ImageSource]) -> QuickOpenAction {
+        let (up, down, enter, escape) = {
+            let input = ctx.input();
+            (
+                input.key_pressed(Key::ArrowUp),
+                input.key_pressed(Key::ArrowDown),
+                input.key_pressed(Key::Enter),
+                input.key_pressed(Key::Escape),
+            )
+        };
+        if escape {
+            return QuickOpenAction::Closed;
+        }
+        let matches = self.matches(files);
+        if down {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        self.selected = self.selected.min(matches.len().saturating_sub(1));
+        let mut action = QuickOpenAction::None;
+        Window::new("Go to image")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_TOP, vec2(0.0, 40.0))
+            .show(ctx, |ui| {
+                let resp = ui.add(
+                    TextEdit::singleline(&mut self.query)
+                        .hint_text("Type part of a file name")
+                        .desired_width(400.0),
+                );
+                // Enter takes the focus away from the field, keep typing.
+                if std::mem::take(&mut self.focus_requested) || resp.lost_focus() {
+                    resp.request_focus();
+                }
+                if resp.changed() {
+                    self.selected = 0;
+                }
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    for (i, file) in matches.iter().enumerate() {
+                        let row = ui
+                            .selectable_label(i == self.selected, file.file_name())
+                            .on_hover_text(file.to_string());
+                        if i == self.selected && (up || down) {
+                            row.scroll_to_me(None);
+                        }
+                        if row.clicked() {
+                            action = QuickOpenAction::Picked((*file).clone());
+                        }
+                    }
+                    if matches.is_empty() {
+                        ui.weak("No matching images");
+                    }
+                });
+            });
+        if enter {
+            if let Some(file) = matches.get(self.selected) {
+                action = QuickOpenAction::Picked((*file).clone());
+            }
+        }
+        action
+    }
+}
@@ -1,3 +1,4 @@
+use crate::geometry;
 use crate::DiffMode;
 use arrayvec::ArrayVec;
 use eframe::egui::*;
@@ -6,32 +7,49 @@ use eframe::egui::*;
 #[derive(Clone, Debug)]
 pub struct SplittedImage {
     texture_id: TextureId,
-    sizes: ArrayVec<Vec2, 2>,
-    uvs: ArrayVec<Rect, 2>,
+    sizes: ArrayVec<Vec2, 3>,
+    uvs: ArrayVec<Rect, 3>,
     bg_fill: Color32,
     tint: Color32,
     sense: Sense,
     mode: DiffMode,
+    overlay: Option<TextureId>,
+    diff_texture: Option<TextureId>,
 }
 
 impl SplittedImage {
     pub fn new(
         texture_id: impl Into<TextureId>,
-        sizes: ArrayVec<Vec2, 2>,
-        uvs: ArrayVec<Rect, 2>,
+        sizes: ArrayVec<Vec2, 3>,
+        uvs: ArrayVec<Rect, 3>,
         mode: DiffMode,
     ) -> Self {
         Self {
             texture_id: texture_id.into(),
-            sizes: sizes,
-            uvs: uvs,
+            sizes,
+            uvs,
             bg_fill: Default::default(),
             tint: Color32::WHITE,
             sense: Sense::hover(),
-            mode: mode,
+            mode,
+            overlay: None,
+            diff_texture: None,
         }
     }
 
+    /// Texture drawn over the image with the same uvs, like a mask marking
+    /// some of its pixels.
+    pub fn overlay(mut self, texture_id: impl Into<TextureId>) -> Self {
+        self.overlay = Some(texture_id.into());
+        self
+    }
+
+    /// Texture of the difference, drawn in the last part of the triptych.
+    pub fn diff_texture(mut self, texture_id: impl Into<TextureId>) -> Self {
+        self.diff_texture = Some(texture_id.into());
+        self
+    }
+
     /// A solid color to put behind the image. Useful for transparent images.
     #[allow(dead_code)]
     pub fn bg_fill(mut self, bg_fill: impl Into<Color32>) -> Self {
@@ -58,11 +76,7 @@ impl SplittedImage {
 
 impl SplittedImage {
     pub fn size(&self) -> Vec2 {
-        match self.mode {
-            DiffMode::Full | DiffMode::VColorDiff | DiffMode::HColorDiff => self.sizes[0],
-            DiffMode::VSplit => vec2(self.sizes[0].x + self.sizes[1].x, self.sizes[0].y),
-            DiffMode::HSplit => vec2(self.sizes[0].x, self.sizes[0].y + self.sizes[1].y),
-        }
+        geometry::total_size(self.mode, &self.sizes)
     }
 
     pub fn paint_at(&self, ui: &mut Ui, rect: Rect) {
@@ -76,6 +90,8 @@ impl SplittedImage {
                 tint,
                 sense: _,
                 mode: _,
+                overlay,
+                diff_texture,
             } = self;
 
             if *bg_fill != Default::default() {
@@ -85,46 +101,24 @@ impl SplittedImage {
             }
 
             {
-                let rects = self.build_mesh_rects(rect);
-                for (rect, uv) in rects.iter().zip(uvs) {
+                let rects = geometry::mesh_rects(self.mode, rect, &self.sizes);
+                for (i, (rect, uv)) in rects.iter().zip(uvs).enumerate() {
+                    let texture_id = match diff_texture {
+                        Some(diff) if self.mode == DiffMode::Triptych && i == 2 => diff,
+                        _ => texture_id,
+                    };
                     let mut mesh = Mesh::with_texture(*texture_id);
                     mesh.add_rect_with_uv(*rect, *uv, *tint);
                     ui.painter().add(Shape::mesh(mesh));
+                    if let Some(overlay) = overlay {
+                        let mut mesh = Mesh::with_texture(*overlay);
+                        mesh.add_rect_with_uv(*rect, *uv, Color32::WHITE);
+                        ui.painter().add(Shape::mesh(mesh));
+                    }
                 }
             }
         }
     }
-
-    fn build_mesh_rects(&self, rect: Rect) -> ArrayVec<Rect, 2> {
-        let mut result = ArrayVec::new();
-        match self.mode {
-            DiffMode::Full | DiffMode::HColorDiff | DiffMode::VColorDiff => {
-                result.push(rect);
-            }
-            DiffMode::VSplit => {
-                let top = rect.top();
-                let bottom = rect.bottom();
-                let l_left = rect.left();
-                let l_right = l_left + self.sizes[0].x;
-                let r_left = l_left + self.sizes[0].x;
-                let r_right = rect.right();
-                result.push(Rect::from_min_max(pos2(l_left, top), pos2(l_right, bottom)));
-                result.push(Rect::from_min_max(pos2(r_left, top), pos2(r_right, bottom)));
-            }
-            DiffMode::HSplit => {
-                let left = rect.left();
-                let right = rect.right();
-                let t_top = rect.top();
-                let t_bottom = t_top + self.sizes[0].y;
-                let b_top = t_top + self.sizes[0].y;
-                let b_bottom = rect.bottom();
-                result.push(Rect::from_min_max(pos2(left, t_top), pos2(right, t_bottom)));
-                result.push(Rect::from_min_max(pos2(left, b_top), pos2(right, b_bottom)));
-            }
-        }
-
-        result
-    }
 }
 
 impl Widget for SplittedImage {
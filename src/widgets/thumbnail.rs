@@ -5,7 +5,11 @@ use crate::ImageData;
 pub struct Thumbnail<'a> {
     image: Option<&'a ImageData>,
     size: f32,
+    resolution: f32,
     is_current: bool,
+    pinned: bool,
+    duplicate: Option<Color32>,
+    caption: Option<(String, f32)>,
 }
 
 impl<'a> Thumbnail<'a> {
@@ -13,15 +17,61 @@ impl<'a> Thumbnail<'a> {
         Self {
             image,
             size,
+            resolution: size,
             is_current,
+            pinned: false,
+            duplicate: None,
+            caption: None,
         }
     }
+
+    /// Writes `text` under the image in a line of `font_size`, shortened in
+    /// the middle when it doesn't fit.
+    pub fn caption(mut self, text: impl Into<String>, font_size: f32) -> Self {
+        self.caption = Some((text.into(), font_size));
+        self
+    }
+
+    /// Height of the caption line of `font_size` under the image.
+    pub fn caption_height(font_size: f32) -> f32 {
+        font_size + 4.0
+    }
+
+    /// Size in pixels the image was made for, when it differs from the shown
+    /// size in points it's scaled to match.
+    pub fn resolution(mut self, resolution: f32) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Badges the thumbnail as a duplicate, in the color of its group.
+    pub fn duplicate(mut self, group_color: Option<Color32>) -> Self {
+        self.duplicate = group_color;
+        self
+    }
+
+    /// Where the duplicate badge is drawn on a thumbnail at `rect`.
+    pub fn badge_rect(rect: Rect) -> Rect {
+        Rect::from_min_size(rect.min + vec2(2.0, 2.0), vec2(28.0, 16.0))
+    }
+
+    /// Marks the thumbnail with a star.
+    pub fn pinned(mut self, pinned: bool) -> Self {
+        self.pinned = pinned;
+        self
+    }
 }
 
 impl Widget for Thumbnail<'_> {
     fn ui(self, ui: &mut Ui) -> Response {
-        let (rect, resp) = ui.allocate_exact_size(vec2(self.size, self.size), Sense::click());
-        if ui.is_rect_visible(rect) {
+        let caption_height = self
+            .caption
+            .as_ref()
+            .map_or(0.0, |(_, size)| Self::caption_height(*size));
+        let (cell, resp) =
+            ui.allocate_exact_size(vec2(self.size, self.size + caption_height), Sense::click());
+        let rect = Rect::from_min_size(cell.min, vec2(self.size, self.size));
+        if ui.is_rect_visible(cell) {
             ui.ctx().request_repaint();
             ui.allocate_ui_at_rect(rect, |ui| {
                 let bg_color = if self.is_current {
@@ -36,21 +86,93 @@ impl Widget for Thumbnail<'_> {
                         ui.centered_and_justified(|ui| ui.add(widgets::Spinner::new()));
                     }
                     Some(data) => {
-                        if data.error_msg.is_some() {
+                        if let Some(error) = data.error.as_ref() {
                             ui.centered_and_justified(|ui| {
-                                let text = RichText::new("Loading error").color(Color32::RED);
+                                let text = RichText::new(error.short_label()).color(Color32::RED);
                                 ui.label(text);
                             });
                         } else {
+                            let size = data.size() * (self.size / self.resolution);
                             ui.centered_and_justified(|ui| {
-                                ui.image(data.color_texture_handle(), data.size())
+                                ui.image(data.color_texture_handle(), size)
                             });
                         }
                     }
                 }
             });
+            if self.pinned {
+                ui.painter_at(rect).text(
+                    rect.right_top() + vec2(-4.0, 2.0),
+                    Align2::RIGHT_TOP,
+                    "★",
+                    FontId::proportional(16.0),
+                    Color32::GOLD,
+                );
+            }
+            if let Some(color) = self.duplicate {
+                let badge = Self::badge_rect(rect);
+                let painter = ui.painter_at(rect);
+                painter.rect_filled(badge, Rounding::same(3.0), color);
+                painter.text(
+                    badge.center(),
+                    Align2::CENTER_CENTER,
+                    "dup",
+                    FontId::proportional(11.0),
+                    Color32::BLACK,
+                );
+            }
+            if let Some((text, size)) = self.caption.as_ref() {
+                let font = FontId::proportional(*size);
+                let color = if self.is_current {
+                    ui.visuals().selection.bg_fill
+                } else {
+                    ui.visuals().text_color()
+                };
+                let text = truncate_middle(ui, text, &font, self.size - 4.0);
+                ui.painter_at(cell).text(
+                    pos2(rect.center().x, rect.bottom() + 2.0),
+                    Align2::CENTER_TOP,
+                    text,
+                    font,
+                    color,
+                );
+            }
         }
 
         resp
     }
 }
+
+/// `text` shortened by replacing its middle with "…" so it's at most
+/// `max_width` wide in `font`.
+fn truncate_middle(ui: &Ui, text: &str, font: &FontId, max_width: f32) -> String {
+    let width = |t: String| {
+        ui.fonts()
+            .layout_no_wrap(t, font.clone(), Color32::WHITE)
+            .size()
+            .x
+    };
+    if width(text.to_owned()) <= max_width {
+        return text.to_owned();
+    }
+    let chars = text.chars().collect::<Vec<_>>();
+    let shortened = |keep: usize| {
+        let head = keep.div_ceil(2);
+        let tail = keep / 2;
+        let mut s = chars[..head].iter().collect::<String>();
+        s.push('…');
+        s.extend(chars[chars.len() - tail..].iter());
+        s
+    };
+    // Longest kept prefix and suffix that still fit.
+    let (mut lo, mut hi) = (0, chars.len().saturating_sub(1));
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if width(shortened(mid)) <= max_width {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    shortened(lo)
+}
@@ -0,0 +1,50 @@
+use eframe::egui::*;
+use std::time::{Duration, Instant};
+
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+/// Short notification shown in the bottom right corner of the window.
+pub struct Toast {
+    text: String,
+    is_error: bool,
+    created: Instant,
+}
+
+impl Toast {
+    pub fn info(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            is_error: false,
+            created: Instant::now(),
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            is_error: true,
+            created: Instant::now(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.created.elapsed() > TOAST_DURATION
+    }
+
+    pub fn show(&self, ctx: &Context) {
+        Area::new("toast")
+            .anchor(Align2::RIGHT_BOTTOM, vec2(-10.0, -40.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    let text = if self.is_error {
+                        RichText::new(&self.text).color(Color32::RED)
+                    } else {
+                        RichText::new(&self.text)
+                    };
+                    ui.label(text);
+                });
+            });
+        ctx.request_repaint();
+    }
+}